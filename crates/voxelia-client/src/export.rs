@@ -0,0 +1,503 @@
+//! Exports regions of a world as meshes for other programs, in OBJ with its MTL or in glTF.
+//!
+//! The chunks go through [chunk::build] like the ones that are drawn, and the packed vertices
+//! are unpacked the way `shaders/voxel.wgsl` does it. A block is one unit wide and the corners of the
+//! blocks are on whole coordinates. The sky light, the ambient occlusion, the shade of each face and
+//! the tint of the biomes are baked into the colors of the vertices. The texture atlas is copied
+//! next to the exported files, and each blend mode becomes a material of its own.
+
+use std::{
+    collections::HashMap,
+    fmt::Write as _,
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use voxelia_engine::{
+    biome::BiomeRegistry,
+    block::BlockRegistry,
+    chunk::{Chunk, ChunkPosition},
+    streaming::ChunkStorage,
+    Position,
+};
+use voxelia_renderer::{BlendMode, VoxelVertex};
+
+use crate::model::{
+    chunk::{self, ChunkNeighbours},
+    cube,
+};
+
+/// Normals of the faces in the order of the faces of the cube.
+const NORMALS: [[f32; 3]; 6] = [
+    [0.0, 0.0, 1.0],
+    [0.0, 0.0, -1.0],
+    [-1.0, 0.0, 0.0],
+    [1.0, 0.0, 0.0],
+    [0.0, 1.0, 0.0],
+    [0.0, -1.0, 0.0],
+];
+
+/// Triangles of one blend mode with their vertices unpacked.
+#[derive(Default)]
+pub struct ExportedLayer {
+    pub positions: Vec<[f32; 3]>,
+    pub normals: Vec<[f32; 3]>,
+    pub tex_coords: Vec<[f32; 2]>,
+    /// Linear RGBA colors that multiply the texture.
+    pub colors: Vec<[f32; 4]>,
+    pub indices: Vec<u32>,
+}
+
+impl ExportedLayer {
+    fn push(&mut self, vertex: &VoxelVertex, origin: [f32; 3]) {
+        let corner = vertex.corner();
+        let face = vertex.face() as usize;
+        let [u, v] = vertex.tex_coords();
+
+        // The same light as the voxel shader, that never lets places without light be black.
+        let [r, g, b, light] = vertex.tint.map(|channel| channel as f32 / 255.0);
+        let shade = face_shade(face) * (0.4 + 0.2 * vertex.ao() as f32) * light.max(0.08);
+
+        self.positions.push([0, 1, 2].map(|axis| origin[axis] + corner[axis] as f32));
+        self.normals.push(NORMALS[face]);
+        // Like in the shader, every face shows the whole texture whatever its layer.
+        self.tex_coords.push([u as f32, v as f32]);
+        let [r, g, b] = [r, g, b].map(|channel| (channel.powf(2.2) * shade).min(1.0));
+        self.colors.push([r, g, b, 1.0]);
+    }
+}
+
+/// Shade of each direction of the faces, from `shaders/voxel.wgsl`.
+fn face_shade(face: usize) -> f32 {
+    match face {
+        0 | 1 => 0.8,
+        2 | 3 => 0.9,
+        4 => 1.0,
+        _ => 0.6,
+    }
+}
+
+/// The meshes of some chunks, split by how they are blended.
+#[derive(Default)]
+pub struct WorldExport {
+    pub opaque: ExportedLayer,
+    pub cutout: ExportedLayer,
+    pub translucent: ExportedLayer,
+}
+
+impl WorldExport {
+    /// Meshes the chunks of a storage between two chunks, both included. Chunks that were never
+    /// saved are left out, and the faces between two exported chunks are hidden like the ones
+    /// inside of a chunk.
+    pub fn from_storage(
+        storage: &dyn ChunkStorage,
+        from: ChunkPosition,
+        to: ChunkPosition,
+        registry: &BlockRegistry,
+        biomes: &BiomeRegistry,
+    ) -> io::Result<WorldExport> {
+        let mut chunks = HashMap::new();
+
+        for x in from.x.min(to.x)..=from.x.max(to.x) {
+            for y in from.y.min(to.y)..=from.y.max(to.y) {
+                for z in from.z.min(to.z)..=from.z.max(to.z) {
+                    let position = ChunkPosition::new(x, y, z);
+
+                    if let Some(chunk) = storage.load(&position)? {
+                        chunks.insert(position, chunk);
+                    }
+                }
+            }
+        }
+
+        Ok(WorldExport::from_chunks(&chunks, registry, biomes))
+    }
+
+    /// Meshes some chunks, hiding the faces between them.
+    pub fn from_chunks(
+        chunks: &HashMap<ChunkPosition, Chunk>,
+        registry: &BlockRegistry,
+        biomes: &BiomeRegistry,
+    ) -> WorldExport {
+        let mut export = WorldExport::default();
+
+        // Sorted so the same chunks are always exported in the same order.
+        let mut positions: Vec<_> = chunks.keys().collect();
+        positions.sort();
+
+        for position in positions {
+            let neighbours = cube::FACE_DISPLACEMENT.map(|direction| {
+                chunks.get(&ChunkPosition::new(
+                    position.x + direction.x,
+                    position.y + direction.y,
+                    position.z + direction.z,
+                ))
+            });
+
+            export.add_chunk(position, &chunks[position], &neighbours, registry, biomes);
+        }
+
+        export
+    }
+
+    pub fn add_chunk(
+        &mut self,
+        position: &ChunkPosition,
+        chunk: &Chunk,
+        neighbours: &ChunkNeighbours,
+        registry: &BlockRegistry,
+        biomes: &BiomeRegistry,
+    ) {
+        let geometry = chunk::build(chunk, neighbours, registry, biomes);
+
+        // The renderer puts chunks two units apart for each block.
+        let chunk_position = Position::new(position.x as f32, position.y as f32, position.z as f32);
+        let origin = chunk::global_chunk_position(&chunk_position) / 2.0;
+        let origin = [origin.x, origin.y, origin.z];
+
+        for (blend_mode, model) in geometry.layers() {
+            let layer = self.layer_mut(blend_mode);
+            let base = layer.positions.len() as u32;
+
+            for vertex in &model.vertices {
+                layer.push(vertex, origin);
+            }
+
+            layer.indices.extend(model.indices.iter().map(|index| base + index));
+        }
+    }
+
+    fn layer_mut(&mut self, blend_mode: BlendMode) -> &mut ExportedLayer {
+        match blend_mode {
+            BlendMode::Opaque => &mut self.opaque,
+            BlendMode::Cutout => &mut self.cutout,
+            BlendMode::Translucent => &mut self.translucent,
+        }
+    }
+
+    /// Layers that have triangles, with the name of their material.
+    fn layers(&self) -> impl Iterator<Item = (&'static str, BlendMode, &ExportedLayer)> {
+        [
+            ("opaque", BlendMode::Opaque, &self.opaque),
+            ("cutout", BlendMode::Cutout, &self.cutout),
+            ("translucent", BlendMode::Translucent, &self.translucent),
+        ]
+        .into_iter()
+        .filter(|(_, _, layer)| !layer.indices.is_empty())
+    }
+
+    /// Writes `<name>.obj` and `<name>.mtl` into a directory with a copy of the atlas. The colors of
+    /// the vertices follow their positions, which most programs read.
+    pub fn write_obj(&self, directory: impl AsRef<Path>, name: &str, atlas: impl AsRef<Path>) -> io::Result<()> {
+        let directory = directory.as_ref();
+        let atlas = copy_atlas(directory, atlas.as_ref())?;
+
+        let mut obj = format!("mtllib {name}.mtl\no {name}\n");
+        let mut mtl = String::new();
+        let mut base = 1;
+
+        for (material, _, layer) in self.layers() {
+            for (position, color) in layer.positions.iter().zip(&layer.colors) {
+                let [x, y, z] = position;
+                let [r, g, b, _] = color;
+                writeln!(obj, "v {x} {y} {z} {r} {g} {b}").unwrap();
+            }
+
+            // Textures start at the bottom in OBJ.
+            for [u, v] in &layer.tex_coords {
+                writeln!(obj, "vt {u} {}", 1.0 - v).unwrap();
+            }
+
+            for [x, y, z] in &layer.normals {
+                writeln!(obj, "vn {x} {y} {z}").unwrap();
+            }
+
+            writeln!(obj, "usemtl {material}").unwrap();
+
+            for triangle in layer.indices.chunks_exact(3) {
+                let [a, b, c] = [0, 1, 2].map(|corner| triangle[corner] + base);
+                writeln!(obj, "f {a}/{a}/{a} {b}/{b}/{b} {c}/{c}/{c}").unwrap();
+            }
+
+            base += layer.positions.len() as u32;
+
+            writeln!(mtl, "newmtl {material}\nKa 1 1 1\nKd 1 1 1\nKs 0 0 0\nillum 1\nmap_Kd {atlas}").unwrap();
+            if material != "opaque" {
+                writeln!(mtl, "map_d {atlas}").unwrap();
+            }
+            mtl.push('\n');
+        }
+
+        fs::write(directory.join(format!("{name}.obj")), obj)?;
+        fs::write(directory.join(format!("{name}.mtl")), mtl)
+    }
+
+    /// Writes `<name>.gltf` and the buffer of its data, `<name>.bin`, into a directory with a copy
+    /// of the atlas.
+    pub fn write_gltf(&self, directory: impl AsRef<Path>, name: &str, atlas: impl AsRef<Path>) -> io::Result<()> {
+        let directory = directory.as_ref();
+        let atlas = copy_atlas(directory, atlas.as_ref())?;
+
+        let mut buffer: Vec<u8> = Vec::new();
+        let mut views = Vec::new();
+        let mut accessors = Vec::new();
+        let mut primitives = Vec::new();
+        let mut materials = Vec::new();
+
+        // Adds a view of the buffer with its accessor, returning the index of the accessor.
+        let mut add = |data: &[u8], count: usize, component: u32, kind: &str, target: u32, bounds: &str| {
+            views.push(format!(
+                r#"{{"buffer":0,"byteOffset":{},"byteLength":{},"target":{target}}}"#,
+                buffer.len(),
+                data.len()
+            ));
+            buffer.extend_from_slice(data);
+            accessors.push(format!(
+                r#"{{"bufferView":{},"componentType":{component},"count":{count},"type":"{kind}"{bounds}}}"#,
+                views.len() - 1
+            ));
+            accessors.len() - 1
+        };
+
+        for (material, blend_mode, layer) in self.layers() {
+            let mut min = [f32::MAX; 3];
+            let mut max = [f32::MIN; 3];
+            for position in &layer.positions {
+                for axis in 0..3 {
+                    min[axis] = min[axis].min(position[axis]);
+                    max[axis] = max[axis].max(position[axis]);
+                }
+            }
+
+            let count = layer.positions.len();
+            let bounds = format!(r#","min":{min:?},"max":{max:?}"#);
+
+            let position = add(&floats(layer.positions.as_flattened()), count, 5126, "VEC3", 34962, &bounds);
+            let normal = add(&floats(layer.normals.as_flattened()), count, 5126, "VEC3", 34962, "");
+            let tex_coord = add(&floats(layer.tex_coords.as_flattened()), count, 5126, "VEC2", 34962, "");
+            let color = add(&floats(layer.colors.as_flattened()), count, 5126, "VEC4", 34962, "");
+
+            let indices: Vec<u8> = layer.indices.iter().flat_map(|index| index.to_le_bytes()).collect();
+            let indices = add(&indices, layer.indices.len(), 5125, "SCALAR", 34963, "");
+
+            primitives.push(format!(
+                r#"{{"attributes":{{"POSITION":{position},"NORMAL":{normal},"TEXCOORD_0":{tex_coord},"COLOR_0":{color}}},"indices":{indices},"material":{}}}"#,
+                materials.len()
+            ));
+
+            let alpha = match blend_mode {
+                BlendMode::Opaque => r#""alphaMode":"OPAQUE""#,
+                BlendMode::Cutout => r#""alphaMode":"MASK","alphaCutoff":0.5"#,
+                BlendMode::Translucent => r#""alphaMode":"BLEND""#,
+            };
+
+            materials.push(format!(
+                r#"{{"name":"{material}","pbrMetallicRoughness":{{"baseColorTexture":{{"index":0}},"metallicFactor":0,"roughnessFactor":1}},{alpha}}}"#
+            ));
+        }
+
+        let meshes = if primitives.is_empty() {
+            String::new()
+        } else {
+            format!(r#""meshes":[{{"name":"{}","primitives":[{}]}}],"#, escape(name), primitives.join(","))
+        };
+
+        let node = if primitives.is_empty() { "{}" } else { r#"{"mesh":0}"# };
+        let buffers = if buffer.is_empty() {
+            String::new()
+        } else {
+            format!(
+                r#""buffers":[{{"byteLength":{},"uri":"{}.bin"}}],"bufferViews":[{}],"accessors":[{}],"#,
+                buffer.len(),
+                escape(name),
+                views.join(","),
+                accessors.join(",")
+            )
+        };
+
+        let gltf = format!(
+            r#"{{"asset":{{"version":"2.0","generator":"voxelia"}},"scene":0,"scenes":[{{"nodes":[0]}}],"nodes":[{node}],{meshes}{buffers}"materials":[{}],"textures":[{{"sampler":0,"source":0}}],"samplers":[{{"magFilter":9728,"minFilter":9728}}],"images":[{{"uri":"{}"}}]}}"#,
+            materials.join(","),
+            escape(&atlas)
+        );
+
+        if !buffer.is_empty() {
+            fs::write(directory.join(format!("{name}.bin")), buffer)?;
+        }
+
+        fs::write(directory.join(format!("{name}.gltf")), gltf)
+    }
+}
+
+fn floats(values: &[f32]) -> Vec<u8> {
+    values.iter().flat_map(|value| value.to_le_bytes()).collect()
+}
+
+/// Escapes a string to put it between quotes in JSON.
+fn escape(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+/// Copies the atlas into the directory, returning the name of the copy.
+fn copy_atlas(directory: &Path, atlas: &Path) -> io::Result<String> {
+    let name = atlas
+        .file_name()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "the atlas is not a file"))?;
+
+    fs::create_dir_all(directory)?;
+
+    let copy: PathBuf = directory.join(name);
+    if fs::canonicalize(atlas)? != fs::canonicalize(&copy).unwrap_or_default() {
+        fs::copy(atlas, &copy)?;
+    }
+
+    Ok(name.to_string_lossy().into_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use voxelia_engine::{
+        block::{BlockDefinition, BlockPosition, Transparency},
+        chunk::{CHUNK_HEIGHT, CHUNK_LENGTH, CHUNK_WIDTH},
+        light::MAX_LIGHT,
+    };
+
+    use super::*;
+
+    fn registry() -> BlockRegistry {
+        let mut registry = BlockRegistry::default();
+        registry.register(BlockDefinition::new("stone", Transparency::Opaque, 3));
+        registry
+    }
+
+    /// A chunk with stone in some blocks and the same light everywhere.
+    fn chunk(blocks: &[[i64; 3]], light: u8) -> Chunk {
+        let mut chunk = Chunk::default();
+
+        for x in 0..CHUNK_WIDTH as i64 {
+            for y in 0..CHUNK_HEIGHT as i64 {
+                for z in 0..CHUNK_LENGTH as i64 {
+                    chunk.set_light(&BlockPosition::new(x, y, z), light);
+                }
+            }
+        }
+
+        for [x, y, z] in blocks {
+            chunk.set(&BlockPosition::new(*x, *y, *z), 1);
+        }
+
+        chunk
+    }
+
+    fn export(chunks: impl IntoIterator<Item = (ChunkPosition, Chunk)>) -> WorldExport {
+        WorldExport::from_chunks(&chunks.into_iter().collect(), &registry(), &BiomeRegistry::default())
+    }
+
+    #[test]
+    fn blocks_become_unit_cubes() {
+        let export = export([(ChunkPosition::new(1, 0, -1), chunk(&[[0, 0, 0]], MAX_LIGHT))]);
+        let layer = &export.opaque;
+
+        assert_eq!(layer.positions.len(), 24);
+        assert_eq!(layer.indices.len(), 36);
+        assert!(layer.indices.iter().all(|index| (*index as usize) < layer.positions.len()));
+        assert!(export.cutout.indices.is_empty() && export.translucent.indices.is_empty());
+
+        let corners = [[16.0, 17.0], [0.0, 1.0], [-16.0, -15.0]];
+        for position in &layer.positions {
+            assert!((0..3).all(|axis| corners[axis].contains(&position[axis])), "{position:?}");
+        }
+
+        let mut normals = Vec::new();
+        for face in 0..6 {
+            let vertices = face * 4..face * 4 + 4;
+            let normal = layer.normals[face * 4];
+            assert!(layer.normals[vertices.clone()].iter().all(|other| *other == normal));
+            normals.push(normal);
+
+            // The face is on the side of the cube that its normal looks at.
+            let axis = normal.iter().position(|x| *x != 0.0).unwrap();
+            let side = corners[axis][(normal[axis] > 0.0) as usize];
+            assert!(layer.positions[vertices.clone()].iter().all(|position| position[axis] == side));
+
+            // Every face shows the whole texture.
+            let mut tex_coords = layer.tex_coords[vertices].to_vec();
+            tex_coords.sort_by(|a, b| a.partial_cmp(b).unwrap());
+            assert_eq!(tex_coords, [[0.0, 0.0], [0.0, 1.0], [1.0, 0.0], [1.0, 1.0]]);
+        }
+
+        assert_eq!(normals, NORMALS);
+        assert!(layer.colors.iter().all(|color| color[3] == 1.0 && color[..3].iter().all(|x| *x > 0.0)));
+    }
+
+    #[test]
+    fn faces_between_chunks_are_hidden() {
+        let left = (ChunkPosition::new(0, 0, 0), chunk(&[[15, 31, 0]], MAX_LIGHT));
+        let right = (ChunkPosition::new(1, 0, 0), chunk(&[[0, 31, 0]], MAX_LIGHT));
+        let above = (ChunkPosition::new(1, 1, 0), chunk(&[[0, 0, 0]], MAX_LIGHT));
+
+        assert_eq!(export([left.clone()]).opaque.positions.len(), 24);
+        assert_eq!(export([left.clone(), right.clone()]).opaque.positions.len(), 40);
+        assert_eq!(export([left, right, above]).opaque.positions.len(), 56);
+    }
+
+    #[test]
+    fn faces_take_the_light_of_the_neighbours() {
+        let block = (ChunkPosition::new(0, 0, 0), chunk(&[[15, 4, 4]], MAX_LIGHT));
+        let dark = (ChunkPosition::new(1, 0, 0), chunk(&[], 0));
+
+        let right = |export: &WorldExport| {
+            let layer = &export.opaque;
+            let index = layer.normals.iter().position(|normal| *normal == [1.0, 0.0, 0.0]).unwrap();
+            layer.colors[index][0]
+        };
+
+        let alone = export([block.clone()]);
+        let next_to_dark = export([block, dark]);
+        assert!(right(&next_to_dark) < right(&alone) / 5.0);
+    }
+
+    #[test]
+    fn gltf_files_are_valid() {
+        let directory = std::env::temp_dir().join(format!("voxelia-export-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&directory);
+        fs::create_dir_all(&directory).unwrap();
+        let atlas = directory.join("source.png");
+        fs::write(&atlas, [0x89, b'P', b'N', b'G']).unwrap();
+
+        let export = export([(ChunkPosition::new(0, 0, 0), chunk(&[[0, 0, 0], [2, 0, 0]], MAX_LIGHT))]);
+        export.write_gltf(directory.join("out"), "with \"quotes\"", &atlas).unwrap();
+
+        let source = fs::read_to_string(directory.join("out/with \"quotes\".gltf")).unwrap();
+        let gltf: serde_json::Value = serde_json::from_str(&source).unwrap();
+        let length = fs::metadata(directory.join("out/with \"quotes\".bin")).unwrap().len();
+
+        assert_eq!(gltf["asset"]["version"], "2.0");
+        assert_eq!(gltf["buffers"][0]["byteLength"], length);
+        assert_eq!(gltf["images"][0]["uri"], "source.png");
+        assert_eq!(gltf["meshes"][0]["name"], "with \"quotes\"");
+        assert!(directory.join("out/source.png").exists());
+
+        for view in gltf["bufferViews"].as_array().unwrap() {
+            assert!(view["byteOffset"].as_u64().unwrap() + view["byteLength"].as_u64().unwrap() <= length);
+        }
+
+        let primitives = gltf["meshes"][0]["primitives"].as_array().unwrap();
+        assert_eq!(primitives.len(), 1);
+
+        let accessor = |name: &str| {
+            let index = primitives[0]["attributes"][name].as_u64().unwrap() as usize;
+            &gltf["accessors"][index]
+        };
+        assert_eq!(accessor("POSITION")["count"], 48);
+        assert_eq!(accessor("POSITION")["min"], serde_json::json!([0.0, 0.0, 0.0]));
+        assert_eq!(accessor("POSITION")["max"], serde_json::json!([3.0, 1.0, 1.0]));
+        assert_eq!(accessor("TEXCOORD_0")["type"], "VEC2");
+        assert_eq!(accessor("COLOR_0")["count"], 48);
+
+        let indices = &gltf["accessors"][primitives[0]["indices"].as_u64().unwrap() as usize];
+        assert_eq!(indices["count"], 72);
+
+        fs::remove_dir_all(&directory).unwrap();
+    }
+}
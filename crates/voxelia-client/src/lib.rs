@@ -1,11 +1,13 @@
-use structures::{graphics::Graphics, mesh::DynamicMesh};
-use systems::{chunk::ChunkRenderSystem, render::RendererSystem};
-use voxelia_engine::Plugin;
+use structures::{graphics::Graphics, mesh::DynamicMesh, mesh_queue::ChunkMeshQueue};
+use systems::{chunk::{ChunkRenderSystem, ChunkUploadSystem}, render::RendererSystem, viewer::CameraViewerSystem};
+use voxelia_engine::{biome::BiomeRegistry, workers::Workers, Plugin};
 
+pub mod export;
 pub mod structures;
 pub mod model;
 pub mod systems;
 pub mod position;
+pub mod workers;
 
 pub struct RendererPlugin {
     pub graphics: Graphics,
@@ -15,8 +17,15 @@ impl Plugin for RendererPlugin {
     fn setup(self, world: &mut voxelia_engine::WorldBuilder) {
         world.with_component::<DynamicMesh>();
 
+        // Chunks are tinted with the colors of the biomes, if there are any.
+        world.resource_mut::<BiomeRegistry>();
+        // Chunks are meshed by the workers, the ones given by plugins added before this one are kept.
+        world.resource_mut::<Workers>();
         world.with_resource(self.graphics);
+        world.with_resource(ChunkMeshQueue::default());
+        world.with_system(CameraViewerSystem, "camera viewer system", &[]);
         world.with_system(ChunkRenderSystem, "chunk render system", &[]);
-        world.with_system(RendererSystem, "renderer system", &[]);
+        world.with_system(ChunkUploadSystem, "chunk upload system", &["chunk render system"]);
+        world.with_system(RendererSystem, "renderer system", &["chunk upload system"]);
     }
 }
@@ -1,11 +1,19 @@
+use cgmath::Point3;
 use specs::{Builder, WorldExt};
 
 use voxelia_client::structures::graphics::Graphics;
+use voxelia_client::workers::TokioExecutor;
 use voxelia_client::RendererPlugin;
 
 use voxelia_engine::{
-    chunk::{Chunk, ChunkPlugin},
-    events::{Created, EventsPlugin},
+    chunk::ChunkPlugin,
+    events::EventsPlugin,
+    generation::terrain::TerrainPlugin,
+    info::WorldInfo,
+    persistence::PersistencePlugin,
+    storage::{self, backup::BackupPlugin, StoragePlugin},
+    streaming::{ChunkViewer, StreamingPlugin},
+    workers::WorkersPlugin,
     BasicPlugin, Engine, Position,
 };
 
@@ -28,21 +36,32 @@ async fn load(graphics: &mut Graphics) {
     graphics.add_material(material);
 }
 
+/// Directory where the world is saved.
+const WORLD_DIRECTORY: &str = "worlds/default";
+
+/// Radius in chunks of the area that is shown around the camera.
+const RENDER_DISTANCE: usize = 4;
+
+/// Chunks that are shown above and below the camera.
+const VERTICAL_RENDER_DISTANCE: usize = 2;
+
+/// Seed of the world when it's created.
+const WORLD_SEED: u64 = 0;
+
 /// Starts all the things in the engine
 async fn start_engine<'a, 'b>(engine: &mut Engine<'a, 'b>) {
+    // Blocks are two units wide in the renderer.
+    if let Some([x, y, z]) = engine.world.read_resource::<WorldInfo>().spawn {
+        let mut graphics = engine.world.write_resource::<Graphics>();
+        graphics.camera.position = Point3::new(x as f32, y as f32, z as f32) * 2.0;
+    }
+
+    // The chunks around the camera are loaded by following this viewer.
     engine
         .world
         .create_entity()
         .with(Position::new(0.0, 0.0, 0.0))
-        .with(Chunk {
-            data: [
-                1, 1, 1, 1, 1, 1, 1, 1, 1, 0, 0, 0, 0, 0, 0, 1,
-                1, 1, 1, 1, 1, 1, 1, 1, 1, 0, 0, 0, 0, 0, 0, 1,
-                1, 1, 1, 1, 1, 1, 1, 1, 1, 0, 0, 0, 0, 0, 0, 1,
-                1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1,
-            ],
-        })
-        .with(Created)
+        .with(ChunkViewer::new(RENDER_DISTANCE, VERTICAL_RENDER_DISTANCE))
         .build();
 }
 
@@ -60,10 +79,17 @@ async fn main() {
 
     graphics.resize(window.size());
 
-    let mut engine = voxelia_engine::Builder::new()
+    let mut engine = voxelia_engine::Builder::open(WORLD_DIRECTORY, WorldInfo::new(WORLD_SEED))
+        .expect("cannot open the world")
+        .with(WorkersPlugin(TokioExecutor::current()))
         .with(BasicPlugin)
         .with(EventsPlugin)
         .with(ChunkPlugin)
+        .with(TerrainPlugin::new(WORLD_SEED))
+        .with(StreamingPlugin::default())
+        .with(StoragePlugin::new(WORLD_DIRECTORY))
+        .with(PersistencePlugin)
+        .with(BackupPlugin::default())
         .with(RendererPlugin { graphics })
         .build();
 
@@ -95,6 +121,11 @@ async fn main() {
         }
         WindowEvents::Resized(size) => engine.world.write_resource::<Graphics>().resize(size),
         WindowEvents::Draw => engine.run(),
+        WindowEvents::Closed => {
+            if let Err(error) = storage::save_world(&engine.world) {
+                log::error!("cannot save the world: {error}");
+            }
+        }
         _ => (),
     })
 }
@@ -1,67 +1,364 @@
 //! This module handles the generation and update of chunk models. It includes functionality for
 //! creating chunk models from data and computing their global positions within the game world.
 
-use cgmath::Vector3;
-use voxelia_engine::{block::BlockPosition, chunk::{Chunk, CHUNK_HEIGHT, CHUNK_LENGTH, CHUNK_WIDTH}, Position};
-use voxelia_renderer::{MaterialId, Mesh, Model, ModelInstance, Renderer};
-
-use crate::position::Absolute;
+use cgmath::{EuclideanSpace, Point3, Vector3};
+use voxelia_engine::{
+    biome::BiomeRegistry,
+    block::{BlockPosition, BlockRegistry, Tint, Transparency, MAX_TEXTURE},
+    chunk::{Chunk, CHUNK_HEIGHT, CHUNK_LENGTH, CHUNK_WIDTH},
+    light::MAX_LIGHT,
+    occupancy::Occupancy,
+    Position,
+};
+use voxelia_renderer::{BlendMode, MaterialId, Mesh, Model, ModelIndex, ModelInstance, ModelVertex, Renderer, VoxelVertex};
 
 use super::cube;
 
-pub struct ChunkModel {
-    pub model: Model,
-    pub mesh: Mesh,
+const _: () = assert!(MAX_TEXTURE == VoxelVertex::MAX_LAYER, "every block texture must fit in the vertices");
+
+/// The chunks next to the one that is meshed, in the order of [cube::FACE_DISPLACEMENT]. Faces
+/// that look into a missing neighbour are kept and lit as if it was empty.
+pub type ChunkNeighbours<'a> = [Option<&'a Chunk>; 6];
+
+/// The models of a chunk split by how they are blended, each one becomes a different [Mesh].
+pub struct ChunkGeometry {
+    pub opaque: Model<VoxelVertex>,
+    pub cutout: Model<VoxelVertex>,
+    pub translucent: Model<VoxelVertex>,
 }
 
-impl ChunkModel {
-    pub fn global_chunk_position(
-        position: &Position,
-    ) -> Vector3<f32> {
-        Vector3::new(
-            position.x * CHUNK_WIDTH as f32 * 2.0,
-            position.y * CHUNK_HEIGHT as f32 * 2.0,
-            position.z * CHUNK_LENGTH as f32 * 2.0,
-        )
-    }
-
-    pub fn from_data(
-        position: &Position,
-        chunk: &Chunk,
-        material_id: MaterialId,
-        renderer: &Renderer,
-    ) -> (Model, Mesh) {
-        let mut vertices = Vec::new();
-        let mut indices = Vec::new();
-
-        for x in 0..CHUNK_WIDTH {
-            for y in 0..CHUNK_HEIGHT {
-                for z in 0..CHUNK_LENGTH {
-                    let coord = BlockPosition::new(x as i64, y as i64, z as i64);
-                    if chunk.get(&coord) == 1 {
-                        for i in 0..6 {
-                            let displacement = &cube::FACE_DISPLACEMENT[i];
-                            let mut neighbor_cube = &coord + displacement;
-                            if neighbor_cube.is_out() || chunk.get(&neighbor_cube) == 0 {
-                                let face_vertices = cube::face(i);
-                                indices.extend(cube::INDICES.iter().map(|x| x + vertices.len() as u16));
-                                vertices.extend(face_vertices.iter().map(|v| v.add(coord.to_slice())));
-                            }
-                        }
+impl Default for ChunkGeometry {
+    fn default() -> Self {
+        let empty = || Model { vertices: Vec::new(), indices: Vec::new() };
+
+        ChunkGeometry {
+            opaque: empty(),
+            cutout: empty(),
+            translucent: empty(),
+        }
+    }
+}
+
+impl ChunkGeometry {
+    pub fn layers(&self) -> [(BlendMode, &Model<VoxelVertex>); 3] {
+        [
+            (BlendMode::Opaque, &self.opaque),
+            (BlendMode::Cutout, &self.cutout),
+            (BlendMode::Translucent, &self.translucent),
+        ]
+    }
+
+    fn layer_mut(&mut self, transparency: Transparency) -> &mut Model<VoxelVertex> {
+        match transparency {
+            Transparency::Opaque => &mut self.opaque,
+            Transparency::Cutout => &mut self.cutout,
+            Transparency::Translucent => &mut self.translucent,
+        }
+    }
+}
+
+pub fn global_chunk_position(
+    position: &Position,
+) -> Vector3<f32> {
+    Vector3::new(
+        position.x * CHUNK_WIDTH as f32 * 2.0,
+        position.y * CHUNK_HEIGHT as f32 * 2.0,
+        position.z * CHUNK_LENGTH as f32 * 2.0,
+    )
+}
+
+/// Center of the chunk in global coordinates, used to sort chunks by distance.
+pub fn global_chunk_center(position: &Position) -> Point3<f32> {
+    let half = Vector3::new(CHUNK_WIDTH as f32, CHUNK_HEIGHT as f32, CHUNK_LENGTH as f32);
+    Point3::from_vec(global_chunk_position(position) + half)
+}
+
+/// Builds the models of a chunk. It does not touch the GPU so it can run in any thread.
+///
+/// Faces are hidden by opaque neighbours only, so leaves and glass show the faces between
+/// them, except for translucent blocks that also hide each other to avoid walls inside water.
+/// Tinted blocks take the colors of the biomes around each vertex and every face gets the sky
+/// light of the block in front of it, that can be in one of the `neighbours`.
+pub fn build(
+    chunk: &Chunk,
+    neighbours: &ChunkNeighbours,
+    registry: &BlockRegistry,
+    biomes: &BiomeRegistry,
+) -> ChunkGeometry {
+    let mut geometry = ChunkGeometry::default();
+
+    let mut opaque = Occupancy::default();
+    let mut cutout = Occupancy::default();
+    let mut translucent = Occupancy::default();
+
+    // Only the solid blocks have to be looked up to split them by transparency.
+    for x in 0..CHUNK_WIDTH as i64 {
+        for z in 0..CHUNK_LENGTH as i64 {
+            let mut solid = chunk.occupancy().column(x, z);
+
+            while solid != 0 {
+                let coord = BlockPosition::new(x, solid.trailing_zeros() as i64, z);
+                solid &= solid - 1;
+
+                let mask = match registry.transparency(chunk.get(&coord)) {
+                    Transparency::Opaque => &mut opaque,
+                    Transparency::Cutout => &mut cutout,
+                    Transparency::Translucent => &mut translucent,
+                };
+
+                mask.set(&coord, true);
+            }
+        }
+    }
+
+    for x in 0..CHUNK_WIDTH as i64 {
+        for z in 0..CHUNK_LENGTH as i64 {
+            for (i, &neighbour) in neighbours.iter().enumerate() {
+                let displacement = &cube::FACE_DISPLACEMENT[i];
+                let hidden = opaque.neighbours(x, z, displacement)
+                    | across(neighbour, registry, x, z, displacement, Transparency::Opaque);
+
+                let layers = [
+                    (Transparency::Opaque, opaque.column(x, z) & !hidden),
+                    (Transparency::Cutout, cutout.column(x, z) & !hidden),
+                    (
+                        Transparency::Translucent,
+                        translucent.visible(x, z, displacement)
+                            & !across(neighbour, registry, x, z, displacement, Transparency::Translucent)
+                            & !hidden,
+                    ),
+                ];
+
+                for (transparency, mut visible) in layers {
+                    let model = geometry.layer_mut(transparency);
+
+                    while visible != 0 {
+                        let y = visible.trailing_zeros() as i64;
+                        visible &= visible - 1;
+
+                        let coord = BlockPosition::new(x, y, z);
+                        let neighbor_cube = &coord + displacement;
+                        let block = registry.get(chunk.get(&coord));
+                        let layer = block.map_or(0, |block| block.texture);
+                        let tint = block.map_or(Tint::None, |block| block.tint);
+                        let light = face_light(chunk, neighbour, &neighbor_cube);
+
+                        model.indices.extend(cube::INDICES.iter().map(|x| x + model.vertices.len() as ModelIndex));
+                        model.vertices.extend(cube::face(i).iter().map(|v| {
+                            let ao = occlusion(&opaque, &neighbor_cube, displacement, v);
+                            let vertex = cube::pack(v, &coord, i, ao, layer);
+                            let [corner_x, _, corner_z] = vertex.corner();
+                            let [r, g, b, _] = biome_tint(chunk, biomes, tint, corner_x as usize, corner_z as usize);
+                            vertex.with_tint([r, g, b, light])
+                        }));
                     }
                 }
             }
         }
+    }
+
+    geometry
+}
+
+/// Uploads the models of a chunk to the GPU, empty models have no mesh.
+pub fn upload(
+    geometry: &ChunkGeometry,
+    position: &Position,
+    material_id: MaterialId,
+    renderer: &Renderer,
+) -> Vec<Mesh> {
+    geometry
+        .layers()
+        .into_iter()
+        .filter(|(_, model)| !model.indices.is_empty())
+        .map(|(blend_mode, model)| {
+            Mesh::from_vertex(
+                renderer,
+                "Chunk".to_owned(),
+                &model.vertices,
+                &model.indices,
+                &[ModelInstance::from_position(global_chunk_position(position))],
+                material_id,
+            )
+            .with_blend_mode(blend_mode)
+        })
+        .collect()
+}
+
+/// Position of a block that is outside of the chunk inside of the neighbour that has it.
+fn wrap(position: &BlockPosition) -> BlockPosition {
+    BlockPosition::new(
+        position.x.rem_euclid(CHUNK_WIDTH as i64),
+        position.y.rem_euclid(CHUNK_HEIGHT as i64),
+        position.z.rem_euclid(CHUNK_LENGTH as i64),
+    )
+}
+
+/// Returns the bits of the column at `x` and `z` whose neighbour in the `direction` is a block of
+/// the `neighbour` chunk with the given transparency.
+fn across(
+    neighbour: Option<&Chunk>,
+    registry: &BlockRegistry,
+    x: i64,
+    z: i64,
+    direction: &BlockPosition,
+    transparency: Transparency,
+) -> u64 {
+    let Some(neighbour) = neighbour else { return 0 };
+    let mut bits = 0;
+
+    for y in 0..CHUNK_HEIGHT as i64 {
+        let mut front = &BlockPosition::new(x, y, z) + direction;
+        if !front.is_out() {
+            continue;
+        }
+
+        let front = wrap(&front);
+        if neighbour.occupancy().is_solid(&front) && registry.transparency(neighbour.get(&front)) == transparency {
+            bits |= 1 << y;
+        }
+    }
+
+    bits
+}
+
+/// Sky light that reaches a face from the block in front of it, scaled to a byte. Blocks outside of
+/// the chunk are looked up in the `neighbour` in front of the face, and count as fully lit without
+/// one.
+fn face_light(chunk: &Chunk, neighbour: Option<&Chunk>, front: &BlockPosition) -> u8 {
+    let mut position = *front;
+    let light = match neighbour {
+        _ if !position.is_out() => chunk.light(&position),
+        Some(neighbour) => neighbour.light(&wrap(&position)),
+        None => MAX_LIGHT,
+    };
+    (light as u32 * 255 / MAX_LIGHT as u32) as u8
+}
+
+/// Color of a tinted vertex at a corner of the columns. It's the average of the colors of the
+/// columns that share the corner, so the colors fade between biomes.
+fn biome_tint(chunk: &Chunk, biomes: &BiomeRegistry, tint: Tint, x: usize, z: usize) -> [u8; 4] {
+    if tint == Tint::None {
+        return VoxelVertex::WHITE;
+    }
+
+    let mut sum = [0u32; 3];
+    let mut count = 0;
+
+    for column_x in x.saturating_sub(1)..x.min(CHUNK_WIDTH - 1) + 1 {
+        for column_z in z.saturating_sub(1)..z.min(CHUNK_LENGTH - 1) + 1 {
+            let Some(biome) = biomes.get(chunk.biome(column_x, column_z)) else {
+                continue;
+            };
+
+            let color = match tint {
+                Tint::Grass => biome.grass_color,
+                _ => biome.foliage_color,
+            };
+
+            for (sum, channel) in sum.iter_mut().zip(color) {
+                *sum += channel as u32;
+            }
+            count += 1;
+        }
+    }
+
+    if count == 0 {
+        return VoxelVertex::WHITE;
+    }
+
+    let [r, g, b] = sum.map(|x| (x / count) as u8);
+    [r, g, b, 255]
+}
+
+/// Computes the ambient occlusion of a vertex of a face by looking at the blocks that touch the
+/// vertex in front of the face. `front` is the block that the face is looking at.
+fn occlusion(occupancy: &Occupancy, front: &BlockPosition, normal: &BlockPosition, vertex: &ModelVertex) -> u32 {
+    // The vertices of the cube are on ±1 so they point to the neighbors that touch them, the axis
+    // of the normal is ignored because we are already in front of the face.
+    let [dx, dy, dz] = vertex.position.map(|x| x as i64);
+
+    let (side1, side2) = if normal.x != 0 {
+        (BlockPosition::new(0, dy, 0), BlockPosition::new(0, 0, dz))
+    } else if normal.y != 0 {
+        (BlockPosition::new(dx, 0, 0), BlockPosition::new(0, 0, dz))
+    } else {
+        (BlockPosition::new(dx, 0, 0), BlockPosition::new(0, dy, 0))
+    };
+
+    let corner = occupancy.is_solid(&((front + &side1) + side2));
+    let side1 = occupancy.is_solid(&(front + &side1));
+    let side2 = occupancy.is_solid(&(front + &side2));
+
+    if side1 && side2 {
+        0
+    } else {
+        3 - (side1 as u32 + side2 as u32 + corner as u32)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use voxelia_engine::block::{BlockDefinition, BlockId};
+
+    use super::*;
+
+    const STONE: BlockId = 1;
+    const GLASS: BlockId = 2;
+    const WATER: BlockId = 3;
+
+    fn registry() -> BlockRegistry {
+        let mut registry = BlockRegistry::default();
+        registry.register(BlockDefinition::new("stone", Transparency::Opaque, 0));
+        registry.register(BlockDefinition::new("glass", Transparency::Cutout, 1));
+        registry.register(BlockDefinition::new("water", Transparency::Translucent, 2));
+        registry
+    }
+
+    fn chunk(blocks: &[([i64; 3], BlockId)]) -> Chunk {
+        let mut chunk = Chunk::default();
+        for ([x, y, z], block) in blocks {
+            chunk.set(&BlockPosition::new(*x, *y, *z), *block);
+        }
+        chunk
+    }
+
+    /// Number of faces of each layer, opaque, cutout and translucent.
+    fn faces(chunk: &Chunk, neighbours: &ChunkNeighbours) -> [usize; 3] {
+        let geometry = build(chunk, neighbours, &registry(), &BiomeRegistry::default());
+        geometry.layers().map(|(_, model)| model.indices.len() / cube::INDICES.len())
+    }
+
+    #[test]
+    fn blocks_go_to_the_layer_of_their_transparency() {
+        let blocks = [([1, 1, 1], STONE), ([5, 1, 1], GLASS), ([9, 1, 1], WATER)];
+        assert_eq!(faces(&chunk(&blocks), &[None; 6]), [6, 6, 6]);
+    }
+
+    #[test]
+    fn only_opaque_blocks_hide_the_faces_of_other_kinds() {
+        // Glass shows the faces between two glass blocks and the stone behind it.
+        assert_eq!(faces(&chunk(&[([1, 1, 1], GLASS), ([2, 1, 1], GLASS)]), &[None; 6]), [0, 12, 0]);
+        assert_eq!(faces(&chunk(&[([1, 1, 1], STONE), ([2, 1, 1], GLASS)]), &[None; 6]), [6, 5, 0]);
+
+        // Water hides the faces between water blocks, but not the ones of the blocks in it.
+        assert_eq!(faces(&chunk(&[([1, 1, 1], WATER), ([2, 1, 1], WATER)]), &[None; 6]), [0, 0, 10]);
+        assert_eq!(faces(&chunk(&[([1, 1, 1], WATER), ([1, 2, 1], STONE)]), &[None; 6]), [6, 0, 5]);
+        assert_eq!(faces(&chunk(&[([1, 1, 1], WATER), ([1, 2, 1], GLASS)]), &[None; 6]), [0, 6, 6]);
+    }
+
+    #[test]
+    fn neighbours_hide_translucent_faces_like_blocks_inside_the_chunk() {
+        let last = CHUNK_WIDTH as i64 - 1;
+        let water = chunk(&[([last, 1, 1], WATER)]);
 
-        let mesh = Mesh::from_vertex(
-            renderer,
-            "Chunk".to_owned(),
-            &vertices,
-            &indices,
-            &[ModelInstance::from_position(ChunkModel::global_chunk_position(position))],
-            material_id,
-        );
+        let mut neighbours: ChunkNeighbours = [None; 6];
+        let next_water = chunk(&[([0, 1, 1], WATER)]);
+        neighbours[cube::RIGHT] = Some(&next_water);
+        assert_eq!(faces(&water, &neighbours), [0, 0, 5]);
 
-        (Model { vertices, indices }, mesh)
+        let next_glass = chunk(&[([0, 1, 1], GLASS)]);
+        neighbours[cube::RIGHT] = Some(&next_glass);
+        assert_eq!(faces(&water, &neighbours), [0, 0, 6]);
     }
 }
@@ -2,7 +2,7 @@
 //! based on Cubes, its not widely used by itself.
 
 use voxelia_engine::block::BlockPosition;
-use voxelia_renderer::{ModelIndex, ModelVertex};
+use voxelia_renderer::{ModelIndex, ModelVertex, VoxelVertex};
 use super::vertex;
 
 pub const FRONT : usize = 0;
@@ -64,3 +64,16 @@ pub const INDICES: &[ModelIndex] = &[0, 1, 2, 3, 0, 2];
 pub fn face(number: usize) -> &'static [ModelVertex] {
     &VERTICES[number * 4..(number + 1) * 4]
 }
+
+/// Packs a vertex of [VERTICES] as a [VoxelVertex] of the block at `coord`.
+pub fn pack(vertex: &ModelVertex, coord: &BlockPosition, face: usize, ao: u32, layer: u32) -> VoxelVertex {
+    let corner = |axis: usize, base: i64| (base + (vertex.position[axis] > 0.0) as i64) as u32;
+
+    VoxelVertex::new(
+        [corner(0, coord.x), corner(1, coord.y), corner(2, coord.z)],
+        face as u32,
+        [vertex.tex_coords[0] as u32, vertex.tex_coords[1] as u32],
+        ao,
+        layer,
+    )
+}
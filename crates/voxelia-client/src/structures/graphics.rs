@@ -30,12 +30,12 @@ impl Graphics {
         let projection = Projection::new(renderer.size);
         
         let camera = Camera::new(
-            (5.0, 2.5, -15.0),
+            (128.0, 70.0, -20.0),
             cgmath::Deg(90.0),
-            cgmath::Deg(0.0),
+            cgmath::Deg(-20.0),
         );
 
-        let camera_controller = camera::CameraController::new(1.0, 0.1);
+        let camera_controller = camera::CameraController::new(10.0, 0.1);
 
         let mut info = Graphics {
             renderer,
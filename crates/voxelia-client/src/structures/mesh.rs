@@ -1,11 +1,17 @@
 //! Definition of meshes and dynamic meshes that can be used as components.
 
+use cgmath::Point3;
 use specs::{Component, VecStorage};
-use voxelia_renderer::model::{Mesh, Model};
+use voxelia_renderer::model::Mesh;
+
+use crate::model::chunk::ChunkGeometry;
 
 #[derive(Component)]
 #[storage(VecStorage)]
 pub struct DynamicMesh {
-    pub data: Mesh,
-    pub model: Model,
+    /// One mesh for each blend mode that has any geometry.
+    pub data: Vec<Mesh>,
+    pub model: ChunkGeometry,
+    /// Point used to sort the translucent meshes from back to front.
+    pub center: Point3<f32>,
 }
@@ -0,0 +1,252 @@
+//! Definition of the [ChunkMeshQueue], the queue of chunks that are waiting to be meshed by the
+//! worker threads and of the meshes that are waiting to be uploaded to the GPU.
+
+use std::collections::HashMap;
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::Mutex;
+use std::thread;
+
+use cgmath::{MetricSpace, Point3};
+use specs::Entity;
+use voxelia_engine::{biome::BiomeRegistry, block::BlockRegistry, chunk::Chunk, workers::Workers};
+
+use crate::model::chunk::{self, ChunkGeometry};
+
+/// A chunk that is waiting for a worker thread to mesh it.
+struct MeshJob {
+    entity: Entity,
+    revision: u64,
+    center: Point3<f32>,
+    chunk: Chunk,
+    registry: BlockRegistry,
+    biomes: BiomeRegistry,
+}
+
+/// The model of a chunk that was meshed by a worker thread.
+pub struct MeshedChunk {
+    pub entity: Entity,
+    pub revision: u64,
+    pub center: Point3<f32>,
+    pub model: ChunkGeometry,
+}
+
+/// Schedules chunks to be meshed on the [Workers] of the engine, nearest to the viewer first, and
+/// hands back the finished models with a limit of uploads for each frame.
+pub struct ChunkMeshQueue {
+    /// Maximum number of chunks that are being meshed at the same time.
+    pub max_in_flight: usize,
+    /// Maximum number of meshes that are uploaded to the GPU in a single frame.
+    pub uploads_per_frame: usize,
+    pending: Vec<MeshJob>,
+    revisions: HashMap<Entity, u64>,
+    in_flight: usize,
+    sender: Sender<MeshedChunk>,
+    receiver: Mutex<Receiver<MeshedChunk>>,
+}
+
+impl Default for ChunkMeshQueue {
+    fn default() -> Self {
+        let threads = thread::available_parallelism().map_or(1, |count| count.get());
+        Self::new(threads * 2, 4)
+    }
+}
+
+impl ChunkMeshQueue {
+    pub fn new(max_in_flight: usize, uploads_per_frame: usize) -> Self {
+        let (sender, receiver) = mpsc::channel();
+
+        ChunkMeshQueue {
+            max_in_flight,
+            uploads_per_frame,
+            pending: Vec::new(),
+            revisions: HashMap::new(),
+            in_flight: 0,
+            sender,
+            receiver: Mutex::new(receiver),
+        }
+    }
+
+    /// Queues a chunk to be meshed. Older jobs of the same entity are discarded and results of
+    /// jobs that are already running are ignored when they arrive.
+    pub fn push(&mut self, entity: Entity, center: Point3<f32>, chunk: Chunk, registry: BlockRegistry, biomes: BiomeRegistry) {
+        let revision = self.revisions.entry(entity).or_default();
+        *revision += 1;
+
+        self.pending.retain(|job| job.entity != entity);
+        self.pending.push(MeshJob {
+            entity,
+            revision: *revision,
+            center,
+            chunk,
+            registry,
+            biomes,
+        });
+    }
+
+    /// Forgets everything about an entity, used when the chunk is removed.
+    pub fn forget(&mut self, entity: Entity) {
+        self.revisions.remove(&entity);
+        self.pending.retain(|job| job.entity != entity);
+    }
+
+    /// Forgets the entities that `alive` returns false for, like the chunks that were unloaded.
+    pub fn retain(&mut self, alive: impl Fn(Entity) -> bool) {
+        self.revisions.retain(|entity, _| alive(*entity));
+        self.pending.retain(|job| alive(job.entity));
+    }
+
+    /// Number of chunks that are waiting for a worker or being meshed right now.
+    pub fn len(&self) -> usize {
+        self.pending.len() + self.in_flight
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Sends the pending chunks that are nearest to the `viewer` to the worker threads.
+    pub fn dispatch(&mut self, viewer: Point3<f32>, workers: &Workers) {
+        if self.in_flight >= self.max_in_flight || self.pending.is_empty() {
+            return;
+        }
+
+        // The viewer moves all the time so the priorities are computed again on every dispatch,
+        // the nearest chunks stay at the end so they can be popped.
+        self.pending.sort_unstable_by(|a, b| {
+            let a = a.center.distance2(viewer);
+            let b = b.center.distance2(viewer);
+            b.total_cmp(&a)
+        });
+
+        while self.in_flight < self.max_in_flight {
+            let Some(job) = self.pending.pop() else {
+                break;
+            };
+
+            self.in_flight += 1;
+
+            let sender = self.sender.clone();
+
+            workers.spawn(move || {
+                let model = chunk::build(&job.chunk, &[None; 6], &job.registry, &job.biomes);
+
+                // The receiver only goes away with the queue itself.
+                let _ = sender.send(MeshedChunk {
+                    entity: job.entity,
+                    revision: job.revision,
+                    center: job.center,
+                    model,
+                });
+            });
+        }
+    }
+
+    /// Takes the finished models that are still up to date, at most `uploads_per_frame` of them.
+    pub fn finished(&mut self) -> Vec<MeshedChunk> {
+        let receiver = self.receiver.get_mut().unwrap();
+        let mut finished = Vec::new();
+
+        while finished.len() < self.uploads_per_frame {
+            let Ok(meshed) = receiver.try_recv() else {
+                break;
+            };
+
+            self.in_flight -= 1;
+
+            if self.revisions.get(&meshed.entity) == Some(&meshed.revision) {
+                finished.push(meshed);
+            }
+        }
+
+        finished
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use specs::{Builder, World, WorldExt};
+    use voxelia_engine::workers::{Executor, Job};
+
+    use super::*;
+
+    /// Runs the jobs right away, in the thread that dispatches them.
+    struct Inline;
+
+    impl Executor for Inline {
+        fn spawn(&self, job: Job) {
+            job();
+        }
+    }
+
+    fn entities(count: usize) -> Vec<Entity> {
+        let mut world = World::new();
+        (0..count).map(|_| world.create_entity().build()).collect()
+    }
+
+    fn push(queue: &mut ChunkMeshQueue, entity: Entity, x: f32) {
+        let center = Point3::new(x, 0.0, 0.0);
+        queue.push(entity, center, Chunk::default(), BlockRegistry::default(), BiomeRegistry::default());
+    }
+
+    fn meshed(finished: Vec<MeshedChunk>) -> Vec<(Entity, u64)> {
+        finished.into_iter().map(|meshed| (meshed.entity, meshed.revision)).collect()
+    }
+
+    #[test]
+    fn nearest_chunks_are_meshed_first() {
+        let workers = Workers::new(Inline);
+        let mut queue = ChunkMeshQueue::new(1, 4);
+        let entities = entities(3);
+
+        for (entity, x) in entities.iter().zip([30.0, -10.0, 20.0]) {
+            push(&mut queue, *entity, x);
+        }
+        assert_eq!(queue.len(), 3);
+
+        let mut order = Vec::new();
+        while !queue.is_empty() {
+            queue.dispatch(Point3::new(0.0, 0.0, 0.0), &workers);
+            order.extend(meshed(queue.finished()).into_iter().map(|(entity, _)| entity));
+        }
+
+        assert_eq!(order, [entities[1], entities[2], entities[0]]);
+    }
+
+    #[test]
+    fn only_the_latest_models_are_kept() {
+        let workers = Workers::new(Inline);
+        let mut queue = ChunkMeshQueue::new(4, 4);
+        let entities = entities(2);
+
+        // The chunk changed while its first model was being built.
+        push(&mut queue, entities[0], 0.0);
+        queue.dispatch(Point3::new(0.0, 0.0, 0.0), &workers);
+        push(&mut queue, entities[0], 0.0);
+        assert!(queue.finished().is_empty());
+
+        queue.dispatch(Point3::new(0.0, 0.0, 0.0), &workers);
+        assert_eq!(meshed(queue.finished()), [(entities[0], 2)]);
+
+        // Chunks that are gone are forgotten, even if they are being meshed.
+        push(&mut queue, entities[1], 0.0);
+        queue.dispatch(Point3::new(0.0, 0.0, 0.0), &workers);
+        queue.retain(|entity| entity != entities[1]);
+        assert!(queue.finished().is_empty());
+        assert!(queue.is_empty());
+    }
+
+    #[test]
+    fn uploads_are_limited_for_each_frame() {
+        let workers = Workers::new(Inline);
+        let mut queue = ChunkMeshQueue::new(4, 2);
+
+        for entity in entities(3) {
+            push(&mut queue, entity, 0.0);
+        }
+        queue.dispatch(Point3::new(0.0, 0.0, 0.0), &workers);
+
+        assert_eq!(queue.finished().len(), 2);
+        assert_eq!(queue.finished().len(), 1);
+        assert!(queue.is_empty());
+    }
+}
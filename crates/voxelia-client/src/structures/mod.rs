@@ -1,2 +1,3 @@
 pub mod graphics;
-pub mod mesh;
\ No newline at end of file
+pub mod mesh;
+pub mod mesh_queue;
\ No newline at end of file
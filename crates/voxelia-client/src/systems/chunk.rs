@@ -1,39 +1,93 @@
 //! Components related to chunks, chunk rendering and stuff.
 
 use specs::Join;
-use specs::{Entities, ReadStorage, System, WriteExpect, WriteStorage};
+use specs::{Entities, ReadExpect, ReadStorage, System, WriteExpect, WriteStorage};
+use voxelia_engine::biome::BiomeRegistry;
+use voxelia_engine::block::BlockRegistry;
 use voxelia_engine::chunk::Chunk;
 use voxelia_engine::events::Created;
+use voxelia_engine::generation::ChunkStatus;
+use voxelia_engine::workers::Workers;
 use voxelia_engine::Position;
 use voxelia_renderer::model::MaterialId;
 
 use crate::structures::graphics::Graphics;
 use crate::structures::mesh::DynamicMesh;
+use crate::structures::mesh_queue::ChunkMeshQueue;
 use crate::model::chunk;
 
-/// Receives a ChunkCreated event and then creates a rendered thing for it.
+/// Receives a ChunkCreated event and then queues the chunk to be meshed in the worker threads. Only
+/// chunks that are [ChunkStatus::Full] are meshed.
 pub struct ChunkRenderSystem;
 
 impl<'a> System<'a> for ChunkRenderSystem {
     type SystemData = (
         Entities<'a>,
-        WriteExpect<'a, Graphics>,
+        ReadExpect<'a, Graphics>,
+        ReadExpect<'a, BlockRegistry>,
+        ReadExpect<'a, BiomeRegistry>,
+        ReadExpect<'a, Workers>,
+        WriteExpect<'a, ChunkMeshQueue>,
         WriteStorage<'a, Created>,
-        WriteStorage<'a, DynamicMesh>,
         ReadStorage<'a, Position>,
         ReadStorage<'a, Chunk>,
+        ReadStorage<'a, ChunkStatus>,
     );
 
-    fn run(&mut self, (entities, info, mut created, mut renders, pos, chunk): Self::SystemData) {
-        let entities_to_remove: Vec<_> = (&entities, &pos, &chunk, &created)
+    fn run(
+        &mut self,
+        (entities, info, registry, biomes, workers, mut queue, mut created, pos, chunk, status): Self::SystemData,
+    ) {
+        let entities_to_remove: Vec<_> = (&entities, &pos, &chunk, &status, &created)
             .join()
-            .map(|(entity, pos, chunk, _)| (entity, pos, chunk))
+            .filter(|(_, _, _, status, _)| **status == ChunkStatus::Full)
+            .map(|(entity, pos, chunk, _, _)| (entity, pos, chunk))
             .collect();
 
         for (entity, pos, chunk) in entities_to_remove {
             created.remove(entity);
-            let (model, data) = chunk::ChunkModel::from_data(pos, chunk, MaterialId(0), &info.renderer);
-            renders.insert(entity, DynamicMesh { data, model }).unwrap();
+            let center = chunk::global_chunk_center(pos);
+            queue.push(entity, center, chunk.clone(), registry.clone(), biomes.clone());
+        }
+
+        queue.dispatch(info.camera.position, &workers);
+    }
+}
+
+/// Uploads the chunks that were meshed by the worker threads to the GPU, and makes the queue forget
+/// the chunks that were removed.
+pub struct ChunkUploadSystem;
+
+impl<'a> System<'a> for ChunkUploadSystem {
+    type SystemData = (
+        Entities<'a>,
+        ReadExpect<'a, Graphics>,
+        WriteExpect<'a, ChunkMeshQueue>,
+        WriteStorage<'a, DynamicMesh>,
+        ReadStorage<'a, Position>,
+    );
+
+    fn run(&mut self, (entities, info, mut queue, mut renders, pos): Self::SystemData) {
+        queue.retain(|entity| entities.is_alive(entity));
+
+        for meshed in queue.finished() {
+            if !entities.is_alive(meshed.entity) {
+                continue;
+            }
+
+            let Some(pos) = pos.get(meshed.entity) else {
+                continue;
+            };
+
+            let data = chunk::upload(&meshed.model, pos, MaterialId(0), &info.renderer);
+
+            let mesh = DynamicMesh {
+                data,
+                model: meshed.model,
+                center: meshed.center,
+            };
+
+            renders.insert(meshed.entity, mesh).unwrap();
         }
     }
 }
@@ -1,2 +1,2 @@
 pub mod chunk;
-pub mod render;
\ No newline at end of file
+pub mod render;pub mod viewer;
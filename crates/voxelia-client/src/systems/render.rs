@@ -1,5 +1,6 @@
+use cgmath::MetricSpace;
 use specs::{System, WriteExpect, WriteStorage, Join};
-use voxelia_renderer::Pass;
+use voxelia_renderer::{BlendMode, Pass};
 
 use crate::structures::{graphics::Graphics, mesh::DynamicMesh};
 
@@ -11,7 +12,24 @@ impl<'a> System<'a> for RendererSystem {
 
     fn run(&mut self, (mut info, renders): Self::SystemData) {
         info.update_camera();
-        let meshes = renders.join().map(|x| &x.data).collect::<Vec<_>>();
+
+        // The translucent meshes are sorted from back to front so they blend with the ones behind
+        // them, and the others from front to back so the depth test skips the pixels that are
+        // hidden. The pass keeps this order inside of each blend mode.
+        let eye = info.camera.position;
+        let mut sorted = renders
+            .join()
+            .flat_map(|x| {
+                x.data.iter().map(move |mesh| {
+                    let distance = x.center.distance2(eye);
+                    let key = if mesh.blend_mode == BlendMode::Translucent { -distance } else { distance };
+                    (key, mesh)
+                })
+            })
+            .collect::<Vec<_>>();
+        sorted.sort_by(|a, b| a.0.total_cmp(&b.0));
+
+        let meshes = sorted.into_iter().map(|(_, mesh)| mesh).collect::<Vec<_>>();
         info.pass
             .draw(&info.renderer, &info.materials, &meshes, &info.globals)
             .unwrap();
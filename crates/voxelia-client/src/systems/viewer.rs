@@ -0,0 +1,27 @@
+//! Systems that keep the chunks around the camera loaded.
+
+use specs::{Join, ReadExpect, ReadStorage, System, WriteStorage};
+use voxelia_engine::streaming::ChunkViewer;
+use voxelia_engine::Position;
+
+use crate::structures::graphics::Graphics;
+
+/// Moves the [ChunkViewer]s to the position of the camera. Blocks are two units wide in the
+/// renderer and the viewers are positioned in blocks.
+pub struct CameraViewerSystem;
+
+impl<'a> System<'a> for CameraViewerSystem {
+    type SystemData = (
+        ReadExpect<'a, Graphics>,
+        ReadStorage<'a, ChunkViewer>,
+        WriteStorage<'a, Position>,
+    );
+
+    fn run(&mut self, (info, viewers, mut positions): Self::SystemData) {
+        let eye = info.camera.position / 2.0;
+
+        for (_, position) in (&viewers, &mut positions).join() {
+            *position = Position::new(eye.x, eye.y, eye.z);
+        }
+    }
+}
@@ -0,0 +1,18 @@
+use tokio::runtime::Handle;
+use voxelia_engine::workers::{Executor, Job};
+
+/// Runs the jobs of the engine in the blocking threads of the tokio runtime of the client.
+pub struct TokioExecutor(pub Handle);
+
+impl TokioExecutor {
+    /// Uses the runtime that the caller is running in, it panics outside of a runtime.
+    pub fn current() -> TokioExecutor {
+        TokioExecutor(Handle::current())
+    }
+}
+
+impl Executor for TokioExecutor {
+    fn spawn(&self, job: Job) {
+        self.0.spawn_blocking(job);
+    }
+}
@@ -0,0 +1,153 @@
+//! Definition of biomes, the regions of the world with their own shape, blocks and colors. They are
+//! picked by the climate of each column of the world.
+
+use std::sync::Arc;
+
+use crate::block::BlockId;
+
+/// Identifier of a biome, stored for each column of a chunk.
+pub type BiomeId = u8;
+
+/// A point in the climate space. Every value goes from `-1` to `1`.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Climate {
+    pub temperature: f64,
+    pub humidity: f64,
+    /// How far from the oceans, low values are oceans and high values are inland.
+    pub continentalness: f64,
+}
+
+impl Climate {
+    pub const fn new(temperature: f64, humidity: f64, continentalness: f64) -> Climate {
+        Climate {
+            temperature,
+            humidity,
+            continentalness,
+        }
+    }
+
+    pub fn distance2(&self, other: &Climate) -> f64 {
+        let t = self.temperature - other.temperature;
+        let h = self.humidity - other.humidity;
+        let c = self.continentalness - other.continentalness;
+        t * t + h * h + c * c
+    }
+}
+
+/// How much vegetation grows in a biome, as the chance of each column to get one.
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct Vegetation {
+    pub trees: f64,
+    pub flowers: f64,
+}
+
+#[derive(Clone, Debug)]
+pub struct Biome {
+    pub name: String,
+    /// The climate where the biome is the most likely to appear.
+    pub climate: Climate,
+    /// Height of the surface in blocks when the height noise is zero.
+    pub base_height: f64,
+    /// How far the surface goes up and down with the height noise.
+    pub amplitude: f64,
+    /// Top block of the columns above the sea level.
+    pub surface: BlockId,
+    /// Blocks between the surface and the stone.
+    pub subsurface: BlockId,
+    pub vegetation: Vegetation,
+    /// Colors that tint grass and foliage blocks in sRGB.
+    pub grass_color: [u8; 3],
+    pub foliage_color: [u8; 3],
+}
+
+/// All the biomes of the world, indexed by their [BiomeId]. Cloning it is cheap so it can be sent
+/// to other threads.
+#[derive(Clone, Default)]
+pub struct BiomeRegistry {
+    biomes: Arc<Vec<Biome>>,
+}
+
+impl BiomeRegistry {
+    /// Registers a new biome and returns its id. Ids are given in order starting from 0.
+    pub fn register(&mut self, biome: Biome) -> BiomeId {
+        let biomes = Arc::make_mut(&mut self.biomes);
+        assert!(biomes.len() <= BiomeId::MAX as usize, "too many biomes registered");
+        biomes.push(biome);
+        (biomes.len() - 1) as BiomeId
+    }
+
+    pub fn get(&self, id: BiomeId) -> Option<&Biome> {
+        self.biomes.get(id as usize)
+    }
+
+    pub fn by_name(&self, name: &str) -> Option<BiomeId> {
+        self.biomes
+            .iter()
+            .position(|biome| biome.name == name)
+            .map(|index| index as BiomeId)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.biomes.is_empty()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (BiomeId, &Biome)> {
+        self.biomes.iter().enumerate().map(|(id, biome)| (id as BiomeId, biome))
+    }
+
+    /// The biome whose climate is the closest to the given one.
+    pub fn closest(&self, climate: &Climate) -> Option<BiomeId> {
+        self.iter()
+            .min_by(|(_, a), (_, b)| a.climate.distance2(climate).total_cmp(&b.climate.distance2(climate)))
+            .map(|(id, _)| id)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn biome(name: &str, climate: Climate) -> Biome {
+        Biome {
+            name: name.to_owned(),
+            climate,
+            base_height: 0.0,
+            amplitude: 1.0,
+            surface: 1,
+            subsurface: 1,
+            vegetation: Vegetation::default(),
+            grass_color: [0; 3],
+            foliage_color: [0; 3],
+        }
+    }
+
+    #[test]
+    fn biomes_are_picked_by_the_closest_climate() {
+        let mut registry = BiomeRegistry::default();
+        assert_eq!(registry.closest(&Climate::new(0.0, 0.0, 0.0)), None);
+
+        let desert = registry.register(biome("desert", Climate::new(1.0, -1.0, 0.5)));
+        let tundra = registry.register(biome("tundra", Climate::new(-1.0, 0.0, 0.5)));
+        let ocean = registry.register(biome("ocean", Climate::new(0.0, 0.0, -1.0)));
+
+        assert_eq!((desert, tundra, ocean), (0, 1, 2));
+        assert_eq!(registry.by_name("tundra"), Some(tundra));
+        assert_eq!(registry.by_name("jungle"), None);
+
+        assert_eq!(registry.closest(&Climate::new(0.8, -0.5, 0.2)), Some(desert));
+        assert_eq!(registry.closest(&Climate::new(-0.6, 0.3, 0.9)), Some(tundra));
+        assert_eq!(registry.closest(&Climate::new(0.2, 0.1, -0.7)), Some(ocean));
+    }
+
+    #[test]
+    fn clones_keep_the_biomes_they_were_made_with() {
+        let mut registry = BiomeRegistry::default();
+        registry.register(biome("plains", Climate::new(0.0, 0.0, 0.0)));
+
+        let clone = registry.clone();
+        registry.register(biome("forest", Climate::new(0.0, 1.0, 0.0)));
+
+        assert_eq!(clone.iter().count(), 1);
+        assert_eq!(registry.get(1).map(|biome| biome.name.as_str()), Some("forest"));
+    }
+}
@@ -1,8 +1,9 @@
 use std::ops::{Add, Sub};
+use std::sync::Arc;
 
 use crate::chunk;
 
-#[derive(Debug)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
 pub struct BlockPosition {
     pub x: i64,
     pub y: i64,
@@ -60,4 +61,110 @@ impl Sub for BlockPosition {
             z: self.z - rhs.z
         }
     }
-}
\ No newline at end of file
+}
+
+/// Identifier of a block inside of a chunk.
+pub type BlockId = u8;
+
+/// The block that fills all the empty space.
+pub const AIR: BlockId = 0;
+
+/// Highest texture layer of a block, the most that fits in the vertices of the renderer.
+pub const MAX_TEXTURE: u32 = 0x7F;
+
+/// How a block lets the blocks behind it be seen.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Transparency {
+    /// Hides everything behind it, like stone.
+    Opaque,
+    /// Either fully opaque or fully transparent in each pixel, like leaves.
+    Cutout,
+    /// Blends with the things behind it, like water and ice.
+    Translucent,
+}
+
+/// Which color of the biome is used to tint a block.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Tint {
+    None,
+    Grass,
+    Foliage,
+}
+
+/// Information shared by all blocks of the same kind.
+#[derive(Clone, Debug)]
+pub struct BlockDefinition {
+    pub name: String,
+    pub transparency: Transparency,
+    /// Layer of the texture atlas used by all the faces of the block, at most [MAX_TEXTURE].
+    pub texture: u32,
+    pub tint: Tint,
+}
+
+impl BlockDefinition {
+    pub fn new(name: &str, transparency: Transparency, texture: u32) -> Self {
+        BlockDefinition {
+            name: name.to_owned(),
+            transparency,
+            texture,
+            tint: Tint::None,
+        }
+    }
+
+    pub fn with_tint(mut self, tint: Tint) -> Self {
+        self.tint = tint;
+        self
+    }
+}
+
+/// All the kinds of blocks that exist in the world, indexed by their [BlockId]. Cloning it is cheap
+/// so it can be sent to other threads.
+#[derive(Clone, Default)]
+pub struct BlockRegistry {
+    blocks: Arc<Vec<BlockDefinition>>,
+}
+
+impl BlockRegistry {
+    /// Registers a new kind of block and returns its id. Ids are given in order starting from 1.
+    pub fn register(&mut self, definition: BlockDefinition) -> BlockId {
+        assert!(
+            definition.texture <= MAX_TEXTURE,
+            "the texture of {} is {}, over {MAX_TEXTURE}",
+            definition.name,
+            definition.texture
+        );
+
+        let blocks = Arc::make_mut(&mut self.blocks);
+        assert!(blocks.len() < BlockId::MAX as usize, "too many blocks registered");
+        blocks.push(definition);
+        blocks.len() as BlockId
+    }
+
+    /// Returns the id of the block with the same name or registers it if there is none.
+    pub fn get_or_register(&mut self, definition: BlockDefinition) -> BlockId {
+        self.by_name(&definition.name)
+            .unwrap_or_else(|| self.register(definition))
+    }
+
+    /// Definition of a block, [AIR] and unknown blocks have none.
+    pub fn get(&self, id: BlockId) -> Option<&BlockDefinition> {
+        self.blocks.get((id as usize).wrapping_sub(1))
+    }
+
+    pub fn by_name(&self, name: &str) -> Option<BlockId> {
+        self.blocks
+            .iter()
+            .position(|block| block.name == name)
+            .map(|index| index as BlockId + 1)
+    }
+
+    /// Goes through the registered blocks in the order of their ids.
+    pub fn iter(&self) -> impl Iterator<Item = (BlockId, &BlockDefinition)> {
+        self.blocks.iter().enumerate().map(|(index, block)| (index as BlockId + 1, block))
+    }
+
+    /// Transparency of a block, unknown blocks are treated as opaque.
+    pub fn transparency(&self, id: BlockId) -> Transparency {
+        self.get(id).map_or(Transparency::Opaque, |block| block.transparency)
+    }
+}
@@ -1,30 +1,193 @@
 //! Definition of Chunks
 
+use std::collections::BTreeMap;
+
 use specs::{Component, VecStorage};
 
-use crate::{block::BlockPosition, Plugin, WorldBuilder};
+use crate::{biome::BiomeId, block::{BlockPosition, BlockRegistry}, occupancy::Occupancy, Plugin, Position, WorldBuilder};
 
-pub const CHUNK_WIDTH: usize = 4;
-pub const CHUNK_HEIGHT: usize = 4;
-pub const CHUNK_LENGTH: usize = 4;
+pub const CHUNK_WIDTH: usize = 16;
+pub const CHUNK_HEIGHT: usize = 32;
+pub const CHUNK_LENGTH: usize = 16;
 
+/// Position of a chunk in chunks. Chunks are cubes that go on in every direction, up and down too.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub struct ChunkPosition {
-    pub x: usize,
-    pub y: usize,
-    pub z: usize,
+    pub x: i64,
+    pub y: i64,
+    pub z: i64,
+}
+
+impl ChunkPosition {
+    pub const fn new(x: i64, y: i64, z: i64) -> ChunkPosition {
+        ChunkPosition { x, y, z }
+    }
+
+    /// Chunk of a global block position and the position of the block inside of it.
+    pub fn of_block(x: i64, y: i64, z: i64) -> (ChunkPosition, BlockPosition) {
+        let size = [CHUNK_WIDTH as i64, CHUNK_HEIGHT as i64, CHUNK_LENGTH as i64];
+
+        let chunk = ChunkPosition::new(x.div_euclid(size[0]), y.div_euclid(size[1]), z.div_euclid(size[2]));
+        let block = BlockPosition::new(x.rem_euclid(size[0]), y.rem_euclid(size[1]), z.rem_euclid(size[2]));
+
+        (chunk, block)
+    }
+
+    /// Chunk that has a [Position] in blocks, like the one of a player.
+    pub fn containing(position: &Position) -> ChunkPosition {
+        let (chunk, _) = ChunkPosition::of_block(
+            position.x.floor() as i64,
+            position.y.floor() as i64,
+            position.z.floor() as i64,
+        );
+
+        chunk
+    }
+
+    /// Chunk of the [Position] of a chunk entity.
+    pub fn of_entity(position: &Position) -> ChunkPosition {
+        ChunkPosition::new(position.x as i64, position.y as i64, position.z as i64)
+    }
+
+    /// Global position of the first block of the chunk.
+    pub fn origin(&self) -> [i64; 3] {
+        [
+            self.x * CHUNK_WIDTH as i64,
+            self.y * CHUNK_HEIGHT as i64,
+            self.z * CHUNK_LENGTH as i64,
+        ]
+    }
 }
 
-/// Chunk component that stores the information about a chunk.
-#[derive(Component)]
+pub const CHUNK_SIZE: usize = CHUNK_WIDTH * CHUNK_HEIGHT * CHUNK_LENGTH;
+
+/// Chunk component that stores the information about a chunk. The [Occupancy] of the blocks is
+/// kept in sync with the data so every change has to go through [Chunk::set].
+#[derive(Component, Clone)]
 #[storage(VecStorage)]
 pub struct Chunk {
-    pub data: [u8; CHUNK_WIDTH * CHUNK_HEIGHT * CHUNK_LENGTH],
+    data: [u8; CHUNK_SIZE],
+    occupancy: Occupancy,
+    /// Biome of each column of the chunk.
+    biomes: [BiomeId; CHUNK_WIDTH * CHUNK_LENGTH],
+    /// Sky light of each block, from 0 to [MAX_LIGHT](crate::light::MAX_LIGHT).
+    light: [u8; CHUNK_SIZE],
+    /// Data of the blocks that have more than their id, by the index of the block.
+    block_entities: BTreeMap<usize, Vec<u8>>,
+    metadata: BTreeMap<String, Vec<u8>>,
+}
+
+impl Default for Chunk {
+    fn default() -> Self {
+        Chunk {
+            data: [0; CHUNK_SIZE],
+            occupancy: Occupancy::default(),
+            biomes: [0; CHUNK_WIDTH * CHUNK_LENGTH],
+            light: [0; CHUNK_SIZE],
+            block_entities: BTreeMap::new(),
+            metadata: BTreeMap::new(),
+        }
+    }
 }
 
 impl Chunk {
+    pub fn new(data: [u8; CHUNK_SIZE]) -> Chunk {
+        let mut chunk = Chunk::default();
+
+        for x in 0..CHUNK_WIDTH {
+            for y in 0..CHUNK_HEIGHT {
+                for z in 0..CHUNK_LENGTH {
+                    let position = BlockPosition::new(x as i64, y as i64, z as i64);
+                    chunk.set(&position, data[Self::index(&position)]);
+                }
+            }
+        }
+
+        chunk
+    }
+
+    fn index(position: &BlockPosition) -> usize {
+        position.z as usize + position.y as usize * CHUNK_LENGTH + position.x as usize * CHUNK_LENGTH * CHUNK_HEIGHT
+    }
+
     pub fn get(&self, position: &BlockPosition) -> u8 {
-        let index = position.z as usize + position.y as usize * CHUNK_WIDTH + position.x as usize * CHUNK_WIDTH * CHUNK_HEIGHT;
-        self.data[index as usize]
+        self.data[Self::index(position)]
+    }
+
+    pub fn set(&mut self, position: &BlockPosition, block: u8) {
+        self.data[Self::index(position)] = block;
+        self.occupancy.set(position, block != 0);
+    }
+
+    pub fn data(&self) -> &[u8; CHUNK_SIZE] {
+        &self.data
+    }
+
+    pub fn occupancy(&self) -> &Occupancy {
+        &self.occupancy
+    }
+
+    pub fn light(&self, position: &BlockPosition) -> u8 {
+        self.light[Self::index(position)]
+    }
+
+    pub fn set_light(&mut self, position: &BlockPosition, light: u8) {
+        self.light[Self::index(position)] = light;
+    }
+
+    pub fn biome(&self, x: usize, z: usize) -> BiomeId {
+        self.biomes[x * CHUNK_LENGTH + z]
+    }
+
+    pub fn set_biome(&mut self, x: usize, z: usize, biome: BiomeId) {
+        self.biomes[x * CHUNK_LENGTH + z] = biome;
+    }
+
+    /// Data of the block entity of a block, like the items of a chest. The plugin that owns the
+    /// block decides how it's serialized.
+    pub fn block_entity(&self, position: &BlockPosition) -> Option<&[u8]> {
+        self.block_entities.get(&Self::index(position)).map(Vec::as_slice)
+    }
+
+    /// Gives a block entity to a block. It stays until it's removed, even if the block changes.
+    pub fn set_block_entity(&mut self, position: &BlockPosition, data: Vec<u8>) {
+        self.block_entities.insert(Self::index(position), data);
+    }
+
+    pub fn remove_block_entity(&mut self, position: &BlockPosition) -> Option<Vec<u8>> {
+        self.block_entities.remove(&Self::index(position))
+    }
+
+    /// Goes through the block entities in the order of the blocks in [Chunk::data].
+    pub fn block_entities(&self) -> impl Iterator<Item = (BlockPosition, &[u8])> {
+        self.block_entities.iter().map(|(index, data)| {
+            let position = BlockPosition::new(
+                (index / (CHUNK_LENGTH * CHUNK_HEIGHT)) as i64,
+                (index / CHUNK_LENGTH % CHUNK_HEIGHT) as i64,
+                (index % CHUNK_LENGTH) as i64,
+            );
+
+            (position, data.as_slice())
+        })
+    }
+
+    /// A value that a plugin keeps for the whole chunk, like the version of the generator that made
+    /// it.
+    pub fn metadata(&self, key: &str) -> Option<&[u8]> {
+        self.metadata.get(key).map(Vec::as_slice)
+    }
+
+    pub fn set_metadata(&mut self, key: impl Into<String>, value: Vec<u8>) {
+        self.metadata.insert(key.into(), value);
+    }
+
+    pub fn remove_metadata(&mut self, key: &str) -> Option<Vec<u8>> {
+        self.metadata.remove(key)
+    }
+
+    /// Goes through the metadata sorted by the keys.
+    pub fn metadata_entries(&self) -> impl Iterator<Item = (&str, &[u8])> {
+        self.metadata.iter().map(|(key, value)| (key.as_str(), value.as_slice()))
     }
 }
 
@@ -33,6 +196,35 @@ pub struct ChunkPlugin;
 
 impl Plugin for ChunkPlugin {
     fn setup(self, world: &mut WorldBuilder) {
-        world.with_component::<Chunk>()
+        world.with_component::<Chunk>();
+        world.resource_mut::<BlockRegistry>();
     }
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn blocks_below_zero_are_in_the_chunks_below() {
+        let (chunk, block) = ChunkPosition::of_block(-1, -1, -17);
+        assert_eq!(chunk, ChunkPosition::new(-1, -1, -2));
+        assert_eq!(block, BlockPosition::new(CHUNK_WIDTH as i64 - 1, CHUNK_HEIGHT as i64 - 1, CHUNK_LENGTH as i64 - 1));
+
+        let (chunk, block) = ChunkPosition::of_block(16, -64, 1000);
+        assert_eq!(chunk, ChunkPosition::new(1, -2, 62));
+        assert_eq!(block, BlockPosition::new(0, 0, 8));
+
+        // The origin is the first block of the chunk at any height.
+        for position in [ChunkPosition::new(-3, -7, 2), ChunkPosition::new(4, 1_000_000, -9)] {
+            let [x, y, z] = position.origin();
+            assert_eq!(ChunkPosition::of_block(x, y, z), (position, BlockPosition::new(0, 0, 0)));
+        }
+    }
+
+    #[test]
+    fn positions_in_blocks_round_down() {
+        assert_eq!(ChunkPosition::containing(&Position::new(-0.5, -0.5, 15.9)), ChunkPosition::new(-1, -1, 0));
+        assert_eq!(ChunkPosition::containing(&Position::new(16.0, 32.0, -16.0)), ChunkPosition::new(1, 1, -1));
+        assert_eq!(ChunkPosition::of_entity(&Position::new(-2.0, -5.0, 3.0)), ChunkPosition::new(-2, -5, 3));
+    }
+}
@@ -0,0 +1,597 @@
+//! Binary format of the chunks, used to save them to disk and to send them over the network.
+//!
+//! An encoded chunk starts with a header:
+//!
+//! | Bytes | Content                                             |
+//! |-------|-----------------------------------------------------|
+//! | 4     | The magic `VXCH`                                    |
+//! | 2     | Version of the format                               |
+//! | 1     | [Compression] of the body                           |
+//! | 4     | Length of the body before it's compressed           |
+//! | 4     | CRC32 of the body as it's stored                    |
+//!
+//! The body is a list of sections, each one with a tag, its length and its content, so sections
+//! can be added without breaking the readers that do not know them, which skip them. Every number
+//! is little endian. Bodies are never longer than [MAX_BODY_SIZE], so a chunk that comes from the
+//! network cannot make the reader take more memory than that.
+
+use std::fmt;
+
+use crate::{
+    block::{BlockId, BlockPosition},
+    chunk::{Chunk, CHUNK_HEIGHT, CHUNK_LENGTH, CHUNK_SIZE, CHUNK_WIDTH},
+};
+
+const MAGIC: &[u8; 4] = b"VXCH";
+
+/// Version of the format that is written. Readers accept this version and the ones before it.
+pub const VERSION: u16 = 1;
+
+const HEADER_SIZE: usize = 15;
+
+/// Blocks as a palette and the indices of the blocks in it, packed in u64 words.
+const BLOCKS: u8 = 1;
+/// Biome of each column.
+const BIOMES: u8 = 2;
+/// Light of each block, 4 bits for each.
+const LIGHT: u8 = 3;
+/// Position of each block entity followed by its data.
+const BLOCK_ENTITIES: u8 = 4;
+/// Keys of the metadata followed by their values.
+const METADATA: u8 = 5;
+
+/// Longest body that a chunk can have before it's compressed. The blocks, biomes and light take a
+/// few kilobytes at most, the rest is for the block entities and the metadata.
+pub const MAX_BODY_SIZE: usize = 2 * 1024 * 1024;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Compression {
+    None,
+    Lz4,
+}
+
+impl Compression {
+    fn from_byte(byte: u8) -> Option<Compression> {
+        match byte {
+            0 => Some(Compression::None),
+            1 => Some(Compression::Lz4),
+            _ => None,
+        }
+    }
+
+    fn to_byte(self) -> u8 {
+        match self {
+            Compression::None => 0,
+            Compression::Lz4 => 1,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum CodecError {
+    /// The data ends before the end of the chunk.
+    Truncated,
+    /// The data is not a chunk.
+    Magic,
+    /// The chunk was written by a newer version of the format.
+    Version(u16),
+    Compression(u8),
+    /// The body does not have the checksum of the header.
+    Checksum,
+    Decompress(lz4_flex::block::DecompressError),
+    /// A section is missing or has a value that does not make sense.
+    Invalid(&'static str),
+    /// The body is longer than [MAX_BODY_SIZE].
+    TooLarge(usize),
+}
+
+impl fmt::Display for CodecError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CodecError::Truncated => write!(f, "the chunk is truncated"),
+            CodecError::Magic => write!(f, "the data is not a chunk"),
+            CodecError::Version(version) => write!(f, "unknown chunk format version {version}"),
+            CodecError::Compression(compression) => write!(f, "unknown compression {compression}"),
+            CodecError::Checksum => write!(f, "the checksum of the chunk does not match"),
+            CodecError::Decompress(error) => write!(f, "cannot decompress the chunk: {error}"),
+            CodecError::Invalid(reason) => write!(f, "invalid chunk: {reason}"),
+            CodecError::TooLarge(length) => write!(f, "the chunk has {length} bytes, more than {MAX_BODY_SIZE}"),
+        }
+    }
+}
+
+impl std::error::Error for CodecError {}
+
+/// Reads the values of a slice one after the other.
+struct Reader<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, count: usize) -> Result<&'a [u8], CodecError> {
+        if self.bytes.len() < count {
+            return Err(CodecError::Truncated);
+        }
+
+        let (taken, rest) = self.bytes.split_at(count);
+        self.bytes = rest;
+        Ok(taken)
+    }
+
+    fn u8(&mut self) -> Result<u8, CodecError> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u16(&mut self) -> Result<u16, CodecError> {
+        Ok(u16::from_le_bytes(self.take(2)?.try_into().unwrap()))
+    }
+
+    fn u32(&mut self) -> Result<u32, CodecError> {
+        Ok(u32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    fn u64(&mut self) -> Result<u64, CodecError> {
+        Ok(u64::from_le_bytes(self.take(8)?.try_into().unwrap()))
+    }
+
+    /// Reads bytes that have their length before them as a u32.
+    fn bytes(&mut self) -> Result<&'a [u8], CodecError> {
+        let length = self.u32()? as usize;
+        self.take(length)
+    }
+}
+
+/// Goes through the blocks of a chunk in the order that they are kept in [Chunk::data].
+fn positions() -> impl Iterator<Item = BlockPosition> {
+    (0..CHUNK_WIDTH as i64).flat_map(|x| {
+        (0..CHUNK_HEIGHT as i64).flat_map(move |y| (0..CHUNK_LENGTH as i64).map(move |z| BlockPosition::new(x, y, z)))
+    })
+}
+
+/// Bits used by each index of a palette with this many blocks.
+fn bits_for(palette: usize) -> u32 {
+    match palette {
+        0 | 1 => 0,
+        count => usize::BITS - (count - 1).leading_zeros(),
+    }
+}
+
+fn section(body: &mut Vec<u8>, tag: u8, content: &[u8]) {
+    body.push(tag);
+    body.extend_from_slice(&(content.len() as u32).to_le_bytes());
+    body.extend_from_slice(content);
+}
+
+fn encode_blocks(chunk: &Chunk) -> Vec<u8> {
+    let mut palette: Vec<BlockId> = Vec::new();
+    let mut indices = Vec::with_capacity(CHUNK_SIZE);
+
+    for block in chunk.data() {
+        let index = match palette.iter().position(|other| other == block) {
+            Some(index) => index,
+            None => {
+                palette.push(*block);
+                palette.len() - 1
+            }
+        };
+        indices.push(index as u64);
+    }
+
+    let bits = bits_for(palette.len());
+
+    let mut content = Vec::new();
+    content.extend_from_slice(&(palette.len() as u16).to_le_bytes());
+    content.extend_from_slice(&palette);
+    content.push(bits as u8);
+
+    // Indices do not cross the borders of the words, and there are none with a single block.
+    if let Some(per_word) = u64::BITS.checked_div(bits) {
+        for word in indices.chunks(per_word as usize) {
+            let packed = word.iter().enumerate().fold(0u64, |packed, (i, index)| packed | index << (i as u32 * bits));
+            content.extend_from_slice(&packed.to_le_bytes());
+        }
+    }
+
+    content
+}
+
+fn decode_blocks(content: &[u8]) -> Result<[BlockId; CHUNK_SIZE], CodecError> {
+    let mut reader = Reader { bytes: content };
+
+    let length = reader.u16()? as usize;
+    let palette = reader.take(length)?;
+    let bits = reader.u8()? as u32;
+
+    if palette.is_empty() || bits != bits_for(palette.len()) {
+        return Err(CodecError::Invalid("the palette does not match its indices"));
+    }
+
+    let mut data = [palette[0]; CHUNK_SIZE];
+
+    if let Some(per_word) = u64::BITS.checked_div(bits) {
+        let per_word = per_word as usize;
+        let mask = (1u64 << bits) - 1;
+
+        for start in (0..CHUNK_SIZE).step_by(per_word) {
+            let word = reader.u64()?;
+
+            for (i, block) in data[start..(start + per_word).min(CHUNK_SIZE)].iter_mut().enumerate() {
+                let index = (word >> (i as u32 * bits) & mask) as usize;
+                *block = *palette.get(index).ok_or(CodecError::Invalid("a block is not in the palette"))?;
+            }
+        }
+    }
+
+    Ok(data)
+}
+
+fn encode_block_entities(chunk: &Chunk) -> Vec<u8> {
+    let mut content = Vec::new();
+    content.extend_from_slice(&(chunk.block_entities().count() as u16).to_le_bytes());
+
+    for (position, data) in chunk.block_entities() {
+        content.extend_from_slice(&[position.x as u8, position.y as u8, position.z as u8]);
+        content.extend_from_slice(&(data.len() as u32).to_le_bytes());
+        content.extend_from_slice(data);
+    }
+
+    content
+}
+
+fn decode_block_entities(content: &[u8], chunk: &mut Chunk) -> Result<(), CodecError> {
+    let mut reader = Reader { bytes: content };
+
+    for _ in 0..reader.u16()? {
+        let [x, y, z] = [reader.u8()?, reader.u8()?, reader.u8()?].map(i64::from);
+        let mut position = BlockPosition::new(x, y, z);
+
+        if position.is_out() {
+            return Err(CodecError::Invalid("a block entity is outside of the chunk"));
+        }
+
+        chunk.set_block_entity(&position, reader.bytes()?.to_vec());
+    }
+
+    Ok(())
+}
+
+fn encode_metadata(chunk: &Chunk) -> Vec<u8> {
+    let mut content = Vec::new();
+    content.extend_from_slice(&(chunk.metadata_entries().count() as u32).to_le_bytes());
+
+    for (key, value) in chunk.metadata_entries() {
+        content.extend_from_slice(&(key.len() as u32).to_le_bytes());
+        content.extend_from_slice(key.as_bytes());
+        content.extend_from_slice(&(value.len() as u32).to_le_bytes());
+        content.extend_from_slice(value);
+    }
+
+    content
+}
+
+fn decode_metadata(content: &[u8], chunk: &mut Chunk) -> Result<(), CodecError> {
+    let mut reader = Reader { bytes: content };
+
+    for _ in 0..reader.u32()? {
+        let key = std::str::from_utf8(reader.bytes()?).map_err(|_| CodecError::Invalid("a key of the metadata is not text"))?;
+        chunk.set_metadata(key, reader.bytes()?.to_vec());
+    }
+
+    Ok(())
+}
+
+fn encode_light(chunk: &Chunk) -> Vec<u8> {
+    let mut content = vec![0; CHUNK_SIZE.div_ceil(2)];
+
+    for (index, position) in positions().enumerate() {
+        content[index / 2] |= (chunk.light(&position) & 0xF) << (index % 2 * 4);
+    }
+
+    content
+}
+
+/// Encodes a chunk with the current version of the format. It fails if the block entities and the
+/// metadata make the body longer than [MAX_BODY_SIZE].
+pub fn encode(chunk: &Chunk, compression: Compression) -> Result<Vec<u8>, CodecError> {
+    let mut body = Vec::new();
+
+    section(&mut body, BLOCKS, &encode_blocks(chunk));
+
+    let biomes: Vec<u8> = (0..CHUNK_WIDTH)
+        .flat_map(|x| (0..CHUNK_LENGTH).map(move |z| (x, z)))
+        .map(|(x, z)| chunk.biome(x, z))
+        .collect();
+    section(&mut body, BIOMES, &biomes);
+
+    section(&mut body, LIGHT, &encode_light(chunk));
+
+    if chunk.block_entities().next().is_some() {
+        section(&mut body, BLOCK_ENTITIES, &encode_block_entities(chunk));
+    }
+
+    if chunk.metadata_entries().next().is_some() {
+        section(&mut body, METADATA, &encode_metadata(chunk));
+    }
+
+    if body.len() > MAX_BODY_SIZE {
+        return Err(CodecError::TooLarge(body.len()));
+    }
+
+    let length = body.len() as u32;
+    let body = match compression {
+        Compression::None => body,
+        Compression::Lz4 => lz4_flex::block::compress(&body),
+    };
+
+    let mut bytes = Vec::with_capacity(HEADER_SIZE + body.len());
+    bytes.extend_from_slice(MAGIC);
+    bytes.extend_from_slice(&VERSION.to_le_bytes());
+    bytes.push(compression.to_byte());
+    bytes.extend_from_slice(&length.to_le_bytes());
+    bytes.extend_from_slice(&crc32fast::hash(&body).to_le_bytes());
+    bytes.extend_from_slice(&body);
+
+    Ok(bytes)
+}
+
+/// Decodes a chunk of any version of the format up to [VERSION], checking its checksum.
+pub fn decode(bytes: &[u8]) -> Result<Chunk, CodecError> {
+    let mut reader = Reader { bytes };
+
+    if reader.take(4)? != MAGIC {
+        return Err(CodecError::Magic);
+    }
+
+    let version = reader.u16()?;
+    if version > VERSION {
+        return Err(CodecError::Version(version));
+    }
+
+    let compression = reader.u8()?;
+    let compression = Compression::from_byte(compression).ok_or(CodecError::Compression(compression))?;
+    let length = reader.u32()? as usize;
+    let checksum = reader.u32()?;
+
+    if length > MAX_BODY_SIZE {
+        return Err(CodecError::TooLarge(length));
+    }
+
+    let stored = reader.bytes;
+    if crc32fast::hash(stored) != checksum {
+        return Err(CodecError::Checksum);
+    }
+
+    let body = match compression {
+        Compression::None => stored.to_vec(),
+        Compression::Lz4 => lz4_flex::block::decompress(stored, length).map_err(CodecError::Decompress)?,
+    };
+
+    if body.len() != length {
+        return Err(CodecError::Truncated);
+    }
+
+    let mut reader = Reader { bytes: &body };
+    let mut chunk = None;
+    let mut biomes = None;
+    let mut light = None;
+    let mut block_entities = None;
+    let mut metadata = None;
+
+    while !reader.bytes.is_empty() {
+        let tag = reader.u8()?;
+        let length = reader.u32()? as usize;
+        let content = reader.take(length)?;
+
+        match tag {
+            BLOCKS => chunk = Some(Chunk::new(decode_blocks(content)?)),
+            BIOMES if content.len() == CHUNK_WIDTH * CHUNK_LENGTH => biomes = Some(content),
+            LIGHT if content.len() == CHUNK_SIZE.div_ceil(2) => light = Some(content),
+            BIOMES | LIGHT => return Err(CodecError::Invalid("a section has the wrong size")),
+            BLOCK_ENTITIES => block_entities = Some(content),
+            METADATA => metadata = Some(content),
+            _ => {}
+        }
+    }
+
+    let mut chunk = chunk.ok_or(CodecError::Invalid("there are no blocks"))?;
+
+    if let Some(biomes) = biomes {
+        for x in 0..CHUNK_WIDTH {
+            for z in 0..CHUNK_LENGTH {
+                chunk.set_biome(x, z, biomes[x * CHUNK_LENGTH + z]);
+            }
+        }
+    }
+
+    if let Some(light) = light {
+        for (index, position) in positions().enumerate() {
+            chunk.set_light(&position, light[index / 2] >> (index % 2 * 4) & 0xF);
+        }
+    }
+
+    if let Some(block_entities) = block_entities {
+        decode_block_entities(block_entities, &mut chunk)?;
+    }
+
+    if let Some(metadata) = metadata {
+        decode_metadata(metadata, &mut chunk)?;
+    }
+
+    Ok(chunk)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A chunk with blocks of many kinds, light, biomes, block entities and metadata.
+    fn sample() -> Chunk {
+        let mut chunk = Chunk::default();
+
+        for (index, position) in positions().enumerate() {
+            if position.y < 12 {
+                chunk.set(&position, (index % 7 + 1) as BlockId);
+            }
+            chunk.set_light(&position, (index % 16) as u8);
+        }
+
+        chunk.set_biome(3, 4, 2);
+        chunk.set_biome(15, 15, 9);
+        chunk.set_block_entity(&BlockPosition::new(1, 2, 3), b"chest".to_vec());
+        chunk.set_block_entity(&BlockPosition::new(15, 31, 15), Vec::new());
+        chunk.set_metadata("generator", vec![1, 0, 0, 0]);
+
+        chunk
+    }
+
+    fn assert_same(a: &Chunk, b: &Chunk) {
+        assert_eq!(a.data(), b.data());
+
+        for position in positions() {
+            assert_eq!(a.light(&position), b.light(&position));
+            assert_eq!(a.occupancy().is_solid(&position), b.occupancy().is_solid(&position));
+        }
+
+        for x in 0..CHUNK_WIDTH {
+            for z in 0..CHUNK_LENGTH {
+                assert_eq!(a.biome(x, z), b.biome(x, z));
+            }
+        }
+
+        assert!(a.block_entities().eq(b.block_entities()));
+        assert!(a.metadata_entries().eq(b.metadata_entries()));
+    }
+
+    /// Encodes a chunk without compression with other sections at the end of the body.
+    fn with_sections(chunk: &Chunk, sections: &[(u8, &[u8])]) -> Vec<u8> {
+        let mut bytes = encode(chunk, Compression::None).unwrap();
+
+        for (tag, content) in sections {
+            section(&mut bytes, *tag, content);
+        }
+
+        let length = (bytes.len() - HEADER_SIZE) as u32;
+        let checksum = crc32fast::hash(&bytes[HEADER_SIZE..]);
+        bytes[7..11].copy_from_slice(&length.to_le_bytes());
+        bytes[11..15].copy_from_slice(&checksum.to_le_bytes());
+
+        bytes
+    }
+
+    #[test]
+    fn round_trip() {
+        let chunk = sample();
+
+        for compression in [Compression::None, Compression::Lz4] {
+            let bytes = encode(&chunk, compression).unwrap();
+            assert_same(&decode(&bytes).unwrap(), &chunk);
+        }
+    }
+
+    #[test]
+    fn round_trip_of_every_palette_size() {
+        for kinds in [1, 2, 3, 16, 17, 255, 256] {
+            let mut chunk = Chunk::default();
+
+            for (index, position) in positions().enumerate() {
+                chunk.set(&position, (index % kinds) as BlockId);
+            }
+
+            let bytes = encode(&chunk, Compression::Lz4).unwrap();
+            assert_same(&decode(&bytes).unwrap(), &chunk);
+        }
+    }
+
+    #[test]
+    fn encoding_is_stable() {
+        let chunk = sample();
+        let bytes = encode(&chunk, Compression::Lz4).unwrap();
+
+        assert_eq!(&bytes[..4], MAGIC);
+        assert_eq!(encode(&decode(&bytes).unwrap(), Compression::Lz4).unwrap(), bytes);
+    }
+
+    #[test]
+    fn rejects_a_wrong_checksum() {
+        let mut bytes = encode(&sample(), Compression::Lz4).unwrap();
+        let last = bytes.len() - 1;
+        bytes[last] ^= 1;
+
+        assert!(matches!(decode(&bytes), Err(CodecError::Checksum)));
+
+        let mut bytes = encode(&sample(), Compression::None).unwrap();
+        bytes[11] ^= 0x80;
+
+        assert!(matches!(decode(&bytes), Err(CodecError::Checksum)));
+    }
+
+    #[test]
+    fn rejects_truncated_data() {
+        let bytes = encode(&sample(), Compression::Lz4).unwrap();
+
+        for length in 0..HEADER_SIZE {
+            assert!(matches!(decode(&bytes[..length]), Err(CodecError::Truncated | CodecError::Magic)));
+        }
+
+        for length in [HEADER_SIZE, HEADER_SIZE + 1, bytes.len() / 2, bytes.len() - 1] {
+            assert!(decode(&bytes[..length]).is_err());
+        }
+    }
+
+    #[test]
+    fn rejects_sections_that_end_early() {
+        let mut bytes = with_sections(&Chunk::default(), &[(METADATA, &[1, 0, 0, 0, 9, 0, 0, 0])]);
+        assert!(matches!(decode(&bytes), Err(CodecError::Truncated)));
+
+        // The length of the body in the header does not match the body.
+        bytes.truncate(bytes.len() - 4);
+        let checksum = crc32fast::hash(&bytes[HEADER_SIZE..]);
+        bytes[11..15].copy_from_slice(&checksum.to_le_bytes());
+        assert!(matches!(decode(&bytes), Err(CodecError::Truncated)));
+    }
+
+    #[test]
+    fn rejects_unknown_versions() {
+        let mut bytes = encode(&sample(), Compression::Lz4).unwrap();
+        bytes[4..6].copy_from_slice(&(VERSION + 1).to_le_bytes());
+
+        assert!(matches!(decode(&bytes), Err(CodecError::Version(version)) if version == VERSION + 1));
+    }
+
+    #[test]
+    fn rejects_unknown_compressions() {
+        let mut bytes = encode(&sample(), Compression::Lz4).unwrap();
+        bytes[6] = 7;
+
+        assert!(matches!(decode(&bytes), Err(CodecError::Compression(7))));
+    }
+
+    #[test]
+    fn rejects_bodies_that_are_too_long() {
+        let mut bytes = encode(&sample(), Compression::Lz4).unwrap();
+        bytes[7..11].copy_from_slice(&u32::MAX.to_le_bytes());
+
+        assert!(matches!(decode(&bytes), Err(CodecError::TooLarge(_))));
+
+        let mut chunk = Chunk::default();
+        chunk.set_metadata("huge", vec![0; MAX_BODY_SIZE]);
+
+        assert!(matches!(encode(&chunk, Compression::Lz4), Err(CodecError::TooLarge(_))));
+    }
+
+    #[test]
+    fn rejects_block_entities_outside_of_the_chunk() {
+        let content = [1, 0, CHUNK_WIDTH as u8, 0, 0, 0, 0, 0, 0];
+        let bytes = with_sections(&Chunk::default(), &[(BLOCK_ENTITIES, &content)]);
+
+        assert!(matches!(decode(&bytes), Err(CodecError::Invalid(_))));
+    }
+
+    #[test]
+    fn skips_unknown_sections() {
+        let chunk = sample();
+        let bytes = with_sections(&chunk, &[(200, b"from a newer version")]);
+
+        assert_same(&decode(&bytes).unwrap(), &chunk);
+    }
+}
@@ -1,6 +1,12 @@
+use std::io;
+use std::path::PathBuf;
+
 use specs::{Component, DispatcherBuilder, World};
 use specs::WorldExt;
 
+use crate::info::{WorldClock, WorldDirectory, WorldInfo};
+use crate::storage::backup;
+
 /// This struct stores all the information needed to run a simulation of a voxelia world.
 pub struct Engine<'a, 'b> {
     pub world: specs::World,
@@ -9,7 +15,9 @@ pub struct Engine<'a, 'b> {
 
 impl<'a, 'b> Engine<'a, 'b> {
     pub fn run(&mut self) {
-        self.dispatcher.dispatch(&self.world)
+        self.dispatcher.dispatch(&self.world);
+        // Entities created and deleted by the systems only take effect here.
+        self.world.maintain();
     }
 }
 
@@ -41,6 +49,15 @@ impl<'a, 'b> WorldBuilder<'a, 'b> {
         self.dispatcher.add(system, name, deps);
     }
 
+    /// Adds a system that runs on the thread of the dispatcher after all the other systems, which can
+    /// get the whole world.
+    pub fn with_thread_local<T>(&mut self, system: T)
+    where
+        T: for<'c> specs::RunNow<'c> + 'b,
+    {
+        self.dispatcher.add_thread_local(system);
+    }
+
     /// Registers a new type of component into the world.
     pub fn with_component<T: Component>(&mut self)
     where
@@ -56,6 +73,23 @@ impl<'a, 'b> WorldBuilder<'a, 'b> {
     {
         self.world.insert(resource);
     }
+
+    /// Gets a resource in order to change it, inserting its default value if no plugin added it
+    /// before.
+    pub fn resource_mut<R>(&mut self) -> specs::shred::FetchMut<'_, R>
+    where
+        R: specs::shred::Resource + Default,
+    {
+        self.world.entry::<R>().or_insert_with(R::default)
+    }
+
+    /// Gets a resource that a plugin added before in order to change it, [None] if there's none.
+    pub fn get_resource_mut<R>(&mut self) -> Option<specs::shred::FetchMut<'_, R>>
+    where
+        R: specs::shred::Resource,
+    {
+        self.world.try_fetch_mut::<R>()
+    }
 }
 
 /// A plugin adds information to the ECS of the engine in order to add new systems and new things
@@ -82,6 +116,26 @@ impl<'a, 'b> Builder<'a, 'b> {
         }
     }
 
+    /// Opens the world saved in a directory, or starts a new one with `info` if there's none there.
+    /// The [WorldInfo] and the [WorldDirectory] are added as resources, and the [WorldClock] moves
+    /// the time of the world forward. A snapshot that was asked to be restored is restored first.
+    pub fn open(directory: impl Into<PathBuf>, info: WorldInfo) -> io::Result<Self> {
+        let directory = directory.into();
+
+        if directory.exists() {
+            backup::restore_requested(&directory)?;
+        }
+
+        let info = WorldInfo::load(&directory)?.unwrap_or(info);
+
+        let mut builder = Builder::new();
+        builder.world_builder.with_resource(info);
+        builder.world_builder.with_resource(WorldDirectory(directory));
+        builder.world_builder.with_system(WorldClock::default(), "world clock", &[]);
+
+        Ok(builder)
+    }
+
     /// Registers a new plugin into the engine
     pub fn with(mut self, plugin: impl Plugin + 'static) -> Self {
         plugin.setup(&mut self.world_builder);
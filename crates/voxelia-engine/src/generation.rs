@@ -0,0 +1,192 @@
+//! Procedural generation of worlds. A [WorldGenerator] turns the position of a chunk and the seed of
+//! the world into the blocks of the chunk, always giving the same blocks for the same input. The
+//! chunks of the world are asked for with the [ChunkRequests] and go through the steps of the
+//! generation in the background with the [ChunkScheduler].
+
+use std::sync::Arc;
+
+use crate::{
+    chunk::{Chunk, ChunkPosition},
+};
+
+pub mod caves;
+pub mod decoration;
+pub mod pipeline;
+pub mod structures;
+pub mod terrain;
+
+pub use pipeline::{ChunkRequests, ChunkScheduler, ChunkStatus};
+
+/// Something that creates the blocks of the chunks of a world. It's shared between threads.
+pub trait WorldGenerator: Send + Sync {
+    /// Creates the blocks of a chunk with the [ChunkStatus::Terrain] status.
+    fn generate(&self, position: &ChunkPosition, seed: u64) -> Chunk;
+
+    /// Takes a chunk to one of the statuses that come after [ChunkStatus::Terrain] and are done
+    /// by the generator, [ChunkStatus::Carved] and [ChunkStatus::Decorated]. It does nothing by
+    /// default.
+    fn advance(&self, _chunk: &mut Chunk, _position: &ChunkPosition, _status: ChunkStatus, _seed: u64) {}
+
+    /// Height of the highest block of the terrain at a global column, used to know which chunks
+    /// are under the ground when the chunks above them are not loaded. It's [None] by default,
+    /// so these chunks are lit as if they were open to the sky.
+    fn height(&self, _x: i64, _z: i64, _seed: u64) -> Option<i64> {
+        None
+    }
+}
+
+impl<T: WorldGenerator + ?Sized> WorldGenerator for Arc<T> {
+    fn generate(&self, position: &ChunkPosition, seed: u64) -> Chunk {
+        (**self).generate(position, seed)
+    }
+
+    fn advance(&self, chunk: &mut Chunk, position: &ChunkPosition, status: ChunkStatus, seed: u64) {
+        (**self).advance(chunk, position, status, seed)
+    }
+
+    fn height(&self, x: i64, z: i64, seed: u64) -> Option<i64> {
+        (**self).height(x, z, seed)
+    }
+}
+
+/// A step of the generation that changes a chunk after it was created by a [WorldGenerator], like
+/// carving caves. It must be deterministic too.
+pub trait GenerationStage: Send + Sync {
+    /// The status that the chunk gets with this stage.
+    fn status(&self) -> ChunkStatus;
+
+    fn apply(&self, chunk: &mut Chunk, position: &ChunkPosition, seed: u64);
+}
+
+/// A [WorldGenerator] followed by stages that are applied in order when the chunk gets their status.
+pub struct StagedGenerator {
+    base: Box<dyn WorldGenerator>,
+    stages: Vec<Box<dyn GenerationStage>>,
+}
+
+impl StagedGenerator {
+    pub fn new(base: impl WorldGenerator + 'static) -> StagedGenerator {
+        StagedGenerator {
+            base: Box::new(base),
+            stages: Vec::new(),
+        }
+    }
+
+    pub fn with_stage(mut self, stage: impl GenerationStage + 'static) -> StagedGenerator {
+        self.stages.push(Box::new(stage));
+        self
+    }
+}
+
+impl WorldGenerator for StagedGenerator {
+    fn generate(&self, position: &ChunkPosition, seed: u64) -> Chunk {
+        self.base.generate(position, seed)
+    }
+
+    fn advance(&self, chunk: &mut Chunk, position: &ChunkPosition, status: ChunkStatus, seed: u64) {
+        self.base.advance(chunk, position, status, seed);
+
+        for stage in self.stages.iter().filter(|stage| stage.status() == status) {
+            stage.apply(chunk, position, seed);
+        }
+    }
+
+    fn height(&self, x: i64, z: i64, seed: u64) -> Option<i64> {
+        self.base.height(x, z, seed)
+    }
+}
+
+/// Resource with the generator and the seed of the world.
+#[derive(Clone)]
+pub struct Generation {
+    pub seed: u64,
+    pub generator: Arc<dyn WorldGenerator>,
+}
+
+impl Generation {
+    pub fn new(seed: u64, generator: impl WorldGenerator + 'static) -> Generation {
+        Generation {
+            seed,
+            generator: Arc::new(generator),
+        }
+    }
+
+    /// Generates the blocks of a chunk going through all the steps of the generator at once,
+    /// without the decorations that the neighbours leave in it.
+    pub fn generate(&self, position: &ChunkPosition) -> Chunk {
+        let mut chunk = self.generator.generate(position, self.seed);
+
+        for status in [ChunkStatus::Carved, ChunkStatus::Decorated] {
+            self.generator.advance(&mut chunk, position, status, self.seed);
+        }
+
+        chunk
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::block::BlockPosition;
+
+    use super::*;
+
+    /// A world of air where every chunk has a block at the height of its position.
+    struct Marked;
+
+    impl WorldGenerator for Marked {
+        fn generate(&self, position: &ChunkPosition, _seed: u64) -> Chunk {
+            let mut chunk = Chunk::default();
+            chunk.set(&BlockPosition::new(0, position.y.rem_euclid(4), 0), 1);
+            chunk
+        }
+    }
+
+    /// Sets a block at the x of its status, and leaves the seed in the block next to it.
+    struct Stage(ChunkStatus);
+
+    impl GenerationStage for Stage {
+        fn status(&self) -> ChunkStatus {
+            self.0
+        }
+
+        fn apply(&self, chunk: &mut Chunk, _position: &ChunkPosition, seed: u64) {
+            chunk.set(&BlockPosition::new(self.0 as i64, 0, 0), 2);
+            chunk.set(&BlockPosition::new(self.0 as i64, 1, 0), seed as u8);
+        }
+    }
+
+    fn staged() -> StagedGenerator {
+        StagedGenerator::new(Marked).with_stage(Stage(ChunkStatus::Carved)).with_stage(Stage(ChunkStatus::Decorated))
+    }
+
+    #[test]
+    fn stages_run_when_the_chunk_gets_their_status() {
+        let generator = staged();
+        let position = ChunkPosition::new(0, 2, 0);
+        let carved = BlockPosition::new(ChunkStatus::Carved as i64, 0, 0);
+        let decorated = BlockPosition::new(ChunkStatus::Decorated as i64, 0, 0);
+
+        let mut chunk = generator.generate(&position, 5);
+        assert_eq!(chunk.get(&BlockPosition::new(0, 2, 0)), 1);
+        assert_eq!((chunk.get(&carved), chunk.get(&decorated)), (0, 0));
+
+        generator.advance(&mut chunk, &position, ChunkStatus::Carved, 5);
+        assert_eq!((chunk.get(&carved), chunk.get(&decorated)), (2, 0));
+        assert_eq!(chunk.get(&BlockPosition::new(ChunkStatus::Carved as i64, 1, 0)), 5);
+
+        generator.advance(&mut chunk, &position, ChunkStatus::Decorated, 5);
+        assert_eq!((chunk.get(&carved), chunk.get(&decorated)), (2, 2));
+    }
+
+    #[test]
+    fn generations_go_through_every_stage() {
+        let generation = Generation::new(9, staged());
+        let chunk = generation.generate(&ChunkPosition::new(0, -1, 0));
+
+        assert_eq!(chunk.get(&BlockPosition::new(0, 3, 0)), 1);
+        for status in [ChunkStatus::Carved, ChunkStatus::Decorated] {
+            assert_eq!(chunk.get(&BlockPosition::new(status as i64, 1, 0)), 9);
+        }
+        assert_eq!(generation.generator.height(0, 0, 9), None);
+    }
+}
@@ -0,0 +1,333 @@
+//! Carving of caves into the terrain. There are three kinds of caves:
+//!
+//! - Cheese caves, big caverns where a 3D noise is above a threshold.
+//! - Spaghetti caves, long thin tunnels where two 3D noises are both close to zero.
+//! - Worms, tunnels that follow a random walk. A worm can start in a chunk and go through many
+//!   others, so every chunk simulates all the worms that start close enough to reach it. Worms only
+//!   depend on the seed and on the column where they start, so the order that the chunks are
+//!   generated in does not matter.
+
+use std::sync::Arc;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    block::{BlockId, BlockPosition},
+    chunk::{Chunk, ChunkPosition, CHUNK_HEIGHT, CHUNK_LENGTH, CHUNK_WIDTH},
+    noise::{Fbm, Noise3, OpenSimplex},
+    random::Random,
+};
+
+use super::{terrain::TerrainGenerator, ChunkStatus, GenerationStage};
+
+const CHEESE_SALT: u64 = 0x6368_6565_7365;
+const SPAGHETTI_SALT: u64 = 0x7370_6167_6865;
+const WORM_SALT: u64 = 0x776F_726D;
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct CaveSettings {
+    /// Nothing is carved at or below this height, [i64::MIN] lets caves go as deep as the world.
+    pub min_height: i64,
+    /// Noise caves are not carved above this height so they do not open the surface everywhere,
+    /// only worms are allowed to make entrances.
+    pub max_noise_height: i64,
+    pub cheese_frequency: f64,
+    pub cheese_threshold: f64,
+    pub spaghetti_frequency: f64,
+    pub spaghetti_width: f64,
+    /// Chance of a chunk column to be the start of a worm.
+    pub worm_chance: f64,
+    /// Heights where worms can start.
+    pub worm_heights: (f64, f64),
+    /// Number of steps of one block of the worms.
+    pub worm_lengths: (i64, i64),
+    pub worm_radii: (f64, f64),
+}
+
+impl Default for CaveSettings {
+    fn default() -> Self {
+        CaveSettings {
+            min_height: i64::MIN,
+            max_noise_height: 10,
+            cheese_frequency: 1.0 / 40.0,
+            cheese_threshold: 0.45,
+            spaghetti_frequency: 1.0 / 48.0,
+            spaghetti_width: 0.05,
+            worm_chance: 0.08,
+            worm_heights: (-48.0, 18.0),
+            worm_lengths: (40, 120),
+            worm_radii: (1.2, 2.8),
+        }
+    }
+}
+
+/// A sphere of air along the path of a worm, in global block coordinates.
+struct Sphere {
+    center: [f64; 3],
+    radius: f64,
+}
+
+/// Stage that carves caves into stone, dirt and grass without ever digging under water. The blocks at
+/// the top of a chunk look at the block that the terrain has above them in the chunk above.
+pub struct CaveCarver {
+    pub settings: CaveSettings,
+    pub terrain: Arc<TerrainGenerator>,
+    /// Blocks that can be replaced by air.
+    pub carvable: Vec<BlockId>,
+    /// Blocks that are never left floating over a cave.
+    pub protected: Vec<BlockId>,
+}
+
+impl CaveCarver {
+    pub fn new(settings: CaveSettings, terrain: Arc<TerrainGenerator>) -> CaveCarver {
+        let blocks = terrain.blocks;
+
+        CaveCarver {
+            settings,
+            terrain,
+            carvable: vec![blocks.stone, blocks.dirt, blocks.grass, blocks.sand, blocks.snow],
+            protected: vec![blocks.water],
+        }
+    }
+
+    /// Carves a block, given the blocks that are above the chunk.
+    fn carve(&self, chunk: &mut Chunk, position: &BlockPosition, global_y: i64, above: &[BlockId]) {
+        if global_y <= self.settings.min_height || !self.carvable.contains(&chunk.get(position)) {
+            return;
+        }
+
+        let above = if position.y + 1 < CHUNK_HEIGHT as i64 {
+            chunk.get(&BlockPosition::new(position.x, position.y + 1, position.z))
+        } else {
+            above[position.x as usize * CHUNK_LENGTH + position.z as usize]
+        };
+
+        if self.protected.contains(&above) {
+            return;
+        }
+
+        chunk.set(position, crate::block::AIR);
+    }
+
+    fn carve_noise(&self, chunk: &mut Chunk, origin: [i64; 3], above: &[BlockId], seed: u64) {
+        let settings = &self.settings;
+
+        let cheese = Fbm::<OpenSimplex>::new(seed ^ CHEESE_SALT, 2, settings.cheese_frequency);
+        let first = OpenSimplex::new(seed ^ SPAGHETTI_SALT);
+        let second = OpenSimplex::new((seed ^ SPAGHETTI_SALT).wrapping_add(1));
+        let f = settings.spaghetti_frequency;
+
+        for x in 0..CHUNK_WIDTH as i64 {
+            for z in 0..CHUNK_LENGTH as i64 {
+                // From the top so a block is carved before the one under it checks for water.
+                for y in (0..CHUNK_HEIGHT as i64).rev() {
+                    let (gx, gy, gz) = (origin[0] + x, origin[1] + y, origin[2] + z);
+
+                    if gy > settings.max_noise_height {
+                        continue;
+                    }
+
+                    let (fx, fy, fz) = (gx as f64, gy as f64, gz as f64);
+
+                    let is_cheese = cheese.get3(fx, fy, fz) > settings.cheese_threshold;
+
+                    // The vertical axis is stretched so the tunnels are mostly horizontal.
+                    let is_spaghetti = || {
+                        let a = first.get3(fx * f, fy * f * 2.0, fz * f);
+                        let b = second.get3(fx * f, fy * f * 2.0, fz * f);
+                        a.abs() < settings.spaghetti_width && b.abs() < settings.spaghetti_width
+                    };
+
+                    if is_cheese || is_spaghetti() {
+                        self.carve(chunk, &BlockPosition::new(x, y, z), gy, above);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Simulates the worm that starts in the chunk column `(column_x, column_z)`, if there is one.
+    fn worm(&self, seed: u64, column_x: i64, column_z: i64) -> Vec<Sphere> {
+        let settings = &self.settings;
+        let mut random = Random::at(seed ^ WORM_SALT, column_x, 0, column_z);
+
+        if !random.chance(settings.worm_chance) {
+            return Vec::new();
+        }
+
+        let mut point = [
+            (column_x * CHUNK_WIDTH as i64) as f64 + random.range_f64(0.0, CHUNK_WIDTH as f64),
+            random.range_f64(settings.worm_heights.0, settings.worm_heights.1),
+            (column_z * CHUNK_LENGTH as i64) as f64 + random.range_f64(0.0, CHUNK_LENGTH as f64),
+        ];
+
+        let mut direction = [random.range_f64(-1.0, 1.0), 0.0, random.range_f64(-1.0, 1.0)];
+        let length = random.range(settings.worm_lengths.0, settings.worm_lengths.1);
+        let radius = random.range_f64(settings.worm_radii.0, settings.worm_radii.1);
+
+        let mut spheres = Vec::with_capacity(length as usize);
+
+        for step in 0..length {
+            // Thin at the ends and thick in the middle.
+            let t = step as f64 / length as f64;
+            spheres.push(Sphere {
+                center: point,
+                radius: radius * (0.6 + 1.6 * t * (1.0 - t)),
+            });
+
+            direction[0] += random.range_f64(-0.3, 0.3);
+            direction[1] = direction[1] * 0.7 + random.range_f64(-0.15, 0.15);
+            direction[2] += random.range_f64(-0.3, 0.3);
+
+            let norm = (direction[0] * direction[0]
+                + direction[1] * direction[1]
+                + direction[2] * direction[2])
+                .sqrt();
+
+            if norm == 0.0 {
+                direction = [1.0, 0.0, 0.0];
+            } else {
+                direction = direction.map(|d| d / norm);
+            }
+
+            point = [point[0] + direction[0], point[1] + direction[1], point[2] + direction[2]];
+        }
+
+        spheres
+    }
+
+    fn carve_worms(&self, chunk: &mut Chunk, position: &ChunkPosition, above: &[BlockId], seed: u64) {
+        let origin = position.origin();
+        let settings = &self.settings;
+        let reach = settings.worm_lengths.1 as f64 + settings.worm_radii.1 * 1.4;
+        let range_x = (reach / CHUNK_WIDTH as f64).ceil() as i64;
+        let range_z = (reach / CHUNK_LENGTH as f64).ceil() as i64;
+
+        let size = [CHUNK_WIDTH as i64, CHUNK_HEIGHT as i64, CHUNK_LENGTH as i64];
+        let (chunk_x, chunk_z) = (position.x, position.z);
+
+        for column_x in chunk_x - range_x..=chunk_x + range_x {
+            for column_z in chunk_z - range_z..=chunk_z + range_z {
+                for sphere in self.worm(seed, column_x, column_z) {
+                    // Bounds of the sphere inside of the chunk, empty if it does not touch it.
+                    let bounds = [0, 1, 2].map(|axis| {
+                        let center = sphere.center[axis] - origin[axis] as f64;
+                        let min = ((center - sphere.radius).floor() as i64).max(0);
+                        let max = ((center + sphere.radius).ceil() as i64).min(size[axis] - 1);
+                        (min, max)
+                    });
+
+                    if bounds.iter().any(|(min, max)| min > max) {
+                        continue;
+                    }
+
+                    let squared = sphere.radius * sphere.radius;
+
+                    for x in bounds[0].0..=bounds[0].1 {
+                        for z in bounds[2].0..=bounds[2].1 {
+                            for y in (bounds[1].0..=bounds[1].1).rev() {
+                                let dx = (origin[0] + x) as f64 + 0.5 - sphere.center[0];
+                                let dy = (origin[1] + y) as f64 + 0.5 - sphere.center[1];
+                                let dz = (origin[2] + z) as f64 + 0.5 - sphere.center[2];
+
+                                if dx * dx + dy * dy + dz * dz <= squared {
+                                    self.carve(chunk, &BlockPosition::new(x, y, z), origin[1] + y, above);
+                                }
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl GenerationStage for CaveCarver {
+    fn status(&self) -> ChunkStatus {
+        ChunkStatus::Carved
+    }
+
+    fn apply(&self, chunk: &mut Chunk, position: &ChunkPosition, seed: u64) {
+        let above = self.terrain.blocks_above(position, seed);
+
+        self.carve_noise(chunk, position.origin(), &above, seed);
+        self.carve_worms(chunk, position, &above, seed);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeSet;
+
+    use super::*;
+    use crate::{
+        biome::BiomeRegistry,
+        block::BlockRegistry,
+        generation::{
+            terrain::{TerrainBlocks, TerrainSettings},
+            WorldGenerator,
+        },
+    };
+
+    #[test]
+    fn never_carves_under_the_water_of_the_chunk_above() {
+        let mut registry = BlockRegistry::default();
+        let blocks = TerrainBlocks::register(&mut registry);
+
+        let mut biomes = BiomeRegistry::default();
+        for biome in blocks.biomes() {
+            biomes.register(biome);
+        }
+
+        // Everything is under water, and every block that can be carved is.
+        let settings = TerrainSettings { sea_level: 1 << 20, ..TerrainSettings::default() };
+        let terrain = Arc::new(TerrainGenerator { settings, blocks, biomes });
+        let caves = CaveSettings {
+            max_noise_height: i64::MAX,
+            cheese_threshold: -2.0,
+            worm_chance: 0.0,
+            ..CaveSettings::default()
+        };
+        let carver = CaveCarver::new(caves, terrain.clone());
+
+        let top = CHUNK_HEIGHT as i64 - 1;
+        let mut checked = 0;
+
+        // Chunks whose top is at the surface of some of their columns.
+        for chunk_x in 0..256 {
+            let surfaces = (0..CHUNK_WIDTH as i64)
+                .flat_map(|x| (0..CHUNK_LENGTH as i64).map(move |z| (x, z)))
+                .filter_map(|(x, z)| terrain.height(chunk_x * CHUNK_WIDTH as i64 + x, z, 7))
+                .filter(|height| height.rem_euclid(CHUNK_HEIGHT as i64) == top);
+
+            for height in surfaces.collect::<BTreeSet<_>>() {
+                let position = ChunkPosition::new(chunk_x, height.div_euclid(CHUNK_HEIGHT as i64), 0);
+                let above = terrain.blocks_above(&position, 7);
+                let mut chunk = terrain.generate(&position, 7);
+                let generated = chunk.clone();
+
+                carver.apply(&mut chunk, &position, 7);
+
+                for x in 0..CHUNK_WIDTH as i64 {
+                    for z in 0..CHUNK_LENGTH as i64 {
+                        let block = BlockPosition::new(x, top, z);
+
+                        if above[x as usize * CHUNK_LENGTH + z as usize] == blocks.water
+                            && carver.carvable.contains(&generated.get(&block))
+                        {
+                            assert_eq!(chunk.get(&block), generated.get(&block), "at {x}, {z} of {position:?}");
+                            checked += 1;
+                        }
+                    }
+                }
+            }
+
+            if checked > 0 {
+                break;
+            }
+        }
+
+        assert!(checked > 0);
+    }
+}
@@ -0,0 +1,650 @@
+//! Decoration of the terrain with features like trees, ores, flowers and boulders.
+//!
+//! A feature starts in one chunk but can reach into its neighbours. The blocks that fall outside of
+//! the chunk that is being decorated go to the [DeferredPlacements] queue and the
+//! [ChunkScheduler](super::ChunkScheduler) writes them into the neighbour once it and all of its
+//! own neighbours are decorated, in the order of the chunks that left them. Every feature gets its
+//! random numbers from its own origin and each placement only replaces the blocks that it is
+//! allowed to, so overlapping trees and veins look the same no matter the order that the chunks are
+//! generated in.
+
+use std::collections::{BTreeMap, HashMap};
+use std::sync::{Arc, Mutex};
+
+use crate::{
+    biome::{BiomeRegistry, Vegetation},
+    block::{BlockDefinition, BlockId, BlockPosition, BlockRegistry, Tint, Transparency, AIR},
+    chunk::{Chunk, ChunkPosition, CHUNK_HEIGHT, CHUNK_LENGTH, CHUNK_WIDTH},
+    random::Random,
+    streaming::ChunkStore,
+    workers::Workers,
+};
+
+use super::{
+    terrain::{TerrainBlocks, TerrainGenerator},
+    ChunkStatus, GenerationStage,
+};
+
+pub mod features;
+
+use features::{Boulder, Flower, OreVein, Tree};
+
+const DECORATION_SALT: u64 = 0x6465_636F_7261;
+
+/// Which blocks a placement is allowed to overwrite.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Replace {
+    Any,
+    Air,
+    AirOr(BlockId),
+    Only(BlockId),
+}
+
+impl Replace {
+    pub fn allows(&self, block: BlockId) -> bool {
+        match *self {
+            Replace::Any => true,
+            Replace::Air => block == AIR,
+            Replace::AirOr(other) => block == AIR || block == other,
+            Replace::Only(other) => block == other,
+        }
+    }
+}
+
+/// A block that a feature wants to write, relative to the origin of the chunk that it goes to.
+#[derive(Copy, Clone, Debug)]
+pub struct Placement {
+    pub position: BlockPosition,
+    pub block: BlockId,
+    pub replace: Replace,
+}
+
+impl Placement {
+    pub fn apply(&self, chunk: &mut Chunk) {
+        if self.replace.allows(chunk.get(&self.position)) {
+            chunk.set(&self.position, self.block);
+        }
+    }
+}
+
+/// What left placements for a chunk. The placements of a chunk are applied in the order of their
+/// sources, so the blocks where they overlap do not depend on the order that the chunks were
+/// generated in.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum PlacementSource {
+    /// The decoration of a chunk. A chunk is decorated the same way every time, so its placements
+    /// replace the ones that it left before.
+    Decoration(ChunkPosition),
+    /// A model placed with [place](crate::import::place), numbered in the order that they were
+    /// placed.
+    Placed(u64),
+}
+
+/// Placements waiting for a chunk, by their source.
+pub type PlacementSources = BTreeMap<PlacementSource, Vec<Placement>>;
+
+#[derive(Default)]
+struct Waiting {
+    sources: PlacementSources,
+    /// If the chunk has placements in the [ChunkStore] too, that are forgotten once it gets them.
+    stored: bool,
+}
+
+#[derive(Default)]
+struct Pending {
+    chunks: HashMap<ChunkPosition, Waiting>,
+    /// Number of the next [PlacementSource::Placed].
+    placed: u64,
+}
+
+/// Blocks written outside of the chunk that was being decorated or by placed models, waiting for
+/// their chunk. The placements of chunks that are not loaded are saved in the [ChunkStore] when
+/// there are too many of them and when the world is saved, and they come back when their chunk is
+/// loaded. Cloning it gives another handle to the same queue.
+#[derive(Clone, Default)]
+pub struct DeferredPlacements {
+    pending: Arc<Mutex<Pending>>,
+}
+
+impl DeferredPlacements {
+    /// Adds the placements of a source to a chunk, replacing the ones that it left before.
+    pub fn push(&self, position: ChunkPosition, source: PlacementSource, placements: Vec<Placement>) {
+        if placements.is_empty() {
+            return;
+        }
+
+        let mut pending = self.pending.lock().unwrap();
+        pending.chunks.entry(position).or_default().sources.insert(source, placements);
+    }
+
+    /// Placements whose sources for placed models start at `next`, that is saved in the
+    /// [WorldInfo](crate::info::WorldInfo) so they keep growing after the world is opened again.
+    pub fn starting_at(next: u64) -> DeferredPlacements {
+        let pending = Pending { chunks: HashMap::new(), placed: next };
+        DeferredPlacements { pending: Arc::new(Mutex::new(pending)) }
+    }
+
+    /// A source for a placed model that goes after every other one.
+    pub fn next_placed(&self) -> PlacementSource {
+        let mut pending = self.pending.lock().unwrap();
+        pending.placed += 1;
+        PlacementSource::Placed(pending.placed - 1)
+    }
+
+    /// Number of the source that the next placed model gets.
+    pub fn next(&self) -> u64 {
+        self.pending.lock().unwrap().placed
+    }
+
+    /// Adds the placements of a chunk that were in the [ChunkStore], keeping the ones of the same
+    /// sources that are in memory.
+    pub fn load(&self, position: ChunkPosition, sources: PlacementSources) {
+        let mut pending = self.pending.lock().unwrap();
+        let Pending { chunks, placed } = &mut *pending;
+        let waiting = chunks.entry(position).or_default();
+        waiting.stored = true;
+
+        for (source, placements) in sources {
+            // Later models still go after the saved ones if the world info was not saved with them.
+            if let PlacementSource::Placed(number) = source {
+                *placed = (*placed).max(number.saturating_add(1));
+            }
+            waiting.sources.entry(source).or_insert(placements);
+        }
+    }
+
+    /// Removes the placements that are waiting for a chunk and returns them in the order of their
+    /// sources, with whether the chunk has placements in the [ChunkStore] that have to be
+    /// forgotten.
+    pub fn take(&self, position: &ChunkPosition) -> (Vec<Placement>, bool) {
+        let Some(waiting) = self.pending.lock().unwrap().chunks.remove(position) else { return (Vec::new(), false) };
+        (waiting.sources.into_values().flatten().collect(), waiting.stored)
+    }
+
+    /// Positions of the chunks that have placements waiting for them.
+    pub fn positions(&self) -> Vec<ChunkPosition> {
+        self.pending.lock().unwrap().chunks.keys().copied().collect()
+    }
+
+    /// Number of chunks that have placements waiting for them.
+    pub fn len(&self) -> usize {
+        self.pending.lock().unwrap().chunks.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pending.lock().unwrap().chunks.is_empty()
+    }
+
+    /// Moves the placements of the chunks that are not loaded to the [ChunkStore], merged with the
+    /// ones that it had for them. Without a storage they are lost.
+    pub fn evict(&self, store: &ChunkStore, workers: &Workers, loaded: impl Fn(&ChunkPosition) -> bool) {
+        let evicted: Vec<(ChunkPosition, Waiting)> = {
+            let mut pending = self.pending.lock().unwrap();
+            let positions: Vec<ChunkPosition> =
+                pending.chunks.keys().filter(|position| !loaded(position)).copied().collect();
+
+            positions
+                .into_iter()
+                .map(|position| (position, pending.chunks.remove(&position).unwrap()))
+                .collect()
+        };
+
+        for (position, waiting) in evicted {
+            store.save_placements_later(workers, position, merge_stored(store, &position, waiting.sources));
+        }
+    }
+
+    /// Saves the placements of every chunk in the [ChunkStore], keeping them in memory too.
+    pub fn save(&self, store: &ChunkStore) {
+        let saved: Vec<(ChunkPosition, PlacementSources)> = {
+            let mut pending = self.pending.lock().unwrap();
+            pending
+                .chunks
+                .iter_mut()
+                .map(|(position, waiting)| {
+                    waiting.stored = true;
+                    (*position, waiting.sources.clone())
+                })
+                .collect()
+        };
+
+        for (position, sources) in saved {
+            store.save_placements(&position, &merge_stored(store, &position, sources));
+        }
+    }
+}
+
+/// Encodes the placements of a chunk merged with the ones that the store has for it.
+fn merge_stored(store: &ChunkStore, position: &ChunkPosition, mut sources: PlacementSources) -> Vec<u8> {
+    let stored = store.load_placements(position).and_then(|bytes| {
+        let sources = decode_placements(&bytes);
+        if sources.is_none() {
+            log::error!("the saved placements of the chunk {position:?} are broken");
+        }
+        sources
+    });
+
+    for (source, placements) in stored.unwrap_or_default() {
+        sources.entry(source).or_insert(placements);
+    }
+
+    encode_placements(&sources)
+}
+
+/// Encodes placements for the [ChunkStore]: each source is a tag with its position or number, then
+/// the number of its placements with their positions, blocks and what they replace.
+pub fn encode_placements(sources: &PlacementSources) -> Vec<u8> {
+    let mut bytes = Vec::new();
+
+    for (source, placements) in sources {
+        match source {
+            PlacementSource::Decoration(position) => {
+                bytes.push(0);
+                for coordinate in [position.x, position.y, position.z] {
+                    bytes.extend_from_slice(&coordinate.to_le_bytes());
+                }
+            }
+            PlacementSource::Placed(number) => {
+                bytes.push(1);
+                bytes.extend_from_slice(&number.to_le_bytes());
+            }
+        }
+
+        bytes.extend_from_slice(&(placements.len() as u32).to_le_bytes());
+
+        for placement in placements {
+            let (replace, other) = match placement.replace {
+                Replace::Any => (0, AIR),
+                Replace::Air => (1, AIR),
+                Replace::AirOr(block) => (2, block),
+                Replace::Only(block) => (3, block),
+            };
+
+            let position = placement.position;
+            bytes.extend_from_slice(&[position.x as u8, position.y as u8, position.z as u8, placement.block, replace, other]);
+        }
+    }
+
+    bytes
+}
+
+/// Decodes placements encoded by [encode_placements], [None] if they are broken.
+pub fn decode_placements(mut bytes: &[u8]) -> Option<PlacementSources> {
+    fn take<'a>(bytes: &mut &'a [u8], count: usize) -> Option<&'a [u8]> {
+        let (taken, rest) = bytes.split_at_checked(count)?;
+        *bytes = rest;
+        Some(taken)
+    }
+
+    fn u64(bytes: &mut &[u8]) -> Option<u64> {
+        Some(u64::from_le_bytes(take(bytes, 8)?.try_into().ok()?))
+    }
+
+    let mut sources = PlacementSources::new();
+
+    while !bytes.is_empty() {
+        let source = match take(&mut bytes, 1)?[0] {
+            0 => {
+                let [x, y, z] = [u64(&mut bytes)?, u64(&mut bytes)?, u64(&mut bytes)?].map(|value| value as i64);
+                PlacementSource::Decoration(ChunkPosition::new(x, y, z))
+            }
+            1 => PlacementSource::Placed(u64(&mut bytes)?),
+            _ => return None,
+        };
+
+        let count = u32::from_le_bytes(take(&mut bytes, 4)?.try_into().ok()?) as usize;
+        let data = take(&mut bytes, count.checked_mul(6)?)?;
+
+        let placements = data
+            .chunks_exact(6)
+            .map(|data| {
+                let replace = match data[4] {
+                    0 => Replace::Any,
+                    1 => Replace::Air,
+                    2 => Replace::AirOr(data[5]),
+                    3 => Replace::Only(data[5]),
+                    _ => return None,
+                };
+
+                let position = BlockPosition::new(data[0] as i64, data[1] as i64, data[2] as i64);
+                Some(Placement { position, block: data[3], replace })
+            })
+            .collect::<Option<Vec<_>>>()?;
+
+        sources.insert(source, placements);
+    }
+
+    Some(sources)
+}
+
+/// Writes the blocks of features in global coordinates, keeping the ones that are outside of the
+/// chunk for later.
+pub struct FeatureWriter<'a> {
+    chunk: &'a mut Chunk,
+    origin: [i64; 3],
+    outside: HashMap<ChunkPosition, Vec<Placement>>,
+}
+
+impl<'a> FeatureWriter<'a> {
+    fn new(chunk: &'a mut Chunk, position: &ChunkPosition) -> Self {
+        FeatureWriter {
+            chunk,
+            origin: position.origin(),
+            outside: HashMap::new(),
+        }
+    }
+
+    fn local(&self, position: &BlockPosition) -> BlockPosition {
+        BlockPosition::new(
+            position.x - self.origin[0],
+            position.y - self.origin[1],
+            position.z - self.origin[2],
+        )
+    }
+
+    /// Block at a global position, [None] if it is outside of the chunk.
+    pub fn get(&self, position: &BlockPosition) -> Option<BlockId> {
+        let mut local = self.local(position);
+        if local.is_out() {
+            None
+        } else {
+            Some(self.chunk.get(&local))
+        }
+    }
+
+    pub fn set(&mut self, position: &BlockPosition, block: BlockId, replace: Replace) {
+        let mut local = self.local(position);
+
+        if !local.is_out() {
+            Placement { position: local, block, replace }.apply(self.chunk);
+            return;
+        }
+
+        let (target, position) = ChunkPosition::of_block(position.x, position.y, position.z);
+        self.outside.entry(target).or_default().push(Placement { position, block, replace });
+    }
+}
+
+/// Something that can be placed in the world, like a tree or an ore vein.
+pub trait Feature: Send + Sync {
+    /// Places the feature at a global position. The origin of surface features is the block right
+    /// above the ground.
+    fn place(&self, writer: &mut FeatureWriter, origin: &BlockPosition, random: &mut Random);
+}
+
+/// Where the origins of a feature are chosen.
+#[derive(Clone, Debug)]
+pub enum FeaturePlacement {
+    /// On top of the surface block of the biome, when it is above the sea.
+    Surface(Rarity),
+    /// At a random height in `[min, max)` for a number of attempts in each chunk that overlaps
+    /// these heights.
+    Underground { attempts: u32, min_height: i64, max_height: i64 },
+}
+
+/// How often a surface feature appears in each column.
+#[derive(Clone, Debug)]
+pub enum Rarity {
+    Chance(f64),
+    /// Uses the amount of trees of the [Vegetation] of the biome.
+    Trees,
+    /// Uses the amount of flowers of the [Vegetation] of the biome.
+    Flowers,
+}
+
+impl Rarity {
+    fn chance(&self, vegetation: &Vegetation) -> f64 {
+        match self {
+            Rarity::Chance(chance) => *chance,
+            Rarity::Trees => vegetation.trees,
+            Rarity::Flowers => vegetation.flowers,
+        }
+    }
+}
+
+pub struct PlacedFeature {
+    pub feature: Box<dyn Feature>,
+    pub placement: FeaturePlacement,
+}
+
+impl PlacedFeature {
+    pub fn new(feature: impl Feature + 'static, placement: FeaturePlacement) -> PlacedFeature {
+        PlacedFeature {
+            feature: Box::new(feature),
+            placement,
+        }
+    }
+}
+
+/// Blocks used by the default features.
+#[derive(Copy, Clone, Debug)]
+pub struct FeatureBlocks {
+    pub log: BlockId,
+    pub leaves: BlockId,
+    pub flower: BlockId,
+    pub coal_ore: BlockId,
+    pub iron_ore: BlockId,
+}
+
+impl FeatureBlocks {
+    /// Gets the feature blocks from the registry, registering the ones that are missing.
+    pub fn register(registry: &mut BlockRegistry) -> FeatureBlocks {
+        FeatureBlocks {
+            log: registry.get_or_register(BlockDefinition::new("log", Transparency::Opaque, 0)),
+            leaves: registry.get_or_register(
+                BlockDefinition::new("leaves", Transparency::Cutout, 0).with_tint(Tint::Foliage),
+            ),
+            flower: registry.get_or_register(BlockDefinition::new("flower", Transparency::Cutout, 0)),
+            coal_ore: registry.get_or_register(BlockDefinition::new("coal_ore", Transparency::Opaque, 0)),
+            iron_ore: registry.get_or_register(BlockDefinition::new("iron_ore", Transparency::Opaque, 0)),
+        }
+    }
+
+    /// The features placed by default, in the order that they are placed.
+    pub fn features(&self, terrain: &TerrainBlocks) -> Vec<PlacedFeature> {
+        vec![
+            PlacedFeature::new(
+                OreVein { ore: self.coal_ore, replaces: terrain.stone, size: 10 },
+                FeaturePlacement::Underground { attempts: 8, min_height: -64, max_height: 24 },
+            ),
+            PlacedFeature::new(
+                OreVein { ore: self.iron_ore, replaces: terrain.stone, size: 6 },
+                FeaturePlacement::Underground { attempts: 4, min_height: -128, max_height: 14 },
+            ),
+            PlacedFeature::new(
+                Boulder { block: terrain.stone, radii: (1.0, 2.2) },
+                FeaturePlacement::Surface(Rarity::Chance(0.0008)),
+            ),
+            PlacedFeature::new(
+                Tree { log: self.log, leaves: self.leaves, heights: (4, 7), radius: 2 },
+                FeaturePlacement::Surface(Rarity::Trees),
+            ),
+            PlacedFeature::new(Flower { block: self.flower }, FeaturePlacement::Surface(Rarity::Flowers)),
+        ]
+    }
+}
+
+/// Stage that places features on the chunks, leaving the blocks that fall in the neighbours in the
+/// [DeferredPlacements].
+pub struct Decorator {
+    pub features: Vec<PlacedFeature>,
+    pub biomes: BiomeRegistry,
+    pub sea_level: i64,
+    pub deferred: DeferredPlacements,
+    /// Tells what's above the chunk for the columns that are solid up to its top.
+    pub terrain: Arc<TerrainGenerator>,
+}
+
+impl Decorator {
+    /// Height of the highest block of a column if it is the surface block of its biome and there is
+    /// air above it. `above` are the blocks right above the chunk, by x and then z, and can be empty
+    /// when no column reaches the top of the chunk.
+    fn surface(&self, chunk: &Chunk, x: usize, z: usize, above: &[BlockId]) -> Option<i64> {
+        let column = chunk.occupancy().column(x as i64, z as i64);
+        if column == 0 {
+            return None;
+        }
+
+        let y = 63 - column.leading_zeros() as i64;
+        let biome = self.biomes.get(chunk.biome(x, z))?;
+
+        if y == CHUNK_HEIGHT as i64 - 1 && above.get(x * CHUNK_LENGTH + z) != Some(&AIR) {
+            return None;
+        }
+
+        (chunk.get(&BlockPosition::new(x as i64, y, z as i64)) == biome.surface).then_some(y)
+    }
+}
+
+impl GenerationStage for Decorator {
+    fn status(&self) -> ChunkStatus {
+        ChunkStatus::Decorated
+    }
+
+    fn apply(&self, chunk: &mut Chunk, position: &ChunkPosition, seed: u64) {
+        let origin = position.origin();
+
+        // The blocks above the chunk are only needed when a column is solid up to its top, which is
+        // the case of every column of the chunks under the ground.
+        let top = 1 << (CHUNK_HEIGHT - 1);
+        let reaches_top = (0..CHUNK_WIDTH as i64)
+            .any(|x| (0..CHUNK_LENGTH as i64).any(|z| chunk.occupancy().column(x, z) & top != 0));
+        let above = if reaches_top { self.terrain.blocks_above(position, seed) } else { Vec::new() };
+
+        // The ground is found before anything is placed so features do not grow on each other.
+        let mut surfaces = Vec::with_capacity(CHUNK_WIDTH * CHUNK_LENGTH);
+        for x in 0..CHUNK_WIDTH {
+            for z in 0..CHUNK_LENGTH {
+                let Some(y) = self.surface(chunk, x, z, &above) else { continue };
+                if origin[1] + y >= self.sea_level {
+                    surfaces.push((x, y, z, chunk.biome(x, z)));
+                }
+            }
+        }
+
+        let mut writer = FeatureWriter::new(chunk, position);
+
+        for (index, placed) in self.features.iter().enumerate() {
+            let salt = seed ^ DECORATION_SALT ^ (index as u64).wrapping_mul(0x9E37_79B9_7F4A_7C15);
+
+            match &placed.placement {
+                FeaturePlacement::Surface(rarity) => {
+                    for (x, y, z, biome) in &surfaces {
+                        let origin = BlockPosition::new(
+                            origin[0] + *x as i64,
+                            origin[1] + y + 1,
+                            origin[2] + *z as i64,
+                        );
+
+                        let mut random = Random::at(salt, origin.x, origin.y, origin.z);
+                        let vegetation = self.biomes.get(*biome).map_or(Vegetation::default(), |b| b.vegetation);
+
+                        if random.chance(rarity.chance(&vegetation)) {
+                            placed.feature.place(&mut writer, &origin, &mut random);
+                        }
+                    }
+                }
+                FeaturePlacement::Underground { attempts, min_height, max_height } => {
+                    let min = (*min_height).max(origin[1]);
+                    let max = (*max_height).min(origin[1] + CHUNK_HEIGHT as i64);
+
+                    if min >= max {
+                        continue;
+                    }
+
+                    let mut random = Random::at(salt, position.x, position.y, position.z);
+
+                    for _ in 0..*attempts {
+                        let origin = BlockPosition::new(
+                            origin[0] + random.range(0, CHUNK_WIDTH as i64),
+                            random.range(min, max),
+                            origin[2] + random.range(0, CHUNK_LENGTH as i64),
+                        );
+
+                        placed.feature.place(&mut writer, &origin, &mut random);
+                    }
+                }
+            }
+        }
+
+        for (target, placements) in writer.outside {
+            self.deferred.push(target, PlacementSource::Decoration(*position), placements);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::generation::{terrain::TerrainSettings, WorldGenerator};
+
+    use super::*;
+
+    fn decorator() -> Decorator {
+        let mut registry = BlockRegistry::default();
+        let blocks = TerrainBlocks::register(&mut registry);
+
+        let mut biomes = BiomeRegistry::default();
+        for biome in blocks.biomes() {
+            biomes.register(biome);
+        }
+
+        let settings = TerrainSettings::default();
+        Decorator {
+            features: Vec::new(),
+            biomes: biomes.clone(),
+            sea_level: settings.sea_level,
+            deferred: DeferredPlacements::default(),
+            terrain: Arc::new(TerrainGenerator { settings, blocks, biomes }),
+        }
+    }
+
+    fn surfaces(decorator: &Decorator, chunk: &Chunk, position: &ChunkPosition) -> Vec<Option<i64>> {
+        let above = decorator.terrain.blocks_above(position, 42);
+        (0..CHUNK_WIDTH)
+            .flat_map(|x| (0..CHUNK_LENGTH).map(move |z| (x, z)))
+            .map(|(x, z)| decorator.surface(chunk, x, z, &above))
+            .collect()
+    }
+
+    #[test]
+    fn finds_the_ground() {
+        let decorator = decorator();
+        let position = ChunkPosition::new(0, 0, 0);
+        let chunk = decorator.terrain.generate(&position, 42);
+
+        let (_, height) = decorator.terrain.column(0, 0, 42).unwrap();
+        assert_eq!(surfaces(&decorator, &chunk, &position)[0], Some(height));
+    }
+
+    #[test]
+    fn chunks_under_the_ground_have_no_surface() {
+        let decorator = decorator();
+        let mountains = decorator.biomes.iter().find(|(_, biome)| biome.name == "mountains").unwrap().0;
+
+        // The surface of the mountains is stone, the same block that fills the chunks below them.
+        let position = ChunkPosition::new(0, -2, 0);
+        let mut chunk = decorator.terrain.generate(&position, 42);
+        for x in 0..CHUNK_WIDTH {
+            for z in 0..CHUNK_LENGTH {
+                chunk.set_biome(x, z, mountains);
+            }
+        }
+
+        assert!(surfaces(&decorator, &chunk, &position).iter().all(Option::is_none));
+    }
+
+    #[test]
+    fn columns_up_to_the_top_have_a_surface_under_air() {
+        let decorator = decorator();
+        let mountains = decorator.biomes.iter().find(|(_, biome)| biome.name == "mountains").unwrap().0;
+
+        let position = ChunkPosition::new(0, 8, 0);
+        let mut chunk = Chunk::default();
+        chunk.set_biome(0, 0, mountains);
+        for y in 0..CHUNK_HEIGHT as i64 {
+            chunk.set(&BlockPosition::new(0, y, 0), decorator.terrain.blocks.stone);
+        }
+
+        assert_eq!(surfaces(&decorator, &chunk, &position)[0], Some(CHUNK_HEIGHT as i64 - 1));
+    }
+}
@@ -0,0 +1,109 @@
+//! The default features: trees, ore veins, flowers and boulders.
+
+use crate::{
+    block::{BlockId, BlockPosition},
+    random::Random,
+};
+
+use super::{Feature, FeatureWriter, Replace};
+
+/// A trunk of logs with a round crown of leaves. The leaves never replace the logs of other trees.
+pub struct Tree {
+    pub log: BlockId,
+    pub leaves: BlockId,
+    /// Heights of the trunk, in `[min, max)`.
+    pub heights: (i64, i64),
+    pub radius: i64,
+}
+
+impl Feature for Tree {
+    fn place(&self, writer: &mut FeatureWriter, origin: &BlockPosition, random: &mut Random) {
+        let height = random.range(self.heights.0, self.heights.1);
+        let top = origin.y + height;
+
+        for dy in -self.radius..=1 {
+            // The crown gets thinner at the top.
+            let radius = if dy > 0 { self.radius - 1 } else { self.radius };
+
+            for dx in -radius..=radius {
+                for dz in -radius..=radius {
+                    // Corners are removed at random so the trees do not look like boxes.
+                    let corner = dx.abs() == radius && dz.abs() == radius;
+                    if corner && (dy > 0 || random.chance(0.5)) {
+                        continue;
+                    }
+
+                    let position = BlockPosition::new(origin.x + dx, top + dy, origin.z + dz);
+                    writer.set(&position, self.leaves, Replace::Air);
+                }
+            }
+        }
+
+        for y in origin.y..top {
+            let position = BlockPosition::new(origin.x, y, origin.z);
+            writer.set(&position, self.log, Replace::AirOr(self.leaves));
+        }
+    }
+}
+
+/// A blob of ore that replaces a single kind of block, made of a short random walk.
+pub struct OreVein {
+    pub ore: BlockId,
+    pub replaces: BlockId,
+    /// Number of blocks that the walk visits.
+    pub size: u32,
+}
+
+impl Feature for OreVein {
+    fn place(&self, writer: &mut FeatureWriter, origin: &BlockPosition, random: &mut Random) {
+        let mut position = BlockPosition::new(origin.x, origin.y, origin.z);
+
+        for _ in 0..self.size {
+            writer.set(&position, self.ore, Replace::Only(self.replaces));
+
+            match random.range(0, 3) {
+                0 => position.x += random.range(-1, 2),
+                1 => position.y += random.range(-1, 2),
+                _ => position.z += random.range(-1, 2),
+            }
+        }
+    }
+}
+
+/// A single flower on the ground.
+pub struct Flower {
+    pub block: BlockId,
+}
+
+impl Feature for Flower {
+    fn place(&self, writer: &mut FeatureWriter, origin: &BlockPosition, _: &mut Random) {
+        writer.set(origin, self.block, Replace::Air);
+    }
+}
+
+/// A rough ball of stone half buried in the ground.
+pub struct Boulder {
+    pub block: BlockId,
+    /// Radii of the boulder, in `[min, max)`.
+    pub radii: (f64, f64),
+}
+
+impl Feature for Boulder {
+    fn place(&self, writer: &mut FeatureWriter, origin: &BlockPosition, random: &mut Random) {
+        let radius = random.range_f64(self.radii.0, self.radii.1);
+        let reach = radius.ceil() as i64;
+
+        for dx in -reach..=reach {
+            for dy in -reach..=reach {
+                for dz in -reach..=reach {
+                    let distance = (dx * dx + dy * dy + dz * dz) as f64;
+
+                    if distance <= radius * radius {
+                        let position = BlockPosition::new(origin.x + dx, origin.y + dy - 1, origin.z + dz);
+                        writer.set(&position, self.block, Replace::Air);
+                    }
+                }
+            }
+        }
+    }
+}
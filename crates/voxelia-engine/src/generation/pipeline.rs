@@ -0,0 +1,546 @@
+//! The steps that a chunk goes through while it's generated. Each step may need the chunks around
+//! it to be far enough in the pipeline:
+//!
+//! - Terrain and caves only depend on the chunk itself.
+//! - Decoration writes into the neighbours, so they must have their caves already.
+//! - The blocks that the decorations of the neighbours left for a chunk land in it once the chunk
+//!   and all of its neighbours are decorated, right before it's lit.
+//! - Lighting must wait for these blocks, and for the chunk above to be lit because the light comes
+//!   from there.
+//! - The light of the neighbours spreads into the chunk when it's made full.
+//!
+//! Neighbours above and below that are not loaded count as ready, so the chunks at the top and at
+//! the bottom of the loaded area do not wait for chunks that nobody asked for. A chunk without a
+//! chunk above takes the sky light from the height of the terrain instead.
+
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::Arc;
+
+use specs::{Component, Entities, Entity, Join, Read, ReadExpect, System, VecStorage, Write, WriteStorage};
+
+use crate::{
+    block::BlockRegistry,
+    chunk::{Chunk, ChunkPosition, CHUNK_HEIGHT, CHUNK_LENGTH, CHUNK_WIDTH},
+    events::Created,
+    light::{self, TopLight, MAX_LIGHT},
+    persistence::SavedEntities,
+    streaming::{ChunkStore, Unsaved},
+    workers::Workers,
+    Position,
+};
+
+use super::{
+    decoration::{self, DeferredPlacements},
+    Generation,
+};
+
+/// How far a chunk is in the generation pipeline. Chunks only get the [Created] marker when they
+/// are [ChunkStatus::Full].
+#[derive(Component, Copy, Clone, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[storage(VecStorage)]
+pub enum ChunkStatus {
+    #[default]
+    Empty,
+    Terrain,
+    Carved,
+    Decorated,
+    Lit,
+    Full,
+}
+
+impl ChunkStatus {
+    pub fn next(&self) -> Option<ChunkStatus> {
+        match self {
+            ChunkStatus::Empty => Some(ChunkStatus::Terrain),
+            ChunkStatus::Terrain => Some(ChunkStatus::Carved),
+            ChunkStatus::Carved => Some(ChunkStatus::Decorated),
+            ChunkStatus::Decorated => Some(ChunkStatus::Lit),
+            ChunkStatus::Lit => Some(ChunkStatus::Full),
+            ChunkStatus::Full => None,
+        }
+    }
+
+    /// The status that the neighbours of a chunk need before it can get this status.
+    pub fn requirement(&self) -> Option<ChunkStatus> {
+        match self {
+            ChunkStatus::Empty | ChunkStatus::Terrain | ChunkStatus::Carved => None,
+            ChunkStatus::Decorated => Some(ChunkStatus::Carved),
+            ChunkStatus::Lit => Some(ChunkStatus::Decorated),
+            ChunkStatus::Full => Some(ChunkStatus::Lit),
+        }
+    }
+}
+
+/// The 26 neighbours of a chunk, with the diagonals.
+fn neighbours(position: &ChunkPosition) -> impl Iterator<Item = ChunkPosition> + '_ {
+    (-1..=1i64)
+        .flat_map(|dx| (-1..=1i64).flat_map(move |dy| (-1..=1i64).map(move |dz| (dx, dy, dz))))
+        .filter(|offset| *offset != (0, 0, 0))
+        .map(|(dx, dy, dz)| ChunkPosition::new(position.x + dx, position.y + dy, position.z + dz))
+}
+
+fn above(position: &ChunkPosition) -> ChunkPosition {
+    ChunkPosition::new(position.x, position.y + 1, position.z)
+}
+
+/// Copies of the horizontal neighbours of a chunk, in the order of [light::light_borders].
+fn sides(
+    world: &HashMap<ChunkPosition, (Entity, ChunkStatus)>,
+    chunks: &WriteStorage<Chunk>,
+    position: &ChunkPosition,
+) -> [Option<Chunk>; 4] {
+    [(-1, 0), (1, 0), (0, -1), (0, 1)].map(|(dx, dz)| {
+        let (neighbour, _) = world.get(&ChunkPosition::new(position.x + dx, position.y, position.z + dz))?;
+        chunks.get(*neighbour).cloned()
+    })
+}
+
+/// Light that comes from the sky into a chunk whose chunk above is not loaded. Columns where the
+/// terrain is higher than the chunk are dark.
+fn sky_light(generation: &Generation, position: &ChunkPosition) -> TopLight {
+    let origin = position.origin();
+    let top = origin[1] + CHUNK_HEIGHT as i64 - 1;
+    let mut light = [MAX_LIGHT; CHUNK_WIDTH * CHUNK_LENGTH];
+
+    for x in 0..CHUNK_WIDTH {
+        for z in 0..CHUNK_LENGTH {
+            let height = generation.generator.height(origin[0] + x as i64, origin[2] + z as i64, generation.seed);
+
+            if height.is_some_and(|height| height >= top) {
+                light[x * CHUNK_LENGTH + z] = 0;
+            }
+        }
+    }
+
+    light
+}
+
+/// Chunks that were asked for or given up on since the last tick. The chunks that are asked for get
+/// an entity right away that the [ChunkScheduler] takes through the pipeline, and they get the
+/// [Created] marker when they are full.
+#[derive(Default)]
+pub struct ChunkRequests {
+    requested: Vec<ChunkPosition>,
+    cancelled: Vec<ChunkPosition>,
+}
+
+impl ChunkRequests {
+    pub fn request(&mut self, position: ChunkPosition) {
+        self.requested.push(position);
+    }
+
+    /// Stops the generation of a chunk that is not full yet and removes its entity. Full chunks are
+    /// kept.
+    pub fn cancel(&mut self, position: ChunkPosition) {
+        self.cancelled.push(position);
+    }
+}
+
+/// A chunk that went through a step in a worker thread.
+struct StepResult {
+    entity: Entity,
+    position: ChunkPosition,
+    status: ChunkStatus,
+    chunk: Chunk,
+    /// Saved entities of a chunk that was loaded from the [ChunkStore].
+    entities: Option<Vec<u8>>,
+    /// Saved [DeferredPlacements] that were waiting for the chunk.
+    placements: Option<Vec<u8>>,
+}
+
+/// Moves the chunks through the pipeline. Every step but the last one runs in the [Workers], with
+/// a copy of the chunk that replaces the chunk of the entity when it is done. Chunks that are in
+/// the [ChunkStore] skip the generation and are made full right away. Jobs of entities
+/// that are deleted or cancelled are dropped before they start or their results are ignored.
+///
+/// The [DeferredPlacements] of a chunk go to it once it could be lit, and to full chunks right
+/// away. When too many chunks that are not loaded have placements waiting for them, these are moved
+/// to the [ChunkStore], and they come back when the chunks are loaded.
+pub struct ChunkScheduler {
+    /// Maximum number of steps that are running in the workers at the same time.
+    pub max_in_flight: usize,
+    /// Maximum number of chunks that are made full in a single tick.
+    pub full_per_tick: usize,
+    /// Maximum number of chunks that are not loaded whose placements are kept in memory.
+    pub max_deferred: usize,
+    in_flight: HashMap<Entity, Arc<AtomicBool>>,
+    sender: Sender<StepResult>,
+    receiver: Receiver<StepResult>,
+}
+
+impl Default for ChunkScheduler {
+    fn default() -> Self {
+        let (sender, receiver) = mpsc::channel();
+
+        ChunkScheduler {
+            max_in_flight: 32,
+            full_per_tick: 16,
+            max_deferred: 1024,
+            in_flight: HashMap::new(),
+            sender,
+            receiver,
+        }
+    }
+}
+
+impl ChunkScheduler {
+    /// Runs a step in the workers. The light from above is only used by [ChunkStatus::Lit] and it's
+    /// taken from the sky if there's no chunk above.
+    fn spawn_step(&mut self, context: &StepContext, step: Step, chunk: Chunk, top: Option<TopLight>) {
+        let StepContext { workers, generation, registry, store } = *context;
+        let (next, position, entity) = step;
+        let cancelled = Arc::new(AtomicBool::new(false));
+        self.in_flight.insert(entity, cancelled.clone());
+
+        let generation = generation.clone();
+        let registry = registry.clone();
+        let store = store.clone();
+        let sender = self.sender.clone();
+        let mut chunk = chunk;
+
+        workers.spawn(move || {
+            if cancelled.load(Ordering::Relaxed) {
+                return;
+            }
+
+            // Chunks that were saved before are already full. The entities and the placements of a
+            // chunk are loaded with it, even if its blocks were never saved.
+            let mut entities = None;
+            let mut placements = None;
+
+            if next == ChunkStatus::Terrain {
+                entities = store.load_entities(&position);
+                placements = store.load_placements(&position);
+
+                if let Some(chunk) = store.load(&position) {
+                    let status = ChunkStatus::Full;
+                    let _ = sender.send(StepResult { entity, position, status, chunk, entities, placements });
+                    return;
+                }
+            }
+
+            match next {
+                ChunkStatus::Terrain => chunk = generation.generator.generate(&position, generation.seed),
+                ChunkStatus::Carved | ChunkStatus::Decorated => {
+                    generation.generator.advance(&mut chunk, &position, next, generation.seed)
+                }
+                ChunkStatus::Lit => {
+                    let top = top.unwrap_or_else(|| sky_light(&generation, &position));
+                    light::light_chunk(&mut chunk, &registry, &top)
+                }
+                ChunkStatus::Empty | ChunkStatus::Full => {}
+            }
+
+            if !cancelled.load(Ordering::Relaxed) {
+                let _ = sender.send(StepResult { entity, position, status: next, chunk, entities, placements });
+            }
+        });
+    }
+}
+
+/// The status that a chunk goes to, its position and its entity.
+type Step = (ChunkStatus, ChunkPosition, Entity);
+
+/// Resources that the jobs of the steps need.
+struct StepContext<'a> {
+    workers: &'a Workers,
+    generation: &'a Generation,
+    registry: &'a BlockRegistry,
+    store: &'a ChunkStore,
+}
+
+impl<'a> System<'a> for ChunkScheduler {
+    type SystemData = (
+        Entities<'a>,
+        ReadExpect<'a, Generation>,
+        ReadExpect<'a, BlockRegistry>,
+        Read<'a, Workers>,
+        Read<'a, ChunkStore>,
+        Read<'a, DeferredPlacements>,
+        Write<'a, ChunkRequests>,
+        WriteStorage<'a, Position>,
+        WriteStorage<'a, Chunk>,
+        WriteStorage<'a, ChunkStatus>,
+        WriteStorage<'a, Created>,
+        WriteStorage<'a, Unsaved>,
+        WriteStorage<'a, SavedEntities>,
+    );
+
+    fn run(
+        &mut self,
+        (entities, generation, registry, workers, store, deferred, mut requests, mut positions, mut chunks, mut statuses, mut created, mut unsaved, mut saved): Self::SystemData,
+    ) {
+        for result in self.receiver.try_iter() {
+            if self.in_flight.remove(&result.entity).is_none() || !entities.is_alive(result.entity) {
+                continue;
+            }
+
+            chunks.insert(result.entity, result.chunk).unwrap();
+            statuses.insert(result.entity, result.status).unwrap();
+
+            if result.status == ChunkStatus::Full {
+                created.insert(result.entity, Created).unwrap();
+            }
+
+            if let Some(entities) = result.entities {
+                saved.insert(result.entity, SavedEntities(entities)).unwrap();
+            }
+
+            if let Some(placements) = result.placements {
+                match decoration::decode_placements(&placements) {
+                    Some(sources) => deferred.load(result.position, sources),
+                    None => log::error!("the saved placements of the chunk {:?} are broken", result.position),
+                }
+            }
+        }
+
+        let mut world: HashMap<ChunkPosition, (Entity, ChunkStatus)> = HashMap::new();
+
+        for (entity, position, status) in (&entities, &positions, &statuses).join() {
+            world.insert(ChunkPosition::of_entity(position), (entity, *status));
+        }
+
+        for position in std::mem::take(&mut requests.requested) {
+            if world.contains_key(&position) {
+                continue;
+            }
+
+            let entity = entities.create();
+            let status = ChunkStatus::Empty;
+            positions
+                .insert(entity, Position::new(position.x as f32, position.y as f32, position.z as f32))
+                .unwrap();
+            chunks.insert(entity, Chunk::default()).unwrap();
+            statuses.insert(entity, status).unwrap();
+            world.insert(position, (entity, status));
+        }
+
+        for position in std::mem::take(&mut requests.cancelled) {
+            if let Some((entity, status)) = world.get(&position).copied() {
+                if status < ChunkStatus::Full {
+                    entities.delete(entity).unwrap();
+                    world.remove(&position);
+                }
+            }
+        }
+
+        // Jobs of the entities that are gone are never going to be needed.
+        self.in_flight.retain(|entity, cancelled| {
+            let alive = entities.is_alive(*entity);
+            if !alive {
+                cancelled.store(true, Ordering::Relaxed);
+            }
+            alive
+        });
+
+        let ready = |position: &ChunkPosition, status: ChunkStatus| {
+            let Some(requirement) = status.requirement() else { return true };
+
+            let neighbours_ready = neighbours(position).all(|neighbour| match world.get(&neighbour) {
+                Some((_, status)) => *status >= requirement,
+                None => neighbour.y != position.y,
+            });
+
+            let above_ready = status != ChunkStatus::Lit
+                || world.get(&above(position)).is_none_or(|(_, status)| *status >= ChunkStatus::Lit);
+
+            neighbours_ready && above_ready
+        };
+
+        // Blocks that were left for other chunks go to the ones that are not in the workers and
+        // whose neighbours cannot leave any more of them, and the placed ones to full chunks. Chunks
+        // that were already lit are lit again, and full chunks are meshed again.
+        for waiting in deferred.positions() {
+            let Some((entity, status)) = world.get(&waiting) else { continue };
+
+            let complete = match status {
+                ChunkStatus::Decorated => ready(&waiting, ChunkStatus::Lit),
+                status => *status > ChunkStatus::Decorated,
+            };
+
+            if complete && !self.in_flight.contains_key(entity) {
+                let light = (*status >= ChunkStatus::Lit).then(|| {
+                    let top = world
+                        .get(&above(&waiting))
+                        .and_then(|(above, _)| chunks.get(*above))
+                        .map(light::bottom_light)
+                        .unwrap_or_else(|| sky_light(&generation, &waiting));
+                    let full = *status == ChunkStatus::Full;
+                    (top, if full { sides(&world, &chunks, &waiting) } else { Default::default() })
+                });
+
+                if let Some(chunk) = chunks.get_mut(*entity) {
+                    let (placements, stored) = deferred.take(&waiting);
+
+                    for placement in placements {
+                        placement.apply(chunk);
+                    }
+
+                    if let Some((top, sides)) = light {
+                        light::light_chunk(chunk, &registry, &top);
+                        light::light_borders(chunk, &registry, sides.each_ref().map(Option::as_ref));
+                    }
+
+                    if stored {
+                        store.save_placements_later(&workers, waiting, Vec::new());
+                    }
+
+                    if *status == ChunkStatus::Full {
+                        created.insert(*entity, Created).unwrap();
+                        unsaved.insert(*entity, Unsaved).unwrap();
+                    }
+                }
+            }
+        }
+
+        if deferred.len() > self.max_deferred {
+            deferred.evict(&store, &workers, |position| world.contains_key(position));
+        }
+
+        // The chunks that are the least far in the pipeline go first, and the higher ones before the
+        // ones under them so the light goes down quickly.
+        let mut steps: Vec<Step> = world
+            .iter()
+            .filter(|(_, (entity, _))| !self.in_flight.contains_key(entity))
+            .filter_map(|(position, (entity, status))| Some((status.next()?, *position, *entity)))
+            .filter(|(next, position, _)| ready(position, *next))
+            .collect();
+
+        steps.sort_by_key(|(next, position, _)| (*next, std::cmp::Reverse(position.y), position.x, position.z));
+
+        let context = StepContext {
+            workers: &workers,
+            generation: &generation,
+            registry: &registry,
+            store: &store,
+        };
+
+        let mut full = 0;
+
+        for step in steps {
+            let (next, position, entity) = step;
+
+            if next != ChunkStatus::Full {
+                if self.in_flight.len() < self.max_in_flight {
+                    let chunk = chunks.get(entity).cloned().unwrap_or_default();
+                    let top = world
+                        .get(&above(&position))
+                        .and_then(|(above, _)| chunks.get(*above))
+                        .map(light::bottom_light);
+                    self.spawn_step(&context, step, chunk, top);
+                }
+                continue;
+            }
+
+            if full == self.full_per_tick {
+                continue;
+            }
+            full += 1;
+
+            // The neighbours are lit, so none of them is in the workers.
+            let sides = sides(&world, &chunks, &position);
+
+            if let Some(chunk) = chunks.get_mut(entity) {
+                light::light_borders(chunk, &registry, sides.each_ref().map(Option::as_ref));
+            }
+
+            created.insert(entity, Created).unwrap();
+            unsaved.insert(entity, Unsaved).unwrap();
+            statuses.insert(entity, next).unwrap();
+            world.insert(position, (entity, next));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use specs::{RunNow, World, WorldExt};
+
+    use crate::{
+        block::BlockPosition,
+        generation::{
+            decoration::{Placement, Replace},
+            WorldGenerator,
+        },
+    };
+
+    use super::*;
+
+    /// A world of air.
+    struct Empty;
+
+    impl WorldGenerator for Empty {
+        fn generate(&self, _position: &ChunkPosition, _seed: u64) -> Chunk {
+            Chunk::default()
+        }
+    }
+
+    fn status(world: &World, position: &ChunkPosition) -> Option<ChunkStatus> {
+        let (positions, statuses) = (world.read_storage::<Position>(), world.read_storage::<ChunkStatus>());
+        (&positions, &statuses)
+            .join()
+            .find(|(chunk, _)| ChunkPosition::of_entity(chunk) == *position)
+            .map(|(_, status)| *status)
+    }
+
+    fn chunk(world: &World, position: &ChunkPosition) -> (Entity, Chunk) {
+        let (entities, positions, chunks) =
+            (world.entities(), world.read_storage::<Position>(), world.read_storage::<Chunk>());
+        (&entities, &positions, &chunks)
+            .join()
+            .find(|(_, chunk, _)| ChunkPosition::of_entity(chunk) == *position)
+            .map(|(entity, _, chunk)| (entity, chunk.clone()))
+            .unwrap()
+    }
+
+    #[test]
+    fn placements_in_full_chunks_are_lit() {
+        let mut world = World::new();
+        let mut scheduler = ChunkScheduler::default();
+        System::setup(&mut scheduler, &mut world);
+        world.insert(Generation::new(0, Empty));
+        world.insert(BlockRegistry::default());
+
+        // The chunks around the center one must be lit for it to be full.
+        for x in -3..=3 {
+            for z in -3..=3 {
+                world.write_resource::<ChunkRequests>().request(ChunkPosition::new(x, 0, z));
+            }
+        }
+
+        let center = ChunkPosition::new(0, 0, 0);
+        let start = Instant::now();
+
+        while status(&world, &center) != Some(ChunkStatus::Full) {
+            assert!(start.elapsed() < Duration::from_secs(30), "the chunk never got full");
+            scheduler.run_now(&world);
+            world.maintain();
+            std::thread::sleep(Duration::from_millis(1));
+        }
+
+        let (entity, full) = chunk(&world, &center);
+        let under = BlockPosition::new(1, CHUNK_HEIGHT as i64 - 2, 1);
+        assert_eq!(full.light(&under), MAX_LIGHT);
+        world.write_storage::<Created>().clear();
+        world.write_storage::<Unsaved>().clear();
+
+        // A block at the top of a column that is placed later shades the blocks under it.
+        let top = BlockPosition::new(1, CHUNK_HEIGHT as i64 - 1, 1);
+        let deferred = world.read_resource::<DeferredPlacements>();
+        let placed = deferred.next_placed();
+        deferred.push(center, placed, vec![Placement { position: top, block: 1, replace: Replace::Any }]);
+        drop(deferred);
+
+        scheduler.run_now(&world);
+
+        let (_, placed) = chunk(&world, &center);
+        assert_eq!(placed.get(&top), 1);
+        assert_eq!(placed.light(&under), MAX_LIGHT - 1);
+        assert!(world.read_storage::<Created>().contains(entity));
+        assert!(world.read_storage::<Unsaved>().contains(entity));
+    }
+}
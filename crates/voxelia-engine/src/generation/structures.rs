@@ -0,0 +1,346 @@
+//! Structures like villages, dungeons and ruins, made out of [StructureTemplate]s that can span many
+//! chunks.
+//!
+//! The world is split into regions of chunks and each structure can start in one chunk of each
+//! region. The pieces of a structure are assembled again by every chunk that they may reach, from
+//! the seed and the region alone, so the order that chunks are generated in does not matter. The
+//! layouts are cached to avoid assembling them for every chunk.
+
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+
+use crate::{
+    biome::{BiomeId, BiomeRegistry},
+    block::{BlockDefinition, BlockId, BlockPosition, BlockRegistry, Transparency},
+    chunk::{Chunk, ChunkPosition, CHUNK_HEIGHT, CHUNK_LENGTH, CHUNK_WIDTH},
+    random::Random,
+};
+
+use super::{terrain::TerrainGenerator, ChunkStatus, GenerationStage};
+
+pub mod jigsaw;
+pub mod template;
+
+use jigsaw::{JigsawSettings, PlacedPiece, StructurePools};
+use template::StructureTemplate;
+
+/// Number of layouts kept in the cache before it is cleared.
+const CACHE_SIZE: usize = 4096;
+
+/// Layouts by the index of the structure, the seed and the region.
+type LayoutCache = HashMap<(usize, u64, i64, i64), Arc<Vec<PlacedPiece>>>;
+
+/// Where the start of a structure goes vertically.
+#[derive(Clone, Debug)]
+pub enum StructureHeight {
+    /// The lowest layer of the start replaces the ground, only above the sea.
+    Surface,
+    /// Buried this many blocks under the ground.
+    Buried(i64),
+}
+
+/// Placement rules of a structure.
+#[derive(Clone, Debug)]
+pub struct Structure {
+    pub name: String,
+    /// Pool of the first piece of the structure.
+    pub start_pool: String,
+    /// Size of the regions in chunks, there's at most one structure in each region.
+    pub spacing: i64,
+    /// Minimum distance in chunks between structures of neighbouring regions.
+    pub separation: i64,
+    /// Makes structures with the same spacing start in different chunks.
+    pub salt: u64,
+    /// Biomes where the structure can start, any biome if it's empty.
+    pub biomes: Vec<BiomeId>,
+    pub height: StructureHeight,
+    pub jigsaw: JigsawSettings,
+}
+
+/// Blocks used by the default templates that are not used by the terrain.
+#[derive(Copy, Clone, Debug)]
+pub struct StructureBlocks {
+    pub planks: BlockId,
+    pub cobblestone: BlockId,
+    pub gravel: BlockId,
+}
+
+impl StructureBlocks {
+    /// Gets the structure blocks from the registry, registering the ones that are missing.
+    pub fn register(registry: &mut BlockRegistry) -> StructureBlocks {
+        StructureBlocks {
+            planks: registry.get_or_register(BlockDefinition::new("planks", Transparency::Opaque, 0)),
+            cobblestone: registry.get_or_register(BlockDefinition::new("cobblestone", Transparency::Opaque, 0)),
+            gravel: registry.get_or_register(BlockDefinition::new("gravel", Transparency::Opaque, 0)),
+        }
+    }
+}
+
+/// The default pools of templates, that are embedded in the engine.
+pub fn default_pools(registry: &BlockRegistry) -> StructurePools {
+    let templates = [
+        ("village/start", include_str!("../../../../assets/structures/well.ron"), 1),
+        ("village/streets", include_str!("../../../../assets/structures/street.ron"), 1),
+        ("village/houses", include_str!("../../../../assets/structures/house.ron"), 3),
+        ("village/houses", include_str!("../../../../assets/structures/garden.ron"), 1),
+        ("ruins", include_str!("../../../../assets/structures/ruin.ron"), 1),
+        ("dungeon/rooms", include_str!("../../../../assets/structures/room.ron"), 1),
+        ("dungeon/corridors", include_str!("../../../../assets/structures/corridor.ron"), 1),
+    ];
+
+    let mut pools = StructurePools::default();
+
+    for (pool, source, weight) in templates {
+        let template = StructureTemplate::parse(source, registry).expect("embedded templates are valid");
+        pools.add(pool, Arc::new(template), weight);
+    }
+
+    pools
+}
+
+/// The default structures. Villages only start in the biomes with grass.
+pub fn default_structures(biomes: &BiomeRegistry) -> Vec<Structure> {
+    let village_biomes = ["plains", "forest"]
+        .iter()
+        .filter_map(|name| biomes.by_name(name))
+        .collect();
+
+    vec![
+        Structure {
+            name: "village".to_owned(),
+            start_pool: "village/start".to_owned(),
+            spacing: 12,
+            separation: 4,
+            salt: 0x7669_6C6C_6167,
+            biomes: village_biomes,
+            height: StructureHeight::Surface,
+            jigsaw: JigsawSettings { max_depth: 5, max_extent: 40, attempts: 4 },
+        },
+        Structure {
+            name: "ruin".to_owned(),
+            start_pool: "ruins".to_owned(),
+            spacing: 8,
+            separation: 3,
+            salt: 0x7275_696E,
+            biomes: Vec::new(),
+            height: StructureHeight::Surface,
+            jigsaw: JigsawSettings { max_depth: 0, max_extent: 8, attempts: 1 },
+        },
+        Structure {
+            name: "dungeon".to_owned(),
+            start_pool: "dungeon/rooms".to_owned(),
+            spacing: 10,
+            separation: 3,
+            salt: 0x6475_6E67_656F,
+            biomes: Vec::new(),
+            height: StructureHeight::Buried(12),
+            jigsaw: JigsawSettings { max_depth: 4, max_extent: 32, attempts: 2 },
+        },
+    ]
+}
+
+/// Stage that writes the pieces of the structures that touch each chunk.
+pub struct StructureStage {
+    pub structures: Vec<Structure>,
+    pub pools: StructurePools,
+    /// Used to find the ground and the biome where each structure starts.
+    pub terrain: Arc<TerrainGenerator>,
+    cache: Mutex<LayoutCache>,
+}
+
+impl StructureStage {
+    pub fn new(structures: Vec<Structure>, pools: StructurePools, terrain: Arc<TerrainGenerator>) -> Self {
+        StructureStage {
+            structures,
+            pools,
+            terrain,
+            cache: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Assembles the structure of a region, it's empty if the structure does not start there.
+    fn layout(&self, index: usize, region_x: i64, region_z: i64, seed: u64) -> Arc<Vec<PlacedPiece>> {
+        if let Some(layout) = self.cache.lock().unwrap().get(&(index, seed, region_x, region_z)) {
+            return layout.clone();
+        }
+
+        let structure = &self.structures[index];
+        let layout = Arc::new(self.assemble(structure, region_x, region_z, seed).unwrap_or_default());
+
+        let mut cache = self.cache.lock().unwrap();
+        if cache.len() >= CACHE_SIZE {
+            cache.clear();
+        }
+        cache.insert((index, seed, region_x, region_z), layout.clone());
+
+        layout
+    }
+
+    fn assemble(&self, structure: &Structure, region_x: i64, region_z: i64, seed: u64) -> Option<Vec<PlacedPiece>> {
+        let mut random = Random::at(seed ^ structure.salt, region_x, 0, region_z);
+        let range = (structure.spacing - structure.separation).max(1);
+
+        let chunk_x = region_x * structure.spacing + random.range(0, range);
+        let chunk_z = region_z * structure.spacing + random.range(0, range);
+
+        let x = chunk_x * CHUNK_WIDTH as i64 + CHUNK_WIDTH as i64 / 2;
+        let z = chunk_z * CHUNK_LENGTH as i64 + CHUNK_LENGTH as i64 / 2;
+
+        let (biome, height) = self.terrain.column(x, z, seed)?;
+
+        if !structure.biomes.is_empty() && !structure.biomes.contains(&biome) {
+            return None;
+        }
+
+        let y = match structure.height {
+            StructureHeight::Surface if height < self.terrain.settings.sea_level => return None,
+            StructureHeight::Surface => height,
+            StructureHeight::Buried(depth) => height - depth,
+        };
+
+        let pieces = jigsaw::assemble(&self.pools, &structure.start_pool, [x, y, z], &structure.jigsaw, &mut random);
+        Some(pieces)
+    }
+}
+
+impl GenerationStage for StructureStage {
+    fn status(&self) -> ChunkStatus {
+        ChunkStatus::Decorated
+    }
+
+    fn apply(&self, chunk: &mut Chunk, position: &ChunkPosition, seed: u64) {
+        let origin = position.origin();
+        let size = [CHUNK_WIDTH as i64, CHUNK_HEIGHT as i64, CHUNK_LENGTH as i64];
+        let (chunk_x, chunk_z) = (position.x, position.z);
+
+        for (index, structure) in self.structures.iter().enumerate() {
+            // Structures that start farther than this cannot reach the chunk.
+            let reach = structure.jigsaw.max_extent / CHUNK_WIDTH.min(CHUNK_LENGTH) as i64 + 2;
+
+            let regions_x = (chunk_x - reach).div_euclid(structure.spacing)..=(chunk_x + reach).div_euclid(structure.spacing);
+            let regions_z = (chunk_z - reach).div_euclid(structure.spacing)..=(chunk_z + reach).div_euclid(structure.spacing);
+
+            for region_x in regions_x {
+                for region_z in regions_z.clone() {
+                    for piece in self.layout(index, region_x, region_z, seed).iter() {
+                        let (min, max) = piece.bounds();
+                        let touches = (0..3).all(|axis| min[axis] < origin[axis] + size[axis] && max[axis] >= origin[axis]);
+
+                        if !touches {
+                            continue;
+                        }
+
+                        for (local, block) in piece.template.blocks() {
+                            let global = piece.global(local);
+                            let mut position = BlockPosition::new(
+                                global[0] - origin[0],
+                                global[1] - origin[1],
+                                global[2] - origin[2],
+                            );
+
+                            if !position.is_out() {
+                                chunk.set(&position, block);
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::generation::{
+        decoration::FeatureBlocks,
+        terrain::{TerrainBlocks, TerrainSettings},
+        WorldGenerator,
+    };
+
+    use super::{template::Transform, *};
+
+    const SEED: u64 = 11;
+
+    fn stage() -> StructureStage {
+        let mut registry = BlockRegistry::default();
+        let blocks = TerrainBlocks::register(&mut registry);
+        FeatureBlocks::register(&mut registry);
+        StructureBlocks::register(&mut registry);
+
+        let mut biomes = BiomeRegistry::default();
+        for biome in blocks.biomes() {
+            biomes.register(biome);
+        }
+
+        let structures = default_structures(&biomes);
+        let terrain = TerrainGenerator { settings: TerrainSettings::default(), blocks, biomes };
+        StructureStage::new(structures, default_pools(&registry), Arc::new(terrain))
+    }
+
+    fn pieces(layout: &[PlacedPiece]) -> Vec<(String, [i64; 3], Transform)> {
+        layout.iter().map(|piece| (piece.template.name.clone(), piece.origin, piece.transform)).collect()
+    }
+
+    /// Chunks touched by the first village with more than one piece.
+    fn village_chunks(stage: &StructureStage) -> Vec<ChunkPosition> {
+        let layout = (0..64)
+            .map(|region| stage.layout(0, region % 8, region / 8, SEED))
+            .find(|layout| layout.len() > 1)
+            .expect("there is a village around the origin");
+
+        let mut chunks = Vec::new();
+        for piece in layout.iter() {
+            let (min, max) = piece.bounds();
+            for x in min[0].div_euclid(CHUNK_WIDTH as i64)..=max[0].div_euclid(CHUNK_WIDTH as i64) {
+                for y in min[1].div_euclid(CHUNK_HEIGHT as i64)..=max[1].div_euclid(CHUNK_HEIGHT as i64) {
+                    for z in min[2].div_euclid(CHUNK_LENGTH as i64)..=max[2].div_euclid(CHUNK_LENGTH as i64) {
+                        chunks.push(ChunkPosition::new(x, y, z));
+                    }
+                }
+            }
+        }
+
+        chunks.sort();
+        chunks.dedup();
+        chunks
+    }
+
+    #[test]
+    fn layouts_only_depend_on_the_seed_and_the_region() {
+        let (first, second) = (stage(), stage());
+
+        for index in 0..first.structures.len() {
+            for region in -3..3 {
+                let layout = first.layout(index, region, -region, SEED);
+                assert_eq!(pieces(&layout), pieces(&second.layout(index, region, -region, SEED)));
+                // Cached layouts are the same as the ones assembled again.
+                assert_eq!(pieces(&layout), pieces(&first.layout(index, region, -region, SEED)));
+            }
+        }
+    }
+
+    #[test]
+    fn chunks_get_the_same_blocks_in_any_order() {
+        let (first, second) = (stage(), stage());
+        let chunks = village_chunks(&first);
+        assert!(chunks.len() > 1);
+
+        let generate = |stage: &StructureStage, position: &ChunkPosition| {
+            let mut chunk = stage.terrain.generate(position, SEED);
+            stage.apply(&mut chunk, position, SEED);
+            chunk
+        };
+
+        let forward: Vec<_> = chunks.iter().map(|position| generate(&first, position)).collect();
+        let backward: Vec<_> = chunks.iter().rev().map(|position| generate(&second, position)).collect();
+
+        let mut changed = false;
+        for ((position, forward), backward) in chunks.iter().zip(&forward).zip(backward.iter().rev()) {
+            assert_eq!(forward.data(), backward.data(), "{position:?}");
+            changed |= forward.data() != first.terrain.generate(position, SEED).data();
+        }
+        assert!(changed, "the village is written into the chunks");
+    }
+}
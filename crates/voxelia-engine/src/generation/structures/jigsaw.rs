@@ -0,0 +1,246 @@
+//! Assembly of structures out of pieces that are attached to each other by their connectors.
+
+use std::{collections::{HashMap, VecDeque}, sync::Arc};
+
+use crate::random::Random;
+
+use super::template::{StructureTemplate, Transform};
+
+/// Weighted lists of templates by the name of the pool.
+#[derive(Clone, Default)]
+pub struct StructurePools {
+    pools: HashMap<String, Vec<(Arc<StructureTemplate>, u32)>>,
+}
+
+impl StructurePools {
+    pub fn add(&mut self, pool: &str, template: Arc<StructureTemplate>, weight: u32) {
+        self.pools.entry(pool.to_owned()).or_default().push((template, weight));
+    }
+
+    /// Chooses a template of the pool, the ones with more weight are chosen more often.
+    pub fn pick(&self, pool: &str, random: &mut Random) -> Option<&Arc<StructureTemplate>> {
+        let templates = self.pools.get(pool)?;
+        let total: u32 = templates.iter().map(|(_, weight)| weight).sum();
+
+        if total == 0 {
+            return None;
+        }
+
+        let mut choice = random.range(0, total as i64) as u32;
+        for (template, weight) in templates {
+            if choice < *weight {
+                return Some(template);
+            }
+            choice -= weight;
+        }
+
+        None
+    }
+}
+
+/// A template placed in the world. `origin` is the global position of its lowest corner after the
+/// transform.
+#[derive(Clone)]
+pub struct PlacedPiece {
+    pub template: Arc<StructureTemplate>,
+    pub transform: Transform,
+    pub origin: [i64; 3],
+}
+
+impl PlacedPiece {
+    pub fn size(&self) -> [i64; 3] {
+        self.transform.size(self.template.size)
+    }
+
+    /// The lowest and the highest corners of the piece, both inclusive.
+    pub fn bounds(&self) -> ([i64; 3], [i64; 3]) {
+        let size = self.size();
+        (self.origin, [0, 1, 2].map(|axis| self.origin[axis] + size[axis] - 1))
+    }
+
+    fn intersects(&self, other: &PlacedPiece) -> bool {
+        let (min, max) = self.bounds();
+        let (other_min, other_max) = other.bounds();
+        (0..3).all(|axis| min[axis] <= other_max[axis] && other_min[axis] <= max[axis])
+    }
+
+    /// Global position of a point of the template.
+    pub fn global(&self, position: [i64; 3]) -> [i64; 3] {
+        let local = self.transform.apply(position, self.template.size);
+        [0, 1, 2].map(|axis| self.origin[axis] + local[axis])
+    }
+}
+
+/// Limits of the assembly of a structure.
+#[derive(Clone, Debug)]
+pub struct JigsawSettings {
+    /// Number of pieces between the start and the farthest piece.
+    pub max_depth: u32,
+    /// Pieces are never placed farther than this, in blocks from the start on x and z.
+    pub max_extent: i64,
+    /// Templates that are tried before leaving a connector empty.
+    pub attempts: u32,
+}
+
+/// Checks if a piece goes farther than the [JigsawSettings::max_extent] from the `origin` of the
+/// structure on x or z.
+fn too_far(piece: &PlacedPiece, origin: [i64; 3], settings: &JigsawSettings) -> bool {
+    let (min, max) = piece.bounds();
+    [0, 2].iter().any(|axis| {
+        (min[*axis] - origin[*axis]).abs() > settings.max_extent || (max[*axis] - origin[*axis]).abs() > settings.max_extent
+    })
+}
+
+/// Builds a structure starting with a template of the `start` pool with its lowest corner at
+/// `origin`. Pieces never overlap, the connectors that cannot get a piece stay empty. Starts that
+/// are bigger than the extent give no structure at all.
+pub fn assemble(
+    pools: &StructurePools,
+    start: &str,
+    origin: [i64; 3],
+    settings: &JigsawSettings,
+    random: &mut Random,
+) -> Vec<PlacedPiece> {
+    let Some(template) = pools.pick(start, random) else {
+        return Vec::new();
+    };
+
+    let transform = Transform {
+        rotation: random.range(0, 4) as u8,
+        mirror: random.chance(0.5),
+    };
+
+    let start = PlacedPiece { template: template.clone(), transform, origin };
+
+    // The chunks only look for the structures whose start is close enough for the extent to reach
+    // them, so the start cannot go farther either.
+    if too_far(&start, origin, settings) {
+        return Vec::new();
+    }
+
+    let mut pieces = vec![start];
+    let mut open = VecDeque::new();
+
+    for index in 0..template.connectors.len() {
+        open.push_back((0, index, 0));
+    }
+
+    while let Some((piece, connector, depth)) = open.pop_front() {
+        if depth >= settings.max_depth {
+            continue;
+        }
+
+        let parent = pieces[piece].clone();
+        let connector = &parent.template.connectors[connector];
+        let Some(pool) = &connector.pool else { continue };
+
+        let (x, y, z) = connector.position;
+        let facing = parent.transform.facing(connector.facing);
+        let offset = facing.offset();
+        let [x, y, z] = parent.global([x, y, z]);
+        let target = [x + offset.x, y + offset.y, z + offset.z];
+
+        for _ in 0..settings.attempts {
+            let Some(template) = pools.pick(pool, random) else { break };
+
+            let transform = Transform {
+                rotation: random.range(0, 4) as u8,
+                mirror: random.chance(0.5),
+            };
+
+            let matching: Vec<_> = template
+                .connectors
+                .iter()
+                .enumerate()
+                .filter(|(_, other)| transform.facing(other.facing) == facing.opposite())
+                .map(|(index, _)| index)
+                .collect();
+
+            if matching.is_empty() {
+                continue;
+            }
+
+            let index = matching[random.range(0, matching.len() as i64) as usize];
+            let (cx, cy, cz) = template.connectors[index].position;
+            let local = transform.apply([cx, cy, cz], template.size);
+
+            let candidate = PlacedPiece {
+                template: template.clone(),
+                transform,
+                origin: [0, 1, 2].map(|axis| target[axis] - local[axis]),
+            };
+
+            if too_far(&candidate, origin, settings) || pieces.iter().any(|piece| piece.intersects(&candidate)) {
+                continue;
+            }
+
+            pieces.push(candidate);
+
+            let added = pieces.len() - 1;
+            for other in 0..template.connectors.len() {
+                if other != index {
+                    open.push_back((added, other, depth + 1));
+                }
+            }
+
+            break;
+        }
+    }
+
+    pieces
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::generation::structures::template::{Connector, Facing};
+
+    fn template(name: &str, size: [i64; 3], connectors: Vec<Connector>) -> Arc<StructureTemplate> {
+        let volume = size.iter().product::<i64>() as usize;
+        Arc::new(StructureTemplate::new(name, size, vec![Some(1); volume], connectors))
+    }
+
+    fn connector(position: (i64, i64, i64), facing: Facing, pool: Option<&str>) -> Connector {
+        Connector { position, facing, pool: pool.map(str::to_owned) }
+    }
+
+    fn pools() -> StructurePools {
+        let mut pools = StructurePools::default();
+        let hall = |name| {
+            template(name, [3, 2, 3], vec![
+                connector((1, 0, 0), Facing::North, Some("halls")),
+                connector((1, 0, 2), Facing::South, Some("halls")),
+                connector((0, 0, 1), Facing::West, Some("halls")),
+                connector((2, 0, 1), Facing::East, Some("halls")),
+            ])
+        };
+
+        pools.add("start", hall("start"), 1);
+        pools.add("halls", hall("hall"), 1);
+        pools.add("big", template("big", [20, 1, 2], Vec::new()), 1);
+        pools
+    }
+
+    fn settings(max_extent: i64) -> JigsawSettings {
+        JigsawSettings { max_depth: 6, max_extent, attempts: 3 }
+    }
+
+    #[test]
+    fn pieces_stay_inside_of_the_extent_without_overlapping() {
+        let pieces = assemble(&pools(), "start", [100, 10, -50], &settings(7), &mut Random::new(3));
+
+        assert!(pieces.len() > 1);
+        for (index, piece) in pieces.iter().enumerate() {
+            assert!(!too_far(piece, [100, 10, -50], &settings(7)));
+            assert!(pieces[..index].iter().all(|other| !other.intersects(piece)));
+        }
+    }
+
+    #[test]
+    fn starts_bigger_than_the_extent_are_not_placed() {
+        let pools = pools();
+
+        assert!(assemble(&pools, "big", [0, 0, 0], &settings(8), &mut Random::new(1)).is_empty());
+        assert_eq!(assemble(&pools, "big", [0, 0, 0], &settings(20), &mut Random::new(1)).len(), 1);
+    }
+}
@@ -0,0 +1,418 @@
+//! Structure templates and the file format that they are loaded from.
+//!
+//! Templates are written in RON. The blocks are given as layers from the bottom to the top, each
+//! layer is a list of rows along z and each character of a row is a block along x. The characters
+//! are looked up in the palette, except for spaces that keep the block that was already there.
+//!
+//! ```ron
+//! (
+//!     name: "hut",
+//!     palette: { 'S': "stone", '.': "air" },
+//!     layers: [
+//!         ["SSS", "SSS", "SSS"],
+//!         ["S.S", "...", "S.S"],
+//!     ],
+//!     connectors: [
+//!         (position: (1, 0, 0), facing: North, pool: Some("paths")),
+//!     ],
+//! )
+//! ```
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt, fs,
+    path::Path,
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::block::{BlockId, BlockPosition, BlockRegistry, AIR};
+
+#[derive(Debug)]
+pub enum TemplateError {
+    Io(std::io::Error),
+    Parse(ron::error::SpannedError),
+    UnknownBlock(String),
+    UnknownCharacter(char),
+    /// The layers or the rows do not have all the same size.
+    Ragged,
+    /// The template has a block that is not in the registry, so it cannot be written.
+    UnknownId(BlockId),
+    Write(ron::Error),
+}
+
+impl fmt::Display for TemplateError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TemplateError::Io(error) => write!(f, "cannot read the template: {error}"),
+            TemplateError::Parse(error) => write!(f, "cannot parse the template: {error}"),
+            TemplateError::UnknownBlock(name) => write!(f, "unknown block '{name}'"),
+            TemplateError::UnknownCharacter(c) => write!(f, "character '{c}' is not in the palette"),
+            TemplateError::Ragged => write!(f, "the layers and rows must all have the same size"),
+            TemplateError::UnknownId(id) => write!(f, "the block {id} is not registered"),
+            TemplateError::Write(error) => write!(f, "cannot write the template: {error}"),
+        }
+    }
+}
+
+impl std::error::Error for TemplateError {}
+
+/// Horizontal direction that a connector is looking at.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Facing {
+    /// Towards -z.
+    North,
+    /// Towards +x.
+    East,
+    /// Towards +z.
+    South,
+    /// Towards -x.
+    West,
+}
+
+impl Facing {
+    const ALL: [Facing; 4] = [Facing::North, Facing::East, Facing::South, Facing::West];
+
+    pub fn offset(&self) -> BlockPosition {
+        match self {
+            Facing::North => BlockPosition::new(0, 0, -1),
+            Facing::East => BlockPosition::new(1, 0, 0),
+            Facing::South => BlockPosition::new(0, 0, 1),
+            Facing::West => BlockPosition::new(-1, 0, 0),
+        }
+    }
+
+    pub fn opposite(&self) -> Facing {
+        self.rotate(2)
+    }
+
+    /// Turns clockwise when looking from above.
+    pub fn rotate(&self, quarter_turns: u8) -> Facing {
+        Facing::ALL[(*self as usize + quarter_turns as usize) % 4]
+    }
+}
+
+/// Rotation and mirroring of a template. The template is mirrored along x before being rotated.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct Transform {
+    /// Quarter turns clockwise when looking from above.
+    pub rotation: u8,
+    pub mirror: bool,
+}
+
+impl Transform {
+    /// Size of the template after the transform.
+    pub fn size(&self, size: [i64; 3]) -> [i64; 3] {
+        if self.rotation % 2 == 1 {
+            [size[2], size[1], size[0]]
+        } else {
+            size
+        }
+    }
+
+    /// Moves a position inside of a template with the given size.
+    pub fn apply(&self, position: [i64; 3], size: [i64; 3]) -> [i64; 3] {
+        let [mut x, y, mut z] = position;
+        let [mut width, _, mut length] = size;
+
+        if self.mirror {
+            x = width - 1 - x;
+        }
+
+        for _ in 0..self.rotation % 4 {
+            (x, z) = (length - 1 - z, x);
+            (width, length) = (length, width);
+        }
+
+        [x, y, z]
+    }
+
+    pub fn facing(&self, facing: Facing) -> Facing {
+        let facing = match (self.mirror, facing) {
+            (true, Facing::East) => Facing::West,
+            (true, Facing::West) => Facing::East,
+            (_, facing) => facing,
+        };
+
+        facing.rotate(self.rotation)
+    }
+}
+
+/// A point where other pieces of a structure can be attached. The piece attached to it goes in the
+/// block in front of the connector, with one of its own connectors looking back at it.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Connector {
+    pub position: (i64, i64, i64),
+    pub facing: Facing,
+    /// Pool of the templates that are attached to it. It's [None] for connectors that are only
+    /// used to attach the template to others.
+    #[serde(default)]
+    pub pool: Option<String>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct TemplateFile {
+    name: String,
+    palette: BTreeMap<char, String>,
+    layers: Vec<Vec<String>>,
+    #[serde(default)]
+    connectors: Vec<Connector>,
+}
+
+/// A piece of a structure. Blocks that are [None] keep what was already in the world.
+#[derive(Clone, Debug)]
+pub struct StructureTemplate {
+    pub name: String,
+    pub size: [i64; 3],
+    blocks: Vec<Option<BlockId>>,
+    pub connectors: Vec<Connector>,
+}
+
+impl StructureTemplate {
+    /// Creates a template from its blocks, ordered by layer, then by row and then along the row.
+    pub fn new(name: &str, size: [i64; 3], blocks: Vec<Option<BlockId>>, connectors: Vec<Connector>) -> Self {
+        assert_eq!(blocks.len() as i64, size.iter().product::<i64>(), "the blocks do not fill the template");
+
+        StructureTemplate {
+            name: name.to_owned(),
+            size,
+            blocks,
+            connectors,
+        }
+    }
+
+    pub fn load(path: impl AsRef<Path>, registry: &BlockRegistry) -> Result<Self, TemplateError> {
+        let source = fs::read_to_string(path).map_err(TemplateError::Io)?;
+        Self::parse(&source, registry)
+    }
+
+    /// Parses a template, the names of the blocks must be in the registry.
+    pub fn parse(source: &str, registry: &BlockRegistry) -> Result<Self, TemplateError> {
+        let file: TemplateFile = ron::from_str(source).map_err(TemplateError::Parse)?;
+
+        let mut palette = HashMap::new();
+        for (character, name) in &file.palette {
+            let block = match name.as_str() {
+                "air" => AIR,
+                name => registry
+                    .by_name(name)
+                    .ok_or_else(|| TemplateError::UnknownBlock(name.to_owned()))?,
+            };
+            palette.insert(*character, block);
+        }
+
+        let height = file.layers.len();
+        let length = file.layers.first().map_or(0, |layer| layer.len());
+        let width = file.layers.first().and_then(|layer| layer.first()).map_or(0, |row| row.chars().count());
+
+        let mut blocks = vec![None; width * height * length];
+
+        for (y, layer) in file.layers.iter().enumerate() {
+            if layer.len() != length {
+                return Err(TemplateError::Ragged);
+            }
+
+            for (z, row) in layer.iter().enumerate() {
+                if row.chars().count() != width {
+                    return Err(TemplateError::Ragged);
+                }
+
+                for (x, character) in row.chars().enumerate() {
+                    if character == ' ' {
+                        continue;
+                    }
+
+                    let block = palette.get(&character).ok_or(TemplateError::UnknownCharacter(character))?;
+                    blocks[(y * length + z) * width + x] = Some(*block);
+                }
+            }
+        }
+
+        Ok(StructureTemplate {
+            name: file.name,
+            size: [width as i64, height as i64, length as i64],
+            blocks,
+            connectors: file.connectors,
+        })
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>, registry: &BlockRegistry) -> Result<(), TemplateError> {
+        let source = self.write(registry)?;
+        fs::write(path, source).map_err(TemplateError::Io)
+    }
+
+    /// Writes the template in the format that [StructureTemplate::parse] reads. Each block gets a
+    /// character of its own, air gets a dot.
+    pub fn write(&self, registry: &BlockRegistry) -> Result<String, TemplateError> {
+        let mut characters = ('!'..)
+            .filter(|character| !character.is_control() && !matches!(character, '.' | '\'' | '"' | '\\'));
+        let mut assigned: HashMap<BlockId, char> = HashMap::new();
+        let mut palette = BTreeMap::new();
+
+        let [width, height, length] = self.size;
+        let mut layers = Vec::with_capacity(height as usize);
+
+        for y in 0..height {
+            let mut layer = Vec::with_capacity(length as usize);
+
+            for z in 0..length {
+                let mut row = String::with_capacity(width as usize);
+
+                for x in 0..width {
+                    let character = match self.get(x, y, z) {
+                        None => ' ',
+                        Some(AIR) => {
+                            palette.insert('.', "air".to_owned());
+                            '.'
+                        }
+                        Some(block) => match assigned.get(&block) {
+                            Some(character) => *character,
+                            None => {
+                                let name = &registry.get(block).ok_or(TemplateError::UnknownId(block))?.name;
+                                let character = characters.next().expect("there are more characters than blocks");
+                                palette.insert(character, name.clone());
+                                assigned.insert(block, character);
+                                character
+                            }
+                        },
+                    };
+
+                    row.push(character);
+                }
+
+                layer.push(row);
+            }
+
+            layers.push(layer);
+        }
+
+        let file = TemplateFile {
+            name: self.name.clone(),
+            palette,
+            layers,
+            connectors: self.connectors.clone(),
+        };
+
+        ron::ser::to_string_pretty(&file, ron::ser::PrettyConfig::default()).map_err(TemplateError::Write)
+    }
+
+    pub fn get(&self, x: i64, y: i64, z: i64) -> Option<BlockId> {
+        let [width, _, length] = self.size;
+        self.blocks[((y * length + z) * width + x) as usize]
+    }
+
+    /// Every block of the template that is not empty with its position inside of the template.
+    pub fn blocks(&self) -> impl Iterator<Item = ([i64; 3], BlockId)> + '_ {
+        let [width, height, length] = self.size;
+
+        (0..height).flat_map(move |y| {
+            (0..length).flat_map(move |z| {
+                (0..width).filter_map(move |x| self.get(x, y, z).map(|block| ([x, y, z], block)))
+            })
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::block::{BlockDefinition, Transparency};
+
+    use super::*;
+
+    const ALL: [Transform; 8] = [
+        Transform { rotation: 0, mirror: false },
+        Transform { rotation: 1, mirror: false },
+        Transform { rotation: 2, mirror: false },
+        Transform { rotation: 3, mirror: false },
+        Transform { rotation: 0, mirror: true },
+        Transform { rotation: 1, mirror: true },
+        Transform { rotation: 2, mirror: true },
+        Transform { rotation: 3, mirror: true },
+    ];
+
+    fn registry() -> BlockRegistry {
+        let mut registry = BlockRegistry::default();
+        registry.register(BlockDefinition::new("stone", Transparency::Opaque, 0));
+        registry.register(BlockDefinition::new("planks", Transparency::Opaque, 0));
+        registry
+    }
+
+    #[test]
+    fn transforms_move_blocks_and_facings_together() {
+        let size = [3, 2, 5];
+
+        for transform in ALL {
+            let moved = transform.size(size);
+            let mut seen = Vec::new();
+
+            for x in 0..size[0] {
+                for z in 0..size[2] {
+                    let position = transform.apply([x, 1, z], size);
+                    assert!((0..3).all(|axis| (0..moved[axis]).contains(&position[axis])));
+                    seen.push(position);
+
+                    // The block in front of a facing stays in front of it after the transform.
+                    for facing in Facing::ALL {
+                        let offset = facing.offset();
+                        let next = [x + offset.x, 1, z + offset.z];
+                        if !(0..size[0]).contains(&next[0]) || !(0..size[2]).contains(&next[2]) {
+                            continue;
+                        }
+
+                        let offset = transform.facing(facing).offset();
+                        let expected = [position[0] + offset.x, 1, position[2] + offset.z];
+                        assert_eq!(transform.apply(next, size), expected, "{transform:?} {facing:?}");
+                    }
+                }
+            }
+
+            seen.sort();
+            seen.dedup();
+            assert_eq!(seen.len() as i64, size[0] * size[2]);
+        }
+    }
+
+    #[test]
+    fn written_templates_are_parsed_back() {
+        let registry = registry();
+        let source = r#"(
+            name: "hut",
+            palette: { 'S': "stone", 'P': "planks", '.': "air" },
+            layers: [
+                ["SSS", "SPS", "SSS"],
+                ["S.S", ". .", "S.S"],
+            ],
+            connectors: [(position: (1, 0, 0), facing: North, pool: Some("paths"))],
+        )"#;
+
+        let template = StructureTemplate::parse(source, &registry).unwrap();
+        assert_eq!(template.size, [3, 2, 3]);
+        assert_eq!(template.get(1, 0, 1), registry.by_name("planks"));
+        assert_eq!(template.get(1, 1, 0), Some(AIR));
+        assert_eq!(template.get(1, 1, 1), None);
+
+        let written = StructureTemplate::parse(&template.write(&registry).unwrap(), &registry).unwrap();
+        assert_eq!(written.name, template.name);
+        assert_eq!(written.size, template.size);
+        assert_eq!(written.blocks().collect::<Vec<_>>(), template.blocks().collect::<Vec<_>>());
+        assert_eq!(written.get(1, 1, 1), None);
+        assert_eq!(written.connectors.len(), 1);
+        assert_eq!(written.connectors[0].facing, Facing::North);
+        assert_eq!(written.connectors[0].pool.as_deref(), Some("paths"));
+    }
+
+    #[test]
+    fn bad_templates_are_rejected() {
+        let registry = registry();
+        let parse = |palette: &str, layers: &str| {
+            StructureTemplate::parse(&format!("(name: \"bad\", palette: {{ {palette} }}, layers: {layers})"), &registry)
+        };
+
+        assert!(parse("'S': \"stone\"", "[[\"SS\", \"SS\"]]").is_ok());
+        assert!(matches!(parse("'S': \"stone\"", "[[\"SS\", \"S\"]]"), Err(TemplateError::Ragged)));
+        assert!(matches!(parse("'S': \"stone\"", "[[\"SS\"], [\"SS\", \"SS\"]]"), Err(TemplateError::Ragged)));
+        assert!(matches!(parse("'S': \"stone\"", "[[\"SX\"]]"), Err(TemplateError::UnknownCharacter('X'))));
+        assert!(matches!(parse("'G': \"gold\"", "[[\"G\"]]"), Err(TemplateError::UnknownBlock(name)) if name == "gold"));
+        assert!(matches!(parse("'S': \"stone\"", "[\"SS\"]"), Err(TemplateError::Parse(_))));
+    }
+}
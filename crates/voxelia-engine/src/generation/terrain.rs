@@ -0,0 +1,504 @@
+//! Heightmap terrain made out of layered noise and shaped by biomes.
+
+use std::sync::Arc;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    biome::{Biome, BiomeId, BiomeRegistry, Climate, Vegetation},
+    block::{BlockDefinition, BlockId, BlockPosition, BlockRegistry, Tint, Transparency, AIR},
+    chunk::{Chunk, ChunkPosition, CHUNK_HEIGHT, CHUNK_LENGTH, CHUNK_WIDTH},
+    info::{GeneratorSettings, WorldInfo},
+    noise::{Fbm, Noise2, OpenSimplex, Perlin},
+    persistence::SavedEntities,
+    streaming::Unsaved,
+    workers::Workers,
+    Plugin, WorldBuilder,
+};
+
+use super::{
+    caves::{CaveCarver, CaveSettings},
+    decoration::{Decorator, DeferredPlacements, FeatureBlocks},
+    structures::{self, StructureBlocks, StructureStage},
+    ChunkRequests, ChunkScheduler, ChunkStatus, Generation, StagedGenerator, WorldGenerator,
+};
+
+/// Shape of the terrain, heights are in blocks. The base height and amplitude come from the
+/// [Biome] of each column.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct TerrainSettings {
+    pub frequency: f64,
+    pub octaves: u32,
+    /// Frequency of the temperature, humidity and continentalness noises.
+    pub climate_frequency: f64,
+    /// Higher values make the borders between biomes sharper.
+    pub blend_sharpness: i32,
+    pub sea_level: i64,
+    /// Depth of the subsurface blocks under the surface.
+    pub soil_depth: i64,
+}
+
+impl Default for TerrainSettings {
+    fn default() -> Self {
+        TerrainSettings {
+            frequency: 1.0 / 64.0,
+            octaves: 4,
+            climate_frequency: 1.0 / 512.0,
+            blend_sharpness: 3,
+            sea_level: 12,
+            soil_depth: 3,
+        }
+    }
+}
+
+/// Blocks used to build the terrain.
+#[derive(Copy, Clone, Debug)]
+pub struct TerrainBlocks {
+    pub stone: BlockId,
+    pub dirt: BlockId,
+    pub grass: BlockId,
+    pub sand: BlockId,
+    pub snow: BlockId,
+    pub water: BlockId,
+}
+
+impl TerrainBlocks {
+    /// Gets the terrain blocks from the registry, registering the ones that are missing.
+    pub fn register(registry: &mut BlockRegistry) -> TerrainBlocks {
+        TerrainBlocks {
+            stone: registry.get_or_register(BlockDefinition::new("stone", Transparency::Opaque, 0)),
+            dirt: registry.get_or_register(BlockDefinition::new("dirt", Transparency::Opaque, 0)),
+            grass: registry.get_or_register(
+                BlockDefinition::new("grass", Transparency::Opaque, 0).with_tint(Tint::Grass),
+            ),
+            sand: registry.get_or_register(BlockDefinition::new("sand", Transparency::Opaque, 0)),
+            snow: registry.get_or_register(BlockDefinition::new("snow", Transparency::Opaque, 0)),
+            water: registry.get_or_register(BlockDefinition::new(
+                "water",
+                Transparency::Translucent,
+                0,
+            )),
+        }
+    }
+
+    /// The biomes generated by default out of the terrain blocks.
+    pub fn biomes(&self) -> Vec<Biome> {
+        vec![
+            Biome {
+                name: "ocean".to_owned(),
+                climate: Climate::new(0.0, 0.0, -0.5),
+                base_height: 6.0,
+                amplitude: 3.0,
+                surface: self.sand,
+                subsurface: self.sand,
+                vegetation: Vegetation::default(),
+                grass_color: [110, 180, 90],
+                foliage_color: [90, 160, 70],
+            },
+            Biome {
+                name: "plains".to_owned(),
+                climate: Climate::new(0.1, -0.1, 0.1),
+                base_height: 14.0,
+                amplitude: 3.0,
+                surface: self.grass,
+                subsurface: self.dirt,
+                vegetation: Vegetation { trees: 0.002, flowers: 0.05 },
+                grass_color: [140, 190, 90],
+                foliage_color: [110, 170, 60],
+            },
+            Biome {
+                name: "forest".to_owned(),
+                climate: Climate::new(0.0, 0.3, 0.15),
+                base_height: 15.0,
+                amplitude: 5.0,
+                surface: self.grass,
+                subsurface: self.dirt,
+                vegetation: Vegetation { trees: 0.04, flowers: 0.02 },
+                grass_color: [100, 170, 70],
+                foliage_color: [70, 140, 50],
+            },
+            Biome {
+                name: "desert".to_owned(),
+                climate: Climate::new(0.4, -0.4, 0.15),
+                base_height: 14.0,
+                amplitude: 3.0,
+                surface: self.sand,
+                subsurface: self.sand,
+                vegetation: Vegetation::default(),
+                grass_color: [190, 180, 100],
+                foliage_color: [170, 160, 80],
+            },
+            Biome {
+                name: "tundra".to_owned(),
+                climate: Climate::new(-0.4, 0.0, 0.15),
+                base_height: 15.0,
+                amplitude: 4.0,
+                surface: self.snow,
+                subsurface: self.dirt,
+                vegetation: Vegetation { trees: 0.005, flowers: 0.0 },
+                grass_color: [130, 170, 150],
+                foliage_color: [100, 150, 130],
+            },
+            Biome {
+                name: "mountains".to_owned(),
+                climate: Climate::new(0.0, 0.0, 0.5),
+                base_height: 40.0,
+                amplitude: 36.0,
+                surface: self.stone,
+                subsurface: self.stone,
+                vegetation: Vegetation { trees: 0.005, flowers: 0.0 },
+                grass_color: [120, 160, 110],
+                foliage_color: [90, 140, 90],
+            },
+        ]
+    }
+}
+
+/// `base` to the power of `exponent` with plain multiplications, that give the same result in every
+/// platform unlike [f64::powi].
+fn power(base: f64, exponent: i32) -> f64 {
+    let power = (0..exponent.unsigned_abs()).fold(1.0, |power, _| power * base);
+    if exponent < 0 {
+        1.0 / power
+    } else {
+        power
+    }
+}
+
+/// Noises sampled by the [TerrainGenerator], created once for each chunk.
+struct TerrainNoises {
+    height: Fbm<Perlin>,
+    temperature: Fbm<OpenSimplex>,
+    humidity: Fbm<OpenSimplex>,
+    continentalness: Fbm<OpenSimplex>,
+}
+
+/// Generates hills out of a heightmap with water filling everything below the sea level. The shape
+/// and the blocks of each column come from the biomes, that are blended together at their borders.
+pub struct TerrainGenerator {
+    pub settings: TerrainSettings,
+    pub blocks: TerrainBlocks,
+    pub biomes: BiomeRegistry,
+}
+
+impl TerrainGenerator {
+    fn noises(&self, seed: u64) -> TerrainNoises {
+        let climate = |salt: u64| {
+            Fbm::<OpenSimplex>::new(seed ^ salt, 3, self.settings.climate_frequency)
+        };
+
+        TerrainNoises {
+            height: Fbm::new(seed, self.settings.octaves, self.settings.frequency),
+            temperature: climate(0x7e3a_11f0_5c2d_9b41),
+            humidity: climate(0x2b9d_c4e7_a016_53f8),
+            continentalness: climate(0xd15c_0a87_3fe2_6b94),
+        }
+    }
+
+    /// Climate at a global block coordinate.
+    fn climate(&self, noises: &TerrainNoises, x: i64, z: i64) -> Climate {
+        let (x, z) = (x as f64, z as f64);
+        Climate::new(
+            noises.temperature.get2(x, z),
+            noises.humidity.get2(x, z),
+            noises.continentalness.get2(x, z),
+        )
+    }
+
+    fn sample(&self, noises: &TerrainNoises, x: i64, z: i64) -> Option<(BiomeId, i64)> {
+        let climate = self.climate(noises, x, z);
+        let biome = self.biomes.closest(&climate)?;
+        Some((biome, self.height(&climate, noises.height.get2(x as f64, z as f64))))
+    }
+
+    /// Biome and height of the surface of a column at a global block coordinate, the same that the
+    /// chunk of the column gets before any stage runs.
+    pub fn column(&self, x: i64, z: i64, seed: u64) -> Option<(BiomeId, i64)> {
+        self.sample(&self.noises(seed), x, z)
+    }
+
+    /// Blocks that the terrain has right above a chunk, by x and then z, for the stages that need to
+    /// know what's there before the chunk above is generated.
+    pub fn blocks_above(&self, position: &ChunkPosition, seed: u64) -> Vec<BlockId> {
+        let noises = self.noises(seed);
+        let [x, y, z] = position.origin();
+        let above = y + CHUNK_HEIGHT as i64;
+
+        (0..CHUNK_WIDTH as i64)
+            .flat_map(|dx| (0..CHUNK_LENGTH as i64).map(move |dz| (x + dx, z + dz)))
+            .map(|(x, z)| {
+                let Some((biome, height)) = self.sample(&noises, x, z) else { return AIR };
+                self.biomes.get(biome).map_or(AIR, |biome| self.block_at(above, height, biome))
+            })
+            .collect()
+    }
+
+    /// Height of the surface given the climate and the height noise. Every biome contributes to it
+    /// with a weight that falls quickly with its distance in the climate space, so the terrain
+    /// changes smoothly between biomes.
+    pub fn height(&self, climate: &Climate, value: f64) -> i64 {
+        let mut total = 0.0;
+        let mut weights = 0.0;
+
+        for (_, biome) in self.biomes.iter() {
+            let weight = 1.0 / power(biome.climate.distance2(climate) + 1e-6, self.settings.blend_sharpness);
+            total += weight * (biome.base_height + value * biome.amplitude);
+            weights += weight;
+        }
+
+        (total / weights).floor() as i64
+    }
+
+    /// Block of a column of the given biome with the surface at `height`.
+    pub fn block_at(&self, y: i64, height: i64, biome: &Biome) -> BlockId {
+        if y > height {
+            if y <= self.settings.sea_level {
+                self.blocks.water
+            } else {
+                AIR
+            }
+        } else if y == height && height >= self.settings.sea_level {
+            biome.surface
+        } else if y > height - self.settings.soil_depth {
+            if height < self.settings.sea_level {
+                self.blocks.sand
+            } else {
+                biome.subsurface
+            }
+        } else {
+            self.blocks.stone
+        }
+    }
+}
+
+impl WorldGenerator for TerrainGenerator {
+    fn generate(&self, position: &ChunkPosition, seed: u64) -> Chunk {
+        let noises = self.noises(seed);
+        let mut chunk = Chunk::default();
+
+        for x in 0..CHUNK_WIDTH as i64 {
+            for z in 0..CHUNK_LENGTH as i64 {
+                let global_x = position.x * CHUNK_WIDTH as i64 + x;
+                let global_z = position.z * CHUNK_LENGTH as i64 + z;
+
+                let Some((id, height)) = self.sample(&noises, global_x, global_z) else {
+                    continue;
+                };
+                let biome = self.biomes.get(id).unwrap();
+
+                chunk.set_biome(x as usize, z as usize, id);
+
+                for y in 0..CHUNK_HEIGHT as i64 {
+                    let global_y = position.y * CHUNK_HEIGHT as i64 + y;
+                    let block = self.block_at(global_y, height, biome);
+
+                    if block != AIR {
+                        chunk.set(&BlockPosition::new(x, y, z), block);
+                    }
+                }
+            }
+        }
+
+        chunk
+    }
+
+    fn height(&self, x: i64, z: i64, seed: u64) -> Option<i64> {
+        self.column(x, z, seed).map(|(_, height)| height)
+    }
+}
+
+/// Plugin that generates the world using a [TerrainGenerator], carves caves into it, places the
+/// default structures and decorates it with the default features. Chunks asked for with
+/// the [ChunkRequests] are taken through the pipeline by the [ChunkScheduler].
+///
+/// The seed and the settings of the [WorldInfo] of a world that was opened are used instead of the
+/// ones of the plugin, so the world is generated the same way every time it's opened. The spawn
+/// of the world is put on top of the terrain if it has none.
+pub struct TerrainPlugin {
+    pub seed: u64,
+    pub settings: TerrainSettings,
+    /// Settings of the caves, there are no caves if it's [None].
+    pub caves: Option<CaveSettings>,
+    pub structures: bool,
+    pub decorate: bool,
+}
+
+impl TerrainPlugin {
+    pub fn new(seed: u64) -> TerrainPlugin {
+        TerrainPlugin {
+            seed,
+            settings: TerrainSettings::default(),
+            caves: Some(CaveSettings::default()),
+            structures: true,
+            decorate: true,
+        }
+    }
+}
+
+impl Plugin for TerrainPlugin {
+    fn setup(self, world: &mut WorldBuilder) {
+        let (seed, settings, next_placed) = match world.get_resource_mut::<WorldInfo>() {
+            Some(info) => (info.seed, info.generator.clone(), info.next_placed),
+            None => (
+                self.seed,
+                GeneratorSettings {
+                    terrain: self.settings,
+                    caves: self.caves,
+                    structures: self.structures,
+                    decorate: self.decorate,
+                },
+                0,
+            ),
+        };
+
+        let (blocks, features, registry) = {
+            let mut registry = world.resource_mut::<BlockRegistry>();
+            let blocks = TerrainBlocks::register(&mut registry);
+            let features = FeatureBlocks::register(&mut registry);
+            StructureBlocks::register(&mut registry);
+            (blocks, features, registry.clone())
+        };
+
+        // Biomes registered by earlier plugins replace the default ones.
+        let biomes = {
+            let mut registry = world.resource_mut::<BiomeRegistry>();
+            if registry.is_empty() {
+                for biome in blocks.biomes() {
+                    registry.register(biome);
+                }
+            }
+            registry.clone()
+        };
+
+        let sea_level = settings.terrain.sea_level;
+
+        let terrain = Arc::new(TerrainGenerator {
+            settings: settings.terrain,
+            blocks,
+            biomes: biomes.clone(),
+        });
+
+        if let Some(mut info) = world.get_resource_mut::<WorldInfo>() {
+            if info.spawn.is_none() {
+                let height = terrain.height(0, 0, seed).unwrap_or(sea_level).max(sea_level);
+                info.spawn = Some([0, height + 1, 0]);
+            }
+        }
+
+        // Placements given by plugins added before this one are kept.
+        if world.get_resource_mut::<DeferredPlacements>().is_none() {
+            world.with_resource(DeferredPlacements::starting_at(next_placed));
+        }
+
+        let mut generator = StagedGenerator::new(terrain.clone());
+
+        if let Some(caves) = settings.caves {
+            generator = generator.with_stage(CaveCarver::new(caves, terrain.clone()));
+        }
+
+        if settings.structures {
+            let structures = structures::default_structures(&biomes);
+            let pools = structures::default_pools(&registry);
+            generator = generator.with_stage(StructureStage::new(structures, pools, terrain.clone()));
+        }
+
+        if settings.decorate {
+            generator = generator.with_stage(Decorator {
+                features: features.features(&blocks),
+                biomes,
+                sea_level,
+                deferred: world.resource_mut::<DeferredPlacements>().clone(),
+                terrain,
+            });
+        }
+
+        world.with_resource(Generation::new(seed, generator));
+        world.with_component::<ChunkStatus>();
+        world.with_component::<Unsaved>();
+        world.with_component::<SavedEntities>();
+        world.resource_mut::<ChunkRequests>();
+        // Workers given by plugins added before this one are kept.
+        world.resource_mut::<Workers>();
+        world.with_system(ChunkScheduler::default(), "chunk scheduler", &[]);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn generator() -> TerrainGenerator {
+        let mut registry = BlockRegistry::default();
+        let blocks = TerrainBlocks::register(&mut registry);
+
+        let mut biomes = BiomeRegistry::default();
+        for biome in blocks.biomes() {
+            biomes.register(biome);
+        }
+
+        TerrainGenerator { settings: TerrainSettings::default(), blocks, biomes }
+    }
+
+    /// Columns far from each other, so they fall in different biomes.
+    const COLUMNS: [(i64, i64); 6] = [(0, 0), (17, -5), (-300, 120), (1000, 1000), (-2500, 700), (4000, -3900)];
+
+    #[test]
+    fn columns_are_the_same_everywhere() {
+        let generator = generator();
+        let columns: Vec<_> = COLUMNS.iter().map(|(x, z)| generator.column(*x, *z, 42)).collect();
+        let expected = [(1, 14), (2, 13), (0, 4), (1, 17), (4, 15), (1, 12)];
+
+        assert_eq!(columns, expected.map(Some));
+    }
+
+    #[test]
+    fn chunks_are_the_same_for_the_same_seed() {
+        let generator = generator();
+        let position = ChunkPosition::new(3, 0, -2);
+
+        assert_eq!(generator.generate(&position, 42).data(), generator.generate(&position, 42).data());
+        assert_ne!(generator.generate(&position, 42).data(), generator.generate(&position, 43).data());
+    }
+
+    #[test]
+    fn chunks_have_the_blocks_of_their_columns() {
+        let generator = generator();
+
+        for position in [ChunkPosition::new(1, 0, -1), ChunkPosition::new(-4, -1, 2), ChunkPosition::new(0, 1, 0)] {
+            let chunk = generator.generate(&position, 7);
+            let [origin_x, origin_y, origin_z] = position.origin();
+
+            for x in 0..CHUNK_WIDTH as i64 {
+                for z in 0..CHUNK_LENGTH as i64 {
+                    let (id, height) = generator.column(origin_x + x, origin_z + z, 7).unwrap();
+                    assert_eq!(WorldGenerator::height(&generator, origin_x + x, origin_z + z, 7), Some(height));
+                    assert_eq!(chunk.biome(x as usize, z as usize), id);
+
+                    let biome = generator.biomes.get(id).unwrap();
+                    for y in 0..CHUNK_HEIGHT as i64 {
+                        let block = generator.block_at(origin_y + y, height, biome);
+                        assert_eq!(chunk.get(&BlockPosition::new(x, y, z)), block);
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn registered_terrain_blocks_are_reused() {
+        let mut registry = BlockRegistry::default();
+        let blocks = TerrainBlocks::register(&mut registry);
+        let again = TerrainBlocks::register(&mut registry);
+
+        assert_eq!((blocks.stone, blocks.water), (again.stone, again.water));
+        assert_eq!(registry.transparency(blocks.water), Transparency::Translucent);
+    }
+
+    #[test]
+    fn powers_are_multiplications() {
+        assert_eq!(power(1.5, 3), 1.5 * 1.5 * 1.5);
+        assert_eq!(power(2.0, 0), 1.0);
+        assert_eq!(power(2.0, -2), 0.25);
+    }
+}
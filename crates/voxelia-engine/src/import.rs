@@ -0,0 +1,76 @@
+//! Importers of models made with other programs. Models become [StructureTemplate]s, that can be
+//! added to the [StructurePools](crate::generation::structures::jigsaw::StructurePools), saved as
+//! template files or placed in the world with [place].
+
+use std::{collections::HashMap, fmt, io};
+
+use specs::World;
+
+use crate::{
+    chunk::ChunkPosition,
+    generation::{
+        decoration::{DeferredPlacements, Placement, Replace},
+        structures::template::StructureTemplate,
+    },
+};
+
+pub mod minecraft;
+pub mod nbt;
+pub mod vox;
+
+/// Most blocks that an imported model can have, so broken files cannot take all the memory.
+pub const MAX_VOLUME: usize = 256 * 256 * 256;
+
+#[derive(Debug)]
+pub enum ImportError {
+    Io(io::Error),
+    /// The file is not in the format that it should be in.
+    Invalid(String),
+    UnknownBlock(String),
+}
+
+impl fmt::Display for ImportError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ImportError::Io(error) => write!(f, "cannot read the model: {error}"),
+            ImportError::Invalid(reason) => write!(f, "invalid model: {reason}"),
+            ImportError::UnknownBlock(name) => write!(f, "unknown block '{name}'"),
+        }
+    }
+}
+
+impl std::error::Error for ImportError {}
+
+impl From<io::Error> for ImportError {
+    fn from(error: io::Error) -> Self {
+        ImportError::Io(error)
+    }
+}
+
+/// Number of blocks of a model of a size, if no side is negative and it's not bigger than
+/// [MAX_VOLUME].
+pub(crate) fn volume(size: [i64; 3]) -> Result<usize, ImportError> {
+    size.iter()
+        .try_fold(1usize, |volume, side| volume.checked_mul(usize::try_from(*side).ok()?))
+        .filter(|volume| *volume <= MAX_VOLUME)
+        .ok_or_else(|| ImportError::Invalid(format!("the size {size:?} is negative or has more than {MAX_VOLUME} blocks")))
+}
+
+/// Places a template in the world with its lowest corner at `origin`, in blocks. The blocks go to
+/// the [DeferredPlacements] after the ones of the decorations, so each chunk gets them once it's
+/// loaded and decorated, and the chunks that are full are saved and meshed again. Blocks of chunks
+/// that are not loaded are saved with the world, and the light of the chunks is not updated.
+pub fn place(world: &World, template: &StructureTemplate, origin: [i64; 3]) {
+    let deferred = world.fetch::<DeferredPlacements>();
+    let source = deferred.next_placed();
+    let mut chunks: HashMap<ChunkPosition, Vec<Placement>> = HashMap::new();
+
+    for ([x, y, z], block) in template.blocks() {
+        let (chunk, position) = ChunkPosition::of_block(origin[0] + x, origin[1] + y, origin[2] + z);
+        chunks.entry(chunk).or_default().push(Placement { position, block, replace: Replace::Any });
+    }
+
+    for (chunk, placements) in chunks {
+        deferred.push(chunk, source, placements);
+    }
+}
@@ -0,0 +1,848 @@
+//! Importers of the schematics and the region files of Minecraft.
+//!
+//! Sponge schematics (`.schem`) and Litematica schematics (`.litematic`) become templates, and the
+//! chunks of Anvil region files (`.mca`) are written to a [ChunkStorage], replacing the chunks of
+//! the world in the same place. Minecraft chunks are 16 blocks wide like ours, so a Minecraft chunk
+//! becomes the column of chunks with the same x and z.
+//!
+//! The blocks of these files are block states, a name with some properties like
+//! `minecraft:oak_log[axis=y]`, that become blocks with a [BlockStateMapping]. Region files of
+//! versions before 1.13, that have numeric ids instead of block states, are not supported.
+
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap},
+    fs,
+    path::Path,
+};
+
+use serde::Deserialize;
+
+use crate::{
+    block::{BlockId, BlockRegistry, AIR},
+    chunk::{Chunk, ChunkPosition, CHUNK_LENGTH, CHUNK_WIDTH},
+    generation::structures::template::StructureTemplate,
+    light::{self, MAX_LIGHT},
+    streaming::ChunkStorage,
+};
+
+use super::{
+    nbt::{self, Tag},
+    volume, ImportError,
+};
+
+/// Data version of the first snapshot whose packed block states do not span two longs.
+const NO_SPANNING_VERSION: i64 = 2529;
+
+/// Bytes of each sector of a region file.
+const SECTOR_SIZE: usize = 4096;
+
+/// Which block each block state becomes, loaded from RON files like:
+///
+/// ```ron
+/// (
+///     states: {
+///         "minecraft:oak_log": "log",
+///         "minecraft:oak_log[axis=x]": "planks",
+///     },
+///     fallback: Some("stone"),
+/// )
+/// ```
+///
+/// A state is looked up with its properties first and then by its name alone, and the namespace can
+/// be left out of the names of the table. States that are not in the table get the fallback, or are
+/// left out of the import if there's none.
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct BlockStateMapping {
+    pub states: HashMap<String, String>,
+    pub fallback: Option<String>,
+}
+
+impl BlockStateMapping {
+    /// Mapping of the common blocks of Minecraft to the blocks of the engine.
+    pub fn blocks() -> BlockStateMapping {
+        let states = [
+            ("air", "air"),
+            ("cave_air", "air"),
+            ("void_air", "air"),
+            ("stone", "stone"),
+            ("granite", "stone"),
+            ("diorite", "stone"),
+            ("andesite", "stone"),
+            ("deepslate", "stone"),
+            ("tuff", "stone"),
+            ("bedrock", "stone"),
+            ("stone_bricks", "cobblestone"),
+            ("cobblestone", "cobblestone"),
+            ("mossy_cobblestone", "cobblestone"),
+            ("cobbled_deepslate", "cobblestone"),
+            ("gravel", "gravel"),
+            ("dirt", "dirt"),
+            ("coarse_dirt", "dirt"),
+            ("rooted_dirt", "dirt"),
+            ("farmland", "dirt"),
+            ("dirt_path", "dirt"),
+            ("grass_path", "dirt"),
+            ("podzol", "dirt"),
+            ("grass_block", "grass"),
+            ("sand", "sand"),
+            ("red_sand", "sand"),
+            ("sandstone", "sand"),
+            ("snow_block", "snow"),
+            ("snow", "snow"),
+            ("powder_snow", "snow"),
+            ("coal_ore", "coal_ore"),
+            ("deepslate_coal_ore", "coal_ore"),
+            ("iron_ore", "iron_ore"),
+            ("deepslate_iron_ore", "iron_ore"),
+            ("dandelion", "flower"),
+            ("poppy", "flower"),
+            ("cornflower", "flower"),
+            ("oxeye_daisy", "flower"),
+        ];
+
+        let woods = ["oak", "spruce", "birch", "jungle", "acacia", "dark_oak", "mangrove", "cherry"];
+
+        let mut mapping = BlockStateMapping {
+            states: states.into_iter().map(|(state, block)| (state.to_owned(), block.to_owned())).collect(),
+            fallback: None,
+        };
+
+        for wood in woods {
+            mapping.states.insert(format!("{wood}_log"), "log".to_owned());
+            mapping.states.insert(format!("{wood}_wood"), "log".to_owned());
+            mapping.states.insert(format!("{wood}_planks"), "planks".to_owned());
+            mapping.states.insert(format!("{wood}_leaves"), "leaves".to_owned());
+        }
+
+        mapping
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> Result<BlockStateMapping, ImportError> {
+        let source = fs::read_to_string(path)?;
+        ron::from_str(&source).map_err(|error| ImportError::Invalid(error.to_string()))
+    }
+
+    pub fn with_state(mut self, state: &str, block: &str) -> BlockStateMapping {
+        self.states.insert(state.to_owned(), block.to_owned());
+        self
+    }
+
+    pub fn with_fallback(mut self, block: &str) -> BlockStateMapping {
+        self.fallback = Some(block.to_owned());
+        self
+    }
+
+    /// Name of the block of a state, [None] if it's not in the table and there's no fallback.
+    pub fn block_of(&self, state: &str) -> Option<&str> {
+        let name = state.split('[').next().unwrap_or(state);
+        let short = |key: &str| key.strip_prefix("minecraft:").map(str::to_owned);
+
+        [Some(state.to_owned()), short(state), Some(name.to_owned()), short(name)]
+            .into_iter()
+            .flatten()
+            .find_map(|key| self.states.get(&key))
+            .or(self.fallback.as_ref())
+            .map(String::as_str)
+    }
+}
+
+/// Turns block states into blocks of the registry, remembering the states that have no block to
+/// report them once.
+struct StateResolver<'a> {
+    mapping: &'a BlockStateMapping,
+    registry: &'a BlockRegistry,
+    cache: HashMap<String, Option<BlockId>>,
+    unmapped: BTreeSet<String>,
+}
+
+impl<'a> StateResolver<'a> {
+    fn new(mapping: &'a BlockStateMapping, registry: &'a BlockRegistry) -> Self {
+        StateResolver {
+            mapping,
+            registry,
+            cache: HashMap::new(),
+            unmapped: BTreeSet::new(),
+        }
+    }
+
+    fn resolve(&mut self, state: &str) -> Result<Option<BlockId>, ImportError> {
+        if let Some(block) = self.cache.get(state) {
+            return Ok(*block);
+        }
+
+        let block = match self.mapping.block_of(state) {
+            Some("air") => Some(AIR),
+            Some(name) => Some(
+                self.registry
+                    .by_name(name)
+                    .ok_or_else(|| ImportError::UnknownBlock(name.to_owned()))?,
+            ),
+            None => {
+                let name = state.split('[').next().unwrap_or(state);
+                self.unmapped.insert(name.to_owned());
+                None
+            }
+        };
+
+        self.cache.insert(state.to_owned(), block);
+        Ok(block)
+    }
+
+    /// Blocks of a palette of block states written as compounds with a name and properties.
+    fn palette(&mut self, palette: &[Tag]) -> Result<Vec<Option<BlockId>>, ImportError> {
+        palette
+            .iter()
+            .map(|entry| {
+                let name = entry
+                    .get("Name")
+                    .and_then(Tag::as_str)
+                    .ok_or_else(|| ImportError::Invalid("a block state has no name".to_owned()))?;
+
+                let properties: BTreeMap<_, _> = entry
+                    .get("Properties")
+                    .and_then(Tag::as_compound)
+                    .into_iter()
+                    .flatten()
+                    .filter_map(|(key, value)| Some((key.as_str(), value.as_str()?)))
+                    .collect();
+
+                if properties.is_empty() {
+                    return self.resolve(name);
+                }
+
+                let properties: Vec<String> = properties.iter().map(|(key, value)| format!("{key}={value}")).collect();
+                self.resolve(&format!("{name}[{}]", properties.join(",")))
+            })
+            .collect()
+    }
+
+    fn finish(self) {
+        if !self.unmapped.is_empty() {
+            let names: Vec<_> = self.unmapped.into_iter().collect();
+            log::warn!("blocks without a mapping were left out: {}", names.join(", "));
+        }
+    }
+}
+
+/// Bits of each index in a packed array of a palette with this many entries.
+fn bits_for(entries: usize, minimum: u32) -> u32 {
+    let bits = usize::BITS - entries.saturating_sub(1).leading_zeros();
+    bits.max(minimum)
+}
+
+/// Reads the index at a position of an array of longs that has indices with a fixed number of
+/// bits. Indices are either packed across the longs, or only as many as fit in a long are put in it.
+fn unpack(longs: &[i64], bits: u32, position: usize, spanning: bool) -> Option<usize> {
+    let bits = bits as usize;
+    let mask = (1u64 << bits) - 1;
+
+    let (long, offset) = if spanning {
+        (position * bits / 64, position * bits % 64)
+    } else {
+        let per_long = 64 / bits;
+        (position / per_long, position % per_long * bits)
+    };
+
+    let mut value = *longs.get(long)? as u64 >> offset;
+    if offset + bits > 64 {
+        value |= (*longs.get(long + 1)? as u64) << (64 - offset);
+    }
+
+    Some((value & mask) as usize)
+}
+
+/// Reads the indices of the blocks of a Sponge schematic, that are variable-length integers.
+fn varints(bytes: &[i8]) -> Result<Vec<usize>, ImportError> {
+    let mut values = Vec::with_capacity(bytes.len());
+    let mut value = 0;
+    let mut shift = 0;
+
+    for byte in bytes {
+        let byte = *byte as u8;
+        value |= ((byte & 0x7f) as usize) << shift;
+
+        if byte & 0x80 == 0 {
+            values.push(value);
+            value = 0;
+            shift = 0;
+        } else {
+            shift += 7;
+            if shift > 28 {
+                return Err(ImportError::Invalid("block index is too long".to_owned()));
+            }
+        }
+    }
+
+    Ok(values)
+}
+
+fn invalid(what: &str) -> ImportError {
+    ImportError::Invalid(format!("the file has no valid {what}"))
+}
+
+/// Reads a Sponge schematic of any version. The places where the blocks have no mapping keep the
+/// block that was already in the world.
+pub fn load_schematic(
+    path: impl AsRef<Path>,
+    mapping: &BlockStateMapping,
+    registry: &BlockRegistry,
+) -> Result<StructureTemplate, ImportError> {
+    let name = path.as_ref().file_stem().map_or_else(String::new, |name| name.to_string_lossy().into_owned());
+    parse_schematic(&fs::read(path)?, &name, mapping, registry)
+}
+
+pub fn parse_schematic(
+    bytes: &[u8],
+    name: &str,
+    mapping: &BlockStateMapping,
+    registry: &BlockRegistry,
+) -> Result<StructureTemplate, ImportError> {
+    let (_, root) = nbt::read(bytes)?;
+
+    // The third version puts everything inside of a compound, and the blocks in a compound of
+    // their own.
+    let schematic = root.get("Schematic").unwrap_or(&root);
+    let blocks = schematic.get("Blocks").unwrap_or(schematic);
+
+    let size = ["Width", "Height", "Length"].map(|key| schematic.get(key).and_then(Tag::as_i64).map(|size| size & 0xffff));
+    let [Some(width), Some(height), Some(length)] = size else {
+        return Err(invalid("size"));
+    };
+
+    let palette = blocks.get("Palette").and_then(Tag::as_compound).ok_or_else(|| invalid("palette"))?;
+    let data = blocks
+        .get("BlockData")
+        .or_else(|| blocks.get("Data"))
+        .and_then(Tag::as_bytes)
+        .ok_or_else(|| invalid("block data"))?;
+
+    let mut resolver = StateResolver::new(mapping, registry);
+    let mut states = HashMap::new();
+
+    for (state, index) in palette {
+        let index = index.as_i64().ok_or_else(|| invalid("palette"))?;
+        states.insert(index as usize, resolver.resolve(state)?);
+    }
+
+    let volume = volume([width, height, length])?;
+
+    // Every index takes at least a byte.
+    if data.len() < volume {
+        return Err(invalid("block data"));
+    }
+
+    let indices = varints(data)?;
+
+    if indices.len() < volume {
+        return Err(invalid("block data"));
+    }
+
+    // Schematics have their blocks in the same order as templates.
+    let blocks = indices[..volume].iter().map(|index| states.get(index).copied().flatten()).collect();
+
+    resolver.finish();
+
+    Ok(StructureTemplate::new(name, [width, height, length], blocks, Vec::new()))
+}
+
+/// A region of a Litematica schematic, with its lowest corner and its size.
+struct LitematicRegion<'a> {
+    min: [i64; 3],
+    size: [i64; 3],
+    palette: &'a [Tag],
+    states: &'a [i64],
+}
+
+impl<'a> LitematicRegion<'a> {
+    fn read(region: &'a Tag) -> Result<LitematicRegion<'a>, ImportError> {
+        let vector = |key: &str| {
+            let tag = region.get(key)?;
+            Some([tag.get("x")?.as_i64()?, tag.get("y")?.as_i64()?, tag.get("z")?.as_i64()?])
+        };
+
+        let position = vector("Position").ok_or_else(|| invalid("region position"))?;
+        let size = vector("Size").ok_or_else(|| invalid("region size"))?;
+
+        // Regions with a negative size go from their position towards the negative side.
+        let min = [0, 1, 2].map(|axis| position[axis].checked_add(size[axis].saturating_add(1).min(0)));
+        let [Some(x), Some(y), Some(z)] = min else {
+            return Err(invalid("region position"));
+        };
+
+        let [Some(width), Some(height), Some(length)] = size.map(i64::checked_abs) else {
+            return Err(invalid("region size"));
+        };
+
+        Ok(LitematicRegion {
+            min: [x, y, z],
+            size: [width, height, length],
+            palette: region.get("BlockStatePalette").and_then(Tag::as_list).ok_or_else(|| invalid("palette"))?,
+            states: region.get("BlockStates").and_then(Tag::as_longs).ok_or_else(|| invalid("block states"))?,
+        })
+    }
+}
+
+/// Reads a Litematica schematic, putting all of its regions in the same template.
+pub fn load_litematic(
+    path: impl AsRef<Path>,
+    mapping: &BlockStateMapping,
+    registry: &BlockRegistry,
+) -> Result<StructureTemplate, ImportError> {
+    let name = path.as_ref().file_stem().map_or_else(String::new, |name| name.to_string_lossy().into_owned());
+    parse_litematic(&fs::read(path)?, &name, mapping, registry)
+}
+
+pub fn parse_litematic(
+    bytes: &[u8],
+    name: &str,
+    mapping: &BlockStateMapping,
+    registry: &BlockRegistry,
+) -> Result<StructureTemplate, ImportError> {
+    let (_, root) = nbt::read(bytes)?;
+
+    let regions = root.get("Regions").and_then(Tag::as_compound).ok_or_else(|| invalid("regions"))?;
+    let regions = regions.values().map(LitematicRegion::read).collect::<Result<Vec<_>, _>>()?;
+
+    let Some(min) = regions.iter().map(|region| region.min).reduce(|a, b| [0, 1, 2].map(|axis| a[axis].min(b[axis])))
+    else {
+        return Ok(StructureTemplate::new(name, [0; 3], Vec::new(), Vec::new()));
+    };
+
+    let mut size = [0; 3];
+
+    for region in &regions {
+        for axis in 0..3 {
+            let side = region.min[axis].checked_add(region.size[axis]).and_then(|max| max.checked_sub(min[axis]));
+            size[axis] = size[axis].max(side.ok_or_else(|| invalid("region size"))?);
+        }
+    }
+
+    let mut blocks = vec![None; volume(size)?];
+    let mut resolver = StateResolver::new(mapping, registry);
+
+    for region in &regions {
+        let palette = resolver.palette(region.palette)?;
+        let bits = bits_for(palette.len(), 2);
+        let [width, height, length] = region.size;
+
+        // The regions are inside of the template, so their volume is not bigger than its own.
+        if region.states.len() * 64 < volume(region.size)? * bits as usize {
+            return Err(invalid("block states"));
+        }
+
+        for y in 0..height {
+            for z in 0..length {
+                for x in 0..width {
+                    let index = ((y * length + z) * width + x) as usize;
+                    let state = unpack(region.states, bits, index, true).ok_or_else(|| invalid("block states"))?;
+
+                    if let Some(block) = palette.get(state).copied().flatten() {
+                        let [x, y, z] = [0, 1, 2].map(|axis| region.min[axis] - min[axis] + [x, y, z][axis]);
+                        blocks[((y * size[2] + z) * size[0] + x) as usize] = Some(block);
+                    }
+                }
+            }
+        }
+    }
+
+    resolver.finish();
+
+    Ok(StructureTemplate::new(name, size, blocks, Vec::new()))
+}
+
+/// Reads the blocks of a section of 16×16×16 blocks of a Minecraft chunk, ordered by y, then z and
+/// then x. Sections without block states are [None].
+fn section_blocks(
+    section: &Tag,
+    resolver: &mut StateResolver,
+    data_version: i64,
+) -> Result<Option<Vec<Option<BlockId>>>, ImportError> {
+    // Since 1.18 the palette and the data are in a compound of their own.
+    let (palette, data) = match section.get("block_states") {
+        Some(states) => (states.get("palette"), states.get("data")),
+        None => (section.get("Palette"), section.get("BlockStates")),
+    };
+
+    let Some(palette) = palette.and_then(Tag::as_list) else {
+        return Ok(None);
+    };
+
+    let palette = resolver.palette(palette)?;
+
+    // A section with a single state has no data.
+    let Some(data) = data.and_then(Tag::as_longs) else {
+        return Ok(Some(vec![palette.first().copied().flatten(); 4096]));
+    };
+
+    let bits = bits_for(palette.len(), 4);
+    let spanning = data_version < NO_SPANNING_VERSION;
+
+    let blocks = (0..4096)
+        .map(|index| {
+            let state = unpack(data, bits, index, spanning).ok_or_else(|| invalid("block states"))?;
+            Ok(palette.get(state).copied().flatten())
+        })
+        .collect::<Result<_, ImportError>>()?;
+
+    Ok(Some(blocks))
+}
+
+/// Turns a Minecraft chunk into the column of chunks with the same x and z, from the lowest to the
+/// highest section that it has. Blocks without a mapping become air. The chunks are lit from the
+/// top of the column. Chunks from before 1.13, whose sections have the numeric ids of the blocks
+/// instead of a palette, are [None].
+fn import_chunk(
+    chunk: &Tag,
+    resolver: &mut StateResolver,
+    registry: &BlockRegistry,
+) -> Result<Option<Vec<(ChunkPosition, Chunk)>>, ImportError> {
+    let data_version = chunk.get("DataVersion").and_then(Tag::as_i64).unwrap_or(0);
+    let level = chunk.get("Level").unwrap_or(chunk);
+
+    let [Some(x), Some(z)] = ["xPos", "zPos"].map(|key| level.get(key).and_then(Tag::as_i64)) else {
+        return Err(invalid("chunk position"));
+    };
+
+    let sections = level
+        .get("sections")
+        .or_else(|| level.get("Sections"))
+        .and_then(Tag::as_list)
+        .unwrap_or_default();
+
+    if sections.iter().any(|section| section.get("Blocks").is_some()) {
+        return Ok(None);
+    }
+
+    let mut column: BTreeMap<i64, Chunk> = BTreeMap::new();
+
+    for section in sections {
+        let Some(section_y) = section.get("Y").and_then(Tag::as_i64) else { continue };
+        let Some(blocks) = section_blocks(section, resolver, data_version)? else { continue };
+
+        for (index, block) in blocks.into_iter().enumerate() {
+            let Some(block) = block.filter(|block| *block != AIR) else { continue };
+
+            let (block_x, block_z) = ((index % 16) as i64, (index / 16 % 16) as i64);
+            let block_y = section_y * 16 + (index / 256) as i64;
+
+            let (position, local) = ChunkPosition::of_block(block_x, block_y, block_z);
+            column.entry(position.y).or_default().set(&local, block);
+        }
+    }
+
+    let (Some(lowest), Some(highest)) = (column.keys().next().copied(), column.keys().last().copied()) else {
+        return Ok(Some(Vec::new()));
+    };
+
+    let mut chunks = Vec::new();
+    let mut top = [MAX_LIGHT; CHUNK_WIDTH * CHUNK_LENGTH];
+
+    for y in (lowest..=highest).rev() {
+        let mut chunk = column.remove(&y).unwrap_or_default();
+        light::light_chunk(&mut chunk, registry, &top);
+        top = light::bottom_light(&chunk);
+        chunks.push((ChunkPosition::new(x, y, z), chunk));
+    }
+
+    Ok(Some(chunks))
+}
+
+/// Reads the chunks of an Anvil region file and saves them to a storage, returning how many
+/// Minecraft chunks were imported. The chunks above and below the imported ones are still
+/// generated. Chunks compressed in a way other than gzip or zlib and chunks from before 1.13 are
+/// skipped with a warning.
+pub fn import_region(
+    path: impl AsRef<Path>,
+    mapping: &BlockStateMapping,
+    registry: &BlockRegistry,
+    storage: &dyn ChunkStorage,
+) -> Result<usize, ImportError> {
+    let bytes = fs::read(path)?;
+
+    if bytes.len() < SECTOR_SIZE {
+        return Err(invalid("header"));
+    }
+
+    let mut resolver = StateResolver::new(mapping, registry);
+    let mut imported = 0;
+
+    for entry in bytes[..SECTOR_SIZE].chunks_exact(4) {
+        let location = u32::from_be_bytes(entry.try_into().unwrap()) as usize;
+        let start = (location >> 8) * SECTOR_SIZE;
+
+        if location == 0 {
+            continue;
+        }
+
+        let Some(header) = bytes.get(start..start + 5) else {
+            return Err(invalid("chunk location"));
+        };
+
+        let length = u32::from_be_bytes(header[..4].try_into().unwrap()) as usize;
+        let compression = header[4];
+
+        if !matches!(compression, 1..=3) {
+            log::warn!("skipping a chunk with the unsupported compression {compression}");
+            continue;
+        }
+
+        let data = bytes.get(start + 5..start + 4 + length).ok_or_else(|| invalid("chunk length"))?;
+        let (_, chunk) = nbt::read(data)?;
+
+        let Some(chunks) = import_chunk(&chunk, &mut resolver, registry)? else {
+            log::warn!("skipping a chunk from before 1.13, which has no palettes");
+            continue;
+        };
+
+        for (position, chunk) in chunks {
+            storage.save(&position, &chunk)?;
+        }
+
+        imported += 1;
+    }
+
+    resolver.finish();
+
+    Ok(imported)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Mutex;
+
+    use flate2::{write::ZlibEncoder, Compression};
+
+    use super::*;
+    use crate::block::{BlockDefinition, Transparency};
+
+    fn compound(tags: &[(&str, Tag)]) -> Tag {
+        Tag::Compound(tags.iter().map(|(name, tag)| (name.to_string(), tag.clone())).collect())
+    }
+
+    fn vector(x: i64, y: i64, z: i64) -> Tag {
+        compound(&[("x", Tag::Int(x as i32)), ("y", Tag::Int(y as i32)), ("z", Tag::Int(z as i32))])
+    }
+
+    fn kind(tag: &Tag) -> u8 {
+        match tag {
+            Tag::Byte(_) => 1,
+            Tag::Short(_) => 2,
+            Tag::Int(_) => 3,
+            Tag::Long(_) => 4,
+            Tag::Float(_) => 5,
+            Tag::Double(_) => 6,
+            Tag::ByteArray(_) => 7,
+            Tag::String(_) => 8,
+            Tag::List(_) => 9,
+            Tag::Compound(_) => 10,
+            Tag::IntArray(_) => 11,
+            Tag::LongArray(_) => 12,
+        }
+    }
+
+    fn write_string(bytes: &mut Vec<u8>, string: &str) {
+        bytes.extend_from_slice(&(string.len() as u16).to_be_bytes());
+        bytes.extend_from_slice(string.as_bytes());
+    }
+
+    fn write_payload(bytes: &mut Vec<u8>, tag: &Tag) {
+        match tag {
+            Tag::Byte(value) => bytes.extend_from_slice(&value.to_be_bytes()),
+            Tag::Short(value) => bytes.extend_from_slice(&value.to_be_bytes()),
+            Tag::Int(value) => bytes.extend_from_slice(&value.to_be_bytes()),
+            Tag::Long(value) => bytes.extend_from_slice(&value.to_be_bytes()),
+            Tag::Float(value) => bytes.extend_from_slice(&value.to_be_bytes()),
+            Tag::Double(value) => bytes.extend_from_slice(&value.to_be_bytes()),
+            Tag::ByteArray(values) => {
+                bytes.extend_from_slice(&(values.len() as i32).to_be_bytes());
+                bytes.extend(values.iter().map(|value| *value as u8));
+            }
+            Tag::String(value) => write_string(bytes, value),
+            Tag::List(tags) => {
+                bytes.push(tags.first().map_or(0, kind));
+                bytes.extend_from_slice(&(tags.len() as i32).to_be_bytes());
+                tags.iter().for_each(|tag| write_payload(bytes, tag));
+            }
+            Tag::Compound(tags) => {
+                for (name, tag) in tags {
+                    bytes.push(kind(tag));
+                    write_string(bytes, name);
+                    write_payload(bytes, tag);
+                }
+                bytes.push(0);
+            }
+            Tag::IntArray(values) => {
+                bytes.extend_from_slice(&(values.len() as i32).to_be_bytes());
+                values.iter().for_each(|value| bytes.extend_from_slice(&value.to_be_bytes()));
+            }
+            Tag::LongArray(values) => {
+                bytes.extend_from_slice(&(values.len() as i32).to_be_bytes());
+                values.iter().for_each(|value| bytes.extend_from_slice(&value.to_be_bytes()));
+            }
+        }
+    }
+
+    /// Writes a root compound without a name, uncompressed.
+    fn write(root: &Tag) -> Vec<u8> {
+        let mut bytes = vec![10];
+        write_string(&mut bytes, "");
+        write_payload(&mut bytes, root);
+        bytes
+    }
+
+    fn registry() -> BlockRegistry {
+        let mut registry = BlockRegistry::default();
+        registry.register(BlockDefinition::new("stone", Transparency::Opaque, 0));
+        registry
+    }
+
+    fn mapping() -> BlockStateMapping {
+        BlockStateMapping::default().with_state("minecraft:stone", "stone")
+    }
+
+    fn palette() -> Tag {
+        Tag::List(vec![
+            compound(&[("Name", Tag::String("minecraft:air".to_owned()))]),
+            compound(&[("Name", Tag::String("minecraft:stone".to_owned()))]),
+        ])
+    }
+
+    fn litematic(position: Tag, size: Tag, states: Vec<i64>) -> Vec<u8> {
+        let region = compound(&[
+            ("Position", position),
+            ("Size", size),
+            ("BlockStatePalette", palette()),
+            ("BlockStates", Tag::LongArray(states)),
+        ]);
+
+        write(&compound(&[("Regions", compound(&[("region", region)]))]))
+    }
+
+    fn assert_invalid<T>(result: Result<T, ImportError>) {
+        assert!(matches!(result, Err(ImportError::Invalid(_))));
+    }
+
+    #[test]
+    fn reads_a_litematic() {
+        // Two bits for each block, all of them stone but the first one, whose air has no mapping.
+        let bytes = litematic(vector(5, 0, 0), vector(-2, 1, 2), vec![0b01010100]);
+        let template = parse_litematic(&bytes, "test", &mapping(), &registry()).unwrap();
+
+        assert_eq!(template.size, [2, 1, 2]);
+        assert_eq!(template.blocks().count(), 3);
+        assert_eq!(template.get(0, 0, 0), None);
+        assert_eq!(template.get(1, 0, 1), registry().by_name("stone"));
+    }
+
+    #[test]
+    fn rejects_litematics_that_are_too_big() {
+        let huge = litematic(vector(0, 0, 0), vector(i32::MAX as i64, i32::MAX as i64, 16), vec![0; 4]);
+        assert_invalid(parse_litematic(&huge, "test", &mapping(), &registry()));
+
+        // Small regions that are far from each other make a huge template.
+        let region = |x: i64| {
+            compound(&[
+                ("Position", vector(x, 0, 0)),
+                ("Size", vector(1, 1, 1)),
+                ("BlockStatePalette", palette()),
+                ("BlockStates", Tag::LongArray(vec![0])),
+            ])
+        };
+        let regions = compound(&[("first", region(i32::MIN as i64)), ("second", region(i32::MAX as i64))]);
+        let far = write(&compound(&[("Regions", regions)]));
+        assert_invalid(parse_litematic(&far, "test", &mapping(), &registry()));
+    }
+
+    #[test]
+    fn rejects_litematics_without_enough_states() {
+        let bytes = litematic(vector(0, 0, 0), vector(64, 64, 64), vec![0; 16]);
+        assert_invalid(parse_litematic(&bytes, "test", &mapping(), &registry()));
+    }
+
+    #[test]
+    fn rejects_schematics_that_are_too_big() {
+        let palette = compound(&[("minecraft:stone", Tag::Int(0))]);
+        let schematic = compound(&[
+            ("Width", Tag::Short(-1)),
+            ("Height", Tag::Short(-1)),
+            ("Length", Tag::Short(-1)),
+            ("Palette", palette),
+            ("BlockData", Tag::ByteArray(vec![0; 64])),
+        ]);
+
+        assert_invalid(parse_schematic(&write(&schematic), "test", &mapping(), &registry()));
+    }
+
+    /// A storage that remembers the positions of the chunks that are saved in it.
+    #[derive(Default)]
+    struct Saved(Mutex<Vec<ChunkPosition>>);
+
+    impl ChunkStorage for Saved {
+        fn load(&self, _position: &ChunkPosition) -> std::io::Result<Option<Chunk>> {
+            Ok(None)
+        }
+
+        fn save(&self, position: &ChunkPosition, _chunk: &Chunk) -> std::io::Result<()> {
+            self.0.lock().unwrap().push(*position);
+            Ok(())
+        }
+    }
+
+    /// A region file with a chunk at each of the first positions of the header.
+    fn region(chunks: &[Tag]) -> Vec<u8> {
+        let mut bytes = vec![0; SECTOR_SIZE];
+
+        for (index, chunk) in chunks.iter().enumerate() {
+            let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+            std::io::Write::write_all(&mut encoder, &write(chunk)).unwrap();
+            let data = encoder.finish().unwrap();
+
+            let sector = bytes.len() / SECTOR_SIZE;
+            let location = ((sector << 8) | 1) as u32;
+            bytes[index * 4..index * 4 + 4].copy_from_slice(&location.to_be_bytes());
+
+            bytes.extend_from_slice(&(data.len() as u32 + 1).to_be_bytes());
+            bytes.push(2);
+            bytes.extend_from_slice(&data);
+            bytes.resize(bytes.len().div_ceil(SECTOR_SIZE) * SECTOR_SIZE, 0);
+        }
+
+        bytes
+    }
+
+    #[test]
+    fn skips_chunks_from_before_palettes() {
+        let old = compound(&[(
+            "Level",
+            compound(&[
+                ("xPos", Tag::Int(0)),
+                ("zPos", Tag::Int(0)),
+                ("Sections", Tag::List(vec![compound(&[("Y", Tag::Byte(0)), ("Blocks", Tag::ByteArray(vec![1; 16]))])])),
+            ]),
+        )]);
+
+        let stone = compound(&[("Name", Tag::String("minecraft:stone".to_owned()))]);
+        let states = compound(&[("palette", Tag::List(vec![stone]))]);
+        let new = compound(&[
+            ("DataVersion", Tag::Int(3465)),
+            ("xPos", Tag::Int(1)),
+            ("zPos", Tag::Int(0)),
+            ("sections", Tag::List(vec![compound(&[("Y", Tag::Byte(0)), ("block_states", states)])])),
+        ]);
+
+        let directory = std::env::temp_dir().join(format!("voxelia-anvil-{}", std::process::id()));
+        std::fs::create_dir_all(&directory).unwrap();
+        let path = directory.join("r.0.0.mca");
+        std::fs::write(&path, region(&[old, new])).unwrap();
+
+        let storage = Saved::default();
+        let imported = import_region(&path, &mapping(), &registry(), &storage);
+        let _ = std::fs::remove_dir_all(&directory);
+
+        assert_eq!(imported.unwrap(), 1);
+        let saved = storage.0.lock().unwrap();
+        assert!(!saved.is_empty());
+        assert!(saved.iter().all(|position| position.x == 1));
+    }
+}
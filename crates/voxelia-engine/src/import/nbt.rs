@@ -0,0 +1,273 @@
+//! Reader of NBT, the binary format of the files of Minecraft.
+//!
+//! Every value is a tag with a type, and the root of a file is a compound with a name. Numbers are
+//! big-endian and strings have their length in bytes before them. Files are usually compressed with
+//! gzip, and the chunks of region files with zlib.
+
+use std::{collections::HashMap, io::Read};
+
+use flate2::read::{GzDecoder, ZlibDecoder};
+
+use super::ImportError;
+
+/// Most bytes that a file can have once it's decompressed, so small files that expand to gigabytes
+/// cannot take all the memory.
+pub const MAX_DECOMPRESSED: u64 = 64 << 20;
+
+/// Deepest that lists and compounds can be nested, to not overflow the stack with broken files.
+const MAX_DEPTH: usize = 512;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Tag {
+    Byte(i8),
+    Short(i16),
+    Int(i32),
+    Long(i64),
+    Float(f32),
+    Double(f64),
+    ByteArray(Vec<i8>),
+    String(String),
+    List(Vec<Tag>),
+    Compound(HashMap<String, Tag>),
+    IntArray(Vec<i32>),
+    LongArray(Vec<i64>),
+}
+
+impl Tag {
+    /// Gets a tag of a compound.
+    pub fn get(&self, key: &str) -> Option<&Tag> {
+        match self {
+            Tag::Compound(tags) => tags.get(key),
+            _ => None,
+        }
+    }
+
+    /// Value of any tag of a whole number.
+    pub fn as_i64(&self) -> Option<i64> {
+        match *self {
+            Tag::Byte(value) => Some(value as i64),
+            Tag::Short(value) => Some(value as i64),
+            Tag::Int(value) => Some(value as i64),
+            Tag::Long(value) => Some(value),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Tag::String(value) => Some(value),
+            _ => None,
+        }
+    }
+
+    pub fn as_list(&self) -> Option<&[Tag]> {
+        match self {
+            Tag::List(tags) => Some(tags),
+            _ => None,
+        }
+    }
+
+    pub fn as_compound(&self) -> Option<&HashMap<String, Tag>> {
+        match self {
+            Tag::Compound(tags) => Some(tags),
+            _ => None,
+        }
+    }
+
+    pub fn as_bytes(&self) -> Option<&[i8]> {
+        match self {
+            Tag::ByteArray(values) => Some(values),
+            _ => None,
+        }
+    }
+
+    pub fn as_ints(&self) -> Option<&[i32]> {
+        match self {
+            Tag::IntArray(values) => Some(values),
+            _ => None,
+        }
+    }
+
+    pub fn as_longs(&self) -> Option<&[i64]> {
+        match self {
+            Tag::LongArray(values) => Some(values),
+            _ => None,
+        }
+    }
+}
+
+/// Decompresses data that is compressed with gzip or zlib, and returns the data that is not as it
+/// is. Data that is bigger than [MAX_DECOMPRESSED] once it's decompressed is invalid.
+pub fn decompress(bytes: &[u8]) -> Result<Vec<u8>, ImportError> {
+    let mut data = Vec::new();
+
+    match bytes {
+        [0x1f, 0x8b, ..] => GzDecoder::new(bytes).take(MAX_DECOMPRESSED + 1).read_to_end(&mut data)?,
+        [0x78, ..] => ZlibDecoder::new(bytes).take(MAX_DECOMPRESSED + 1).read_to_end(&mut data)?,
+        _ => return Ok(bytes.to_vec()),
+    };
+
+    if data.len() as u64 > MAX_DECOMPRESSED {
+        return Err(ImportError::Invalid(format!("the file has more than {MAX_DECOMPRESSED} bytes once it's decompressed")));
+    }
+
+    Ok(data)
+}
+
+/// Reads the root tag of a file with its name, decompressing it first if it's compressed.
+pub fn read(bytes: &[u8]) -> Result<(String, Tag), ImportError> {
+    let data = decompress(bytes)?;
+    let mut reader = Reader { bytes: &data };
+
+    let kind = reader.u8()?;
+    if kind != 10 {
+        return Err(ImportError::Invalid("the root of the file is not a compound".to_owned()));
+    }
+
+    let name = reader.string()?;
+    let root = reader.tag(kind, 0)?;
+
+    Ok((name, root))
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, count: usize) -> Result<&'a [u8], ImportError> {
+        if self.bytes.len() < count {
+            return Err(ImportError::Invalid("the file ends too early".to_owned()));
+        }
+
+        let (taken, rest) = self.bytes.split_at(count);
+        self.bytes = rest;
+        Ok(taken)
+    }
+
+    fn array<const N: usize>(&mut self) -> Result<[u8; N], ImportError> {
+        Ok(self.take(N)?.try_into().unwrap())
+    }
+
+    fn u8(&mut self) -> Result<u8, ImportError> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn length(&mut self) -> Result<usize, ImportError> {
+        let length = i32::from_be_bytes(self.array()?);
+        usize::try_from(length).map_err(|_| ImportError::Invalid("negative length".to_owned()))
+    }
+
+    /// Reads a string. They are in a modified UTF-8, that's the same for everything but the null
+    /// character and the characters outside of the basic plane, which are rare in block names.
+    fn string(&mut self) -> Result<String, ImportError> {
+        let length = u16::from_be_bytes(self.array()?) as usize;
+        Ok(String::from_utf8_lossy(self.take(length)?).into_owned())
+    }
+
+    /// Reads the values of an array, never reserving more than what the file can have.
+    fn values<T, const N: usize>(&mut self, parse: fn([u8; N]) -> T) -> Result<Vec<T>, ImportError> {
+        let length = self.length()?;
+        let mut values = Vec::with_capacity(length.min(self.bytes.len() / N));
+
+        for _ in 0..length {
+            values.push(parse(self.array()?));
+        }
+
+        Ok(values)
+    }
+
+    fn tag(&mut self, kind: u8, depth: usize) -> Result<Tag, ImportError> {
+        if depth > MAX_DEPTH {
+            return Err(ImportError::Invalid("the tags are nested too deep".to_owned()));
+        }
+
+        let tag = match kind {
+            1 => Tag::Byte(i8::from_be_bytes(self.array()?)),
+            2 => Tag::Short(i16::from_be_bytes(self.array()?)),
+            3 => Tag::Int(i32::from_be_bytes(self.array()?)),
+            4 => Tag::Long(i64::from_be_bytes(self.array()?)),
+            5 => Tag::Float(f32::from_be_bytes(self.array()?)),
+            6 => Tag::Double(f64::from_be_bytes(self.array()?)),
+            7 => Tag::ByteArray(self.values(i8::from_be_bytes)?),
+            8 => Tag::String(self.string()?),
+            9 => {
+                let kind = self.u8()?;
+                let length = self.length()?;
+                let mut tags = Vec::with_capacity(length.min(self.bytes.len()));
+
+                // Lists of nothing have the type of the end tag.
+                if kind != 0 {
+                    for _ in 0..length {
+                        tags.push(self.tag(kind, depth + 1)?);
+                    }
+                }
+
+                Tag::List(tags)
+            }
+            10 => {
+                let mut tags = HashMap::new();
+
+                loop {
+                    let kind = self.u8()?;
+                    if kind == 0 {
+                        break;
+                    }
+
+                    let name = self.string()?;
+                    tags.insert(name, self.tag(kind, depth + 1)?);
+                }
+
+                Tag::Compound(tags)
+            }
+            11 => Tag::IntArray(self.values(i32::from_be_bytes)?),
+            12 => Tag::LongArray(self.values(i64::from_be_bytes)?),
+            kind => return Err(ImportError::Invalid(format!("unknown tag type {kind}"))),
+        };
+
+        Ok(tag)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use flate2::{
+        write::{GzEncoder, ZlibEncoder},
+        Compression,
+    };
+
+    use super::*;
+
+    /// A compound named `root` with a byte named `answer`.
+    const FILE: [u8; 19] = [10, 0, 4, b'r', b'o', b'o', b't', 1, 0, 6, b'a', b'n', b's', b'w', b'e', b'r', 42, 0, 0];
+
+    fn gzip(bytes: &[u8]) -> Vec<u8> {
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(bytes).unwrap();
+        encoder.finish().unwrap()
+    }
+
+    #[test]
+    fn reads_compressed_files() {
+        let (name, root) = read(&gzip(&FILE)).unwrap();
+
+        assert_eq!(name, "root");
+        assert_eq!(root.get("answer"), Some(&Tag::Byte(42)));
+        assert_eq!(read(&FILE).unwrap().1, root);
+    }
+
+    #[test]
+    fn rejects_files_that_decompress_to_too_much() {
+        let zeros = vec![0; MAX_DECOMPRESSED as usize + 1];
+
+        let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(&zeros).unwrap();
+        let zlib = encoder.finish().unwrap();
+
+        assert!(matches!(decompress(&zlib), Err(ImportError::Invalid(_))));
+        assert!(matches!(decompress(&gzip(&zeros)), Err(ImportError::Invalid(_))));
+        assert_eq!(decompress(&gzip(&zeros[1..])).unwrap().len(), zeros.len() - 1);
+    }
+}
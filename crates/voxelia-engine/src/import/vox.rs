@@ -0,0 +1,599 @@
+//! Importer of the `.vox` models of MagicaVoxel.
+//!
+//! A file has the models, the palette of their colors and the scene that places copies of the
+//! models in the space, each with a translation and a rotation. The whole scene is imported as a
+//! single template. Models are z-up in MagicaVoxel, so their z becomes the y of the world, and their
+//! y becomes -z to keep the front of the model looking at the same side.
+//!
+//! Colors become blocks with a [ColorMapping]. It can give a block to a color of the palette, and
+//! the colors without one get the block with the nearest color.
+
+use std::{collections::HashMap, fs, path::Path};
+
+use serde::Deserialize;
+
+use crate::{
+    block::{BlockId, BlockRegistry, AIR},
+    generation::structures::template::StructureTemplate,
+};
+
+use super::{volume, ImportError, MAX_VOLUME};
+
+/// Farthest that a transform can move its children on each axis. MagicaVoxel keeps its scenes
+/// within a few thousand voxels of the center.
+const MAX_TRANSLATION: i64 = 1 << 20;
+
+/// Most nodes that are visited while the scene graph is read. Groups that have the same child many
+/// times make copies of it, so broken files could have more of them than atoms in the universe.
+const MAX_VISITS: usize = 1 << 20;
+
+/// RGBA colors of the palette by their index. The index 0 is not used by any voxel.
+pub type Palette = [[u8; 4]; 256];
+
+/// A grid of voxels. Each voxel has the index of its color in the palette.
+#[derive(Clone, Debug)]
+pub struct VoxModel {
+    pub size: [i64; 3],
+    pub voxels: Vec<([i64; 3], u8)>,
+}
+
+/// A copy of a model in the scene.
+#[derive(Clone, Debug)]
+pub struct VoxInstance {
+    pub model: usize,
+    /// Rows of the rotation matrix, that only has 0, 1 and -1.
+    pub rotation: [[i64; 3]; 3],
+    /// Position of the center of the model.
+    pub translation: [i64; 3],
+}
+
+const IDENTITY: [[i64; 3]; 3] = [[1, 0, 0], [0, 1, 0], [0, 0, 1]];
+
+/// Everything that is in a `.vox` file, in the coordinates of MagicaVoxel.
+#[derive(Clone, Debug)]
+pub struct VoxScene {
+    pub models: Vec<VoxModel>,
+    pub instances: Vec<VoxInstance>,
+    pub palette: Palette,
+}
+
+/// Nodes of the scene graph of a file.
+enum Node {
+    Transform {
+        child: i32,
+        rotation: [[i64; 3]; 3],
+        translation: [i64; 3],
+    },
+    Group(Vec<i32>),
+    Shape(Vec<usize>),
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, count: usize) -> Result<&'a [u8], ImportError> {
+        if self.bytes.len() < count {
+            return Err(ImportError::Invalid("the file ends too early".to_owned()));
+        }
+
+        let (taken, rest) = self.bytes.split_at(count);
+        self.bytes = rest;
+        Ok(taken)
+    }
+
+    fn i32(&mut self) -> Result<i32, ImportError> {
+        Ok(i32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    /// Reads a count, that cannot be negative.
+    fn count(&mut self) -> Result<usize, ImportError> {
+        usize::try_from(self.i32()?).map_err(|_| ImportError::Invalid("negative count".to_owned()))
+    }
+
+    fn string(&mut self) -> Result<String, ImportError> {
+        let length = self.count()?;
+        Ok(String::from_utf8_lossy(self.take(length)?).into_owned())
+    }
+
+    fn dictionary(&mut self) -> Result<HashMap<String, String>, ImportError> {
+        let count = self.count()?;
+        (0..count).map(|_| Ok((self.string()?, self.string()?))).collect()
+    }
+}
+
+/// Palette that MagicaVoxel uses when a file does not have one. It has a cube of colors followed by
+/// ramps of blue, green, red and gray.
+fn default_palette() -> Palette {
+    const STEPS: [u8; 6] = [0xff, 0xcc, 0x99, 0x66, 0x33, 0x00];
+    const RAMP: [u8; 10] = [0xee, 0xdd, 0xbb, 0xaa, 0x88, 0x77, 0x55, 0x44, 0x22, 0x11];
+
+    let mut colors = Vec::with_capacity(256);
+    colors.push([0, 0, 0, 0]);
+
+    for r in STEPS {
+        for g in STEPS {
+            for b in STEPS {
+                colors.push([r, g, b, 0xff]);
+            }
+        }
+    }
+
+    // The last color of the cube is black, that's in the gray ramp.
+    colors.pop();
+
+    for channel in [2, 1, 0] {
+        for value in RAMP {
+            let mut color = [0, 0, 0, 0xff];
+            color[channel] = value;
+            colors.push(color);
+        }
+    }
+
+    for value in RAMP {
+        colors.push([value, value, value, 0xff]);
+    }
+
+    colors.try_into().unwrap()
+}
+
+/// Reads the rotation of a transform, a byte with the column of the non-zero value of the first and
+/// second rows and the signs of the three rows.
+fn rotation(byte: u8) -> [[i64; 3]; 3] {
+    let first = (byte & 3) as usize;
+    let second = ((byte >> 2) & 3) as usize;
+    let third = 3usize.saturating_sub(first + second).min(2);
+
+    let mut matrix = [[0; 3]; 3];
+    for (row, column) in [first, second, third].into_iter().enumerate() {
+        matrix[row][column] = if byte & (1 << (4 + row)) != 0 { -1 } else { 1 };
+    }
+
+    matrix
+}
+
+fn multiply(matrix: &[[i64; 3]; 3], vector: [i64; 3]) -> [i64; 3] {
+    matrix.map(|row| row[0] * vector[0] + row[1] * vector[1] + row[2] * vector[2])
+}
+
+fn compose(a: &[[i64; 3]; 3], b: &[[i64; 3]; 3]) -> [[i64; 3]; 3] {
+    std::array::from_fn(|row| std::array::from_fn(|column| (0..3).map(|k| a[row][k] * b[k][column]).sum()))
+}
+
+impl VoxScene {
+    pub fn load(path: impl AsRef<Path>) -> Result<VoxScene, ImportError> {
+        Self::parse(&fs::read(path)?)
+    }
+
+    pub fn parse(bytes: &[u8]) -> Result<VoxScene, ImportError> {
+        let mut reader = Reader { bytes };
+
+        if reader.take(4)? != b"VOX " {
+            return Err(ImportError::Invalid("it's not a .vox file".to_owned()));
+        }
+
+        let _version = reader.i32()?;
+
+        if reader.take(4)? != b"MAIN" {
+            return Err(ImportError::Invalid("the file has no main chunk".to_owned()));
+        }
+
+        let content = reader.count()?;
+        let children = reader.count()?;
+        reader.take(content)?;
+        let mut reader = Reader { bytes: reader.take(children)? };
+
+        let mut sizes = Vec::new();
+        let mut models = Vec::new();
+        let mut palette = default_palette();
+        let mut nodes = HashMap::new();
+
+        while !reader.bytes.is_empty() {
+            let id: [u8; 4] = reader.take(4)?.try_into().unwrap();
+            let content = reader.count()?;
+            let children = reader.count()?;
+            let mut chunk = Reader { bytes: reader.take(content)? };
+            reader.take(children)?;
+
+            match &id {
+                b"SIZE" => sizes.push([chunk.i32()?, chunk.i32()?, chunk.i32()?].map(i64::from)),
+                b"XYZI" => {
+                    let size = *sizes
+                        .get(models.len())
+                        .ok_or_else(|| ImportError::Invalid("a model has no size".to_owned()))?;
+
+                    let count = chunk.count()?;
+                    let mut voxels = Vec::with_capacity(count.min(chunk.bytes.len() / 4));
+
+                    for _ in 0..count {
+                        let [x, y, z, color] = chunk.take(4)?.try_into().unwrap();
+                        voxels.push(([x, y, z].map(i64::from), color));
+                    }
+
+                    models.push(VoxModel { size, voxels });
+                }
+                b"RGBA" => {
+                    for color in palette.iter_mut().skip(1) {
+                        *color = chunk.take(4)?.try_into().unwrap();
+                    }
+                }
+                b"nTRN" => {
+                    let node = chunk.i32()?;
+                    chunk.dictionary()?;
+                    let child = chunk.i32()?;
+                    let _reserved = chunk.i32()?;
+                    let _layer = chunk.i32()?;
+                    let frames = chunk.count()?;
+
+                    // Only the first frame of animations is imported.
+                    let frame = if frames > 0 { chunk.dictionary()? } else { HashMap::new() };
+
+                    let rotation = match frame.get("_r") {
+                        Some(value) => rotation(value.parse().map_err(|_| ImportError::Invalid("bad rotation".to_owned()))?),
+                        None => IDENTITY,
+                    };
+
+                    let mut translation = [0; 3];
+                    if let Some(value) = frame.get("_t") {
+                        for (axis, part) in value.split_whitespace().take(3).enumerate() {
+                            translation[axis] = part
+                                .parse()
+                                .ok()
+                                .filter(|value: &i64| value.abs() <= MAX_TRANSLATION)
+                                .ok_or_else(|| ImportError::Invalid(format!("bad translation '{part}'")))?;
+                        }
+                    }
+
+                    nodes.insert(node, Node::Transform { child, rotation, translation });
+                }
+                b"nGRP" => {
+                    let node = chunk.i32()?;
+                    chunk.dictionary()?;
+                    let count = chunk.count()?;
+                    let children = (0..count).map(|_| chunk.i32()).collect::<Result<_, _>>()?;
+                    nodes.insert(node, Node::Group(children));
+                }
+                b"nSHP" => {
+                    let node = chunk.i32()?;
+                    chunk.dictionary()?;
+                    let count = chunk.count()?;
+                    let mut shapes = Vec::with_capacity(count.min(64));
+
+                    for _ in 0..count {
+                        shapes.push(chunk.count()?);
+                        chunk.dictionary()?;
+                    }
+
+                    nodes.insert(node, Node::Shape(shapes));
+                }
+                _ => {}
+            }
+        }
+
+        let mut instances = Vec::new();
+
+        if nodes.is_empty() {
+            instances.extend((0..models.len()).map(|model| VoxInstance {
+                model,
+                rotation: IDENTITY,
+                translation: [0; 3],
+            }));
+        } else {
+            let mut stack = vec![(0, IDENTITY, [0i64; 3], 0)];
+            let mut visits = 0;
+
+            while let Some((node, rotation, translation, depth)) = stack.pop() {
+                // Broken files could have cycles in their graph.
+                if depth > nodes.len() {
+                    return Err(ImportError::Invalid("the scene has a cycle".to_owned()));
+                }
+
+                visits += 1;
+                if visits > MAX_VISITS {
+                    return Err(ImportError::Invalid("the scene has too many nodes".to_owned()));
+                }
+
+                match nodes.get(&node) {
+                    Some(Node::Transform { child, rotation: local, translation: offset }) => {
+                        let moved = multiply(&rotation, *offset);
+                        let translation = [0, 1, 2].map(|axis| translation[axis].checked_add(moved[axis]));
+                        let [Some(x), Some(y), Some(z)] = translation else {
+                            return Err(ImportError::Invalid("the scene is too big".to_owned()));
+                        };
+
+                        stack.push((*child, compose(&rotation, local), [x, y, z], depth + 1));
+                    }
+                    Some(Node::Group(children)) => {
+                        stack.extend(children.iter().map(|child| (*child, rotation, translation, depth + 1)));
+                    }
+                    Some(Node::Shape(shapes)) => {
+                        instances.extend(shapes.iter().map(|model| VoxInstance {
+                            model: *model,
+                            rotation,
+                            translation,
+                        }));
+                    }
+                    None => return Err(ImportError::Invalid(format!("the scene has no node {node}"))),
+                }
+            }
+        }
+
+        if let Some(instance) = instances.iter().find(|instance| instance.model >= models.len()) {
+            return Err(ImportError::Invalid(format!("the scene has no model {}", instance.model)));
+        }
+
+        // The copies of the models are as slow to import as the voxels that they would have.
+        let voxels = instances
+            .iter()
+            .map(|instance| models[instance.model].voxels.len())
+            .fold(0usize, usize::saturating_add);
+        if voxels > MAX_VOLUME {
+            return Err(ImportError::Invalid(format!("the scene has {voxels} voxels, more than {MAX_VOLUME}")));
+        }
+
+        Ok(VoxScene { models, instances, palette })
+    }
+
+    /// Voxels of every instance of the scene with their color, in the coordinates of the world.
+    pub fn voxels(&self) -> impl Iterator<Item = ([i64; 3], u8)> + '_ {
+        self.instances.iter().flat_map(move |instance| {
+            let model = &self.models[instance.model];
+
+            model.voxels.iter().map(move |(position, color)| {
+                // Models are rotated around their center.
+                let centered = [0, 1, 2].map(|axis| position[axis] - model.size[axis] / 2);
+                let rotated = multiply(&instance.rotation, centered);
+                let [x, y, z] = [0, 1, 2].map(|axis| rotated[axis] + instance.translation[axis]);
+
+                ([x, z, -y], *color)
+            })
+        })
+    }
+
+    /// Turns the whole scene into a template whose lowest corner is the lowest voxel. The places
+    /// without voxels keep the block that was already in the world.
+    pub fn to_template(
+        &self,
+        name: &str,
+        mapping: &ColorMapping,
+        registry: &BlockRegistry,
+    ) -> Result<StructureTemplate, ImportError> {
+        let blocks = mapping.resolve(&self.palette, registry)?;
+
+        let mut min = [i64::MAX; 3];
+        let mut max = [i64::MIN; 3];
+
+        for (position, _) in self.voxels() {
+            for axis in 0..3 {
+                min[axis] = min[axis].min(position[axis]);
+                max[axis] = max[axis].max(position[axis]);
+            }
+        }
+
+        if min[0] > max[0] {
+            return Ok(StructureTemplate::new(name, [0; 3], Vec::new(), Vec::new()));
+        }
+
+        // Translations are bounded, so the size cannot overflow.
+        let size = [0, 1, 2].map(|axis| max[axis] - min[axis] + 1);
+        let mut template = vec![None; volume(size)?];
+
+        for (position, color) in self.voxels() {
+            let [x, y, z] = [0, 1, 2].map(|axis| position[axis] - min[axis]);
+            template[((y * size[2] + z) * size[0] + x) as usize] = blocks[color as usize];
+        }
+
+        Ok(StructureTemplate::new(name, size, template, Vec::new()))
+    }
+}
+
+/// Which block each color of a palette becomes, loaded from RON files like:
+///
+/// ```ron
+/// (
+///     colors: [((125, 125, 125), "stone"), ((95, 159, 53), "grass")],
+///     indices: { 12: "planks", 255: "air" },
+/// )
+/// ```
+///
+/// The indices of the palette have the block that is given to them, and the other colors get the
+/// block of the nearest color in `colors`. Blocks in `colors` that are not registered are ignored, so
+/// the same mapping works with any set of plugins.
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct ColorMapping {
+    pub colors: Vec<([u8; 3], String)>,
+    pub indices: HashMap<u8, String>,
+}
+
+impl ColorMapping {
+    /// Mapping with the approximate colors of the blocks of the engine.
+    pub fn blocks() -> ColorMapping {
+        let colors = [
+            ([125, 125, 125], "stone"),
+            ([100, 100, 100], "cobblestone"),
+            ([136, 126, 126], "gravel"),
+            ([134, 96, 67], "dirt"),
+            ([95, 159, 53], "grass"),
+            ([219, 207, 163], "sand"),
+            ([240, 240, 250], "snow"),
+            ([102, 81, 51], "log"),
+            ([162, 130, 78], "planks"),
+            ([60, 120, 40], "leaves"),
+        ];
+
+        ColorMapping {
+            colors: colors.into_iter().map(|(color, name)| (color, name.to_owned())).collect(),
+            indices: HashMap::new(),
+        }
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> Result<ColorMapping, ImportError> {
+        let source = fs::read_to_string(path)?;
+        ron::from_str(&source).map_err(|error| ImportError::Invalid(error.to_string()))
+    }
+
+    pub fn with_color(mut self, color: [u8; 3], block: &str) -> ColorMapping {
+        self.colors.push((color, block.to_owned()));
+        self
+    }
+
+    pub fn with_index(mut self, index: u8, block: &str) -> ColorMapping {
+        self.indices.insert(index, block.to_owned());
+        self
+    }
+
+    /// The block of each index of a palette, [None] for the ones that have none.
+    pub fn resolve(&self, palette: &Palette, registry: &BlockRegistry) -> Result<[Option<BlockId>; 256], ImportError> {
+        let find = |name: &str| match name {
+            "air" => Some(AIR),
+            name => registry.by_name(name),
+        };
+
+        let colors: Vec<([u8; 3], BlockId)> =
+            self.colors.iter().filter_map(|(color, name)| Some((*color, find(name)?))).collect();
+
+        let mut blocks = [None; 256];
+
+        for (index, block) in blocks.iter_mut().enumerate().skip(1) {
+            if let Some(name) = self.indices.get(&(index as u8)) {
+                *block = Some(find(name).ok_or_else(|| ImportError::UnknownBlock(name.clone()))?);
+                continue;
+            }
+
+            let [r, g, b, _] = palette[index].map(i32::from);
+
+            *block = colors
+                .iter()
+                .min_by_key(|(color, _)| {
+                    let [cr, cg, cb] = color.map(i32::from);
+                    (r - cr).pow(2) + (g - cg).pow(2) + (b - cb).pow(2)
+                })
+                .map(|(_, block)| *block);
+        }
+
+        Ok(blocks)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::block::{BlockDefinition, Transparency};
+
+    fn chunk(id: &[u8; 4], content: &[u8]) -> Vec<u8> {
+        let mut bytes = id.to_vec();
+        bytes.extend_from_slice(&(content.len() as i32).to_le_bytes());
+        bytes.extend_from_slice(&0i32.to_le_bytes());
+        bytes.extend_from_slice(content);
+        bytes
+    }
+
+    fn ints(values: &[i32]) -> Vec<u8> {
+        values.iter().flat_map(|value| value.to_le_bytes()).collect()
+    }
+
+    fn dictionary(entries: &[(&str, &str)]) -> Vec<u8> {
+        let mut bytes = ints(&[entries.len() as i32]);
+
+        for string in entries.iter().flat_map(|(key, value)| [key, value]) {
+            bytes.extend(ints(&[string.len() as i32]));
+            bytes.extend_from_slice(string.as_bytes());
+        }
+
+        bytes
+    }
+
+    /// A model of 2×2×2 voxels with two voxels of the color 1.
+    fn model() -> Vec<u8> {
+        let mut bytes = chunk(b"SIZE", &ints(&[2, 2, 2]));
+        bytes.extend(chunk(b"XYZI", &[ints(&[2]), vec![0, 0, 0, 1, 1, 1, 1, 1]].concat()));
+        bytes
+    }
+
+    fn transform(node: i32, child: i32, translation: &str) -> Vec<u8> {
+        let mut content = ints(&[node]);
+        content.extend(dictionary(&[]));
+        content.extend(ints(&[child, -1, 0, 1]));
+        content.extend(dictionary(&[("_t", translation)]));
+        chunk(b"nTRN", &content)
+    }
+
+    fn group(node: i32, children: &[i32]) -> Vec<u8> {
+        let content = [ints(&[node]), dictionary(&[]), ints(&[children.len() as i32]), ints(children)].concat();
+        chunk(b"nGRP", &content)
+    }
+
+    fn shape(node: i32) -> Vec<u8> {
+        let content = [ints(&[node]), dictionary(&[]), ints(&[1, 0]), dictionary(&[])].concat();
+        chunk(b"nSHP", &content)
+    }
+
+    fn file(chunks: &[Vec<u8>]) -> Vec<u8> {
+        let children = chunks.concat();
+        let mut bytes = b"VOX ".to_vec();
+        bytes.extend(ints(&[150]));
+        bytes.extend(b"MAIN");
+        bytes.extend(ints(&[0, children.len() as i32]));
+        bytes.extend(children);
+        bytes
+    }
+
+    fn assert_invalid<T>(result: Result<T, ImportError>) {
+        assert!(matches!(result, Err(ImportError::Invalid(_))));
+    }
+
+    #[test]
+    fn imports_the_instances_of_the_scene() {
+        let bytes = file(&[
+            model(),
+            transform(0, 1, "0 0 0"),
+            group(1, &[2, 4]),
+            transform(2, 3, "-4 0 0"),
+            shape(3),
+            transform(4, 3, "4 0 0"),
+        ]);
+
+        let scene = VoxScene::parse(&bytes).unwrap();
+        assert_eq!(scene.instances.len(), 2);
+
+        let mut registry = BlockRegistry::default();
+        let stone = registry.register(BlockDefinition::new("stone", Transparency::Opaque, 0));
+        let template = scene.to_template("test", &ColorMapping::default().with_index(1, "stone"), &registry).unwrap();
+
+        assert_eq!(template.size, [10, 2, 2]);
+        assert_eq!(template.blocks().filter(|(_, block)| *block == stone).count(), 4);
+    }
+
+    #[test]
+    fn rejects_translations_that_are_too_far() {
+        let bytes = file(&[model(), transform(0, 1, "9223372036854775807 0 0"), shape(1)]);
+        assert_invalid(VoxScene::parse(&bytes));
+    }
+
+    #[test]
+    fn rejects_groups_that_grow_too_much() {
+        let children = [1; 64];
+        let mut chunks = vec![model()];
+        chunks.extend((0..5).map(|level| group(level, &children.map(|child| child + level))));
+        chunks.push(shape(5));
+
+        assert_invalid(VoxScene::parse(&file(&chunks)));
+    }
+
+    #[test]
+    fn rejects_templates_that_are_too_big() {
+        let far = MAX_TRANSLATION.to_string();
+        let bytes = file(&[
+            model(),
+            group(0, &[1, 3]),
+            transform(1, 2, &format!("{far} {far} 0")),
+            shape(2),
+            transform(3, 2, &format!("-{far} -{far} 0")),
+        ]);
+
+        let scene = VoxScene::parse(&bytes).unwrap();
+        assert_invalid(scene.to_template("test", &ColorMapping::default().with_index(1, "air"), &BlockRegistry::default()));
+    }
+}
@@ -0,0 +1,222 @@
+//! Information about a world that is not part of its chunks, kept in the `world.ron` file of the
+//! directory of the world so it can be read and changed by hand.
+
+use std::{
+    collections::BTreeMap,
+    fs, io,
+    path::{Path, PathBuf},
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use serde::{Deserialize, Serialize};
+use specs::{System, WriteExpect};
+
+use crate::{
+    generation::{caves::CaveSettings, terrain::TerrainSettings},
+    storage,
+};
+
+pub const INFO_FILE: &str = "world.ron";
+
+/// Length of a tick of the [WorldClock], the unit of the time of the world.
+pub const TICK: Duration = Duration::from_millis(50);
+
+/// How the chunks of the world are generated. They have to stay the same for the whole life of the
+/// world, otherwise the new chunks do not match the ones that were saved.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct GeneratorSettings {
+    pub terrain: TerrainSettings,
+    /// There are no caves if it's [None].
+    pub caves: Option<CaveSettings>,
+    pub structures: bool,
+    pub decorate: bool,
+}
+
+impl Default for GeneratorSettings {
+    fn default() -> Self {
+        GeneratorSettings {
+            terrain: TerrainSettings::default(),
+            caves: Some(CaveSettings::default()),
+            structures: true,
+            decorate: true,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Copy, Clone, Debug, PartialEq, Eq)]
+pub enum GameRule {
+    Bool(bool),
+    Int(i64),
+}
+
+/// Rules that change how the game works in a world, by name.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+#[serde(transparent)]
+pub struct GameRules {
+    rules: BTreeMap<String, GameRule>,
+}
+
+impl Default for GameRules {
+    fn default() -> Self {
+        let mut rules = GameRules { rules: BTreeMap::new() };
+        rules.set("daylight_cycle", GameRule::Bool(true));
+        rules
+    }
+}
+
+impl GameRules {
+    pub fn get(&self, name: &str) -> Option<GameRule> {
+        self.rules.get(name).copied()
+    }
+
+    pub fn set(&mut self, name: &str, rule: GameRule) {
+        self.rules.insert(name.to_owned(), rule);
+    }
+
+    /// Value of a rule that is on or off, [None] if there's no such rule or it's not a bool.
+    pub fn bool(&self, name: &str) -> Option<bool> {
+        match self.get(name)? {
+            GameRule::Bool(value) => Some(value),
+            GameRule::Int(_) => None,
+        }
+    }
+
+    pub fn int(&self, name: &str) -> Option<i64> {
+        match self.get(name)? {
+            GameRule::Int(value) => Some(value),
+            GameRule::Bool(_) => None,
+        }
+    }
+}
+
+/// Resource with the information of the world that was opened with
+/// [Builder::open](crate::Builder::open).
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct WorldInfo {
+    pub seed: u64,
+    #[serde(default)]
+    pub generator: GeneratorSettings,
+    /// Block where the players appear, [None] until the generator finds one.
+    #[serde(default)]
+    pub spawn: Option<[i64; 3]>,
+    /// Ticks that the world was open for, see [TICK].
+    #[serde(default)]
+    pub time: u64,
+    #[serde(default)]
+    pub rules: GameRules,
+    /// Seconds since the UNIX epoch of the last time the world was saved.
+    #[serde(default)]
+    pub last_played: u64,
+    /// Id of the next entity that is saved, see [EntityIds](crate::persistence::EntityIds).
+    #[serde(default)]
+    pub next_entity_id: u64,
+    /// Number of the next model placed in the world, see
+    /// [DeferredPlacements](crate::generation::decoration::DeferredPlacements).
+    #[serde(default)]
+    pub next_placed: u64,
+}
+
+impl WorldInfo {
+    /// Information of a new world with the default settings.
+    pub fn new(seed: u64) -> WorldInfo {
+        WorldInfo {
+            seed,
+            generator: GeneratorSettings::default(),
+            spawn: None,
+            time: 0,
+            rules: GameRules::default(),
+            last_played: 0,
+            next_entity_id: 0,
+            next_placed: 0,
+        }
+    }
+
+    /// Reads the information of the world in a directory, [None] if the world does not exist.
+    pub fn load(directory: impl AsRef<Path>) -> io::Result<Option<WorldInfo>> {
+        let source = match fs::read_to_string(directory.as_ref().join(INFO_FILE)) {
+            Ok(source) => source,
+            Err(error) if error.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(error) => return Err(error),
+        };
+
+        ron::from_str(&source)
+            .map(Some)
+            .map_err(|error| io::Error::new(io::ErrorKind::InvalidData, error))
+    }
+
+    /// Writes the information to the directory of the world, setting the time when it was last
+    /// played to now.
+    pub fn save(&mut self, directory: impl AsRef<Path>) -> io::Result<()> {
+        self.last_played = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |duration| duration.as_secs());
+
+        let source = ron::ser::to_string_pretty(self, ron::ser::PrettyConfig::default())
+            .map_err(|error| io::Error::new(io::ErrorKind::InvalidData, error))?;
+
+        fs::create_dir_all(&directory)?;
+        storage::write_atomic(directory.as_ref().join(INFO_FILE), source)
+    }
+}
+
+/// Resource with the directory of the world that was opened.
+#[derive(Clone, Debug)]
+pub struct WorldDirectory(pub PathBuf);
+
+/// Moves the time of the world forward one tick for every [TICK] that passes while the
+/// `daylight_cycle` rule is on, however often the systems run.
+pub struct WorldClock {
+    last: Instant,
+    /// Time that passed since the last tick.
+    elapsed: Duration,
+}
+
+impl Default for WorldClock {
+    fn default() -> Self {
+        WorldClock {
+            last: Instant::now(),
+            elapsed: Duration::ZERO,
+        }
+    }
+}
+
+impl WorldClock {
+    /// Adds the time that passed and returns the ticks that it completes.
+    fn advance(&mut self, elapsed: Duration) -> u64 {
+        self.elapsed += elapsed;
+        let ticks = (self.elapsed.as_nanos() / TICK.as_nanos()) as u64;
+        self.elapsed -= TICK * ticks as u32;
+        ticks
+    }
+}
+
+impl<'a> System<'a> for WorldClock {
+    type SystemData = WriteExpect<'a, WorldInfo>;
+
+    fn run(&mut self, mut info: Self::SystemData) {
+        let now = Instant::now();
+        let ticks = self.advance(now - self.last);
+        self.last = now;
+
+        if info.rules.bool("daylight_cycle") != Some(false) {
+            info.time += ticks;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn the_clock_ticks_with_the_time_that_passes() {
+        let mut clock = WorldClock::default();
+
+        assert_eq!(clock.advance(TICK / 2), 0);
+        assert_eq!(clock.advance(TICK / 2), 1);
+        assert_eq!(clock.advance(TICK * 3 + TICK / 4), 3);
+        assert_eq!(clock.advance(TICK * 3 / 4), 1);
+        assert_eq!(clock.advance(Duration::from_secs(60)), 1200);
+    }
+}
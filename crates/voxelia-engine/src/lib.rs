@@ -2,15 +2,29 @@
 //! world and provide ways to interact with the world in a high-level way.
 
 pub mod chunk;
+pub mod codec;
 pub mod core;
 pub mod events;
 pub mod block;
+pub mod biome;
+pub mod occupancy;
+pub mod persistence;
+pub mod noise;
+pub mod random;
+pub mod generation;
+pub mod import;
+pub mod info;
+pub mod light;
+pub mod storage;
+pub mod streaming;
+pub mod workers;
 
 pub use core::*;
 
+use serde::{Deserialize, Serialize};
 use specs::{Component, VecStorage};
 
-#[derive(Component)]
+#[derive(Component, Serialize, Deserialize, Clone, Debug)]
 #[storage(VecStorage)]
 pub struct Position {
     pub x: f32,
@@ -0,0 +1,188 @@
+//! Sky light of the chunks. The light comes straight down from the sky through air and then spreads
+//! to the blocks around it, losing one level for each block, through every block that is not
+//! opaque. Chunks are stacked, so the light of a chunk comes in from the bottom of the one above.
+
+use std::collections::VecDeque;
+
+use crate::{
+    block::{BlockId, BlockPosition, BlockRegistry, Transparency, AIR},
+    chunk::{Chunk, CHUNK_HEIGHT, CHUNK_LENGTH, CHUNK_WIDTH},
+};
+
+pub const MAX_LIGHT: u8 = 15;
+
+/// Light that comes into each column of a chunk from above, indexed like the biomes of a [Chunk].
+pub type TopLight = [u8; CHUNK_WIDTH * CHUNK_LENGTH];
+
+/// Light that goes from the bottom of a chunk to the chunk under it.
+pub fn bottom_light(chunk: &Chunk) -> TopLight {
+    let mut light = [0; CHUNK_WIDTH * CHUNK_LENGTH];
+
+    for x in 0..CHUNK_WIDTH {
+        for z in 0..CHUNK_LENGTH {
+            light[x * CHUNK_LENGTH + z] = chunk.light(&BlockPosition::new(x as i64, 0, z as i64));
+        }
+    }
+
+    light
+}
+
+const DIRECTIONS: [BlockPosition; 6] = [
+    BlockPosition::new(1, 0, 0),
+    BlockPosition::new(-1, 0, 0),
+    BlockPosition::new(0, 1, 0),
+    BlockPosition::new(0, -1, 0),
+    BlockPosition::new(0, 0, 1),
+    BlockPosition::new(0, 0, -1),
+];
+
+fn lets_light(registry: &BlockRegistry, block: BlockId) -> bool {
+    block == AIR || registry.transparency(block) != Transparency::Opaque
+}
+
+/// Lights a chunk with the light that comes in from above, without looking at its horizontal
+/// neighbours. Columns with [MAX_LIGHT] on top are open to the sky.
+pub fn light_chunk(chunk: &mut Chunk, registry: &BlockRegistry, top: &TopLight) {
+    let mut queue = VecDeque::new();
+    let last_y = CHUNK_HEIGHT as i64 - 1;
+
+    for x in 0..CHUNK_WIDTH as i64 {
+        for z in 0..CHUNK_LENGTH as i64 {
+            for y in 0..CHUNK_HEIGHT as i64 {
+                chunk.set_light(&BlockPosition::new(x, y, z), 0);
+            }
+
+            let light = top[x as usize * CHUNK_LENGTH + z as usize];
+
+            if light < MAX_LIGHT {
+                let position = BlockPosition::new(x, last_y, z);
+                if light > 1 && lets_light(registry, chunk.get(&position)) {
+                    chunk.set_light(&position, light - 1);
+                    queue.push_back(position);
+                }
+                continue;
+            }
+
+            for y in (0..CHUNK_HEIGHT as i64).rev() {
+                let position = BlockPosition::new(x, y, z);
+                if chunk.get(&position) != AIR {
+                    break;
+                }
+
+                chunk.set_light(&position, MAX_LIGHT);
+                queue.push_back(position);
+            }
+        }
+    }
+
+    spread(chunk, registry, queue);
+}
+
+/// Brings in the light of the borders of the horizontal neighbours of a chunk, given in the order
+/// -x, +x, -z and +z.
+pub fn light_borders(chunk: &mut Chunk, registry: &BlockRegistry, neighbours: [Option<&Chunk>; 4]) {
+    let mut queue = VecDeque::new();
+    let (last_x, last_z) = (CHUNK_WIDTH as i64 - 1, CHUNK_LENGTH as i64 - 1);
+
+    let mut bring = |neighbour: Option<&Chunk>, inside: BlockPosition, outside: BlockPosition| {
+        let Some(neighbour) = neighbour else { return };
+        let light = neighbour.light(&outside).saturating_sub(1);
+
+        if light > chunk.light(&inside) && lets_light(registry, chunk.get(&inside)) {
+            chunk.set_light(&inside, light);
+            queue.push_back(inside);
+        }
+    };
+
+    for y in 0..CHUNK_HEIGHT as i64 {
+        for z in 0..CHUNK_LENGTH as i64 {
+            bring(neighbours[0], BlockPosition::new(0, y, z), BlockPosition::new(last_x, y, z));
+            bring(neighbours[1], BlockPosition::new(last_x, y, z), BlockPosition::new(0, y, z));
+        }
+
+        for x in 0..CHUNK_WIDTH as i64 {
+            bring(neighbours[2], BlockPosition::new(x, y, 0), BlockPosition::new(x, y, last_z));
+            bring(neighbours[3], BlockPosition::new(x, y, last_z), BlockPosition::new(x, y, 0));
+        }
+    }
+
+    spread(chunk, registry, queue);
+}
+
+fn spread(chunk: &mut Chunk, registry: &BlockRegistry, mut queue: VecDeque<BlockPosition>) {
+    while let Some(position) = queue.pop_front() {
+        let light = chunk.light(&position);
+        if light <= 1 {
+            continue;
+        }
+
+        for direction in &DIRECTIONS {
+            let mut next = &position + direction;
+            if next.is_out() || chunk.light(&next) >= light - 1 || !lets_light(registry, chunk.get(&next)) {
+                continue;
+            }
+
+            chunk.set_light(&next, light - 1);
+            queue.push_back(next);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::block::BlockDefinition;
+
+    use super::*;
+
+    fn stone(registry: &mut BlockRegistry) -> BlockId {
+        registry.register(BlockDefinition::new("stone", Transparency::Opaque, 0))
+    }
+
+    #[test]
+    fn light_goes_down_through_stacked_chunks() {
+        let mut registry = BlockRegistry::default();
+        let stone = stone(&mut registry);
+
+        // A roof over one column of the chunk above, and an open sky everywhere else.
+        let mut above = Chunk::default();
+        above.set(&BlockPosition::new(4, 10, 4), stone);
+        light_chunk(&mut above, &registry, &[MAX_LIGHT; CHUNK_WIDTH * CHUNK_LENGTH]);
+
+        let mut below = Chunk::default();
+        light_chunk(&mut below, &registry, &bottom_light(&above));
+
+        let last_y = CHUNK_HEIGHT as i64 - 1;
+        assert_eq!(below.light(&BlockPosition::new(0, 0, 0)), MAX_LIGHT);
+        assert_eq!(above.light(&BlockPosition::new(4, 9, 4)), MAX_LIGHT - 1);
+        assert_eq!(below.light(&BlockPosition::new(4, last_y, 4)), MAX_LIGHT - 1);
+        assert_eq!(below.light(&BlockPosition::new(4, 0, 4)), MAX_LIGHT - 1);
+    }
+
+    #[test]
+    fn chunks_under_the_ground_are_dark() {
+        let mut registry = BlockRegistry::default();
+        let stone = stone(&mut registry);
+
+        let mut ground = Chunk::default();
+        for x in 0..CHUNK_WIDTH as i64 {
+            for z in 0..CHUNK_LENGTH as i64 {
+                ground.set(&BlockPosition::new(x, CHUNK_HEIGHT as i64 - 1, z), stone);
+            }
+        }
+        light_chunk(&mut ground, &registry, &[MAX_LIGHT; CHUNK_WIDTH * CHUNK_LENGTH]);
+
+        let mut cave = Chunk::default();
+        light_chunk(&mut cave, &registry, &bottom_light(&ground));
+        assert_eq!(bottom_light(&cave), [0; CHUNK_WIDTH * CHUNK_LENGTH]);
+
+        // The light of an open neighbour comes in from the side.
+        let mut open = Chunk::default();
+        light_chunk(&mut open, &registry, &[MAX_LIGHT; CHUNK_WIDTH * CHUNK_LENGTH]);
+        light_borders(&mut cave, &registry, [None, Some(&open), None, None]);
+
+        let last_x = CHUNK_WIDTH as i64 - 1;
+        assert_eq!(cave.light(&BlockPosition::new(last_x, 5, 5)), MAX_LIGHT - 1);
+        assert_eq!(cave.light(&BlockPosition::new(last_x - 3, 5, 5)), MAX_LIGHT - 4);
+        assert_eq!(cave.light(&BlockPosition::new(0, 5, 5)), 0);
+    }
+}
@@ -0,0 +1,209 @@
+//! Seeded noise functions used by the world generation. They only use integer hashing and basic
+//! float operations, that are exact in IEEE 754, so the same seed gives the same world in every
+//! platform.
+//!
+//! The basic noises are [Perlin], [Simplex] and [OpenSimplex], all of them in 2D and 3D and with
+//! values inside of `[-1, 1]`. They can be layered with [Fbm] and [Ridged], bent with [Warp] and
+//! remapped with a [Spline].
+
+pub mod fractal;
+pub mod open_simplex;
+pub mod perlin;
+pub mod simplex;
+pub mod spline;
+
+pub use fractal::*;
+pub use open_simplex::*;
+pub use perlin::*;
+pub use simplex::*;
+pub use spline::*;
+
+/// Noise that can be sampled in two dimensions.
+pub trait Noise2 {
+    fn get2(&self, x: f64, y: f64) -> f64;
+}
+
+/// Noise that can be sampled in three dimensions.
+pub trait Noise3 {
+    fn get3(&self, x: f64, y: f64, z: f64) -> f64;
+}
+
+/// Noise that can be created out of a seed, needed to create the octaves of fractal noises.
+pub trait Seeded {
+    fn seeded(seed: u64) -> Self;
+}
+
+/// Hashes a lattice point together with a seed, used to pick gradients.
+pub(crate) fn hash(seed: u64, x: i64, y: i64, z: i64) -> u64 {
+    let mut h = seed.wrapping_mul(0x9E37_79B9_7F4A_7C15);
+    h ^= (x as u64).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    h = h.rotate_left(27).wrapping_mul(0x94D0_49BB_1331_11EB);
+    h ^= (y as u64).wrapping_mul(0xD6E8_FEB8_6659_FD93);
+    h = h.rotate_left(31).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    h ^= (z as u64).wrapping_mul(0x94D0_49BB_1331_11EB);
+
+    // Finalizer of splitmix64 so every bit of the input changes about half of the output.
+    h ^= h >> 30;
+    h = h.wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    h ^= h >> 27;
+    h = h.wrapping_mul(0x94D0_49BB_1331_11EB);
+    h ^ (h >> 31)
+}
+
+/// Picks one of `count` gradients for a lattice point.
+pub(crate) fn pick(seed: u64, x: i64, y: i64, z: i64, count: usize) -> usize {
+    ((hash(seed, x, y, z) >> 32) % count as u64) as usize
+}
+
+const DIAGONAL: f64 = std::f64::consts::FRAC_1_SQRT_2;
+
+/// Unit gradients along the axes and the diagonals.
+pub(crate) const GRADIENTS_2D: [(f64, f64); 8] = [
+    (1.0, 0.0),
+    (-1.0, 0.0),
+    (0.0, 1.0),
+    (0.0, -1.0),
+    (DIAGONAL, DIAGONAL),
+    (-DIAGONAL, DIAGONAL),
+    (DIAGONAL, -DIAGONAL),
+    (-DIAGONAL, -DIAGONAL),
+];
+
+const COS_22_5: f64 = 0.923_879_532_511_286_7;
+const SIN_22_5: f64 = 0.382_683_432_365_089_8;
+
+/// Sixteen unit gradients evenly spaced around the circle, used by the simplex noises that have
+/// less directional artifacts and deserve more directions.
+#[rustfmt::skip]
+pub(crate) const GRADIENTS_2D_FINE: [(f64, f64); 16] = [
+    (1.0, 0.0), (COS_22_5, SIN_22_5), (DIAGONAL, DIAGONAL), (SIN_22_5, COS_22_5),
+    (0.0, 1.0), (-SIN_22_5, COS_22_5), (-DIAGONAL, DIAGONAL), (-COS_22_5, SIN_22_5),
+    (-1.0, 0.0), (-COS_22_5, -SIN_22_5), (-DIAGONAL, -DIAGONAL), (-SIN_22_5, -COS_22_5),
+    (0.0, -1.0), (SIN_22_5, -COS_22_5), (DIAGONAL, -DIAGONAL), (COS_22_5, -SIN_22_5),
+];
+
+/// The gradients of the improved Perlin noise, the middle of the edges of a cube.
+#[rustfmt::skip]
+pub(crate) const GRADIENTS_3D: [(f64, f64, f64); 12] = [
+    (1.0, 1.0, 0.0), (-1.0, 1.0, 0.0), (1.0, -1.0, 0.0), (-1.0, -1.0, 0.0),
+    (1.0, 0.0, 1.0), (-1.0, 0.0, 1.0), (1.0, 0.0, -1.0), (-1.0, 0.0, -1.0),
+    (0.0, 1.0, 1.0), (0.0, -1.0, 1.0), (0.0, 1.0, -1.0), (0.0, -1.0, -1.0),
+];
+
+pub(crate) fn grad2(seed: u64, x: i64, y: i64, dx: f64, dy: f64) -> f64 {
+    let (gx, gy) = GRADIENTS_2D_FINE[pick(seed, x, y, 0, GRADIENTS_2D_FINE.len())];
+    gx * dx + gy * dy
+}
+
+pub(crate) fn grad3(seed: u64, x: i64, y: i64, z: i64, dx: f64, dy: f64, dz: f64) -> f64 {
+    let (gx, gy, gz) = GRADIENTS_3D[pick(seed, x, y, z, GRADIENTS_3D.len())];
+    gx * dx + gy * dy + gz * dz
+}
+
+/// Floors a float into a lattice coordinate.
+pub(crate) fn cell(x: f64) -> i64 {
+    x.floor() as i64
+}
+
+pub(crate) fn fade(t: f64) -> f64 {
+    t * t * t * (t * (t * 6.0 - 15.0) + 10.0)
+}
+
+pub(crate) fn lerp(a: f64, b: f64, t: f64) -> f64 {
+    a + t * (b - a)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const POINTS_2D: [(f64, f64); 2] = [(0.3, 0.7), (-12.25, 5.5)];
+    const POINTS_3D: [(f64, f64, f64); 2] = [(0.3, 0.7, 1.1), (-12.25, 5.5, 3.75)];
+
+    /// Checks the values of a noise at [POINTS_2D] and then at [POINTS_3D].
+    fn assert_values(noise: &(impl Noise2 + Noise3), expected: [f64; 4]) {
+        let values = [
+            noise.get2(POINTS_2D[0].0, POINTS_2D[0].1),
+            noise.get2(POINTS_2D[1].0, POINTS_2D[1].1),
+            noise.get3(POINTS_3D[0].0, POINTS_3D[0].1, POINTS_3D[0].2),
+            noise.get3(POINTS_3D[1].0, POINTS_3D[1].1, POINTS_3D[1].2),
+        ];
+
+        assert_eq!(values, expected);
+    }
+
+    #[test]
+    fn perlin_reference_values() {
+        assert_values(
+            &Perlin::new(42),
+            [-0.23055391528929195, -0.6111787338479442, -0.12975030546416633, -0.15452098846435547],
+        );
+    }
+
+    #[test]
+    fn simplex_reference_values() {
+        assert_values(
+            &Simplex::new(42),
+            [0.5258866132927476, -0.48678175315829236, 0.11479759466666638, 0.6153971354166667],
+        );
+    }
+
+    #[test]
+    fn open_simplex_reference_values() {
+        assert_values(
+            &OpenSimplex::new(42),
+            [0.33504267671487437, -0.3294541213545375, 0.36532085400000014, -0.16644287109375],
+        );
+    }
+
+    #[test]
+    fn fbm_reference_values() {
+        assert_values(
+            &Fbm::<Perlin>::new(7, 4, 0.05),
+            [0.0016287964293470087, 0.17271336091832218, -0.05355354506222681, 0.053342882997374906],
+        );
+    }
+
+    #[test]
+    fn ridged_reference_values() {
+        assert_values(
+            &Ridged::<Simplex>::new(7, 4, 0.05),
+            [-0.06930069087571944, -0.7713094494375732, 0.25605104169758874, -0.9840478914782373],
+        );
+    }
+
+    #[test]
+    fn warp_reference_values() {
+        assert_values(
+            &Warp::new(Perlin::new(1), OpenSimplex::new(2), 4.0),
+            [-0.22801414459378286, 0.2793394328949774, -0.019458017627262403, 0.2482963852032117],
+        );
+    }
+
+    fn assert_in_range(noise: &(impl Noise2 + Noise3)) {
+        for i in 0..4096 {
+            // Steps that are not multiples of the lattice, so the samples fall everywhere in the cells.
+            let x = (i % 64) as f64 * 0.173 - 5.0;
+            let y = (i / 64) as f64 * 0.241 - 7.0;
+            let z = (i % 17) as f64 * 0.319;
+
+            let value = noise.get2(x, y);
+            assert!((-1.0..=1.0).contains(&value), "{value} at ({x}, {y})");
+
+            let value = noise.get3(x, y, z);
+            assert!((-1.0..=1.0).contains(&value), "{value} at ({x}, {y}, {z})");
+        }
+    }
+
+    #[test]
+    fn values_are_in_range() {
+        for seed in [0, 1, 42, u64::MAX] {
+            assert_in_range(&Perlin::new(seed));
+            assert_in_range(&Simplex::new(seed));
+            assert_in_range(&OpenSimplex::new(seed));
+            assert_in_range(&Fbm::<OpenSimplex>::new(seed, 5, 0.3));
+            assert_in_range(&Ridged::<Perlin>::new(seed, 5, 0.3));
+            assert_in_range(&Warp::new(Simplex::new(seed), Perlin::new(seed ^ 1), 3.0));
+        }
+    }
+}
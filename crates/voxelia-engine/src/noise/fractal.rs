@@ -0,0 +1,165 @@
+//! Noises built on top of other noises: fractal sums of octaves and domain warping.
+
+use super::{Noise2, Noise3, Perlin, Seeded};
+
+/// Fractal Brownian motion, a sum of octaves of a noise where each octave has a higher frequency
+/// and a smaller amplitude than the last one. The result is normalized to `[-1, 1]`.
+#[derive(Clone, Debug)]
+pub struct Fbm<N = Perlin> {
+    octaves: Vec<N>,
+    pub frequency: f64,
+    pub lacunarity: f64,
+    pub persistence: f64,
+}
+
+impl<N: Seeded> Fbm<N> {
+    /// Creates the octaves with consecutive seeds starting from `seed`.
+    pub fn new(seed: u64, octaves: u32, frequency: f64) -> Fbm<N> {
+        Fbm {
+            octaves: (0..octaves as u64).map(|i| N::seeded(seed.wrapping_add(i))).collect(),
+            frequency,
+            lacunarity: 2.0,
+            persistence: 0.5,
+        }
+    }
+}
+
+impl<N> Fbm<N> {
+    /// Sums the octaves, `sample` gets each octave with its frequency.
+    fn sum(&self, sample: impl Fn(&N, f64) -> f64) -> f64 {
+        let mut frequency = self.frequency;
+        let mut amplitude = 1.0;
+        let mut total = 0.0;
+        let mut sum = 0.0;
+
+        for octave in &self.octaves {
+            sum += sample(octave, frequency) * amplitude;
+            total += amplitude;
+            frequency *= self.lacunarity;
+            amplitude *= self.persistence;
+        }
+
+        if total == 0.0 {
+            0.0
+        } else {
+            sum / total
+        }
+    }
+}
+
+impl<N: Noise2> Noise2 for Fbm<N> {
+    fn get2(&self, x: f64, y: f64) -> f64 {
+        self.sum(|noise, f| noise.get2(x * f, y * f))
+    }
+}
+
+impl<N: Noise3> Noise3 for Fbm<N> {
+    fn get3(&self, x: f64, y: f64, z: f64) -> f64 {
+        self.sum(|noise, f| noise.get3(x * f, y * f, z * f))
+    }
+}
+
+/// Ridged multifractal noise. Each octave is folded with `1 - |n|` so the zeros of the noise turn
+/// into sharp crests, good for mountain ranges. Octaves are weighted by the previous ones so the
+/// valleys stay smooth. The result is normalized to `[-1, 1]`.
+#[derive(Clone, Debug)]
+pub struct Ridged<N = Perlin> {
+    octaves: Vec<N>,
+    pub frequency: f64,
+    pub lacunarity: f64,
+    pub persistence: f64,
+    /// How much the crests of an octave let the next octave show.
+    pub gain: f64,
+}
+
+impl<N: Seeded> Ridged<N> {
+    pub fn new(seed: u64, octaves: u32, frequency: f64) -> Ridged<N> {
+        Ridged {
+            octaves: (0..octaves as u64).map(|i| N::seeded(seed.wrapping_add(i))).collect(),
+            frequency,
+            lacunarity: 2.0,
+            persistence: 0.5,
+            gain: 2.0,
+        }
+    }
+}
+
+impl<N> Ridged<N> {
+    fn sum(&self, sample: impl Fn(&N, f64) -> f64) -> f64 {
+        let mut frequency = self.frequency;
+        let mut amplitude = 1.0;
+        let mut weight = 1.0;
+        let mut total = 0.0;
+        let mut sum = 0.0;
+
+        for octave in &self.octaves {
+            let ridge = 1.0 - sample(octave, frequency).abs();
+            let signal = ridge * ridge * weight;
+
+            weight = (signal * self.gain).clamp(0.0, 1.0);
+            sum += signal * amplitude;
+            total += amplitude;
+            frequency *= self.lacunarity;
+            amplitude *= self.persistence;
+        }
+
+        if total == 0.0 {
+            0.0
+        } else {
+            sum / total * 2.0 - 1.0
+        }
+    }
+}
+
+impl<N: Noise2> Noise2 for Ridged<N> {
+    fn get2(&self, x: f64, y: f64) -> f64 {
+        self.sum(|noise, f| noise.get2(x * f, y * f))
+    }
+}
+
+impl<N: Noise3> Noise3 for Ridged<N> {
+    fn get3(&self, x: f64, y: f64, z: f64) -> f64 {
+        self.sum(|noise, f| noise.get3(x * f, y * f, z * f))
+    }
+}
+
+/// Offsets used to sample the warp noise once for each axis so the axes are not correlated.
+const WARP_OFFSETS: [f64; 3] = [0.0, 5.2, 11.7];
+
+/// Domain warping, moves the point given to the `source` noise by the value of the `warp` noise
+/// times the `strength`. It bends the features of the source into swirls and overhangs.
+#[derive(Clone, Debug)]
+pub struct Warp<N, W> {
+    pub source: N,
+    pub warp: W,
+    pub strength: f64,
+}
+
+impl<N, W> Warp<N, W> {
+    pub fn new(source: N, warp: W, strength: f64) -> Warp<N, W> {
+        Warp {
+            source,
+            warp,
+            strength,
+        }
+    }
+}
+
+impl<N: Noise2, W: Noise2> Noise2 for Warp<N, W> {
+    fn get2(&self, x: f64, y: f64) -> f64 {
+        let [a, b, _] = WARP_OFFSETS;
+        let dx = self.warp.get2(x + a, y + a) * self.strength;
+        let dy = self.warp.get2(x + b, y + b) * self.strength;
+        self.source.get2(x + dx, y + dy)
+    }
+}
+
+impl<N: Noise3, W: Noise3> Noise3 for Warp<N, W> {
+    fn get3(&self, x: f64, y: f64, z: f64) -> f64 {
+        let [a, b, c] = WARP_OFFSETS;
+        let dx = self.warp.get3(x + a, y + a, z + a) * self.strength;
+        let dy = self.warp.get3(x + b, y + b, z + b) * self.strength;
+        let dz = self.warp.get3(x + c, y + c, z + c) * self.strength;
+        self.source.get3(x + dx, y + dy, z + dz)
+    }
+}
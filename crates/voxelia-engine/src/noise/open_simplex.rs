@@ -0,0 +1,109 @@
+//! Noise in the style of OpenSimplex2S. It uses bigger kernels than [super::Simplex], so every
+//! sample gets contributions from more lattice points and looks smoother and less grid aligned.
+//!
+//! In 2D the lattice is the same triangle grid of the simplex noise. In 3D it is a body centered
+//! cubic lattice, the integer points together with the centers of the cubes.
+
+use super::{cell, grad2, grad3, Noise2, Noise3, Seeded};
+
+const SKEW_2D: f64 = 0.366_025_403_784_438_6;
+const UNSKEW_2D: f64 = 0.211_324_865_405_187_1;
+
+/// Squared radius of the kernels.
+const RADIUS_2D: f64 = 2.0 / 3.0;
+const RADIUS_3D: f64 = 0.75;
+
+/// Scales that take the sum of the contributions into `[-1, 1]`.
+const SCALE_2D: f64 = 18.2;
+const SCALE_3D: f64 = 9.0;
+
+#[derive(Copy, Clone, Debug)]
+pub struct OpenSimplex {
+    pub seed: u64,
+}
+
+impl OpenSimplex {
+    pub fn new(seed: u64) -> OpenSimplex {
+        OpenSimplex { seed }
+    }
+}
+
+impl Seeded for OpenSimplex {
+    fn seeded(seed: u64) -> Self {
+        OpenSimplex::new(seed)
+    }
+}
+
+fn falloff(radius: f64, squared: f64) -> f64 {
+    let a = radius - squared;
+    if a > 0.0 {
+        (a * a) * (a * a)
+    } else {
+        0.0
+    }
+}
+
+impl Noise2 for OpenSimplex {
+    fn get2(&self, x: f64, y: f64) -> f64 {
+        let s = (x + y) * SKEW_2D;
+        let (i, j) = (cell(x + s), cell(y + s));
+
+        let mut value = 0.0;
+
+        // The kernel reaches further than the cell that contains the point, so one ring of cells
+        // around it is visited too. Points outside of the kernel give nothing.
+        for vi in i - 1..=i + 2 {
+            for vj in j - 1..=j + 2 {
+                let t = (vi + vj) as f64 * UNSKEW_2D;
+                let dx = x - (vi as f64 - t);
+                let dy = y - (vj as f64 - t);
+
+                let weight = falloff(RADIUS_2D, dx * dx + dy * dy);
+                if weight > 0.0 {
+                    value += weight * grad2(self.seed, vi, vj, dx, dy);
+                }
+            }
+        }
+
+        (value * SCALE_2D).clamp(-1.0, 1.0)
+    }
+}
+
+impl Noise3 for OpenSimplex {
+    fn get3(&self, x: f64, y: f64, z: f64) -> f64 {
+        let mut value = 0.0;
+
+        // The two cubic lattices that form the body centered one. Points of the second one are
+        // hashed with odd coordinates so they never share gradients with the first one.
+        for (shift, odd) in [(0.0, 0), (0.5, 1)] {
+            let (i, j, k) = (cell(x - shift), cell(y - shift), cell(z - shift));
+
+            for (oi, oj, ok) in CUBE {
+                let (pi, pj, pk) = (i + oi, j + oj, k + ok);
+                let dx = x - (pi as f64 + shift);
+                let dy = y - (pj as f64 + shift);
+                let dz = z - (pk as f64 + shift);
+
+                let weight = falloff(RADIUS_3D, dx * dx + dy * dy + dz * dz);
+                if weight > 0.0 {
+                    let (hi, hj, hk) = (pi * 2 + odd, pj * 2 + odd, pk * 2 + odd);
+                    value += weight * grad3(self.seed, hi, hj, hk, dx, dy, dz);
+                }
+            }
+        }
+
+        (value * SCALE_3D).clamp(-1.0, 1.0)
+    }
+}
+
+/// Corners of a cube, every lattice point in the kernel of a sample is one of them.
+const CUBE: [(i64, i64, i64); 8] = [
+    (0, 0, 0),
+    (1, 0, 0),
+    (0, 1, 0),
+    (1, 1, 0),
+    (0, 0, 1),
+    (1, 0, 1),
+    (0, 1, 1),
+    (1, 1, 1),
+];
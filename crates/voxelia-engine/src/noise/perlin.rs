@@ -0,0 +1,64 @@
+//! Classic gradient noise with the quintic fade of the improved Perlin noise.
+
+use super::{cell, fade, grad3, lerp, pick, Noise2, Noise3, Seeded, GRADIENTS_2D};
+
+/// Gradient noise on a square grid. It's zero on every integer coordinate.
+#[derive(Copy, Clone, Debug)]
+pub struct Perlin {
+    pub seed: u64,
+}
+
+impl Perlin {
+    pub fn new(seed: u64) -> Perlin {
+        Perlin { seed }
+    }
+
+    fn gradient2(&self, cell_x: i64, cell_y: i64, x: f64, y: f64) -> f64 {
+        let (gx, gy) = GRADIENTS_2D[pick(self.seed, cell_x, cell_y, 0, GRADIENTS_2D.len())];
+        gx * x + gy * y
+    }
+}
+
+impl Seeded for Perlin {
+    fn seeded(seed: u64) -> Self {
+        Perlin::new(seed)
+    }
+}
+
+impl Noise2 for Perlin {
+    fn get2(&self, x: f64, y: f64) -> f64 {
+        let (cx, cy) = (cell(x), cell(y));
+        let (fx, fy) = (x - cx as f64, y - cy as f64);
+
+        let n00 = self.gradient2(cx, cy, fx, fy);
+        let n10 = self.gradient2(cx + 1, cy, fx - 1.0, fy);
+        let n01 = self.gradient2(cx, cy + 1, fx, fy - 1.0);
+        let n11 = self.gradient2(cx + 1, cy + 1, fx - 1.0, fy - 1.0);
+
+        let (u, v) = (fade(fx), fade(fy));
+
+        // The biggest value that unit gradients can reach is sqrt(1/2).
+        lerp(lerp(n00, n10, u), lerp(n01, n11, u), v) * std::f64::consts::SQRT_2
+    }
+}
+
+impl Noise3 for Perlin {
+    fn get3(&self, x: f64, y: f64, z: f64) -> f64 {
+        let (cx, cy, cz) = (cell(x), cell(y), cell(z));
+        let (fx, fy, fz) = (x - cx as f64, y - cy as f64, z - cz as f64);
+
+        let corner = |i: i64, j: i64, k: i64| {
+            grad3(self.seed, cx + i, cy + j, cz + k, fx - i as f64, fy - j as f64, fz - k as f64)
+        };
+
+        let (u, v, w) = (fade(fx), fade(fy), fade(fz));
+
+        let x00 = lerp(corner(0, 0, 0), corner(1, 0, 0), u);
+        let x10 = lerp(corner(0, 1, 0), corner(1, 1, 0), u);
+        let x01 = lerp(corner(0, 0, 1), corner(1, 0, 1), u);
+        let x11 = lerp(corner(0, 1, 1), corner(1, 1, 1), u);
+
+        let value = lerp(lerp(x00, x10, v), lerp(x01, x11, v), w);
+        value.clamp(-1.0, 1.0)
+    }
+}
@@ -0,0 +1,109 @@
+//! Simplex noise, gradient noise over a lattice of triangles (or tetrahedra in 3D), that needs less
+//! samples than [super::Perlin] and has less axis aligned artifacts.
+
+use super::{cell, grad2, grad3, Noise2, Noise3, Seeded};
+
+/// Skews the square grid into the triangle grid.
+const SKEW_2D: f64 = 0.366_025_403_784_438_6;
+/// Goes back from the triangle grid to the square one.
+const UNSKEW_2D: f64 = 0.211_324_865_405_187_1;
+
+const SKEW_3D: f64 = 1.0 / 3.0;
+const UNSKEW_3D: f64 = 1.0 / 6.0;
+
+/// Scales that take the sum of the contributions into `[-1, 1]`.
+const SCALE_2D: f64 = 99.2;
+const SCALE_3D: f64 = 76.0;
+
+/// Simplex noise with a kernel that ends at the edge of each simplex, so it's continuous.
+#[derive(Copy, Clone, Debug)]
+pub struct Simplex {
+    pub seed: u64,
+}
+
+impl Simplex {
+    pub fn new(seed: u64) -> Simplex {
+        Simplex { seed }
+    }
+}
+
+impl Seeded for Simplex {
+    fn seeded(seed: u64) -> Self {
+        Simplex::new(seed)
+    }
+}
+
+/// Contribution of a corner that is at distance `(dx, dy)` of the sample.
+fn falloff(radius: f64, squared: f64) -> f64 {
+    let a = radius - squared;
+    if a > 0.0 {
+        (a * a) * (a * a)
+    } else {
+        0.0
+    }
+}
+
+impl Noise2 for Simplex {
+    fn get2(&self, x: f64, y: f64) -> f64 {
+        let s = (x + y) * SKEW_2D;
+        let (i, j) = (cell(x + s), cell(y + s));
+
+        let t = (i + j) as f64 * UNSKEW_2D;
+        let (x0, y0) = (x - (i as f64 - t), y - (j as f64 - t));
+
+        // The triangle that contains the point is either the lower or the upper one of the cell.
+        let (i1, j1) = if x0 > y0 { (1, 0) } else { (0, 1) };
+
+        let (x1, y1) = (x0 - i1 as f64 + UNSKEW_2D, y0 - j1 as f64 + UNSKEW_2D);
+        let (x2, y2) = (x0 - 1.0 + 2.0 * UNSKEW_2D, y0 - 1.0 + 2.0 * UNSKEW_2D);
+
+        let n0 = falloff(0.5, x0 * x0 + y0 * y0) * grad2(self.seed, i, j, x0, y0);
+        let n1 = falloff(0.5, x1 * x1 + y1 * y1) * grad2(self.seed, i + i1, j + j1, x1, y1);
+        let n2 = falloff(0.5, x2 * x2 + y2 * y2) * grad2(self.seed, i + 1, j + 1, x2, y2);
+
+        ((n0 + n1 + n2) * SCALE_2D).clamp(-1.0, 1.0)
+    }
+}
+
+impl Noise3 for Simplex {
+    fn get3(&self, x: f64, y: f64, z: f64) -> f64 {
+        let s = (x + y + z) * SKEW_3D;
+        let (i, j, k) = (cell(x + s), cell(y + s), cell(z + s));
+
+        let t = (i + j + k) as f64 * UNSKEW_3D;
+        let (x0, y0, z0) = (x - (i as f64 - t), y - (j as f64 - t), z - (k as f64 - t));
+
+        // Finds which of the six tetrahedra of the cube contains the point by sorting the axes.
+        let (first, second) = if x0 >= y0 {
+            if y0 >= z0 {
+                ((1, 0, 0), (1, 1, 0))
+            } else if x0 >= z0 {
+                ((1, 0, 0), (1, 0, 1))
+            } else {
+                ((0, 0, 1), (1, 0, 1))
+            }
+        } else if y0 < z0 {
+            ((0, 0, 1), (0, 1, 1))
+        } else if x0 < z0 {
+            ((0, 1, 0), (0, 1, 1))
+        } else {
+            ((0, 1, 0), (1, 1, 0))
+        };
+
+        let mut value = 0.0;
+
+        for (step, (oi, oj, ok)) in [(0, (0, 0, 0)), (1, first), (2, second), (3, (1, 1, 1))] {
+            let offset = step as f64 * UNSKEW_3D;
+            let dx = x0 - oi as f64 + offset;
+            let dy = y0 - oj as f64 + offset;
+            let dz = z0 - ok as f64 + offset;
+
+            let weight = falloff(0.5, dx * dx + dy * dy + dz * dz);
+            if weight > 0.0 {
+                value += weight * grad3(self.seed, i + oi, j + oj, k + ok, dx, dy, dz);
+            }
+        }
+
+        (value * SCALE_3D).clamp(-1.0, 1.0)
+    }
+}
@@ -0,0 +1,131 @@
+//! Remapping of noise values through a curve defined by control points.
+
+/// Monotone cubic spline through a list of points. It never overshoots between two points, so a
+/// flat section of the curve stays flat, which makes it good to shape terrain out of noise values
+/// (plains, cliffs and plateaus). Values outside of the points are clamped.
+#[derive(Clone, Debug)]
+pub struct Spline {
+    points: Vec<(f64, f64)>,
+    tangents: Vec<f64>,
+}
+
+impl Spline {
+    /// Creates a spline out of `(input, output)` points. They are sorted by the input, there must
+    /// be at least one and no two of them can have the same input.
+    pub fn new(mut points: Vec<(f64, f64)>) -> Spline {
+        assert!(!points.is_empty(), "a spline needs at least one point");
+        points.sort_by(|a, b| a.0.total_cmp(&b.0));
+
+        // Two points with the same input would make a segment without width and infinite slopes.
+        assert!(
+            points.windows(2).all(|w| w[0].0 < w[1].0),
+            "the inputs of the points of a spline must be different numbers"
+        );
+
+        let secants = points
+            .windows(2)
+            .map(|w| (w[1].1 - w[0].1) / (w[1].0 - w[0].0))
+            .collect::<Vec<_>>();
+
+        let mut tangents = vec![0.0; points.len()];
+
+        if let (Some(first), Some(last)) = (secants.first(), secants.last()) {
+            tangents[0] = *first;
+            tangents[points.len() - 1] = *last;
+        }
+
+        for i in 1..secants.len() {
+            let (before, after) = (secants[i - 1], secants[i]);
+            if before * after > 0.0 {
+                tangents[i] = (before + after) / 2.0;
+            }
+        }
+
+        // Fritsch-Carlson: limits the tangents so each segment stays monotone.
+        for (i, secant) in secants.iter().enumerate() {
+            if *secant == 0.0 {
+                tangents[i] = 0.0;
+                tangents[i + 1] = 0.0;
+                continue;
+            }
+
+            let a = tangents[i] / secant;
+            let b = tangents[i + 1] / secant;
+            let length = a * a + b * b;
+
+            if length > 9.0 {
+                let tau = 3.0 / length.sqrt();
+                tangents[i] = tau * a * secant;
+                tangents[i + 1] = tau * b * secant;
+            }
+        }
+
+        Spline { points, tangents }
+    }
+
+    pub fn get(&self, x: f64) -> f64 {
+        let last = self.points.len() - 1;
+
+        if x <= self.points[0].0 {
+            return self.points[0].1;
+        } else if x >= self.points[last].0 {
+            return self.points[last].1;
+        }
+
+        let i = self.points.partition_point(|point| point.0 <= x) - 1;
+
+        let (x0, y0) = self.points[i];
+        let (x1, y1) = self.points[i + 1];
+        let h = x1 - x0;
+        let t = (x - x0) / h;
+        let (t2, t3) = (t * t, t * t * t);
+
+        (2.0 * t3 - 3.0 * t2 + 1.0) * y0
+            + (t3 - 2.0 * t2 + t) * h * self.tangents[i]
+            + (-2.0 * t3 + 3.0 * t2) * y1
+            + (t3 - t2) * h * self.tangents[i + 1]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn spline() -> Spline {
+        Spline::new(vec![(0.4, 0.8), (-1.0, -0.5), (1.0, 1.0), (0.0, 0.0)])
+    }
+
+    #[test]
+    fn reference_values() {
+        let values = [-2.0, -0.5, 0.2, 0.7, 3.0].map(|x| spline().get(x));
+        assert_eq!(values, [-0.5, -0.34375, 0.41442380261795886, 0.9515102114807584, 1.0]);
+    }
+
+    #[test]
+    fn passes_through_the_points() {
+        for (x, y) in [(-1.0, -0.5), (0.0, 0.0), (0.4, 0.8), (1.0, 1.0)] {
+            assert_eq!(spline().get(x), y);
+        }
+    }
+
+    #[test]
+    fn flat_segments_stay_flat() {
+        let spline = Spline::new(vec![(0.0, 0.0), (1.0, 1.0), (2.0, 1.0), (3.0, 2.0)]);
+
+        for i in 0..=10 {
+            assert_eq!(spline.get(1.0 + i as f64 / 10.0), 1.0);
+        }
+    }
+
+    #[test]
+    #[should_panic]
+    fn rejects_repeated_inputs() {
+        Spline::new(vec![(0.0, 0.0), (1.0, 1.0), (1.0, 2.0)]);
+    }
+
+    #[test]
+    #[should_panic]
+    fn rejects_nan_inputs() {
+        Spline::new(vec![(0.0, 0.0), (f64::NAN, 1.0)]);
+    }
+}
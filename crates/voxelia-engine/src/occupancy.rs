@@ -0,0 +1,129 @@
+//! Bitmask of the solid blocks of a chunk. Each column of the chunk is stored as a [u64] where the
+//! bit `y` tells if the block at that height is solid, so finding the faces that are visible is a
+//! matter of a couple of bitwise operations instead of one lookup for each neighbour.
+
+use crate::block::BlockPosition;
+use crate::chunk::{CHUNK_HEIGHT, CHUNK_LENGTH, CHUNK_WIDTH};
+
+const _: () = assert!(CHUNK_HEIGHT <= u64::BITS as usize, "a column must fit in a u64");
+
+/// One bit for each block of a chunk, laid out as columns along the y axis.
+#[derive(Clone)]
+pub struct Occupancy {
+    columns: [u64; CHUNK_WIDTH * CHUNK_LENGTH],
+}
+
+impl Default for Occupancy {
+    fn default() -> Self {
+        Occupancy {
+            columns: [0; CHUNK_WIDTH * CHUNK_LENGTH],
+        }
+    }
+}
+
+impl Occupancy {
+    /// Column of bits at `x` and `z`, columns outside of the chunk are empty.
+    pub fn column(&self, x: i64, z: i64) -> u64 {
+        if x < 0 || x >= CHUNK_WIDTH as i64 || z < 0 || z >= CHUNK_LENGTH as i64 {
+            0
+        } else {
+            self.columns[x as usize * CHUNK_LENGTH + z as usize]
+        }
+    }
+
+    /// Checks if the block is solid. Blocks outside of the chunk are never solid.
+    pub fn is_solid(&self, position: &BlockPosition) -> bool {
+        if position.y < 0 || position.y >= CHUNK_HEIGHT as i64 {
+            return false;
+        }
+
+        self.column(position.x, position.z) >> position.y & 1 == 1
+    }
+
+    pub fn set(&mut self, position: &BlockPosition, solid: bool) {
+        let column = &mut self.columns[position.x as usize * CHUNK_LENGTH + position.z as usize];
+
+        if solid {
+            *column |= 1 << position.y;
+        } else {
+            *column &= !(1 << position.y);
+        }
+    }
+
+    /// Returns the bits of the column at `x` and `z` whose neighbour in the `direction`, that is a
+    /// unit vector along one of the axes, is solid.
+    pub fn neighbours(&self, x: i64, z: i64, direction: &BlockPosition) -> u64 {
+        match direction.y {
+            1 => self.column(x, z) >> 1,
+            -1 => self.column(x, z) << 1,
+            _ => self.column(x + direction.x, z + direction.z),
+        }
+    }
+
+    /// Returns the bits of the column at `x` and `z` whose blocks are solid and have no solid
+    /// neighbour in the `direction`.
+    pub fn visible(&self, x: i64, z: i64, direction: &BlockPosition) -> u64 {
+        self.column(x, z) & !self.neighbours(x, z, direction)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chunk::Chunk;
+
+    const UP: BlockPosition = BlockPosition::new(0, 1, 0);
+    const DOWN: BlockPosition = BlockPosition::new(0, -1, 0);
+    const EAST: BlockPosition = BlockPosition::new(1, 0, 0);
+    const WEST: BlockPosition = BlockPosition::new(-1, 0, 0);
+
+    #[test]
+    fn blocks_are_bits_of_their_column() {
+        let mut occupancy = Occupancy::default();
+        occupancy.set(&BlockPosition::new(3, 0, 4), true);
+        occupancy.set(&BlockPosition::new(3, 31, 4), true);
+        occupancy.set(&BlockPosition::new(3, 5, 4), true);
+        occupancy.set(&BlockPosition::new(3, 5, 4), false);
+
+        assert_eq!(occupancy.column(3, 4), 1 | 1 << 31);
+        assert!(occupancy.is_solid(&BlockPosition::new(3, 31, 4)));
+        assert!(!occupancy.is_solid(&BlockPosition::new(3, 5, 4)));
+
+        // Nothing outside of the chunk is solid.
+        assert_eq!(occupancy.column(-1, 4), 0);
+        assert_eq!(occupancy.column(3, CHUNK_LENGTH as i64), 0);
+        assert!(!occupancy.is_solid(&BlockPosition::new(3, -1, 4)));
+        assert!(!occupancy.is_solid(&BlockPosition::new(3, CHUNK_HEIGHT as i64, 4)));
+    }
+
+    #[test]
+    fn only_the_faces_without_a_solid_neighbour_are_visible() {
+        let mut occupancy = Occupancy::default();
+        for y in 2..5 {
+            occupancy.set(&BlockPosition::new(0, y, 0), true);
+        }
+        occupancy.set(&BlockPosition::new(1, 3, 0), true);
+
+        assert_eq!(occupancy.visible(0, 0, &UP), 1 << 4);
+        assert_eq!(occupancy.visible(0, 0, &DOWN), 1 << 2);
+        assert_eq!(occupancy.visible(0, 0, &EAST), 1 << 2 | 1 << 4);
+        assert_eq!(occupancy.visible(1, 0, &WEST), 0);
+
+        // The borders of the chunk are always visible, the neighbouring chunks are not looked at.
+        assert_eq!(occupancy.visible(0, 0, &WEST), 0b11100);
+    }
+
+    #[test]
+    fn chunks_keep_their_occupancy() {
+        let mut data = [0; CHUNK_WIDTH * CHUNK_HEIGHT * CHUNK_LENGTH];
+        data[0] = 7;
+        let mut chunk = Chunk::new(data);
+
+        assert_eq!(chunk.occupancy().column(0, 0), 1);
+
+        chunk.set(&BlockPosition::new(0, 0, 0), 0);
+        chunk.set(&BlockPosition::new(15, 9, 15), 2);
+        assert_eq!(chunk.occupancy().column(0, 0), 0);
+        assert_eq!(chunk.occupancy().column(15, 15), 1 << 9);
+    }
+}
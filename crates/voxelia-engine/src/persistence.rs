@@ -0,0 +1,363 @@
+//! Saving the entities of the world, like players, mobs and items, with the chunk that they are in.
+//!
+//! Entities with an [EntityId] are saved with the chunk that has their [Position] when the chunk is
+//! unloaded or the world is saved, and they come back when the chunk is loaded again. Only their
+//! [SavedComponents] are kept. Ids are never given twice in a world, so components that point to
+//! other entities still point to the same ones after they are loaded, even from other chunks.
+
+use std::{
+    collections::{HashMap, HashSet},
+    convert::Infallible,
+};
+
+use serde::{Deserialize, Serialize};
+use specs::{
+    saveload::{DeserializeComponents, EntityData, Marker, MarkerAllocator, SerializeComponents},
+    world::EntitiesRes,
+    Component, Entities, Entity, HashMapStorage, Join, Read, ReadStorage, System, VecStorage, World, WorldExt, Write,
+    WriteStorage,
+};
+
+use crate::{
+    chunk::ChunkPosition,
+    generation::ChunkStatus,
+    info::WorldInfo,
+    streaming::ChunkStore,
+    workers::Workers,
+    Plugin, Position, WorldBuilder,
+};
+
+/// Storages of the components that are saved with the entities. Components that are [Clone],
+/// [Serialize] and [Deserialize] can be added to it, the ones that point to other entities have to
+/// implement [ConvertSaveload](specs::saveload::ConvertSaveload) too.
+pub type SavedComponents<'a> = (WriteStorage<'a, Position>,);
+
+/// Marker of the entities that are saved, with an id that is the same every time they are loaded.
+/// Entities get one with [MarkedBuilder::marked](specs::saveload::MarkedBuilder::marked).
+#[derive(Component, Serialize, Deserialize, Copy, Clone, Debug, PartialEq, Eq, Hash)]
+#[storage(VecStorage)]
+pub struct EntityId(u64);
+
+impl Marker for EntityId {
+    type Identifier = u64;
+    type Allocator = EntityIds;
+
+    fn id(&self) -> u64 {
+        self.0
+    }
+}
+
+/// Resource that gives the [EntityId]s and knows the entity of each of them. The id of the next
+/// entity is kept in the [WorldInfo] so ids are not given again when the world is opened again.
+#[derive(Debug, Default)]
+pub struct EntityIds {
+    next: u64,
+    entities: HashMap<u64, Entity>,
+}
+
+impl EntityIds {
+    pub fn starting_at(next: u64) -> EntityIds {
+        EntityIds {
+            next,
+            entities: HashMap::new(),
+        }
+    }
+
+    /// Id that the next entity gets.
+    pub fn next(&self) -> u64 {
+        self.next
+    }
+}
+
+impl MarkerAllocator<EntityId> for EntityIds {
+    fn allocate(&mut self, entity: Entity, id: Option<u64>) -> EntityId {
+        let id = id.unwrap_or(self.next);
+        self.next = self.next.max(id + 1);
+        self.entities.insert(id, entity);
+
+        EntityId(id)
+    }
+
+    fn retrieve_entity_internal(&self, id: u64) -> Option<Entity> {
+        self.entities.get(&id).copied()
+    }
+
+    fn maintain(&mut self, entities: &EntitiesRes, storage: &ReadStorage<EntityId>) {
+        self.entities = (entities, storage).join().map(|(entity, id)| (id.0, entity)).collect();
+    }
+}
+
+/// Saved entities of a chunk that was loaded, until the [EntityLoader] puts them in the world.
+#[derive(Component)]
+#[storage(HashMapStorage)]
+pub struct SavedEntities(pub Vec<u8>);
+
+/// Resource with the chunks that were unloaded after their entities were loaded, so the
+/// [EntityUnloader] saves the entities that were in them.
+#[derive(Default)]
+pub struct UnloadedChunks {
+    pub positions: Vec<ChunkPosition>,
+}
+
+/// Serializes some entities with their components, nothing if there are none.
+fn serialize(components: &SavedComponents, ids: &ReadStorage<EntityId>, entities: &[Entity]) -> ron::Result<Vec<u8>> {
+    if entities.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let data: Vec<_> = entities
+        .iter()
+        .filter_map(|entity| {
+            let marker = *ids.get(*entity)?;
+            let components =
+                SerializeComponents::<Infallible, EntityId>::serialize_entity(components, *entity, |other| ids.get(other).copied());
+
+            Some(EntityData {
+                marker,
+                components: components.unwrap_or_else(|never| match never {}),
+            })
+        })
+        .collect();
+
+    ron::to_string(&data).map(String::into_bytes)
+}
+
+/// Creates the entities of a chunk, or changes them if they are already in the world.
+fn deserialize(
+    components: &mut SavedComponents,
+    entities: &EntitiesRes,
+    markers: &mut WriteStorage<EntityId>,
+    ids: &mut EntityIds,
+    data: &[u8],
+) -> ron::Result<()> {
+    let mut deserializer = ron::Deserializer::from_bytes(data).map_err(|error| error.code)?;
+    DeserializeComponents::<Infallible, EntityId>::deserialize(components, entities, markers, ids, &mut deserializer)
+}
+
+/// Groups the saved entities by the chunks that they are in, only for the chunks that are asked for.
+fn entities_in(
+    chunks: &HashSet<ChunkPosition>,
+    entities: &EntitiesRes,
+    ids: &ReadStorage<EntityId>,
+    positions: &WriteStorage<Position>,
+) -> HashMap<ChunkPosition, Vec<Entity>> {
+    let mut grouped: HashMap<ChunkPosition, Vec<Entity>> = HashMap::new();
+
+    for (entity, _, position) in (entities, ids, positions).join() {
+        let chunk = ChunkPosition::containing(position);
+        if chunks.contains(&chunk) {
+            grouped.entry(chunk).or_default().push(entity);
+        }
+    }
+
+    grouped
+}
+
+/// Puts the entities of the chunks that were loaded in the world.
+pub struct EntityLoader;
+
+impl<'a> System<'a> for EntityLoader {
+    type SystemData = (
+        Entities<'a>,
+        Write<'a, EntityIds>,
+        WriteStorage<'a, EntityId>,
+        WriteStorage<'a, SavedEntities>,
+        SavedComponents<'a>,
+    );
+
+    fn run(&mut self, (entities, mut ids, mut markers, mut saved, mut components): Self::SystemData) {
+        let loaded: Vec<SavedEntities> = saved.drain().join().collect();
+
+        for SavedEntities(data) in loaded {
+            if let Err(error) = deserialize(&mut components, &entities, &mut markers, &mut ids, &data) {
+                log::error!("cannot load the entities of a chunk: {error}");
+            }
+        }
+    }
+}
+
+/// Saves the entities of the [UnloadedChunks] in the background and removes them from the world.
+pub struct EntityUnloader;
+
+impl<'a> System<'a> for EntityUnloader {
+    type SystemData = (
+        Entities<'a>,
+        Read<'a, ChunkStore>,
+        Read<'a, Workers>,
+        Write<'a, UnloadedChunks>,
+        Write<'a, EntityIds>,
+        ReadStorage<'a, EntityId>,
+        SavedComponents<'a>,
+    );
+
+    fn run(&mut self, (entities, store, workers, mut unloaded, mut ids, markers, components): Self::SystemData) {
+        let chunks: HashSet<ChunkPosition> = unloaded.positions.drain(..).collect();
+        if chunks.is_empty() {
+            return;
+        }
+
+        let mut grouped = entities_in(&chunks, &entities, &markers, &components.0);
+
+        for position in chunks {
+            let unloading = grouped.remove(&position).unwrap_or_default();
+
+            // Entities that cannot be saved stay in the world rather than being lost.
+            let data = match serialize(&components, &markers, &unloading) {
+                Ok(data) => data,
+                Err(error) => {
+                    log::error!("cannot save the entities of the chunk {position:?}: {error}");
+                    continue;
+                }
+            };
+
+            store.save_entities_later(&workers, position, data);
+
+            for entity in unloading {
+                if let Some(id) = markers.get(entity) {
+                    ids.entities.remove(&id.0);
+                }
+
+                entities.delete(entity).unwrap();
+            }
+        }
+    }
+}
+
+/// Saves the entities of every chunk of the world whose entities were loaded right away, keeping
+/// them in the world.
+pub fn save_entities(world: &World) {
+    let store = world.read_resource::<ChunkStore>();
+    let entities = world.entities();
+    let markers = world.read_storage::<EntityId>();
+    let statuses = world.read_storage::<ChunkStatus>();
+    let saved = world.read_storage::<SavedEntities>();
+    let components: SavedComponents = world.system_data();
+
+    let chunks: HashSet<ChunkPosition> = (&entities, &components.0, &statuses, !&saved)
+        .join()
+        .filter(|(_, _, status, _)| **status >= ChunkStatus::Terrain)
+        .map(|(_, position, _, _)| ChunkPosition::of_entity(position))
+        .collect();
+
+    let mut grouped = entities_in(&chunks, &entities, &markers, &components.0);
+
+    for position in chunks {
+        let saving = grouped.remove(&position).unwrap_or_default();
+
+        match serialize(&components, &markers, &saving) {
+            Ok(data) => store.save_entities(&position, &data),
+            Err(error) => log::error!("cannot save the entities of the chunk {position:?}: {error}"),
+        }
+    }
+}
+
+/// Plugin that saves the entities with an [EntityId] with their chunks, in the [ChunkStore] of the
+/// world.
+pub struct PersistencePlugin;
+
+impl Plugin for PersistencePlugin {
+    fn setup(self, world: &mut WorldBuilder) {
+        let next = world.get_resource_mut::<WorldInfo>().map_or(0, |info| info.next_entity_id);
+
+        world.with_component::<EntityId>();
+        world.with_component::<SavedEntities>();
+        world.with_resource(EntityIds::starting_at(next));
+        world.with_resource(UnloadedChunks::default());
+        world.with_system(EntityLoader, "entity loader", &[]);
+        world.with_system(EntityUnloader, "entity unloader", &[]);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{io, sync::Mutex};
+
+    use specs::{saveload::MarkedBuilder, Builder, RunNow};
+
+    use super::*;
+    use crate::{
+        chunk::Chunk,
+        streaming::ChunkStorage,
+        workers::{ThreadPool, Workers},
+    };
+
+    /// A storage in memory that only keeps entities.
+    #[derive(Default)]
+    struct Memory(Mutex<HashMap<ChunkPosition, Vec<u8>>>);
+
+    impl ChunkStorage for Memory {
+        fn load(&self, _position: &ChunkPosition) -> io::Result<Option<Chunk>> {
+            Ok(None)
+        }
+
+        fn save(&self, _position: &ChunkPosition, _chunk: &Chunk) -> io::Result<()> {
+            Ok(())
+        }
+
+        fn load_entities(&self, position: &ChunkPosition) -> io::Result<Option<Vec<u8>>> {
+            Ok(self.0.lock().unwrap().get(position).cloned())
+        }
+
+        fn save_entities(&self, position: &ChunkPosition, entities: &[u8]) -> io::Result<()> {
+            self.0.lock().unwrap().insert(*position, entities.to_vec());
+            Ok(())
+        }
+    }
+
+    fn world() -> World {
+        let mut world = World::new();
+        world.register::<Position>();
+        world.register::<EntityId>();
+        world.register::<SavedEntities>();
+        world.insert(EntityIds::starting_at(5));
+        world.insert(UnloadedChunks::default());
+        world.insert(ChunkStore::new(Memory::default()));
+        world.insert(Workers::new(ThreadPool::new(1)));
+        world
+    }
+
+    fn saved(world: &World) -> Vec<(u64, [f32; 3])> {
+        let (ids, positions) = (world.read_storage::<EntityId>(), world.read_storage::<Position>());
+        let mut saved: Vec<_> =
+            (&ids, &positions).join().map(|(id, position)| (id.0, [position.x, position.y, position.z])).collect();
+        saved.sort_by_key(|(id, _)| *id);
+        saved
+    }
+
+    #[test]
+    fn entities_come_back_with_their_chunk() {
+        let mut world = world();
+        let chunk = ChunkPosition::new(1, 0, -1);
+
+        world.create_entity().with(Position::new(20.5, 3.0, -4.0)).marked::<EntityId>().build();
+        world.create_entity().with(Position::new(30.0, 1.0, -16.0)).marked::<EntityId>().build();
+        let elsewhere = world.create_entity().with(Position::new(0.0, 0.0, 0.0)).marked::<EntityId>().build();
+        world.create_entity().with(Position::new(21.0, 3.0, -4.0)).build();
+        assert_eq!(world.read_resource::<EntityIds>().next(), 8);
+
+        // Entities without an id stay, like the ones of other chunks.
+        world.write_resource::<UnloadedChunks>().positions.push(chunk);
+        EntityUnloader.run_now(&world);
+        world.maintain();
+        assert_eq!(saved(&world), [(7, [0.0, 0.0, 0.0])]);
+        assert_eq!(world.read_storage::<Position>().count(), 2);
+
+        let data = {
+            let store = world.read_resource::<ChunkStore>();
+            store.flush();
+            store.load_entities(&chunk).unwrap()
+        };
+
+        world.create_entity().with(SavedEntities(data)).build();
+        EntityLoader.run_now(&world);
+        world.maintain();
+
+        assert_eq!(saved(&world), [(5, [20.5, 3.0, -4.0]), (6, [30.0, 1.0, -16.0]), (7, [0.0, 0.0, 0.0])]);
+        assert!(world.read_storage::<SavedEntities>().is_empty());
+
+        // Ids that were loaded are not given again.
+        let mut ids = world.write_resource::<EntityIds>();
+        assert_eq!(ids.retrieve_entity_internal(7), Some(elsewhere));
+        assert_eq!(ids.allocate(elsewhere, None), EntityId(8));
+    }
+}
@@ -0,0 +1,221 @@
+//! Saving worlds to disk. A world is a directory with the chunks kept in region files inside of its
+//! `region` directory, their entities in the ones of its `entities` directory, the blocks that are
+//! waiting for chunks that were never loaded in the ones of its `placements` directory, the
+//! [WorldFormat] of the chunks in `format.ron` and the [WorldInfo](crate::info::WorldInfo) in
+//! `world.ron`.
+
+use std::{
+    fs::{self, File},
+    io::{self, Write},
+    path::{Path, PathBuf},
+};
+
+use specs::World;
+
+use crate::{
+    block::BlockRegistry,
+    chunk::{Chunk, ChunkPosition},
+    generation::decoration::DeferredPlacements,
+    info::{WorldDirectory, WorldInfo},
+    persistence::{self, EntityIds},
+    streaming::{self, ChunkStorage, ChunkStore},
+    Plugin, WorldBuilder,
+};
+
+pub mod backup;
+pub mod journal;
+pub mod migration;
+pub mod region;
+
+use migration::{BlockMapping, Migrations, WorldFormat, WORLD_VERSION};
+use region::RegionStorage;
+
+/// Replaces the contents of a file so that it has either the old contents or the new ones if the
+/// program stops at any point: they are written to a temporary file that is synced to the disk and
+/// renamed over the file.
+pub fn write_atomic(path: impl AsRef<Path>, contents: impl AsRef<[u8]>) -> io::Result<()> {
+    let path = path.as_ref();
+    let mut temporary = path.as_os_str().to_owned();
+    temporary.push(".tmp");
+
+    let mut file = File::create(&temporary)?;
+    file.write_all(contents.as_ref())?;
+    file.sync_all()?;
+    drop(file);
+
+    fs::rename(&temporary, path)?;
+
+    match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => sync_directory(parent),
+        _ => Ok(()),
+    }
+}
+
+/// Makes the files that were created or renamed in a directory stay after a crash. Only some
+/// systems can do it, the others do nothing.
+fn sync_directory(directory: &Path) -> io::Result<()> {
+    match File::open(directory) {
+        Ok(directory) => directory.sync_all().or(Ok(())),
+        Err(_) => Ok(()),
+    }
+}
+
+/// The [ChunkStorage] of the directory of a world.
+pub struct WorldStorage {
+    pub chunks: RegionStorage,
+    pub entities: RegionStorage,
+    pub placements: RegionStorage,
+}
+
+impl ChunkStorage for WorldStorage {
+    fn load(&self, position: &ChunkPosition) -> io::Result<Option<Chunk>> {
+        self.chunks.load(position)
+    }
+
+    fn save(&self, position: &ChunkPosition, chunk: &Chunk) -> io::Result<()> {
+        self.chunks.save(position, chunk)
+    }
+
+    fn load_entities(&self, position: &ChunkPosition) -> io::Result<Option<Vec<u8>>> {
+        self.entities.read(position)
+    }
+
+    fn save_entities(&self, position: &ChunkPosition, entities: &[u8]) -> io::Result<()> {
+        self.entities.write(position, entities)
+    }
+
+    fn load_placements(&self, position: &ChunkPosition) -> io::Result<Option<Vec<u8>>> {
+        self.placements.read(position)
+    }
+
+    fn save_placements(&self, position: &ChunkPosition, placements: &[u8]) -> io::Result<()> {
+        self.placements.write(position, placements)
+    }
+
+    fn flush(&self) -> io::Result<()> {
+        self.chunks.flush()?;
+        self.entities.flush()?;
+        self.placements.flush()
+    }
+}
+
+/// Opens the chunks of a world, creating the world if it does not exist. Worlds of older versions
+/// are upgraded with the migrations, and the format is written back with the blocks of the registry
+/// that the world did not have.
+pub fn open_world(directory: &Path, registry: &BlockRegistry, migrations: &Migrations) -> io::Result<WorldStorage> {
+    let format_path = directory.join("format.ron");
+    let region_path = directory.join("region");
+
+    let mut format = match WorldFormat::load(&format_path)? {
+        Some(format) => format,
+        // Worlds from before the format was written saved the ids of the registry.
+        None if region_path.exists() => WorldFormat {
+            version: 0,
+            blocks: registry.iter().map(|(_, block)| block.name.clone()).collect(),
+        },
+        None => WorldFormat {
+            version: WORLD_VERSION,
+            blocks: Vec::new(),
+        },
+    };
+
+    if format.version > WORLD_VERSION {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("the world has the version {}, which is newer than this one", format.version),
+        ));
+    }
+
+    migrations.rename_blocks(&mut format);
+
+    let mapping = BlockMapping::new(&mut format, registry)?;
+    let storage = RegionStorage::open(region_path)?.with_mapping(mapping);
+
+    if migrations.converts_chunks(format.version) {
+        let positions = storage.positions()?;
+        log::info!("upgrading {} chunks from the version {}", positions.len(), format.version);
+
+        for position in positions {
+            if let Some(mut chunk) = storage.load(&position)? {
+                migrations.convert(format.version, &mut chunk, &position);
+                storage.save(&position, &chunk)?;
+            }
+        }
+
+        storage.flush()?;
+    }
+
+    format.version = WORLD_VERSION;
+    format.save(format_path)?;
+
+    Ok(WorldStorage {
+        chunks: storage,
+        entities: RegionStorage::open(directory.join("entities"))?,
+        placements: RegionStorage::open(directory.join("placements"))?,
+    })
+}
+
+/// Saves the [Unsaved](streaming::Unsaved) chunks of the world, the [DeferredPlacements] that are
+/// waiting for chunks, the entities of the chunks if there's a
+/// [PersistencePlugin](persistence::PersistencePlugin), and its [WorldInfo] if it was opened with
+/// [Builder::open](crate::Builder::open).
+pub fn save_world(world: &World) -> io::Result<()> {
+    streaming::save_all(world);
+
+    let deferred = world.try_fetch::<DeferredPlacements>();
+    if let Some(deferred) = &deferred {
+        deferred.save(&world.fetch::<ChunkStore>());
+    }
+
+    let ids = world.try_fetch::<EntityIds>();
+    if ids.is_some() {
+        persistence::save_entities(world);
+    }
+
+    let directory = world.try_fetch::<WorldDirectory>();
+    if let (Some(directory), Some(mut info)) = (directory, world.try_fetch_mut::<WorldInfo>()) {
+        if let Some(ids) = ids {
+            info.next_entity_id = ids.next();
+        }
+        if let Some(deferred) = deferred {
+            info.next_placed = deferred.next();
+        }
+
+        info.save(&directory.0)?;
+    }
+
+    world.fetch::<ChunkStore>().flush();
+
+    Ok(())
+}
+
+/// Plugin that loads and saves the chunks of the world in a directory, creating it if it does not
+/// exist. It goes after the plugins that register blocks and [Migrations], as it uses them to open
+/// the world. If the world cannot be opened, the error is logged and nothing is saved, so the
+/// directory stays as it was.
+pub struct StoragePlugin {
+    pub directory: PathBuf,
+}
+
+impl StoragePlugin {
+    pub fn new(directory: impl Into<PathBuf>) -> StoragePlugin {
+        StoragePlugin {
+            directory: directory.into(),
+        }
+    }
+}
+
+impl Plugin for StoragePlugin {
+    fn setup(self, world: &mut WorldBuilder) {
+        let registry = world.resource_mut::<BlockRegistry>().clone();
+        let migrations = world.resource_mut::<Migrations>();
+
+        let storage = open_world(&self.directory, &registry, &migrations);
+        drop(migrations);
+
+        match storage {
+            Ok(storage) => world.with_resource(ChunkStore::new(storage)),
+            Err(error) => log::error!("cannot open the world in {:?}, it's not going to be saved: {error}", self.directory),
+        }
+    }
+}
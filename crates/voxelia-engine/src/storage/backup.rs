@@ -0,0 +1,324 @@
+//! Snapshots of a world that are taken while it's running.
+//!
+//! A snapshot is a directory inside of the `backups` directory of the world, named after the time
+//! when it was taken, with the files of the world as they were after everything was saved. Region
+//! files and the other files of the world are only replaced by renaming new files over them, never
+//! changed in place, so snapshots are made of hard links and take no space until the world changes.
+//! They are copied when the system cannot link them.
+//!
+//! A snapshot is restored the next time the world is opened when its name is in the `restore` file
+//! of the `backups` directory, written by [request_restore] or by hand. The world that is replaced
+//! is kept as another snapshot first.
+
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use specs::{RunNow, World};
+
+use crate::{info::WorldDirectory, workers::Workers, Plugin, WorldBuilder};
+
+const BACKUPS_DIRECTORY: &str = "backups";
+const RESTORE_FILE: &str = "restore";
+
+/// When snapshots are taken and how many of them are kept.
+#[derive(Clone, Debug)]
+pub struct BackupPolicy {
+    /// Time between two snapshots.
+    pub interval: Duration,
+    /// Most snapshots that are kept, the oldest ones are removed first. The newest one is always
+    /// kept.
+    pub keep: usize,
+    /// Snapshots older than this are removed, but the newest one is always kept.
+    pub max_age: Option<Duration>,
+}
+
+impl Default for BackupPolicy {
+    fn default() -> Self {
+        BackupPolicy {
+            interval: Duration::from_secs(30 * 60),
+            keep: 10,
+            max_age: None,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Snapshot {
+    pub name: String,
+    pub path: PathBuf,
+    /// Seconds since the UNIX epoch of the moment when it was taken.
+    pub time: u64,
+    /// Order of the snapshot among the ones taken in the same second.
+    pub number: u64,
+}
+
+pub fn backups_directory(world: &Path) -> PathBuf {
+    world.join(BACKUPS_DIRECTORY)
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |duration| duration.as_secs())
+}
+
+/// Snapshots of a world, from the oldest to the newest.
+pub fn snapshots(world: &Path) -> io::Result<Vec<Snapshot>> {
+    let directory = backups_directory(world);
+    if !directory.exists() {
+        return Ok(Vec::new());
+    }
+
+    let mut snapshots = Vec::new();
+
+    for entry in fs::read_dir(directory)? {
+        let entry = entry?;
+        let name = entry.file_name().to_string_lossy().into_owned();
+
+        let Some((time, number)) = name.strip_prefix("snapshot-").and_then(parse_name) else {
+            continue;
+        };
+
+        if entry.file_type()?.is_dir() {
+            snapshots.push(Snapshot {
+                name,
+                path: entry.path(),
+                time,
+                number,
+            });
+        }
+    }
+
+    snapshots.sort_by_key(|snapshot| (snapshot.time, snapshot.number));
+
+    Ok(snapshots)
+}
+
+/// Reads the time and the number of a snapshot from its name, after the `snapshot-` prefix.
+fn parse_name(name: &str) -> Option<(u64, u64)> {
+    match name.split_once('-') {
+        Some((time, number)) => Some((time.parse().ok()?, number.parse().ok()?)),
+        None => Some((name.parse().ok()?, 0)),
+    }
+}
+
+/// Journals are changed in place instead of being replaced, so they cannot be linked.
+fn is_journal(path: &Path) -> bool {
+    path.file_name().unwrap_or_default() == "journal"
+}
+
+/// Temporary files are not done yet, they are never part of a snapshot.
+fn is_temporary(path: &Path) -> bool {
+    path.extension().is_some_and(|extension| extension == "tmp")
+}
+
+/// Links or copies the files of a directory into another one, skipping `skip`. Journals are copied
+/// when `journals` is set and left out otherwise, as they are empty after the world is saved.
+fn link_tree(from: &Path, to: &Path, skip: Option<&Path>, journals: bool) -> io::Result<()> {
+    fs::create_dir_all(to)?;
+
+    for entry in fs::read_dir(from)? {
+        let entry = entry?;
+        let path = entry.path();
+
+        if Some(path.as_path()) == skip {
+            continue;
+        }
+
+        let target = to.join(entry.file_name());
+
+        if entry.file_type()?.is_dir() {
+            link_tree(&path, &target, skip, journals)?;
+        } else if is_journal(&path) {
+            if journals {
+                fs::copy(&path, &target)?;
+            }
+        } else if !is_temporary(&path) && fs::hard_link(&path, &target).is_err() {
+            fs::copy(&path, &target)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Takes a snapshot of the files of a world as they are on the disk. The world has to be saved
+/// first for the snapshot to have its last changes, like [snapshot_world] does.
+pub fn create_snapshot(world: &Path) -> io::Result<Snapshot> {
+    take_snapshot(world, false)
+}
+
+fn take_snapshot(world: &Path, journals: bool) -> io::Result<Snapshot> {
+    let backups = backups_directory(world);
+    let time = now();
+
+    // Snapshots taken in the same second get a number after the time, higher than the ones before.
+    let number = snapshots(world)?
+        .iter()
+        .filter(|snapshot| snapshot.time == time)
+        .map(|snapshot| snapshot.number + 1)
+        .max()
+        .unwrap_or(0);
+
+    let name = match number {
+        0 => format!("snapshot-{time}"),
+        number => format!("snapshot-{time}-{number}"),
+    };
+
+    let path = backups.join(&name);
+    link_tree(world, &path, Some(&backups), journals)?;
+
+    Ok(Snapshot {
+        name,
+        path,
+        time,
+        number,
+    })
+}
+
+/// Removes the snapshots that the policy does not keep.
+pub fn prune(world: &Path, policy: &BackupPolicy) -> io::Result<()> {
+    let mut snapshots = snapshots(world)?;
+    if snapshots.pop().is_none() {
+        return Ok(());
+    }
+
+    let now = now();
+    let kept = policy.keep.saturating_sub(1);
+    let excess = snapshots.len().saturating_sub(kept);
+
+    for (index, snapshot) in snapshots.iter().enumerate() {
+        let old = policy
+            .max_age
+            .is_some_and(|age| now.saturating_sub(snapshot.time) > age.as_secs());
+
+        if index < excess || old {
+            fs::remove_dir_all(&snapshot.path)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Saves the world that was opened with [Builder::open](crate::Builder::open) and takes a snapshot
+/// of it.
+pub fn snapshot_world(world: &World) -> io::Result<Snapshot> {
+    let Some(directory) = world.try_fetch::<WorldDirectory>().map(|directory| directory.0.clone()) else {
+        return Err(io::Error::new(io::ErrorKind::NotFound, "the world was not opened from a directory"));
+    };
+
+    super::save_world(world)?;
+    create_snapshot(&directory)
+}
+
+/// Makes the next opening of the world restore a snapshot.
+pub fn request_restore(world: &Path, snapshot: &str) -> io::Result<()> {
+    if !backups_directory(world).join(snapshot).is_dir() {
+        return Err(io::Error::new(io::ErrorKind::NotFound, format!("there's no snapshot {snapshot}")));
+    }
+
+    super::write_atomic(backups_directory(world).join(RESTORE_FILE), snapshot)
+}
+
+/// Restores the snapshot that was asked for with [request_restore], if there's one, returning its
+/// name. It runs before anything of the world is read.
+pub fn restore_requested(world: &Path) -> io::Result<Option<String>> {
+    let backups = backups_directory(world);
+    let request = backups.join(RESTORE_FILE);
+
+    let name = match fs::read_to_string(&request) {
+        Ok(name) => name.trim().to_owned(),
+        Err(error) if error.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(error) => return Err(error),
+    };
+
+    let source = backups.join(&name);
+    if name.is_empty() || !source.is_dir() {
+        return Err(io::Error::new(io::ErrorKind::NotFound, format!("there's no snapshot {name} to restore")));
+    }
+
+    // The world that is replaced was not opened, so its journals may have writes that are not in
+    // the region files yet.
+    let previous = take_snapshot(world, true)?;
+    log::info!("restoring the snapshot {name}, the world that was there is kept in {}", previous.name);
+
+    for entry in fs::read_dir(world)? {
+        let path = entry?.path();
+
+        if path == backups {
+            continue;
+        }
+
+        if path.is_dir() {
+            fs::remove_dir_all(path)?;
+        } else {
+            fs::remove_file(path)?;
+        }
+    }
+
+    link_tree(&source, world, None, true)?;
+    fs::remove_file(request)?;
+
+    Ok(Some(name))
+}
+
+/// Takes snapshots of the world every interval of its [BackupPolicy] and prunes the old ones in
+/// the background. It runs on the thread of the dispatcher after the other systems, as it saves the
+/// whole world.
+pub struct BackupSystem {
+    pub policy: BackupPolicy,
+    last: Instant,
+}
+
+impl BackupSystem {
+    pub fn new(policy: BackupPolicy) -> BackupSystem {
+        BackupSystem {
+            policy,
+            last: Instant::now(),
+        }
+    }
+}
+
+impl<'a> RunNow<'a> for BackupSystem {
+    fn run_now(&mut self, world: &'a World) {
+        if self.last.elapsed() < self.policy.interval {
+            return;
+        }
+
+        self.last = Instant::now();
+
+        match snapshot_world(world) {
+            Ok(snapshot) => log::info!("took the snapshot {}", snapshot.name),
+            Err(error) => {
+                log::error!("cannot take a snapshot of the world: {error}");
+                return;
+            }
+        }
+
+        let directory = world.fetch::<WorldDirectory>().0.clone();
+        let policy = self.policy.clone();
+
+        world.fetch::<Workers>().spawn(move || {
+            if let Err(error) = prune(&directory, &policy) {
+                log::error!("cannot remove the old snapshots: {error}");
+            }
+        });
+    }
+
+    fn setup(&mut self, _world: &mut World) {}
+}
+
+/// Plugin that takes snapshots of a world opened with [Builder::open](crate::Builder::open).
+#[derive(Default)]
+pub struct BackupPlugin {
+    pub policy: BackupPolicy,
+}
+
+impl Plugin for BackupPlugin {
+    fn setup(self, world: &mut WorldBuilder) {
+        world.resource_mut::<Workers>();
+        world.with_thread_local(BackupSystem::new(self.policy));
+    }
+}
@@ -0,0 +1,113 @@
+//! Write-ahead journal of the chunks written to a [RegionStorage](super::region::RegionStorage).
+//!
+//! Every write is appended to the journal and synced to the disk before it's done, and the region
+//! files are only changed later, all at once, when the journal is checkpointed. A crash at any point
+//! loses nothing that was written: the records that are complete are replayed when the storage is
+//! opened again, and a record that was being appended when the crash happened is thrown away.
+//!
+//! Each record has the length of its content, the CRC32 of the content, and the content, which is
+//! the position of the chunk and its data. Empty data means that the chunk was removed.
+
+use std::{
+    fs::{File, OpenOptions},
+    io::{self, Read, Seek, SeekFrom, Write},
+    path::Path,
+};
+
+use crate::chunk::ChunkPosition;
+
+/// Bytes of the position at the start of the content of a record.
+const POSITION_SIZE: usize = 24;
+
+/// The data that was written for a chunk, empty if the chunk was removed.
+pub type JournalWrite = (ChunkPosition, Vec<u8>);
+
+pub struct Journal {
+    file: File,
+    length: u64,
+}
+
+impl Journal {
+    /// Opens a journal, creating it if it does not exist, and reads the writes of its records in
+    /// order. The records after the first one that is not complete are discarded.
+    pub fn open(path: impl AsRef<Path>) -> io::Result<(Journal, Vec<JournalWrite>)> {
+        let mut file = OpenOptions::new().read(true).write(true).create(true).truncate(false).open(path)?;
+
+        let mut bytes = Vec::new();
+        file.read_to_end(&mut bytes)?;
+
+        let mut writes = Vec::new();
+        let mut valid = 0;
+
+        while let Some((write, length)) = Self::record(&bytes[valid..]) {
+            writes.push(write);
+            valid += length;
+        }
+
+        if valid < bytes.len() {
+            log::warn!("discarding {} bytes of an incomplete write in the journal", bytes.len() - valid);
+            file.set_len(valid as u64)?;
+            file.sync_all()?;
+        }
+
+        file.seek(SeekFrom::Start(valid as u64))?;
+
+        let journal = Journal {
+            file,
+            length: valid as u64,
+        };
+
+        Ok((journal, writes))
+    }
+
+    /// Reads the record at the start of the bytes and its length, [None] if it's not complete.
+    fn record(bytes: &[u8]) -> Option<(JournalWrite, usize)> {
+        let length = u32::from_le_bytes(bytes.get(..4)?.try_into().unwrap()) as usize;
+        let checksum = u32::from_le_bytes(bytes.get(4..8)?.try_into().unwrap());
+        let content = bytes.get(8..8 + length)?;
+
+        if length < POSITION_SIZE || crc32fast::hash(content) != checksum {
+            return None;
+        }
+
+        let [x, y, z] = [0, 1, 2].map(|axis| i64::from_le_bytes(content[axis * 8..axis * 8 + 8].try_into().unwrap()));
+        let write = (ChunkPosition::new(x, y, z), content[POSITION_SIZE..].to_vec());
+
+        Some((write, 8 + length))
+    }
+
+    /// Adds a write to the journal, returning once it's on the disk.
+    pub fn append(&mut self, position: &ChunkPosition, data: &[u8]) -> io::Result<()> {
+        let mut content = Vec::with_capacity(POSITION_SIZE + data.len());
+        for axis in [position.x, position.y, position.z] {
+            content.extend_from_slice(&axis.to_le_bytes());
+        }
+        content.extend_from_slice(data);
+
+        let mut record = Vec::with_capacity(8 + content.len());
+        record.extend_from_slice(&(content.len() as u32).to_le_bytes());
+        record.extend_from_slice(&crc32fast::hash(&content).to_le_bytes());
+        record.extend_from_slice(&content);
+
+        self.file.write_all(&record)?;
+        self.file.sync_data()?;
+        self.length += record.len() as u64;
+
+        Ok(())
+    }
+
+    /// Bytes of the records in the journal.
+    pub fn length(&self) -> u64 {
+        self.length
+    }
+
+    /// Forgets all the records, once the region files have them.
+    pub fn clear(&mut self) -> io::Result<()> {
+        self.file.set_len(0)?;
+        self.file.seek(SeekFrom::Start(0))?;
+        self.file.sync_all()?;
+        self.length = 0;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,247 @@
+//! Upgrades of the worlds that were saved by older versions of the engine.
+//!
+//! The ids of the blocks depend on the order in which plugins register them, so the saved chunks
+//! do not use the ids of the [BlockRegistry]. The [WorldFormat] of a world keeps the name of each id
+//! of its chunks, and a [BlockMapping] translates them to the ids of the registry when the chunks
+//! are loaded and back when they are saved. Blocks that the world did not have yet are added to the
+//! end of the format, so the ids of the saved chunks never change their meaning.
+//!
+//! The format also has the version of the world. When a world of an older version is opened, the
+//! [Migration]s that came after it rename the blocks of the format, and the ones that convert chunks
+//! go through all the chunks of the world.
+
+use std::{fs, io, path::Path};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    block::{BlockId, BlockPosition, BlockRegistry, AIR},
+    chunk::{Chunk, ChunkPosition, CHUNK_HEIGHT, CHUNK_LENGTH, CHUNK_WIDTH},
+};
+
+/// Version of the worlds written by this version of the engine.
+pub const WORLD_VERSION: u32 = 1;
+
+/// Contents of the `format.ron` file of a world.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct WorldFormat {
+    pub version: u32,
+    /// Names of the blocks of the saved chunks, the first one has the id 1 as [AIR] has no name.
+    pub blocks: Vec<String>,
+}
+
+impl WorldFormat {
+    /// Reads the format of a world, [None] if the file does not exist.
+    pub fn load(path: impl AsRef<Path>) -> io::Result<Option<WorldFormat>> {
+        let source = match fs::read_to_string(path) {
+            Ok(source) => source,
+            Err(error) if error.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(error) => return Err(error),
+        };
+
+        let format: WorldFormat =
+            ron::from_str(&source).map_err(|error| io::Error::new(io::ErrorKind::InvalidData, error))?;
+        format.check()?;
+
+        Ok(Some(format))
+    }
+
+    /// Checks that every block of the format has an id, as the ids of the chunks are a single byte.
+    pub fn check(&self) -> io::Result<()> {
+        if self.blocks.len() > BlockId::MAX as usize {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("the world has {} blocks, more than {}", self.blocks.len(), BlockId::MAX),
+            ));
+        }
+
+        Ok(())
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        let source = ron::ser::to_string_pretty(self, ron::ser::PrettyConfig::default())
+            .map_err(|error| io::Error::new(io::ErrorKind::InvalidData, error))?;
+
+        super::write_atomic(path, source)
+    }
+}
+
+type ChunkConversion = Box<dyn Fn(&mut Chunk, &ChunkPosition) + Send + Sync>;
+
+/// The changes that take a world to a version from the version before it.
+pub struct Migration {
+    /// Version of the world after the migration.
+    pub version: u32,
+    renames: Vec<(String, String)>,
+    conversion: Option<ChunkConversion>,
+}
+
+impl Migration {
+    pub fn new(version: u32) -> Migration {
+        Migration {
+            version,
+            renames: Vec::new(),
+            conversion: None,
+        }
+    }
+
+    /// Gives the blocks that had the old name the new one. Renaming a block to the name of another
+    /// block merges them.
+    pub fn rename_block(mut self, old: &str, new: &str) -> Migration {
+        self.renames.push((old.to_owned(), new.to_owned()));
+        self
+    }
+
+    /// Changes every saved chunk, like when the way some blocks are laid out changes. Chunks get
+    /// to the function with the ids of the [BlockRegistry].
+    pub fn convert_chunks(mut self, conversion: impl Fn(&mut Chunk, &ChunkPosition) + Send + Sync + 'static) -> Migration {
+        self.conversion = Some(Box::new(conversion));
+        self
+    }
+}
+
+/// Resource with the migrations of the worlds. Plugins add theirs before the world is opened by the
+/// [StoragePlugin](super::StoragePlugin).
+#[derive(Default)]
+pub struct Migrations {
+    migrations: Vec<Migration>,
+}
+
+impl Migrations {
+    pub fn add(&mut self, migration: Migration) {
+        self.migrations.push(migration);
+        self.migrations.sort_by_key(|migration| migration.version);
+    }
+
+    /// Migrations that a world of this version needs, in order.
+    pub fn after(&self, version: u32) -> impl Iterator<Item = &Migration> {
+        self.migrations.iter().filter(move |migration| migration.version > version)
+    }
+
+    /// Renames the blocks of a format with the migrations that come after its version.
+    pub fn rename_blocks(&self, format: &mut WorldFormat) {
+        for migration in self.after(format.version) {
+            for (old, new) in &migration.renames {
+                for name in format.blocks.iter_mut().filter(|name| *name == old) {
+                    name.clone_from(new);
+                }
+            }
+        }
+    }
+
+    /// Converts a chunk with the migrations that come after a version.
+    pub fn convert(&self, version: u32, chunk: &mut Chunk, position: &ChunkPosition) {
+        for migration in self.after(version) {
+            if let Some(conversion) = &migration.conversion {
+                conversion(chunk, position);
+            }
+        }
+    }
+
+    /// If the chunks of a world of this version have to be converted.
+    pub fn converts_chunks(&self, version: u32) -> bool {
+        self.after(version).any(|migration| migration.conversion.is_some())
+    }
+}
+
+/// Translation between the ids of the saved chunks of a world and the ids of the [BlockRegistry].
+#[derive(Clone, Debug)]
+pub struct BlockMapping {
+    to_registry: [BlockId; 256],
+    to_world: [BlockId; 256],
+    identity: bool,
+}
+
+impl Default for BlockMapping {
+    fn default() -> Self {
+        let ids = std::array::from_fn(|id| id as BlockId);
+
+        BlockMapping {
+            to_registry: ids,
+            to_world: ids,
+            identity: true,
+        }
+    }
+}
+
+impl BlockMapping {
+    /// Maps the blocks of a format to the blocks with the same name of the registry, adding the
+    /// blocks of the registry that are missing to the format. The blocks of the format that are not
+    /// registered anymore become [AIR].
+    pub fn new(format: &mut WorldFormat, registry: &BlockRegistry) -> io::Result<BlockMapping> {
+        // The migrations may have renamed the blocks of a format that was checked when it was loaded.
+        format.check()?;
+
+        let mut mapping = BlockMapping {
+            to_registry: [AIR; 256],
+            to_world: [AIR; 256],
+            identity: true,
+        };
+
+        for (id, definition) in registry.iter() {
+            // A block can have many ids in the world when a migration merged it with other blocks.
+            // All of them are read as the block, and it's written with the first one.
+            let indices = format.blocks.iter().enumerate();
+            let indices: Vec<usize> = indices.filter(|(_, name)| **name == definition.name).map(|(index, _)| index).collect();
+
+            let index = match indices.first() {
+                Some(index) => *index,
+                None => {
+                    if format.blocks.len() >= BlockId::MAX as usize {
+                        return Err(io::Error::new(io::ErrorKind::InvalidData, "the world has too many blocks"));
+                    }
+
+                    format.blocks.push(definition.name.clone());
+                    format.blocks.len() - 1
+                }
+            };
+
+            for merged in indices {
+                mapping.to_registry[merged + 1] = id;
+            }
+
+            let world = index as BlockId + 1;
+            mapping.to_world[id as usize] = world;
+            mapping.to_registry[world as usize] = id;
+        }
+
+        for (index, name) in format.blocks.iter().enumerate() {
+            if mapping.to_registry[index + 1] == AIR {
+                log::warn!("the block {name} is not registered, it's replaced by air");
+            }
+        }
+
+        mapping.identity = (0..256).all(|id| mapping.to_registry[id] as usize == id);
+
+        Ok(mapping)
+    }
+
+    /// Turns a chunk that was read from the world into a chunk with the ids of the registry.
+    pub fn to_registry(&self, chunk: &mut Chunk) {
+        if !self.identity {
+            replace(chunk, &self.to_registry);
+        }
+    }
+
+    /// Turns a chunk with the ids of the registry into a chunk that can be written to the world.
+    pub fn to_world(&self, chunk: &mut Chunk) {
+        if !self.identity {
+            replace(chunk, &self.to_world);
+        }
+    }
+
+    pub fn is_identity(&self) -> bool {
+        self.identity
+    }
+}
+
+fn replace(chunk: &mut Chunk, table: &[BlockId; 256]) {
+    for x in 0..CHUNK_WIDTH as i64 {
+        for y in 0..CHUNK_HEIGHT as i64 {
+            for z in 0..CHUNK_LENGTH as i64 {
+                let position = BlockPosition::new(x, y, z);
+                chunk.set(&position, table[chunk.get(&position) as usize]);
+            }
+        }
+    }
+}
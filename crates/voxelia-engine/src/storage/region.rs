@@ -0,0 +1,614 @@
+//! Region files, that keep the chunks of a cube of [REGION_SIZE]³ chunks in a single file.
+//!
+//! A region file is split in sectors of [SECTOR_SIZE] bytes. The first sector is a table with an
+//! entry for each chunk: the sector where the chunk starts and the number of sectors that it takes,
+//! both zero if the chunk was never saved. The sectors of a chunk start with the length of its data
+//! in bytes. A chunk that grows beyond its sectors moves to the first free run of sectors that is
+//! big enough for it, and the sectors that it leaves behind are reused by the chunks saved later.
+
+use std::{
+    collections::{HashMap, HashSet},
+    fs::{self, File, OpenOptions},
+    io::{self, Read, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+
+use crate::{
+    chunk::{Chunk, ChunkPosition},
+    codec::{self, Compression},
+    streaming::ChunkStorage,
+};
+
+use super::{journal::Journal, migration::BlockMapping};
+
+/// Chunks along each axis of a region.
+pub const REGION_SIZE: i64 = 8;
+
+pub const SECTOR_SIZE: usize = 4096;
+
+const CHUNKS: usize = (REGION_SIZE * REGION_SIZE * REGION_SIZE) as usize;
+
+/// Size of an entry of the table, the first sector and the number of sectors as two u32.
+const ENTRY_SIZE: usize = 8;
+
+const HEADER_SECTORS: u32 = (CHUNKS * ENTRY_SIZE).div_ceil(SECTOR_SIZE) as u32;
+
+/// Regions that are kept open by a [RegionStorage] before they are all closed.
+const OPEN_REGIONS: usize = 64;
+
+/// Bytes of the journal of a [RegionStorage] after which its writes go to the region files.
+pub const JOURNAL_LIMIT: u64 = 8 * 1024 * 1024;
+
+const JOURNAL_FILE: &str = "journal";
+
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+struct Entry {
+    offset: u32,
+    sectors: u32,
+}
+
+impl Entry {
+    fn is_empty(&self) -> bool {
+        self.sectors == 0
+    }
+}
+
+/// A region file that is open for reading and writing chunks. Chunks are identified by their
+/// index in the region, given by [RegionFile::index].
+pub struct RegionFile {
+    file: File,
+    entries: Vec<Entry>,
+    /// If each sector of the file is taken by the table or by a chunk.
+    used: Vec<bool>,
+}
+
+impl RegionFile {
+    /// Opens a region file, creating it if it does not exist. Entries that point outside of the
+    /// file or to sectors that another entry already took are dropped.
+    pub fn open(path: impl AsRef<Path>) -> io::Result<RegionFile> {
+        let mut file = OpenOptions::new().read(true).write(true).create(true).truncate(false).open(path)?;
+        let length = file.metadata()?.len() as usize;
+
+        let mut header = vec![0; HEADER_SECTORS as usize * SECTOR_SIZE];
+
+        if length < header.len() {
+            file.set_len(header.len() as u64)?;
+            file.write_all(&header)?;
+        } else {
+            file.read_exact(&mut header)?;
+        }
+
+        let sectors = length.max(header.len()).div_ceil(SECTOR_SIZE);
+        let mut used = vec![false; sectors];
+        used[..HEADER_SECTORS as usize].fill(true);
+
+        let mut entries = vec![Entry::default(); CHUNKS];
+
+        for (index, entry) in entries.iter_mut().enumerate() {
+            let bytes = &header[index * ENTRY_SIZE..(index + 1) * ENTRY_SIZE];
+            let offset = u32::from_le_bytes(bytes[..4].try_into().unwrap());
+            let count = u32::from_le_bytes(bytes[4..].try_into().unwrap());
+
+            let range = offset as usize..offset as usize + count as usize;
+            if count == 0 || range.end > used.len() || used[range.clone()].iter().any(|used| *used) {
+                continue;
+            }
+
+            used[range].fill(true);
+            *entry = Entry { offset, sectors: count };
+        }
+
+        Ok(RegionFile { file, entries, used })
+    }
+
+    /// Region of a chunk and the index of the chunk inside of it.
+    pub fn index(position: &ChunkPosition) -> ([i64; 3], usize) {
+        let region = [position.x, position.y, position.z].map(|axis| axis.div_euclid(REGION_SIZE));
+        let [x, y, z] = [position.x, position.y, position.z].map(|axis| axis.rem_euclid(REGION_SIZE) as usize);
+        let size = REGION_SIZE as usize;
+
+        (region, (y * size + z) * size + x)
+    }
+
+    /// Position of the chunk with an index in a region, the inverse of [RegionFile::index].
+    pub fn position(region: [i64; 3], index: usize) -> ChunkPosition {
+        let size = REGION_SIZE as usize;
+        let [x, y, z] = [index % size, index / (size * size), index / size % size].map(|axis| axis as i64);
+
+        ChunkPosition::new(
+            region[0] * REGION_SIZE + x,
+            region[1] * REGION_SIZE + y,
+            region[2] * REGION_SIZE + z,
+        )
+    }
+
+    pub fn contains(&self, index: usize) -> bool {
+        !self.entries[index].is_empty()
+    }
+
+    pub fn read(&mut self, index: usize) -> io::Result<Option<Vec<u8>>> {
+        let entry = self.entries[index];
+        if entry.is_empty() {
+            return Ok(None);
+        }
+
+        self.file.seek(SeekFrom::Start(entry.offset as u64 * SECTOR_SIZE as u64))?;
+
+        let mut length = [0; 4];
+        self.file.read_exact(&mut length)?;
+        let length = u32::from_le_bytes(length) as usize;
+
+        if length + 4 > entry.sectors as usize * SECTOR_SIZE {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "the chunk is longer than its sectors"));
+        }
+
+        let mut data = vec![0; length];
+        self.file.read_exact(&mut data)?;
+
+        Ok(Some(data))
+    }
+
+    pub fn write(&mut self, index: usize, data: &[u8]) -> io::Result<()> {
+        let needed = (data.len() + 4).div_ceil(SECTOR_SIZE);
+        let old = self.entries[index];
+
+        let entry = if !old.is_empty() && needed <= old.sectors as usize {
+            // The chunk still fits where it was, the sectors that it does not need anymore are freed.
+            self.free(Entry { offset: old.offset + needed as u32, sectors: old.sectors - needed as u32 });
+            Entry { offset: old.offset, sectors: needed as u32 }
+        } else {
+            self.free(old);
+            self.allocate(needed)
+        };
+
+        let mut bytes = Vec::with_capacity(needed * SECTOR_SIZE);
+        bytes.extend_from_slice(&(data.len() as u32).to_le_bytes());
+        bytes.extend_from_slice(data);
+        bytes.resize(needed * SECTOR_SIZE, 0);
+
+        self.file.seek(SeekFrom::Start(entry.offset as u64 * SECTOR_SIZE as u64))?;
+        self.file.write_all(&bytes)?;
+
+        self.set_entry(index, entry)
+    }
+
+    /// Forgets a chunk, its sectors are reused by the next chunks that are written.
+    pub fn remove(&mut self, index: usize) -> io::Result<()> {
+        self.free(self.entries[index]);
+        self.set_entry(index, Entry::default())
+    }
+
+    /// Waits until everything that was written is on the disk.
+    pub fn sync(&self) -> io::Result<()> {
+        self.file.sync_all()
+    }
+
+    fn set_entry(&mut self, index: usize, entry: Entry) -> io::Result<()> {
+        self.entries[index] = entry;
+
+        let mut bytes = [0; ENTRY_SIZE];
+        bytes[..4].copy_from_slice(&entry.offset.to_le_bytes());
+        bytes[4..].copy_from_slice(&entry.sectors.to_le_bytes());
+
+        self.file.seek(SeekFrom::Start((index * ENTRY_SIZE) as u64))?;
+        self.file.write_all(&bytes)
+    }
+
+    fn free(&mut self, entry: Entry) {
+        let start = entry.offset as usize;
+        self.used[start..start + entry.sectors as usize].fill(false);
+    }
+
+    /// Takes the first run of free sectors that is long enough, growing the file if there's none.
+    fn allocate(&mut self, sectors: usize) -> Entry {
+        let mut start = 0;
+        let mut run = 0;
+
+        for (index, used) in self.used.iter().enumerate() {
+            if *used {
+                run = 0;
+                continue;
+            }
+
+            if run == 0 {
+                start = index;
+            }
+
+            run += 1;
+            if run == sectors {
+                break;
+            }
+        }
+
+        // The free sectors at the end of the file are extended with new ones.
+        if run < sectors {
+            start = self.used.len() - run;
+            self.used.resize(start + sectors, false);
+        }
+
+        self.used[start..start + sectors].fill(true);
+
+        Entry { offset: start as u32, sectors: sectors as u32 }
+    }
+}
+
+/// A [ChunkStorage] that keeps the chunks in region files inside of a directory. The blocks of the
+/// chunks are translated with a [BlockMapping], which does nothing by default.
+///
+/// Writes go to the [Journal] of the directory first, and they are kept in memory until the journal
+/// is checkpointed. A checkpoint copies each region file that changed to a temporary file, writes
+/// the chunks to the copy, syncs it and renames it over the original, so a region file is always
+/// either the old one or the new one. It happens when the journal grows beyond [JOURNAL_LIMIT],
+/// when the storage is flushed and when it's opened, replaying the journal of the last time.
+pub struct RegionStorage {
+    directory: PathBuf,
+    regions: Mutex<HashMap<[i64; 3], RegionFile>>,
+    mapping: BlockMapping,
+    pending: Mutex<Pending>,
+}
+
+/// Writes that are in the journal but not in the region files yet.
+struct Pending {
+    journal: Journal,
+    writes: HashMap<ChunkPosition, Vec<u8>>,
+}
+
+impl RegionStorage {
+    /// Uses the region files in a directory, creating it if it does not exist. The writes of the
+    /// journal that did not reach the region files are written to them.
+    pub fn open(directory: impl Into<PathBuf>) -> io::Result<RegionStorage> {
+        let directory = directory.into();
+        fs::create_dir_all(&directory)?;
+
+        // Region files that were being replaced when the last checkpoint was interrupted, the
+        // originals and the journal are still there.
+        for entry in fs::read_dir(&directory)? {
+            let path = entry?.path();
+            if path.extension().is_some_and(|extension| extension == "tmp") {
+                fs::remove_file(path)?;
+            }
+        }
+
+        let (journal, writes) = Journal::open(directory.join(JOURNAL_FILE))?;
+
+        let storage = RegionStorage {
+            directory,
+            regions: Mutex::new(HashMap::new()),
+            mapping: BlockMapping::default(),
+            pending: Mutex::new(Pending {
+                journal,
+                writes: HashMap::new(),
+            }),
+        };
+
+        if !writes.is_empty() {
+            log::info!("replaying {} writes of the journal of {:?}", writes.len(), storage.directory);
+
+            let mut pending = storage.pending.lock().unwrap();
+            pending.writes.extend(writes);
+            storage.checkpoint(&mut pending)?;
+        }
+
+        Ok(storage)
+    }
+
+    pub fn with_mapping(mut self, mapping: BlockMapping) -> RegionStorage {
+        self.mapping = mapping;
+        self
+    }
+
+    /// Reads the data that was written for a chunk, [None] if there's none.
+    pub fn read(&self, position: &ChunkPosition) -> io::Result<Option<Vec<u8>>> {
+        if let Some(data) = self.pending.lock().unwrap().writes.get(position) {
+            return Ok((!data.is_empty()).then(|| data.clone()));
+        }
+
+        Ok(self.with_region(position, |region, index| region.read(index))?.flatten())
+    }
+
+    /// Writes the data of a chunk, forgetting the chunk if the data is empty. The data is safe once
+    /// this returns, even if it's not in the region file yet.
+    pub fn write(&self, position: &ChunkPosition, data: &[u8]) -> io::Result<()> {
+        let mut pending = self.pending.lock().unwrap();
+
+        pending.journal.append(position, data)?;
+        pending.writes.insert(*position, data.to_vec());
+
+        if pending.journal.length() > JOURNAL_LIMIT {
+            self.checkpoint(&mut pending)?;
+        }
+
+        Ok(())
+    }
+
+    /// Writes all the writes of the journal to the region files.
+    pub fn flush(&self) -> io::Result<()> {
+        self.checkpoint(&mut self.pending.lock().unwrap())
+    }
+
+    fn checkpoint(&self, pending: &mut Pending) -> io::Result<()> {
+        if pending.writes.is_empty() {
+            return Ok(());
+        }
+
+        let mut changed: HashMap<[i64; 3], Vec<(usize, &[u8])>> = HashMap::new();
+        for (position, data) in &pending.writes {
+            let (region, index) = RegionFile::index(position);
+            changed.entry(region).or_default().push((index, data));
+        }
+
+        // The regions stay locked until the new files are in place, or a reader could open one of
+        // the old files and keep reading it once the writes are not pending anymore.
+        let mut regions = self.regions.lock().unwrap();
+
+        for (region, writes) in changed {
+            regions.remove(&region);
+            self.replace_region(region, &writes)?;
+        }
+
+        drop(regions);
+
+        pending.journal.clear()?;
+        pending.writes.clear();
+
+        Ok(())
+    }
+
+    /// Writes chunks to a copy of a region file that replaces it once it's on the disk.
+    fn replace_region(&self, region: [i64; 3], writes: &[(usize, &[u8])]) -> io::Result<()> {
+        let path = self.path(region);
+        let temporary = path.with_extension("region.tmp");
+
+        // Removing chunks from a region that does not exist does nothing.
+        if !path.exists() {
+            if writes.iter().all(|(_, data)| data.is_empty()) {
+                return Ok(());
+            }
+        } else {
+            fs::copy(&path, &temporary)?;
+        }
+
+        let mut file = RegionFile::open(&temporary)?;
+
+        for (index, data) in writes {
+            if !data.is_empty() {
+                file.write(*index, data)?;
+            } else if file.contains(*index) {
+                file.remove(*index)?;
+            }
+        }
+
+        file.sync()?;
+        drop(file);
+
+        fs::rename(&temporary, &path)?;
+        super::sync_directory(&self.directory)
+    }
+
+    /// Positions of all the chunks that were saved.
+    pub fn positions(&self) -> io::Result<Vec<ChunkPosition>> {
+        let mut positions = HashSet::new();
+
+        for entry in fs::read_dir(&self.directory)? {
+            let name = entry?.file_name();
+
+            let Some(region) = name
+                .to_str()
+                .and_then(|name| name.strip_prefix("r.")?.strip_suffix(".region"))
+                .and_then(|name| {
+                    let axes: Vec<i64> = name.split('.').map(str::parse).collect::<Result<_, _>>().ok()?;
+                    <[i64; 3]>::try_from(axes).ok()
+                })
+            else {
+                continue;
+            };
+
+            let position = ChunkPosition::new(region[0] * REGION_SIZE, region[1] * REGION_SIZE, region[2] * REGION_SIZE);
+            let saved = self.with_region(&position, |region, _| {
+                Ok((0..CHUNKS).filter(|index| region.contains(*index)).collect::<Vec<_>>())
+            })?;
+
+            for index in saved.into_iter().flatten() {
+                positions.insert(RegionFile::position(region, index));
+            }
+        }
+
+        for (position, data) in &self.pending.lock().unwrap().writes {
+            if data.is_empty() {
+                positions.remove(position);
+            } else {
+                positions.insert(*position);
+            }
+        }
+
+        Ok(positions.into_iter().collect())
+    }
+
+    pub fn path(&self, region: [i64; 3]) -> PathBuf {
+        let [x, y, z] = region;
+        self.directory.join(format!("r.{x}.{y}.{z}.region"))
+    }
+
+    /// Runs a function with the region file of a chunk and the index of the chunk in it, [None] if
+    /// the file does not exist. Region files are opened the first time they are needed and they are
+    /// kept open.
+    fn with_region<T>(
+        &self,
+        position: &ChunkPosition,
+        function: impl FnOnce(&mut RegionFile, usize) -> io::Result<T>,
+    ) -> io::Result<Option<T>> {
+        let (region, index) = RegionFile::index(position);
+        let mut regions = self.regions.lock().unwrap();
+
+        if !regions.contains_key(&region) {
+            let path = self.path(region);
+            if !path.exists() {
+                return Ok(None);
+            }
+
+            if regions.len() >= OPEN_REGIONS {
+                regions.clear();
+            }
+
+            regions.insert(region, RegionFile::open(path)?);
+        }
+
+        function(regions.get_mut(&region).unwrap(), index).map(Some)
+    }
+}
+
+impl ChunkStorage for RegionStorage {
+    fn load(&self, position: &ChunkPosition) -> io::Result<Option<Chunk>> {
+        let Some(data) = self.read(position)? else {
+            return Ok(None);
+        };
+
+        let mut chunk = codec::decode(&data).map_err(|error| io::Error::new(io::ErrorKind::InvalidData, error))?;
+        self.mapping.to_registry(&mut chunk);
+
+        Ok(Some(chunk))
+    }
+
+    fn save(&self, position: &ChunkPosition, chunk: &Chunk) -> io::Result<()> {
+        let data = if self.mapping.is_identity() {
+            codec::encode(chunk, Compression::Lz4)
+        } else {
+            let mut chunk = chunk.clone();
+            self.mapping.to_world(&mut chunk);
+            codec::encode(&chunk, Compression::Lz4)
+        };
+
+        let data = data.map_err(|error| io::Error::new(io::ErrorKind::InvalidData, error))?;
+        self.write(position, &data)
+    }
+
+    fn flush(&self) -> io::Result<()> {
+        RegionStorage::flush(self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const START: u32 = HEADER_SECTORS;
+
+    /// A region file in the temporary directory that is removed when it's dropped.
+    struct TestRegion(PathBuf);
+
+    impl TestRegion {
+        fn new(name: &str) -> TestRegion {
+            let path = std::env::temp_dir().join(format!("voxelia-region-{name}-{}", std::process::id()));
+            let _ = fs::remove_file(&path);
+            TestRegion(path)
+        }
+
+        fn open(&self) -> RegionFile {
+            RegionFile::open(&self.0).unwrap()
+        }
+    }
+
+    impl Drop for TestRegion {
+        fn drop(&mut self) {
+            let _ = fs::remove_file(&self.0);
+        }
+    }
+
+    /// Data that takes exactly `sectors` sectors with its length.
+    fn data(value: u8, sectors: usize) -> Vec<u8> {
+        vec![value; sectors * SECTOR_SIZE - 4]
+    }
+
+    fn entry(offset: u32, sectors: u32) -> Entry {
+        Entry { offset, sectors }
+    }
+
+    #[test]
+    fn chunks_that_grow_move_and_leave_their_sectors() {
+        let path = TestRegion::new("grow");
+        let mut region = path.open();
+
+        region.write(0, &data(1, 1)).unwrap();
+        region.write(1, &data(2, 1)).unwrap();
+        region.write(0, &data(3, 3)).unwrap();
+        assert_eq!(region.entries[0], entry(START + 2, 3));
+
+        // The sector that the first chunk left is the first one that is free.
+        region.write(2, &data(4, 1)).unwrap();
+        assert_eq!(region.entries[2], entry(START, 1));
+
+        drop(region);
+        let mut region = path.open();
+        assert_eq!(region.read(0).unwrap(), Some(data(3, 3)));
+        assert_eq!(region.read(1).unwrap(), Some(data(2, 1)));
+        assert_eq!(region.read(2).unwrap(), Some(data(4, 1)));
+        assert_eq!(region.used.len(), START as usize + 5);
+    }
+
+    #[test]
+    fn chunks_that_shrink_stay_and_free_the_rest() {
+        let path = TestRegion::new("shrink");
+        let mut region = path.open();
+
+        region.write(0, &data(1, 3)).unwrap();
+        region.write(1, &data(2, 1)).unwrap();
+        region.write(0, &data(3, 1)).unwrap();
+        assert_eq!(region.entries[0], entry(START, 1));
+
+        region.write(2, &data(4, 2)).unwrap();
+        assert_eq!(region.entries[2], entry(START + 1, 2));
+
+        // Runs that are too short are skipped, and the free sectors at the end are extended.
+        region.write(0, &data(5, 2)).unwrap();
+        assert_eq!(region.entries[0], entry(START + 4, 2));
+        region.write(3, &data(6, 2)).unwrap();
+        assert_eq!(region.entries[3], entry(START + 6, 2));
+
+        assert_eq!(region.read(0).unwrap(), Some(data(5, 2)));
+        assert_eq!(region.read(2).unwrap(), Some(data(4, 2)));
+    }
+
+    #[test]
+    fn removed_chunks_free_their_sectors() {
+        let path = TestRegion::new("remove");
+        let mut region = path.open();
+
+        region.write(5, &data(1, 2)).unwrap();
+        region.write(6, &data(2, 1)).unwrap();
+        region.remove(5).unwrap();
+
+        assert!(!region.contains(5));
+        assert_eq!(region.read(5).unwrap(), None);
+
+        region.write(7, &data(3, 2)).unwrap();
+        assert_eq!(region.entries[7], entry(START, 2));
+
+        drop(region);
+        let region = path.open();
+        assert!(!region.contains(5));
+        assert!(region.contains(6) && region.contains(7));
+    }
+
+    #[test]
+    fn overlapping_entries_are_dropped() {
+        let path = TestRegion::new("overlap");
+
+        // A header whose entries overlap or point past the end of the file, with two sectors of data.
+        let mut bytes = vec![0; (START as usize + 2) * SECTOR_SIZE];
+        let entries = [(0, entry(START, 2)), (1, entry(START + 1, 1)), (2, entry(START + 1, 5)), (3, entry(0, 1))];
+        for (index, entry) in entries {
+            bytes[index * ENTRY_SIZE..index * ENTRY_SIZE + 4].copy_from_slice(&entry.offset.to_le_bytes());
+            bytes[index * ENTRY_SIZE + 4..(index + 1) * ENTRY_SIZE].copy_from_slice(&entry.sectors.to_le_bytes());
+        }
+        fs::write(&path.0, bytes).unwrap();
+
+        let mut region = path.open();
+        assert_eq!(region.entries[0], entry(START, 2));
+        assert!((1..4).all(|index| !region.contains(index)));
+
+        // The sectors of the entry that was kept are not given to other chunks.
+        region.write(1, &data(1, 1)).unwrap();
+        assert_eq!(region.entries[1], entry(START + 2, 1));
+    }
+}
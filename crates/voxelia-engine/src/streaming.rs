@@ -0,0 +1,452 @@
+//! Loading and unloading of the chunks around the viewers, like the camera of a player.
+//!
+//! Every [ChunkViewer] keeps the chunks within its radius loaded, plus a horizontal margin that
+//! lets the chunks at the border of the radius go through the whole generation. Chunks do not wait
+//! for the ones above and below that are not loaded, so there is no vertical margin. Chunks are
+//! only unloaded once they are a few chunks farther than that, so walking back and forth over a
+//! chunk border does not load and unload the same chunks again and again.
+//!
+//! Chunks that changed since they were loaded are marked as [Unsaved] and written to the
+//! [ChunkStore] when they are unloaded, or by [save_all] before the world is closed.
+
+use std::collections::HashMap;
+use std::io;
+use std::sync::{Arc, Condvar, Mutex};
+
+use specs::{Component, Entities, Join, NullStorage, Read, ReadStorage, System, VecStorage, World, WorldExt, Write};
+
+use crate::{
+    chunk::{Chunk, ChunkPosition},
+    generation::{ChunkRequests, ChunkStatus},
+    persistence::{SavedEntities, UnloadedChunks},
+    workers::Workers,
+    Plugin, Position, WorldBuilder,
+};
+
+/// Chunks around the radius of a viewer that are loaded so the ones inside of it can become full.
+/// A chunk is only full when its neighbours are lit, and these need their own neighbours to be
+/// decorated, and these need theirs to be carved.
+pub const GENERATION_MARGIN: usize = 3;
+
+/// Something that keeps the chunks around its [Position] loaded. The position is in blocks.
+#[derive(Component, Clone, Debug)]
+#[storage(VecStorage)]
+pub struct ChunkViewer {
+    /// Horizontal radius in chunks of the area that is full around the viewer.
+    pub radius: usize,
+    /// Chunks above and below the viewer that are full.
+    pub vertical_radius: usize,
+}
+
+impl ChunkViewer {
+    pub fn new(radius: usize, vertical_radius: usize) -> ChunkViewer {
+        ChunkViewer { radius, vertical_radius }
+    }
+}
+
+/// Area that a viewer keeps loaded, centered on the chunk of the viewer.
+struct Area {
+    center: ChunkPosition,
+    radius: i64,
+    vertical_radius: i64,
+}
+
+impl Area {
+    /// If the chunk is in the area grown by `margin` chunks in every direction.
+    fn contains(&self, position: &ChunkPosition, margin: i64) -> bool {
+        let horizontal = (position.x - self.center.x).abs().max((position.z - self.center.z).abs());
+        horizontal <= self.radius + margin && (position.y - self.center.y).abs() <= self.vertical_radius + margin
+    }
+}
+
+/// Marker of the full chunks that changed since they were generated or loaded.
+#[derive(Component, Default)]
+#[storage(NullStorage)]
+pub struct Unsaved;
+
+/// Where chunks go when they are unloaded and come back from when they are needed again.
+pub trait ChunkStorage: Send + Sync {
+    /// Gets a chunk that was saved before, [None] if it has to be generated.
+    fn load(&self, position: &ChunkPosition) -> io::Result<Option<Chunk>>;
+
+    fn save(&self, position: &ChunkPosition, chunk: &Chunk) -> io::Result<()>;
+
+    /// Gets the saved entities of a chunk, [None] if there are none. Entities are not kept by
+    /// default.
+    fn load_entities(&self, _position: &ChunkPosition) -> io::Result<Option<Vec<u8>>> {
+        Ok(None)
+    }
+
+    /// Replaces the saved entities of a chunk, forgetting them if the data is empty.
+    fn save_entities(&self, _position: &ChunkPosition, _entities: &[u8]) -> io::Result<()> {
+        Ok(())
+    }
+
+    /// Gets the encoded [DeferredPlacements](crate::generation::decoration::DeferredPlacements)
+    /// that were waiting for a chunk, [None] if there are none. They are not kept by default.
+    fn load_placements(&self, _position: &ChunkPosition) -> io::Result<Option<Vec<u8>>> {
+        Ok(None)
+    }
+
+    /// Replaces the placements that are waiting for a chunk, forgetting them if the data is empty.
+    fn save_placements(&self, _position: &ChunkPosition, _placements: &[u8]) -> io::Result<()> {
+        Ok(())
+    }
+
+    /// Makes sure that everything that was saved is in its final place, like before the world is
+    /// closed.
+    fn flush(&self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Data of one kind that was given to a [ChunkStore] and is not in the storage yet, by position.
+/// Loads look here before the storage. Writing a position always writes the latest data that was
+/// given for it, so a write that runs late never replaces the data of a newer one.
+struct PendingWrites<T> {
+    writes: Mutex<Writes<T>>,
+    /// Writes of the storage go one at a time, so the checks of the latest data and the writes of
+    /// different threads do not interleave.
+    writing: Mutex<()>,
+    written: Condvar,
+}
+
+/// The data of each position with the number of the write that gave it.
+struct Writes<T> {
+    count: u64,
+    data: HashMap<ChunkPosition, (u64, Arc<T>)>,
+}
+
+impl<T> Default for PendingWrites<T> {
+    fn default() -> Self {
+        PendingWrites {
+            writes: Mutex::new(Writes {
+                count: 0,
+                data: HashMap::new(),
+            }),
+            writing: Mutex::new(()),
+            written: Condvar::new(),
+        }
+    }
+}
+
+impl<T> PendingWrites<T> {
+    fn queue(&self, position: ChunkPosition, data: T) {
+        let mut writes = self.writes.lock().unwrap();
+        writes.count += 1;
+        let count = writes.count;
+        writes.data.insert(position, (count, Arc::new(data)));
+    }
+
+    fn get(&self, position: &ChunkPosition) -> Option<Arc<T>> {
+        self.writes.lock().unwrap().data.get(position).map(|(_, data)| data.clone())
+    }
+
+    /// Writes the latest data of a position, if it was not written yet.
+    fn write(&self, position: &ChunkPosition, write: impl FnOnce(&T)) {
+        let _writing = self.writing.lock().unwrap();
+        let Some((number, data)) = self.writes.lock().unwrap().data.get(position).cloned() else { return };
+
+        write(&data);
+
+        let mut writes = self.writes.lock().unwrap();
+        if writes.data.get(position).is_some_and(|(latest, _)| *latest == number) {
+            writes.data.remove(position);
+        }
+
+        if writes.data.is_empty() {
+            self.written.notify_all();
+        }
+    }
+
+    /// Waits until every write is in the storage.
+    fn wait(&self) {
+        let mut writes = self.writes.lock().unwrap();
+        while !writes.data.is_empty() {
+            writes = self.written.wait(writes).unwrap();
+        }
+    }
+}
+
+/// Resource with the [ChunkStorage] of the world. Chunks are generated every time they are loaded
+/// and thrown away when they are unloaded if there's none. Errors are logged, and chunks that
+/// cannot be loaded are generated again.
+///
+/// Saves can run in the [Workers]. Until they are done, loads of their chunks get the data that is
+/// being saved, and [ChunkStore::flush] waits for them.
+#[derive(Clone, Default)]
+pub struct ChunkStore {
+    storage: Option<Arc<dyn ChunkStorage>>,
+    chunks: Arc<PendingWrites<Chunk>>,
+    entities: Arc<PendingWrites<Vec<u8>>>,
+    placements: Arc<PendingWrites<Vec<u8>>>,
+}
+
+impl ChunkStore {
+    pub fn new(storage: impl ChunkStorage + 'static) -> ChunkStore {
+        ChunkStore {
+            storage: Some(Arc::new(storage)),
+            ..ChunkStore::default()
+        }
+    }
+
+    pub fn load(&self, position: &ChunkPosition) -> Option<Chunk> {
+        if let Some(chunk) = self.chunks.get(position) {
+            return Some(Chunk::clone(&chunk));
+        }
+
+        match self.storage.as_ref()?.load(position) {
+            Ok(chunk) => chunk,
+            Err(error) => {
+                log::error!("cannot load the chunk {position:?}: {error}");
+                None
+            }
+        }
+    }
+
+    pub fn save(&self, position: &ChunkPosition, chunk: &Chunk) {
+        if self.storage.is_some() {
+            self.chunks.queue(*position, chunk.clone());
+            self.write_chunk(position);
+        }
+    }
+
+    /// Saves a chunk in the workers.
+    pub fn save_later(&self, workers: &Workers, position: ChunkPosition, chunk: Chunk) {
+        if self.storage.is_some() {
+            self.chunks.queue(position, chunk);
+
+            let store = self.clone();
+            workers.spawn(move || store.write_chunk(&position));
+        }
+    }
+
+    fn write_chunk(&self, position: &ChunkPosition) {
+        let Some(storage) = &self.storage else { return };
+
+        self.chunks.write(position, |chunk| {
+            if let Err(error) = storage.save(position, chunk) {
+                log::error!("cannot save the chunk {position:?}: {error}");
+            }
+        });
+    }
+
+    /// Waits for the saves that are running in the workers and makes sure that everything that was
+    /// saved is in its final place.
+    pub fn flush(&self) {
+        if let Some(storage) = &self.storage {
+            self.chunks.wait();
+            self.entities.wait();
+            self.placements.wait();
+
+            if let Err(error) = storage.flush() {
+                log::error!("cannot flush the saved chunks: {error}");
+            }
+        }
+    }
+
+    pub fn load_entities(&self, position: &ChunkPosition) -> Option<Vec<u8>> {
+        if let Some(entities) = self.entities.get(position) {
+            return (!entities.is_empty()).then(|| entities.to_vec());
+        }
+
+        match self.storage.as_ref()?.load_entities(position) {
+            Ok(entities) => entities,
+            Err(error) => {
+                log::error!("cannot load the entities of the chunk {position:?}: {error}");
+                None
+            }
+        }
+    }
+
+    pub fn save_entities(&self, position: &ChunkPosition, entities: &[u8]) {
+        if self.storage.is_some() {
+            self.entities.queue(*position, entities.to_vec());
+            self.write_entities(position);
+        }
+    }
+
+    /// Saves the entities of a chunk in the workers.
+    pub fn save_entities_later(&self, workers: &Workers, position: ChunkPosition, entities: Vec<u8>) {
+        if self.storage.is_some() {
+            self.entities.queue(position, entities);
+
+            let store = self.clone();
+            workers.spawn(move || store.write_entities(&position));
+        }
+    }
+
+    fn write_entities(&self, position: &ChunkPosition) {
+        let Some(storage) = &self.storage else { return };
+
+        self.entities.write(position, |entities| {
+            if let Err(error) = storage.save_entities(position, entities) {
+                log::error!("cannot save the entities of the chunk {position:?}: {error}");
+            }
+        });
+    }
+
+    pub fn load_placements(&self, position: &ChunkPosition) -> Option<Vec<u8>> {
+        if let Some(placements) = self.placements.get(position) {
+            return (!placements.is_empty()).then(|| placements.to_vec());
+        }
+
+        match self.storage.as_ref()?.load_placements(position) {
+            Ok(placements) => placements,
+            Err(error) => {
+                log::error!("cannot load the placements of the chunk {position:?}: {error}");
+                None
+            }
+        }
+    }
+
+    pub fn save_placements(&self, position: &ChunkPosition, placements: &[u8]) {
+        if self.storage.is_some() {
+            self.placements.queue(*position, placements.to_vec());
+            self.write_placements(position);
+        }
+    }
+
+    /// Saves the placements that are waiting for a chunk in the workers.
+    pub fn save_placements_later(&self, workers: &Workers, position: ChunkPosition, placements: Vec<u8>) {
+        if self.storage.is_some() {
+            self.placements.queue(position, placements);
+
+            let store = self.clone();
+            workers.spawn(move || store.write_placements(&position));
+        }
+    }
+
+    fn write_placements(&self, position: &ChunkPosition) {
+        let Some(storage) = &self.storage else { return };
+
+        self.placements.write(position, |placements| {
+            if let Err(error) = storage.save_placements(position, placements) {
+                log::error!("cannot save the placements of the chunk {position:?}: {error}");
+            }
+        });
+    }
+}
+
+/// Requests the chunks that are missing around the viewers and unloads the ones that are too far
+/// from all of them, saving the [Unsaved] ones in the background. Nothing is unloaded while there are
+/// no viewers. The chunks whose entities were loaded are told to the [UnloadedChunks], if there's
+/// one, so their entities go with them.
+pub struct ChunkStreamer {
+    /// Chunks that a loaded chunk can be away from the area of every viewer before it's unloaded.
+    pub hysteresis: usize,
+}
+
+impl Default for ChunkStreamer {
+    fn default() -> Self {
+        ChunkStreamer { hysteresis: 2 }
+    }
+}
+
+impl<'a> System<'a> for ChunkStreamer {
+    type SystemData = (
+        Entities<'a>,
+        Read<'a, ChunkStore>,
+        Read<'a, Workers>,
+        Write<'a, ChunkRequests>,
+        Option<Write<'a, UnloadedChunks>>,
+        ReadStorage<'a, ChunkViewer>,
+        ReadStorage<'a, Position>,
+        ReadStorage<'a, Chunk>,
+        ReadStorage<'a, ChunkStatus>,
+        ReadStorage<'a, Unsaved>,
+        ReadStorage<'a, SavedEntities>,
+    );
+
+    fn run(
+        &mut self,
+        (entities, store, workers, mut requests, mut unloaded, viewers, positions, chunks, statuses, unsaved, saved): Self::SystemData,
+    ) {
+        let areas: Vec<Area> = (&viewers, &positions)
+            .join()
+            .map(|(viewer, position)| {
+                Area {
+                    center: ChunkPosition::containing(position),
+                    radius: (viewer.radius + GENERATION_MARGIN) as i64,
+                    vertical_radius: viewer.vertical_radius as i64,
+                }
+            })
+            .collect();
+
+        if areas.is_empty() {
+            return;
+        }
+
+        let mut loaded = HashMap::new();
+
+        for (entity, position, _) in (&entities, &positions, &statuses).join() {
+            loaded.insert(ChunkPosition::of_entity(position), entity);
+        }
+
+        for Area { center, radius, vertical_radius } in &areas {
+            for x in center.x - radius..=center.x + radius {
+                for y in center.y - vertical_radius..=center.y + vertical_radius {
+                    for z in center.z - radius..=center.z + radius {
+                        let position = ChunkPosition::new(x, y, z);
+                        if !loaded.contains_key(&position) {
+                            requests.request(position);
+                        }
+                    }
+                }
+            }
+        }
+
+        let hysteresis = self.hysteresis as i64;
+
+        for (position, entity) in loaded {
+            if areas.iter().any(|area| area.contains(&position, hysteresis)) {
+                continue;
+            }
+
+            // The others are generated or loaded again the next time they are needed.
+            if unsaved.contains(entity) {
+                if let Some(chunk) = chunks.get(entity) {
+                    store.save_later(&workers, position, chunk.clone());
+                }
+            }
+
+            // Chunks whose entities were not loaded yet still have the saved entities as they were.
+            if let Some(unloaded) = &mut unloaded {
+                let loaded = statuses.get(entity).is_some_and(|status| *status >= ChunkStatus::Terrain);
+                if loaded && !saved.contains(entity) {
+                    unloaded.positions.push(position);
+                }
+            }
+
+            entities.delete(entity).unwrap();
+        }
+    }
+}
+
+/// Saves every [Unsaved] chunk of the world right away, like when the world is closed.
+pub fn save_all(world: &World) {
+    let store = world.read_resource::<ChunkStore>();
+    let positions = world.read_storage::<Position>();
+    let chunks = world.read_storage::<Chunk>();
+    let mut unsaved = world.write_storage::<Unsaved>();
+
+    for (position, chunk, _) in (&positions, &chunks, unsaved.drain()).join() {
+        store.save(&ChunkPosition::of_entity(position), chunk);
+    }
+}
+
+/// Plugin that streams the chunks around the [ChunkViewer]s. It needs a plugin that generates the
+/// chunks, like the [TerrainPlugin](crate::generation::terrain::TerrainPlugin).
+#[derive(Default)]
+pub struct StreamingPlugin {
+    pub streamer: ChunkStreamer,
+}
+
+impl Plugin for StreamingPlugin {
+    fn setup(self, world: &mut WorldBuilder) {
+        world.with_component::<ChunkViewer>();
+        world.with_component::<Unsaved>();
+        world.resource_mut::<ChunkStore>();
+        world.with_system(self.streamer, "chunk streamer", &[]);
+    }
+}
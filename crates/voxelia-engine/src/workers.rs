@@ -0,0 +1,144 @@
+//! Background threads for the work that must not block the simulation, like generating chunks.
+
+use std::sync::mpsc::{self, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+use crate::{Plugin, WorldBuilder};
+
+pub type Job = Box<dyn FnOnce() + Send>;
+
+/// Something that runs jobs outside of the thread that spawns them.
+pub trait Executor: Send + Sync {
+    fn spawn(&self, job: Job);
+}
+
+/// A fixed number of threads that take jobs from a shared queue. The threads stop when the pool
+/// is dropped.
+pub struct ThreadPool {
+    sender: Sender<Job>,
+}
+
+impl ThreadPool {
+    pub fn new(threads: usize) -> ThreadPool {
+        let (sender, receiver) = mpsc::channel::<Job>();
+        let receiver = Arc::new(Mutex::new(receiver));
+
+        for index in 0..threads {
+            let receiver = receiver.clone();
+
+            thread::Builder::new()
+                .name(format!("worker {index}"))
+                .spawn(move || loop {
+                    let job = receiver.lock().unwrap().recv();
+                    match job {
+                        Ok(job) => job(),
+                        Err(_) => break,
+                    }
+                })
+                .expect("cannot spawn a worker thread");
+        }
+
+        ThreadPool { sender }
+    }
+}
+
+impl Executor for ThreadPool {
+    fn spawn(&self, job: Job) {
+        // It only fails if every thread is gone and then there's nobody to run it anyway.
+        let _ = self.sender.send(job);
+    }
+}
+
+/// Resource with the [Executor] shared by every system that has background work. It uses a
+/// [ThreadPool] with a thread for each core but one by default.
+#[derive(Clone)]
+pub struct Workers {
+    executor: Arc<dyn Executor>,
+}
+
+impl Workers {
+    pub fn new(executor: impl Executor + 'static) -> Workers {
+        Workers {
+            executor: Arc::new(executor),
+        }
+    }
+
+    pub fn spawn(&self, job: impl FnOnce() + Send + 'static) {
+        self.executor.spawn(Box::new(job));
+    }
+}
+
+impl Default for Workers {
+    fn default() -> Self {
+        let threads = thread::available_parallelism().map_or(1, |count| count.get().saturating_sub(1).max(1));
+        Workers::new(ThreadPool::new(threads))
+    }
+}
+
+/// Plugin that replaces the default [Workers] with another [Executor].
+pub struct WorkersPlugin<E>(pub E);
+
+impl<E: Executor + 'static> Plugin for WorkersPlugin<E> {
+    fn setup(self, world: &mut WorldBuilder) {
+        world.with_resource(Workers::new(self.0));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Barrier;
+    use std::time::Duration;
+
+    use super::*;
+    use crate::Builder;
+
+    #[test]
+    fn pools_run_jobs_in_their_threads() {
+        let pool = Workers::new(ThreadPool::new(2));
+        let (sender, receiver) = mpsc::channel();
+
+        // Both jobs wait for each other, so they only end if they run at the same time.
+        let barrier = Arc::new(Barrier::new(2));
+        for _ in 0..2 {
+            let (sender, barrier) = (sender.clone(), barrier.clone());
+            pool.spawn(move || {
+                barrier.wait();
+                let _ = sender.send(thread::current().name().map(str::to_owned));
+            });
+        }
+
+        let mut names: Vec<_> = (0..2).map(|_| receiver.recv_timeout(Duration::from_secs(10)).unwrap()).collect();
+        names.sort();
+        assert_eq!(names, [Some("worker 0".to_owned()), Some("worker 1".to_owned())]);
+    }
+
+    /// Runs the jobs right away and counts them.
+    #[derive(Clone, Default)]
+    struct Inline(Arc<AtomicUsize>);
+
+    impl Executor for Inline {
+        fn spawn(&self, job: Job) {
+            self.0.fetch_add(1, Ordering::Relaxed);
+            job();
+        }
+    }
+
+    #[test]
+    fn plugins_replace_the_executor() {
+        let executor = Inline::default();
+        let engine = Builder::new().with(WorkersPlugin(executor.clone())).build();
+        let done = Arc::new(AtomicUsize::new(0));
+
+        for _ in 0..3 {
+            let done = done.clone();
+            engine.world.fetch::<Workers>().spawn(move || {
+                done.fetch_add(1, Ordering::Relaxed);
+            });
+        }
+
+        assert_eq!(executor.0.load(Ordering::Relaxed), 3);
+        assert_eq!(done.load(Ordering::Relaxed), 3);
+    }
+}
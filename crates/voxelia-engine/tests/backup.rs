@@ -0,0 +1,152 @@
+//! Snapshots of worlds: their names, which ones are pruned and how they are restored.
+
+mod common;
+
+use std::{
+    fs,
+    path::Path,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use common::TestDirectory;
+use voxelia_engine::storage::backup::{
+    backups_directory, create_snapshot, prune, request_restore, restore_requested, snapshots, BackupPolicy,
+};
+
+fn now() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs()
+}
+
+/// Replaces a file like the storage does, without changing the snapshots that link to it.
+fn replace(path: impl AsRef<Path>, contents: &str) {
+    let _ = fs::remove_file(&path);
+    fs::write(path, contents).unwrap();
+}
+
+/// A world with a region file and the journal of its storage.
+fn world(directory: &TestDirectory, region: &str, journal: &str) {
+    fs::create_dir_all(directory.join("chunks")).unwrap();
+    replace(directory.join("info.ron"), region);
+    replace(directory.join("chunks/r.0.0.0.vxr"), region);
+    replace(directory.join("chunks/journal"), journal);
+    replace(directory.join("chunks/r.1.0.0.vxr.tmp"), "unfinished");
+}
+
+/// Empty snapshots with the given names, like the ones taken at those times.
+fn fake_snapshots(world: &Path, names: &[String]) {
+    for name in names {
+        fs::create_dir_all(backups_directory(world).join(name)).unwrap();
+    }
+}
+
+fn names(world: &Path) -> Vec<String> {
+    snapshots(world).unwrap().into_iter().map(|snapshot| snapshot.name).collect()
+}
+
+#[test]
+fn snapshots_taken_in_the_same_second_get_numbers() {
+    let directory = TestDirectory::new("backup-names");
+    world(&directory, "region", "");
+
+    let taken: Vec<_> = (0..3).map(|_| create_snapshot(&directory.0).unwrap()).collect();
+
+    for (index, snapshot) in taken.iter().enumerate() {
+        let expected = match index.checked_sub(1).map(|previous| &taken[previous]) {
+            Some(previous) if previous.time == snapshot.time => previous.number + 1,
+            _ => 0,
+        };
+
+        assert_eq!(snapshot.number, expected);
+        match snapshot.number {
+            0 => assert_eq!(snapshot.name, format!("snapshot-{}", snapshot.time)),
+            number => assert_eq!(snapshot.name, format!("snapshot-{}-{number}", snapshot.time)),
+        }
+    }
+
+    let listed = names(&directory.0);
+    assert_eq!(listed, taken.iter().map(|snapshot| snapshot.name.clone()).collect::<Vec<_>>());
+
+    // Journals are empty once the world is saved and temporary files are not done yet.
+    let path = &taken[0].path;
+    assert_eq!(fs::read_to_string(path.join("chunks/r.0.0.0.vxr")).unwrap(), "region");
+    assert!(!path.join("chunks/journal").exists());
+    assert!(!path.join("chunks/r.1.0.0.vxr.tmp").exists());
+    assert!(!path.join("backups").exists());
+}
+
+#[test]
+fn pruning_keeps_the_newest_snapshots() {
+    let directory = TestDirectory::new("backup-keep");
+    let listed = ["snapshot-100", "snapshot-200", "snapshot-200-1", "snapshot-300"].map(str::to_owned);
+    fake_snapshots(&directory.0, &listed);
+    fake_snapshots(&directory.0, &["notes".to_owned()]);
+
+    assert_eq!(names(&directory.0), listed);
+
+    prune(&directory.0, &BackupPolicy { keep: 2, ..BackupPolicy::default() }).unwrap();
+    assert_eq!(names(&directory.0), ["snapshot-200-1", "snapshot-300"]);
+    assert!(backups_directory(&directory.0).join("notes").exists());
+
+    // The newest snapshot is kept even if the policy keeps none.
+    prune(&directory.0, &BackupPolicy { keep: 0, ..BackupPolicy::default() }).unwrap();
+    assert_eq!(names(&directory.0), ["snapshot-300"]);
+}
+
+#[test]
+fn pruning_removes_old_snapshots() {
+    let directory = TestDirectory::new("backup-age");
+    let now = now();
+    let times = [now - 7200, now - 5000, now - 60, now - 30];
+    fake_snapshots(&directory.0, &times.map(|time| format!("snapshot-{time}")));
+
+    let policy = BackupPolicy { max_age: Some(Duration::from_secs(3600)), ..BackupPolicy::default() };
+    prune(&directory.0, &policy).unwrap();
+
+    assert_eq!(names(&directory.0), [now - 60, now - 30].map(|time| format!("snapshot-{time}")));
+
+    // The newest snapshot is kept however old it is.
+    let directory = TestDirectory::new("backup-age-newest");
+    fake_snapshots(&directory.0, &["snapshot-100".to_owned(), "snapshot-200".to_owned()]);
+    prune(&directory.0, &policy).unwrap();
+
+    assert_eq!(names(&directory.0), ["snapshot-200"]);
+}
+
+#[test]
+fn requested_snapshots_are_restored() {
+    let directory = TestDirectory::new("backup-restore");
+    world(&directory, "before", "");
+    let snapshot = create_snapshot(&directory.0).unwrap();
+
+    world(&directory, "after", "writes that were not replayed");
+    fs::write(directory.join("new.ron"), "after").unwrap();
+
+    assert!(request_restore(&directory.0, "snapshot-1").is_err());
+    assert_eq!(restore_requested(&directory.0).unwrap(), None);
+
+    request_restore(&directory.0, &snapshot.name).unwrap();
+    assert_eq!(restore_requested(&directory.0).unwrap(), Some(snapshot.name.clone()));
+
+    assert_eq!(fs::read_to_string(directory.join("chunks/r.0.0.0.vxr")).unwrap(), "before");
+    assert_eq!(fs::read_to_string(directory.join("info.ron")).unwrap(), "before");
+    assert!(!directory.join("new.ron").exists());
+    assert!(!directory.join("chunks/journal").exists());
+
+    // The world that was replaced is kept with its journal, that has writes of its own.
+    let replaced = snapshots(&directory.0).unwrap().pop().unwrap();
+    assert_ne!(replaced.name, snapshot.name);
+    assert_eq!(fs::read_to_string(replaced.path.join("chunks/r.0.0.0.vxr")).unwrap(), "after");
+    assert_eq!(fs::read_to_string(replaced.path.join("new.ron")).unwrap(), "after");
+    assert_eq!(
+        fs::read_to_string(replaced.path.join("chunks/journal")).unwrap(),
+        "writes that were not replayed"
+    );
+
+    // Restoring it brings the journal back, and the request is only done once.
+    assert_eq!(restore_requested(&directory.0).unwrap(), None);
+    request_restore(&directory.0, &replaced.name).unwrap();
+    restore_requested(&directory.0).unwrap();
+
+    assert_eq!(fs::read_to_string(directory.join("chunks/journal")).unwrap(), "writes that were not replayed");
+    assert_eq!(fs::read_to_string(directory.join("new.ron")).unwrap(), "after");
+}
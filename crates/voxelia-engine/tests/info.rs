@@ -0,0 +1,87 @@
+//! The [WorldInfo] file of a world and how [Builder::open] uses it.
+
+mod common;
+
+use std::fs;
+
+use common::TestDirectory;
+use voxelia_engine::{
+    info::{GameRule, GameRules, WorldDirectory, WorldInfo, INFO_FILE},
+    Builder,
+};
+
+#[test]
+fn saved_info_comes_back() {
+    let directory = TestDirectory::new("info-round-trip");
+
+    let mut info = WorldInfo::new(1234);
+    info.generator.structures = false;
+    info.generator.caves = None;
+    info.generator.terrain.sea_level = 20;
+    info.spawn = Some([5, 40, -7]);
+    info.time = 98765;
+    info.rules.set("daylight_cycle", GameRule::Bool(false));
+    info.rules.set("view_distance", GameRule::Int(12));
+    info.next_entity_id = 77;
+    info.next_placed = 3;
+    info.save(&directory.0).unwrap();
+
+    assert!(info.last_played > 0);
+
+    let loaded = WorldInfo::load(&directory.0).unwrap().unwrap();
+    assert_eq!(loaded.seed, 1234);
+    assert!(!loaded.generator.structures);
+    assert!(loaded.generator.caves.is_none());
+    assert_eq!(loaded.generator.terrain.sea_level, 20);
+    assert_eq!(loaded.spawn, Some([5, 40, -7]));
+    assert_eq!(loaded.time, 98765);
+    assert_eq!(loaded.rules, info.rules);
+    assert_eq!(loaded.last_played, info.last_played);
+    assert_eq!(loaded.next_entity_id, 77);
+    assert_eq!(loaded.next_placed, 3);
+}
+
+#[test]
+fn missing_fields_get_their_defaults() {
+    let directory = TestDirectory::new("info-defaults");
+    fs::write(directory.join(INFO_FILE), "(seed: 42, generator: (decorate: false))").unwrap();
+
+    let info = WorldInfo::load(&directory.0).unwrap().unwrap();
+    let defaults = WorldInfo::new(42);
+
+    assert_eq!(info.seed, 42);
+    assert!(!info.generator.decorate);
+    assert_eq!(info.generator.structures, defaults.generator.structures);
+    assert_eq!(info.generator.terrain.sea_level, defaults.generator.terrain.sea_level);
+    assert_eq!(info.spawn, None);
+    assert_eq!(info.time, 0);
+    assert_eq!(info.rules, GameRules::default());
+    assert_eq!(info.rules.bool("daylight_cycle"), Some(true));
+    assert_eq!((info.last_played, info.next_entity_id, info.next_placed), (0, 0, 0));
+}
+
+#[test]
+fn missing_and_broken_info() {
+    let directory = TestDirectory::new("info-missing");
+    assert!(WorldInfo::load(&directory.0).unwrap().is_none());
+
+    fs::write(directory.join(INFO_FILE), "(time: 5)").unwrap();
+    assert_eq!(WorldInfo::load(&directory.0).unwrap_err().kind(), std::io::ErrorKind::InvalidData);
+}
+
+#[test]
+fn opening_a_world_prefers_the_saved_info() {
+    let directory = TestDirectory::new("info-open");
+
+    let engine = Builder::open(&directory.0, WorldInfo::new(1)).unwrap().build();
+    assert_eq!(engine.world.fetch::<WorldInfo>().seed, 1);
+    assert_eq!(engine.world.fetch::<WorldDirectory>().0, directory.0);
+
+    let mut saved = WorldInfo::new(2);
+    saved.time = 500;
+    saved.save(&directory.0).unwrap();
+
+    let engine = Builder::open(&directory.0, WorldInfo::new(3)).unwrap().build();
+    let info = engine.world.fetch::<WorldInfo>();
+    assert_eq!((info.seed, info.time), (2, 500));
+}
@@ -0,0 +1,194 @@
+//! Opens the worlds of `tests/fixtures/worlds`, that were saved by older versions of the engine.
+//!
+//! - `v0` has no format, its chunks have the ids of a registry with stone, dirt and grass. The
+//!   chunk at 0, 0, 0 has a layer of each, the one at -1, 0, 9 two layers of stone, one of dirt and
+//!   one of grass.
+//! - `v1` has the format with dirt, stone, flower and grass, and the chunk at 0, 0, 0 has a layer
+//!   of stone, dirt, grass and flower.
+//! - `too-many-blocks` has a format with 256 blocks, one more than the ids that chunks can have.
+
+mod common;
+
+use common::TestDirectory;
+use voxelia_engine::{
+    block::{BlockDefinition, BlockId, BlockPosition, BlockRegistry, Transparency, AIR},
+    chunk::{Chunk, ChunkPosition, CHUNK_LENGTH, CHUNK_WIDTH},
+    storage::{
+        migration::{BlockMapping, Migration, Migrations, WorldFormat, WORLD_VERSION},
+        open_world,
+    },
+    streaming::ChunkStorage,
+};
+
+fn registry_of(names: &[&str]) -> BlockRegistry {
+    let mut registry = BlockRegistry::default();
+
+    for name in names {
+        registry.register(BlockDefinition::new(name, Transparency::Opaque, 0));
+    }
+
+    registry
+}
+
+/// Blocks of the column at 3, 5 from the bottom up to the first air.
+fn column(chunk: &Chunk) -> Vec<BlockId> {
+    (0..)
+        .map(|y| chunk.get(&BlockPosition::new(3, y, 5)))
+        .take_while(|block| *block != AIR)
+        .collect()
+}
+
+/// Checks that every column of a chunk has the same blocks.
+fn assert_layers(chunk: &Chunk, layers: &[BlockId]) {
+    for x in 0..CHUNK_WIDTH as i64 {
+        for z in 0..CHUNK_LENGTH as i64 {
+            for (y, block) in layers.iter().enumerate() {
+                assert_eq!(chunk.get(&BlockPosition::new(x, y as i64, z)), *block, "at {x}, {y}, {z}");
+            }
+        }
+    }
+}
+
+fn load(storage: &dyn ChunkStorage, x: i64, y: i64, z: i64) -> Chunk {
+    storage.load(&ChunkPosition::new(x, y, z)).unwrap().expect("the chunk was saved")
+}
+
+#[test]
+fn opens_a_world_without_format() {
+    let directory = TestDirectory::with_world("migration-v0", "v0");
+    let registry = registry_of(&["stone", "dirt", "grass"]);
+
+    let storage = open_world(&directory.0, &registry, &Migrations::default()).unwrap();
+    assert_layers(&load(&storage, 0, 0, 0), &[1, 2, 3]);
+    assert_layers(&load(&storage, -1, 0, 9), &[1, 1, 2, 3]);
+
+    let format = WorldFormat::load(directory.join("format.ron")).unwrap().unwrap();
+    assert_eq!(format.version, WORLD_VERSION);
+    assert_eq!(format.blocks, ["stone", "dirt", "grass"]);
+}
+
+#[test]
+fn converts_the_chunks_of_older_versions() {
+    let directory = TestDirectory::with_world("migration-convert", "v0");
+    let registry = registry_of(&["stone", "dirt", "grass"]);
+
+    // Turns the dirt into stone in every chunk.
+    let mut migrations = Migrations::default();
+    migrations.add(Migration::new(1).convert_chunks(|chunk, _| {
+        for x in 0..CHUNK_WIDTH as i64 {
+            for y in 0..4 {
+                for z in 0..CHUNK_LENGTH as i64 {
+                    let position = BlockPosition::new(x, y, z);
+                    if chunk.get(&position) == 2 {
+                        chunk.set(&position, 1);
+                    }
+                }
+            }
+        }
+    }));
+
+    let storage = open_world(&directory.0, &registry, &migrations).unwrap();
+    assert_layers(&load(&storage, 0, 0, 0), &[1, 1, 3]);
+    assert_layers(&load(&storage, -1, 0, 9), &[1, 1, 1, 3]);
+    drop(storage);
+
+    // The world is in the last version now, so it's not converted again.
+    let storage = open_world(&directory.0, &registry, &migrations).unwrap();
+    assert_eq!(column(&load(&storage, 0, 0, 0)), [1, 1, 3]);
+}
+
+#[test]
+fn merges_renamed_blocks() {
+    let directory = TestDirectory::with_world("migration-merge", "v0");
+    let registry = registry_of(&["stone", "dirt", "grass"]);
+
+    let mut migrations = Migrations::default();
+    migrations.add(Migration::new(1).rename_block("dirt", "grass"));
+
+    let storage = open_world(&directory.0, &registry, &migrations).unwrap();
+    assert_layers(&load(&storage, 0, 0, 0), &[1, 3, 3]);
+    assert_layers(&load(&storage, -1, 0, 9), &[1, 1, 3, 3]);
+
+    // The dirt that is placed now gets a new id in the world.
+    let mut chunk = load(&storage, 0, 0, 0);
+    chunk.set(&BlockPosition::new(3, 3, 5), 2);
+    storage.save(&ChunkPosition::new(0, 0, 0), &chunk).unwrap();
+    drop(storage);
+
+    let format = WorldFormat::load(directory.join("format.ron")).unwrap().unwrap();
+    assert_eq!(format.blocks, ["stone", "grass", "grass", "dirt"]);
+
+    let storage = open_world(&directory.0, &registry, &migrations).unwrap();
+    assert_eq!(column(&load(&storage, 0, 0, 0)), [1, 3, 3, 2]);
+}
+
+#[test]
+fn maps_the_format_to_the_registry() {
+    let directory = TestDirectory::with_world("migration-v1", "v1");
+
+    // The flower is not registered anymore and the order of the blocks changed.
+    let registry = registry_of(&["grass", "stone", "dirt", "sand"]);
+
+    let storage = open_world(&directory.0, &registry, &Migrations::default()).unwrap();
+    let chunk = load(&storage, 0, 0, 0);
+    assert_layers(&chunk, &[2, 3, 1, AIR]);
+
+    // Saved chunks keep the ids of the world, and the new blocks are added to its end.
+    storage.save(&ChunkPosition::new(0, 0, 0), &chunk).unwrap();
+    storage.flush().unwrap();
+    drop(storage);
+
+    let format = WorldFormat::load(directory.join("format.ron")).unwrap().unwrap();
+    assert_eq!(format.blocks, ["dirt", "stone", "flower", "grass", "sand"]);
+
+    let registry = registry_of(&["flower", "grass", "stone", "dirt", "sand"]);
+    let storage = open_world(&directory.0, &registry, &Migrations::default()).unwrap();
+    assert_layers(&load(&storage, 0, 0, 0), &[3, 4, 2, AIR]);
+}
+
+#[test]
+fn rejects_newer_worlds() {
+    let directory = TestDirectory::with_world("migration-newer", "v1");
+    let format = WorldFormat {
+        version: WORLD_VERSION + 1,
+        blocks: Vec::new(),
+    };
+    format.save(directory.join("format.ron")).unwrap();
+
+    assert!(open_world(&directory.0, &registry_of(&["stone"]), &Migrations::default()).is_err());
+}
+
+#[test]
+fn rejects_formats_with_too_many_blocks() {
+    let directory = TestDirectory::with_world("migration-too-many", "too-many-blocks");
+
+    let error = open_world(&directory.0, &registry_of(&["block3"]), &Migrations::default()).err().unwrap();
+    assert_eq!(error.kind(), std::io::ErrorKind::InvalidData);
+
+    let error = WorldFormat::load(directory.join("format.ron")).err().unwrap();
+    assert_eq!(error.kind(), std::io::ErrorKind::InvalidData);
+}
+
+#[test]
+fn rejects_too_many_blocks_after_renames() {
+    let mut format = WorldFormat {
+        version: 0,
+        blocks: (0..256).map(|index| format!("block{index}")).collect(),
+    };
+
+    Migrations::default().rename_blocks(&mut format);
+    let error = BlockMapping::new(&mut format, &registry_of(&["block3"])).err().unwrap();
+    assert_eq!(error.kind(), std::io::ErrorKind::InvalidData);
+}
+
+#[test]
+fn opens_worlds_with_every_id_used() {
+    let directory = TestDirectory::new("migration-every-id");
+    let format = WorldFormat {
+        version: WORLD_VERSION,
+        blocks: (1..=255).map(|index| format!("block{index}")).collect(),
+    };
+    format.save(directory.join("format.ron")).unwrap();
+
+    open_world(&directory.0, &registry_of(&["block255"]), &Migrations::default()).unwrap();
+}
@@ -0,0 +1,148 @@
+//! Placements that wait for chunks in the [DeferredPlacements] and in the storage of a world.
+
+mod common;
+
+use common::TestDirectory;
+use voxelia_engine::{
+    block::{BlockId, BlockPosition, BlockRegistry},
+    chunk::ChunkPosition,
+    generation::decoration::{
+        decode_placements, encode_placements, DeferredPlacements, Placement, PlacementSource, PlacementSources,
+        Replace,
+    },
+    storage::{migration::Migrations, open_world},
+    streaming::ChunkStore,
+    workers::{ThreadPool, Workers},
+};
+
+const TARGET: ChunkPosition = ChunkPosition::new(4, 0, -3);
+
+fn placement(block: BlockId, replace: Replace) -> Placement {
+    Placement { position: BlockPosition::new(1, 2, 3), block, replace }
+}
+
+fn decoration(x: i64) -> PlacementSource {
+    PlacementSource::Decoration(ChunkPosition::new(x, 0, 0))
+}
+
+fn blocks(placements: &[Placement]) -> Vec<BlockId> {
+    placements.iter().map(|placement| placement.block).collect()
+}
+
+#[test]
+fn placements_come_in_the_order_of_their_sources() {
+    let deferred = DeferredPlacements::default();
+    let placed = deferred.next_placed();
+
+    deferred.push(TARGET, placed, vec![placement(9, Replace::Any)]);
+    deferred.push(TARGET, decoration(5), vec![placement(5, Replace::Air)]);
+    deferred.push(TARGET, decoration(-2), vec![placement(2, Replace::Air)]);
+
+    let (placements, stored) = deferred.take(&TARGET);
+    assert_eq!(blocks(&placements), [2, 5, 9]);
+    assert!(!stored);
+    assert!(deferred.is_empty());
+}
+
+#[test]
+fn sources_replace_the_placements_they_left_before() {
+    let deferred = DeferredPlacements::default();
+
+    deferred.push(TARGET, decoration(1), vec![placement(1, Replace::Air)]);
+    deferred.push(TARGET, decoration(1), vec![placement(3, Replace::Air)]);
+
+    assert_eq!(blocks(&deferred.take(&TARGET).0), [3]);
+}
+
+#[test]
+fn placed_sources_keep_growing() {
+    let deferred = DeferredPlacements::starting_at(40);
+
+    assert_eq!(deferred.next_placed(), PlacementSource::Placed(40));
+    assert_eq!(deferred.next_placed(), PlacementSource::Placed(41));
+    assert_eq!(deferred.next(), 42);
+
+    // Saved sources that are past the counter move it after them.
+    let mut sources = PlacementSources::new();
+    sources.insert(PlacementSource::Placed(99), vec![placement(1, Replace::Any)]);
+    deferred.load(TARGET, sources);
+
+    assert_eq!(deferred.next_placed(), PlacementSource::Placed(100));
+}
+
+#[test]
+fn encoded_placements_come_back() {
+    let mut sources = PlacementSources::new();
+    sources.insert(
+        PlacementSource::Decoration(ChunkPosition::new(-7, 3, i64::MAX)),
+        vec![placement(4, Replace::AirOr(6)), placement(8, Replace::Only(2))],
+    );
+    sources.insert(PlacementSource::Placed(u64::MAX), vec![placement(1, Replace::Any), placement(2, Replace::Air)]);
+
+    let bytes = encode_placements(&sources);
+    let decoded = decode_placements(&bytes).unwrap();
+
+    assert_eq!(decoded.keys().collect::<Vec<_>>(), sources.keys().collect::<Vec<_>>());
+    for (source, placements) in &sources {
+        let decoded = &decoded[source];
+        assert_eq!(blocks(decoded), blocks(placements));
+        assert!(decoded.iter().zip(placements).all(|(a, b)| a.replace == b.replace && a.position == b.position));
+    }
+
+    for length in 0..bytes.len() {
+        assert!(decode_placements(&bytes[..length]).is_none_or(|decoded| decoded.len() < sources.len()));
+    }
+}
+
+#[test]
+fn evicted_placements_come_back_with_their_chunk() {
+    let directory = TestDirectory::new("placements-evict");
+    let registry = BlockRegistry::default();
+    let workers = Workers::new(ThreadPool::new(2));
+
+    let store = ChunkStore::new(open_world(&directory.0, &registry, &Migrations::default()).unwrap());
+    let deferred = DeferredPlacements::default();
+    let loaded = ChunkPosition::new(0, 0, 0);
+
+    deferred.push(TARGET, decoration(3), vec![placement(3, Replace::Air)]);
+    deferred.push(loaded, decoration(3), vec![placement(7, Replace::Air)]);
+    deferred.evict(&store, &workers, |position| *position == loaded);
+
+    assert_eq!(deferred.positions(), [loaded]);
+
+    // Later placements are merged with the ones that were evicted before.
+    deferred.push(TARGET, decoration(1), vec![placement(1, Replace::Air)]);
+    deferred.evict(&store, &workers, |position| *position == loaded);
+    store.flush();
+    drop(store);
+
+    let store = ChunkStore::new(open_world(&directory.0, &registry, &Migrations::default()).unwrap());
+    let sources = decode_placements(&store.load_placements(&TARGET).unwrap()).unwrap();
+
+    let deferred = DeferredPlacements::default();
+    deferred.load(TARGET, sources);
+
+    let (placements, stored) = deferred.take(&TARGET);
+    assert_eq!(blocks(&placements), [1, 3]);
+    assert!(stored);
+
+    // Chunks forget their saved placements once they get them.
+    store.save_placements(&TARGET, &[]);
+    assert_eq!(store.load_placements(&TARGET), None);
+}
+
+#[test]
+fn saved_placements_stay_in_memory() {
+    let directory = TestDirectory::new("placements-save");
+    let store = ChunkStore::new(open_world(&directory.0, &BlockRegistry::default(), &Migrations::default()).unwrap());
+    let deferred = DeferredPlacements::default();
+
+    deferred.push(TARGET, decoration(0), vec![placement(5, Replace::Air)]);
+    deferred.save(&store);
+
+    assert!(store.load_placements(&TARGET).is_some());
+
+    let (placements, stored) = deferred.take(&TARGET);
+    assert_eq!(blocks(&placements), [5]);
+    assert!(stored);
+}
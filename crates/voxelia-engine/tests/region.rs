@@ -0,0 +1,200 @@
+//! Crashes of a [RegionStorage] at each step of its writes. A storage that is dropped without being
+//! flushed is like a program that stopped, as the journal is on the disk after each write.
+
+mod common;
+
+use std::{fs, sync::Arc, thread};
+
+use common::{copy_directory, TestDirectory};
+use voxelia_engine::{chunk::ChunkPosition, storage::region::RegionStorage};
+
+/// Two chunks of the same region and one of another region.
+const CHUNKS: [ChunkPosition; 3] = [
+    ChunkPosition::new(0, 0, 0),
+    ChunkPosition::new(1, 0, 0),
+    ChunkPosition::new(-9, 0, 0),
+];
+
+/// Data of a version of a chunk, that has a different length for each version.
+fn data(version: u8) -> Vec<u8> {
+    vec![version; 100 + version as usize]
+}
+
+fn open(directory: &TestDirectory) -> RegionStorage {
+    RegionStorage::open(&directory.0).unwrap()
+}
+
+fn assert_versions(storage: &RegionStorage, versions: [u8; 3]) {
+    for (position, version) in CHUNKS.iter().zip(versions) {
+        assert_eq!(storage.read(position).unwrap(), Some(data(version)), "version of {position:?}");
+    }
+}
+
+/// Name of the file of a region.
+fn region_file([x, y, z]: [i64; 3]) -> String {
+    format!("r.{x}.{y}.{z}.region")
+}
+
+fn journal_length(directory: &TestDirectory) -> u64 {
+    fs::metadata(directory.join("journal")).unwrap().len()
+}
+
+/// A directory with the first version of the chunks in the region files and the second one in the
+/// journal, like it's left when the program stops before a checkpoint.
+fn crashed_before_checkpoint(name: &str) -> TestDirectory {
+    let directory = TestDirectory::new(name);
+    let storage = open(&directory);
+
+    for position in &CHUNKS {
+        storage.write(position, &data(1)).unwrap();
+    }
+    storage.flush().unwrap();
+
+    for position in &CHUNKS {
+        storage.write(position, &data(2)).unwrap();
+    }
+
+    directory
+}
+
+/// The region files of a directory after the checkpoint of the journal of a crashed one.
+fn checkpointed(crashed: &TestDirectory, name: &str) -> TestDirectory {
+    let directory = TestDirectory::new(name);
+    copy_directory(&crashed.0, &directory.0).unwrap();
+    open(&directory).flush().unwrap();
+    directory
+}
+
+#[test]
+fn replays_the_journal() {
+    let directory = crashed_before_checkpoint("region-replay");
+
+    let storage = open(&directory);
+    assert_versions(&storage, [2, 2, 2]);
+    assert_eq!(journal_length(&directory), 0);
+}
+
+#[test]
+fn discards_a_write_that_was_being_appended() {
+    let base = TestDirectory::new("region-append");
+    let storage = open(&base);
+
+    for position in &CHUNKS {
+        storage.write(position, &data(1)).unwrap();
+    }
+    storage.flush().unwrap();
+
+    storage.write(&CHUNKS[0], &data(2)).unwrap();
+    let before = journal_length(&base);
+    storage.write(&CHUNKS[1], &data(2)).unwrap();
+    let after = journal_length(&base);
+    drop(storage);
+
+    // Stops the write of the second chunk after each of its bytes.
+    for length in before..after {
+        let directory = TestDirectory::new(&format!("region-append-{length}"));
+        copy_directory(&base.0, &directory.0).unwrap();
+        fs::OpenOptions::new().write(true).open(directory.join("journal")).unwrap().set_len(length).unwrap();
+
+        let storage = open(&directory);
+        assert_versions(&storage, [2, 1, 1]);
+        assert_eq!(journal_length(&directory), 0);
+    }
+
+    // The end of the record was written with the wrong bytes.
+    let directory = TestDirectory::new("region-append-corrupt");
+    copy_directory(&base.0, &directory.0).unwrap();
+    let mut journal = fs::read(directory.join("journal")).unwrap();
+    *journal.last_mut().unwrap() ^= 0xFF;
+    fs::write(directory.join("journal"), journal).unwrap();
+
+    assert_versions(&open(&directory), [2, 1, 1]);
+}
+
+#[test]
+fn recovers_from_a_crash_while_copying_a_region() {
+    let directory = crashed_before_checkpoint("region-copy");
+    let region = region_file([0, 0, 0]);
+
+    // Half of the copy of the region was written.
+    let original = fs::read(directory.join(&region)).unwrap();
+    fs::write(directory.join(&region).with_extension("region.tmp"), &original[..original.len() / 2]).unwrap();
+
+    let storage = open(&directory);
+    assert_versions(&storage, [2, 2, 2]);
+
+    let temporary = fs::read_dir(&directory.0).unwrap().filter(|entry| {
+        entry.as_ref().unwrap().path().extension().is_some_and(|extension| extension == "tmp")
+    });
+    assert_eq!(temporary.count(), 0);
+}
+
+#[test]
+fn recovers_from_a_crash_before_the_rename() {
+    let directory = crashed_before_checkpoint("region-rename");
+    let done = checkpointed(&directory, "region-rename-done");
+
+    // The first region was replaced and the copy of the second one is complete but not renamed.
+    let [first, second] = [[0, 0, 0], [-2, 0, 0]].map(region_file);
+    fs::copy(done.join(&first), directory.join(&first)).unwrap();
+    fs::copy(done.join(&second), directory.join(&second).with_extension("region.tmp")).unwrap();
+
+    assert_versions(&open(&directory), [2, 2, 2]);
+}
+
+#[test]
+fn recovers_from_a_crash_before_the_journal_is_cleared() {
+    let directory = crashed_before_checkpoint("region-clear");
+    let done = checkpointed(&directory, "region-clear-done");
+
+    for name in [[0, 0, 0], [-2, 0, 0]].map(region_file) {
+        fs::copy(done.join(&name), directory.join(&name)).unwrap();
+    }
+
+    // Writing the journal again over the regions that have it changes nothing.
+    assert!(journal_length(&directory) > 0);
+    let storage = open(&directory);
+    assert_versions(&storage, [2, 2, 2]);
+    assert_eq!(journal_length(&directory), 0);
+}
+
+#[test]
+fn reads_never_go_back_while_checkpointing() {
+    let directory = TestDirectory::new("region-readers");
+    let storage = Arc::new(open(&directory));
+
+    for position in &CHUNKS {
+        storage.write(position, &data(1)).unwrap();
+    }
+    storage.flush().unwrap();
+
+    let readers: Vec<_> = (0..4)
+        .map(|_| {
+            let storage = storage.clone();
+
+            thread::spawn(move || {
+                let mut last = 1;
+
+                for _ in 0..2000 {
+                    // The second chunk is never pending, so reading it opens the region again.
+                    storage.read(&CHUNKS[1]).unwrap();
+
+                    let version = storage.read(&CHUNKS[0]).unwrap().unwrap()[0];
+                    assert!(version >= last, "read the version {version} after the version {last}");
+                    last = version;
+                }
+            })
+        })
+        .collect();
+
+    for version in 2..60 {
+        storage.write(&CHUNKS[0], &data(version)).unwrap();
+        storage.flush().unwrap();
+    }
+
+    for reader in readers {
+        reader.join().unwrap();
+    }
+
+    assert_versions(&storage, [59, 1, 1]);
+}
@@ -0,0 +1,205 @@
+//! Saves of a [ChunkStore] that run in the workers, and the chunks that the [ChunkStreamer] keeps
+//! loaded around the viewers.
+
+use std::{
+    collections::HashMap,
+    io,
+    sync::{Arc, Mutex},
+    thread,
+    time::Duration,
+};
+
+use specs::{Builder, Join, RunNow, System, World, WorldExt};
+use voxelia_engine::{
+    block::{BlockId, BlockPosition, BlockRegistry},
+    chunk::{Chunk, ChunkPosition, CHUNK_HEIGHT, CHUNK_LENGTH, CHUNK_WIDTH},
+    generation::{ChunkScheduler, ChunkStatus, Generation, WorldGenerator},
+    streaming::{ChunkStorage, ChunkStore, ChunkStreamer, ChunkViewer, Unsaved, GENERATION_MARGIN},
+    workers::{ThreadPool, Workers},
+    Position,
+};
+
+const POSITION: ChunkPosition = ChunkPosition::new(2, -1, 5);
+
+/// A storage in memory that takes its time to save.
+#[derive(Clone, Default)]
+struct SlowStorage {
+    chunks: Arc<Mutex<HashMap<ChunkPosition, BlockId>>>,
+    entities: Arc<Mutex<HashMap<ChunkPosition, Vec<u8>>>>,
+}
+
+impl ChunkStorage for SlowStorage {
+    fn load(&self, position: &ChunkPosition) -> io::Result<Option<Chunk>> {
+        Ok(self.chunks.lock().unwrap().get(position).map(|block| chunk(*block)))
+    }
+
+    fn save(&self, position: &ChunkPosition, chunk: &Chunk) -> io::Result<()> {
+        thread::sleep(Duration::from_millis(50));
+        self.chunks.lock().unwrap().insert(*position, block(chunk));
+        Ok(())
+    }
+
+    fn load_entities(&self, position: &ChunkPosition) -> io::Result<Option<Vec<u8>>> {
+        Ok(self.entities.lock().unwrap().get(position).cloned())
+    }
+
+    fn save_entities(&self, position: &ChunkPosition, entities: &[u8]) -> io::Result<()> {
+        thread::sleep(Duration::from_millis(50));
+        self.entities.lock().unwrap().insert(*position, entities.to_vec());
+        Ok(())
+    }
+}
+
+/// A chunk that is told apart by one of its blocks.
+fn chunk(block: BlockId) -> Chunk {
+    let mut chunk = Chunk::default();
+    chunk.set(&BlockPosition::new(1, 2, 3), block);
+    chunk
+}
+
+fn block(chunk: &Chunk) -> BlockId {
+    chunk.get(&BlockPosition::new(1, 2, 3))
+}
+
+fn store() -> (SlowStorage, ChunkStore, Workers) {
+    let storage = SlowStorage::default();
+    (storage.clone(), ChunkStore::new(storage), Workers::new(ThreadPool::new(4)))
+}
+
+#[test]
+fn loads_get_the_chunks_that_are_being_saved() {
+    let (storage, store, workers) = store();
+
+    store.save_later(&workers, POSITION, chunk(7));
+    assert_eq!(store.load(&POSITION).as_ref().map(block), Some(7));
+
+    store.flush();
+    assert_eq!(storage.chunks.lock().unwrap().get(&POSITION), Some(&7));
+    assert_eq!(store.load(&POSITION).as_ref().map(block), Some(7));
+}
+
+#[test]
+fn late_saves_never_replace_newer_ones() {
+    let (storage, store, workers) = store();
+
+    for block in 1..=10 {
+        store.save_later(&workers, POSITION, chunk(block));
+    }
+    store.save(&POSITION, &chunk(11));
+
+    assert_eq!(storage.chunks.lock().unwrap().get(&POSITION), Some(&11));
+    store.flush();
+    assert_eq!(storage.chunks.lock().unwrap().get(&POSITION), Some(&11));
+}
+
+#[test]
+fn flush_waits_for_every_save() {
+    let (storage, store, workers) = store();
+    let positions: Vec<ChunkPosition> = (0..8).map(|x| ChunkPosition::new(x, 0, 0)).collect();
+
+    for (block, position) in positions.iter().enumerate() {
+        store.save_later(&workers, *position, chunk(block as BlockId + 1));
+        store.save_entities_later(&workers, *position, vec![block as u8]);
+    }
+
+    store.flush();
+
+    for (block, position) in positions.iter().enumerate() {
+        assert_eq!(storage.chunks.lock().unwrap().get(position), Some(&(block as BlockId + 1)));
+        assert_eq!(storage.entities.lock().unwrap().get(position), Some(&vec![block as u8]));
+    }
+}
+
+#[test]
+fn loads_get_the_entities_that_are_being_saved() {
+    let (storage, store, workers) = store();
+
+    store.save_entities_later(&workers, POSITION, b"entities".to_vec());
+    assert_eq!(store.load_entities(&POSITION), Some(b"entities".to_vec()));
+
+    // Entities that were all removed are saved as nothing.
+    store.save_entities_later(&workers, POSITION, Vec::new());
+    assert_eq!(store.load_entities(&POSITION), None);
+
+    store.flush();
+    assert_eq!(storage.entities.lock().unwrap().get(&POSITION), Some(&Vec::new()));
+}
+
+/// A world of air.
+struct Empty;
+
+impl WorldGenerator for Empty {
+    fn generate(&self, _position: &ChunkPosition, _seed: u64) -> Chunk {
+        Chunk::default()
+    }
+}
+
+/// Position in blocks of the middle of a chunk.
+fn middle(x: i64) -> Position {
+    let x = x * CHUNK_WIDTH as i64 + CHUNK_WIDTH as i64 / 2;
+    Position::new(x as f32, (CHUNK_HEIGHT / 2) as f32, (CHUNK_LENGTH / 2) as f32)
+}
+
+/// Positions of the loaded chunks, sorted by x.
+fn loaded(world: &World) -> Vec<ChunkPosition> {
+    let (positions, statuses) = (world.read_storage::<Position>(), world.read_storage::<ChunkStatus>());
+    let mut loaded: Vec<_> =
+        (&positions, &statuses).join().map(|(position, _)| ChunkPosition::of_entity(position)).collect();
+    loaded.sort_by_key(|position| (position.x, position.y, position.z));
+    loaded
+}
+
+/// Chunks from `from` to `to` along x, in every z of the area of a viewer at z 0.
+fn columns(from: i64, to: i64) -> Vec<ChunkPosition> {
+    let margin = GENERATION_MARGIN as i64;
+    (from..=to).flat_map(|x| (-margin..=margin).map(move |z| ChunkPosition::new(x, 0, z))).collect()
+}
+
+#[test]
+fn viewers_keep_the_chunks_around_them() {
+    let storage = SlowStorage::default();
+    let mut world = World::new();
+    let (mut streamer, mut scheduler) = (ChunkStreamer::default(), ChunkScheduler::default());
+    System::setup(&mut streamer, &mut world);
+    System::setup(&mut scheduler, &mut world);
+    world.register::<ChunkViewer>();
+    world.insert(ChunkStore::new(storage.clone()));
+    world.insert(Workers::new(ThreadPool::new(2)));
+    world.insert(Generation::new(0, Empty));
+    world.insert(BlockRegistry::default());
+
+    let mut tick = |world: &mut World| {
+        streamer.run_now(world);
+        scheduler.run_now(world);
+        world.maintain();
+    };
+
+    let margin = GENERATION_MARGIN as i64;
+    let viewer = world.create_entity().with(middle(0)).with(ChunkViewer::new(0, 0)).build();
+    tick(&mut world);
+    assert_eq!(loaded(&world), columns(-margin, margin));
+
+    // The chunks that changed are saved when they are unloaded.
+    let edited = ChunkPosition::new(-margin, 0, 0);
+    {
+        let (entities, positions) = (world.entities(), world.read_storage::<Position>());
+        let (entity, _) =
+            (&entities, &positions).join().find(|(_, position)| ChunkPosition::of_entity(position) == edited).unwrap();
+        world.write_storage::<Chunk>().get_mut(entity).unwrap().set(&BlockPosition::new(1, 2, 3), 5);
+        world.write_storage::<Unsaved>().insert(entity, Unsaved).unwrap();
+    }
+
+    // Chunks a little farther than the area stay, the ones beyond that go.
+    let hysteresis = ChunkStreamer::default().hysteresis as i64;
+    world.write_storage::<Position>().insert(viewer, middle(6)).unwrap();
+    tick(&mut world);
+    assert_eq!(loaded(&world), columns(6 - margin - hysteresis, 6 + margin));
+
+    world.read_resource::<ChunkStore>().flush();
+    assert_eq!(*storage.chunks.lock().unwrap(), HashMap::from([(edited, 5)]));
+
+    // Nothing is unloaded while there are no viewers.
+    world.delete_entity(viewer).unwrap();
+    tick(&mut world);
+    assert_eq!(loaded(&world), columns(6 - margin - hysteresis, 6 + margin));
+}
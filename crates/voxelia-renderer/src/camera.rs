@@ -67,7 +67,7 @@ impl Projection {
             aspect: size.width as f32 / size.height as f32,
             fovy: 45.0,
             znear: 0.1,
-            zfar: 100.0,
+            zfar: 1000.0,
         }
     }
 
@@ -1,9 +1,10 @@
 //! Defines what some things are like [Mesh] and [Material] that are extremely important for rendering
 //! every [Model].
 
-use crate::{instance::ModelInstance, renderer::Renderer, texture, vertex::{ModelIndex, ModelVertex}};
+use crate::{instance::ModelInstance, pipeline::BlendMode, renderer::Renderer, texture, vertex::{self, ModelIndex, ModelVertex, Vertex, VertexKind}};
 use wgpu::util::DeviceExt;
 
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
 pub struct MaterialId(pub u32);
 
 /// Defines a [Texture] with a BindGroup
@@ -50,16 +51,19 @@ pub struct Mesh {
     pub vertex_buffer: wgpu::Buffer,
     pub index_buffer: wgpu::Buffer,
     pub instance_buffer: wgpu::Buffer,
+    pub index_format: wgpu::IndexFormat,
+    pub vertex_kind: VertexKind,
+    pub blend_mode: BlendMode,
     pub num_indices: u32,
     pub num_instances: u32,
     pub material_id: MaterialId,
 }
 
 impl Mesh {
-    pub fn from_vertex(
+    pub fn from_vertex<V: Vertex>(
         renderer: &Renderer,
         label: String,
-        vertices: &[ModelVertex],
+        vertices: &[V],
         indices: &[ModelIndex],
         instances: &[ModelInstance],
         material_id: MaterialId,
@@ -72,11 +76,13 @@ impl Mesh {
                 usage: wgpu::BufferUsages::VERTEX,
             });
 
+        let index_format = vertex::index_format(vertices.len());
+
         let index_buffer = renderer
             .device
             .create_buffer_init(&wgpu::util::BufferInitDescriptor {
                 label: Some(&format!("{:?} Index Buffer", label)),
-                contents: bytemuck::cast_slice(indices),
+                contents: &vertex::index_bytes(indices, index_format),
                 usage: wgpu::BufferUsages::INDEX,
             });
 
@@ -95,23 +101,43 @@ impl Mesh {
             vertex_buffer,
             index_buffer,
             instance_buffer,
+            index_format,
+            vertex_kind: V::KIND,
+            blend_mode: BlendMode::Opaque,
             num_indices: indices.len() as u32,
             num_instances: instances.len() as u32,
             material_id,
         }
     }
+
+    /// Changes how the mesh is blended, meshes are [BlendMode::Opaque] by default.
+    pub fn with_blend_mode(mut self, blend_mode: BlendMode) -> Mesh {
+        self.blend_mode = blend_mode;
+        self
+    }
 }
 
 /// A model here contains all the vertices and indices. Its used in order to update some mesh
-pub struct Model {
-    pub vertices: Vec<ModelVertex>,
-    pub indices: Vec<u16>,
+pub struct Model<V = ModelVertex> {
+    pub vertices: Vec<V>,
+    pub indices: Vec<ModelIndex>,
 }
 
-impl Model {
+impl<V: Vertex> Model<V> {
+    /// Writes the model into the buffers of the mesh. The mesh must be big enough to hold the model
+    /// and its index format must be able to address all the vertices of the model, otherwise it
+    /// panics.
     pub fn update_mesh(&self, queue: &wgpu::Queue, mesh: &mut Mesh) {
+        assert!(
+            mesh.index_format == wgpu::IndexFormat::Uint32
+                || vertex::index_format(self.vertices.len()) == wgpu::IndexFormat::Uint16,
+            "the mesh {:?} has 16 bit indices but the model has {} vertices",
+            mesh.label,
+            self.vertices.len()
+        );
+
         queue.write_buffer(&mesh.vertex_buffer, 0, bytemuck::cast_slice(&self.vertices));
-        queue.write_buffer(&mesh.index_buffer, 0, bytemuck::cast_slice(&self.indices));
+        queue.write_buffer(&mesh.index_buffer, 0, &vertex::index_bytes(&self.indices, mesh.index_format));
         mesh.num_indices = self.indices.len() as u32;
     } 
 }
\ No newline at end of file
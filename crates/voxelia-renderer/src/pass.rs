@@ -6,6 +6,8 @@ pub mod phong;
 
 /// Shared behaviour of being something that is able to render thigns to the screen
 pub trait Pass {
+    /// Draws the meshes. Translucent meshes are drawn after the others in the order that they are
+    /// given, so they should be sorted from back to front.
     fn draw(
         &self,
         renderer: &Renderer,
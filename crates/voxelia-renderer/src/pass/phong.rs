@@ -1,15 +1,17 @@
 //! The [PhongPass] is a struct that represents the phong pass of to render objects.
 
-use wgpu::{BindGroupLayout, IndexFormat};
+use std::collections::HashMap;
+
+use wgpu::BindGroupLayout;
 
 use crate::{
     globals::Globals,
     instance::InstanceRaw,
     model::{Material, Mesh},
-    pipeline,
+    pipeline::{self, BlendMode},
     renderer::Renderer,
     texture,
-    vertex::ModelVertex,
+    vertex::{ModelVertex, Vertex, VertexKind, VoxelVertex},
 };
 
 use super::Pass;
@@ -17,7 +19,7 @@ use super::Pass;
 /// The primary pass for rendering the entire thing.
 pub struct PhongPass {
     pub depth_texture: texture::Texture,
-    pub render_pipeline: pipeline::Pipeline,
+    pub pipelines: HashMap<(VertexKind, BlendMode), pipeline::Pipeline>,
     pub texture_bind_group_layout: BindGroupLayout,
 }
 
@@ -30,27 +32,46 @@ impl PhongPass {
             texture::Texture::create_depth_texture(device, config, "Depth Texture");
         let texture_bind_group_layout = texture::default_texture_bind_group_layout(device);
 
-        let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
-            label: Some("Render Pipeline Layout"),
-            bind_group_layouts: &[&texture_bind_group_layout, &globals.camera.layout],
-            push_constant_ranges: &[],
-        });
-
-        let vertex = pipeline::include_shader!(device, "../shaders/shader.wgsl");
-        let fragment = pipeline::include_shader!(device, "../shaders/shader.wgsl");
-
-        let render_pipeline = pipeline::Pipeline::new(
-            device,
-            config,
-            layout,
-            vertex,
-            &[ModelVertex::desc(), InstanceRaw::desc()],
-            fragment,
-        );
+        let mut pipelines = HashMap::new();
+
+        for kind in [VertexKind::Model, VertexKind::Voxel] {
+            for blend_mode in BlendMode::ALL {
+                let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                    label: Some("Render Pipeline Layout"),
+                    bind_group_layouts: &[&texture_bind_group_layout, &globals.camera.layout],
+                    push_constant_ranges: &[],
+                });
+
+                let (vertex, fragment, vertex_layout) = match kind {
+                    VertexKind::Model => (
+                        pipeline::include_shader!(device, "../shaders/shader.wgsl"),
+                        pipeline::include_shader!(device, "../shaders/shader.wgsl"),
+                        ModelVertex::desc(),
+                    ),
+                    VertexKind::Voxel => (
+                        pipeline::include_shader!(device, "../shaders/voxel.wgsl"),
+                        pipeline::include_shader!(device, "../shaders/voxel.wgsl"),
+                        VoxelVertex::desc(),
+                    ),
+                };
+
+                let render_pipeline = pipeline::Pipeline::new(
+                    device,
+                    config,
+                    layout,
+                    vertex,
+                    &[vertex_layout, InstanceRaw::desc()],
+                    fragment,
+                    blend_mode,
+                );
+
+                pipelines.insert((kind, blend_mode), render_pipeline);
+            }
+        }
 
         Self {
             depth_texture,
-            render_pipeline,
+            pipelines,
             texture_bind_group_layout,
         }
     }
@@ -114,9 +135,17 @@ impl Pass for PhongPass {
                 }),
             });
 
-            render_pass.set_pipeline(&self.render_pipeline.pipeline);
+            // Meshes are grouped by blend mode keeping the order that they were given, translucent
+            // ones come last and must be sorted from back to front by the caller.
+            let ordered = BlendMode::ALL
+                .iter()
+                .flat_map(|mode| meshes.iter().filter(move |mesh| mesh.blend_mode == *mode));
+
+            for mesh in ordered {
+                let pipeline = &self.pipelines[&(mesh.vertex_kind, mesh.blend_mode)];
+
+                render_pass.set_pipeline(&pipeline.pipeline);
 
-            for mesh in meshes {
                 let material = &materials[mesh.material_id.0 as usize];
 
                 render_pass.set_vertex_buffer(0, mesh.vertex_buffer.slice(..));
@@ -125,7 +154,7 @@ impl Pass for PhongPass {
                 render_pass.set_bind_group(1, &globals.camera.group, &[]);
 
                 render_pass.set_vertex_buffer(1, mesh.instance_buffer.slice(..));
-                render_pass.set_index_buffer(mesh.index_buffer.slice(..), IndexFormat::Uint16);
+                render_pass.set_index_buffer(mesh.index_buffer.slice(..), mesh.index_format);
 
                 render_pass.draw_indexed(0..mesh.num_indices, 0, 0..mesh.num_instances);
             }
@@ -4,6 +4,24 @@ use std::fs;
 
 use crate::texture;
 
+/// How the fragments of a mesh are blended with the things that were drawn before.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum BlendMode {
+    /// Replaces the color behind it.
+    Opaque,
+    /// Like [BlendMode::Opaque] but transparent pixels are discarded by the `fs_cutout` entry point
+    /// of the fragment shader.
+    Cutout,
+    /// Blended using the alpha and does not write to the depth buffer, so it must be drawn after the
+    /// other modes and from back to front.
+    Translucent,
+}
+
+impl BlendMode {
+    /// All the modes in the order that they must be drawn.
+    pub const ALL: [BlendMode; 3] = [BlendMode::Opaque, BlendMode::Cutout, BlendMode::Translucent];
+}
+
 /// A wrapper struct for `wgpu::RenderPipeline` that encapsulates a rendering pipeline.
 pub struct Pipeline {
     pub pipeline: wgpu::RenderPipeline,
@@ -17,7 +35,14 @@ impl Pipeline {
         vertex_shader: wgpu::ShaderModule,
         vertex_layout: &[wgpu::VertexBufferLayout],
         fragment_shader: wgpu::ShaderModule,
+        blend_mode: BlendMode,
     ) -> Self {
+        let (entry_point, blend) = match blend_mode {
+            BlendMode::Opaque => ("fs_main", wgpu::BlendState::REPLACE),
+            BlendMode::Cutout => ("fs_cutout", wgpu::BlendState::REPLACE),
+            BlendMode::Translucent => ("fs_main", wgpu::BlendState::ALPHA_BLENDING),
+        };
+
         Pipeline {
             pipeline: device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
                 label: Some("Render Pipeline"),
@@ -29,10 +54,10 @@ impl Pipeline {
                 },
                 fragment: Some(wgpu::FragmentState {
                     module: &fragment_shader,
-                    entry_point: "fs_main",
+                    entry_point,
                     targets: &[Some(wgpu::ColorTargetState {
                         format: config.format,
-                        blend: Some(wgpu::BlendState::REPLACE),
+                        blend: Some(blend),
                         write_mask: wgpu::ColorWrites::ALL,
                     })],
                 }),
@@ -47,7 +72,7 @@ impl Pipeline {
                 },
                 depth_stencil: Some(wgpu::DepthStencilState {
                     format: texture::Texture::DEPTH_FORMAT,
-                    depth_write_enabled: true,
+                    depth_write_enabled: blend_mode != BlendMode::Translucent,
                     depth_compare: wgpu::CompareFunction::Less,
                     stencil: wgpu::StencilState::default(),
                     bias: wgpu::DepthBiasState::default(),
@@ -0,0 +1,88 @@
+@group(1) @binding(0) var<uniform> camera: CameraUniform;
+@group(0) @binding(0) var t_diffuse: texture_2d<f32>;
+@group(0) @binding(1) var s_diffuse: sampler;
+
+struct CameraUniform {
+    view_proj: mat4x4<f32>,
+};
+
+struct VertexInput {
+    @location(0) data: u32,
+    @location(1) tint: vec4<f32>,
+}
+
+struct InstanceInput {
+    @location(5) model_matrix_0: vec4<f32>,
+    @location(6) model_matrix_1: vec4<f32>,
+    @location(7) model_matrix_2: vec4<f32>,
+    @location(8) model_matrix_3: vec4<f32>,
+};
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) tex_coords: vec2<f32>,
+    @location(1) shade: f32,
+    @location(2) tint: vec3<f32>,
+}
+
+// Fixed light for each face direction: front, back, left, right, top and bottom.
+fn face_shade(face: u32) -> f32 {
+    switch face {
+        case 0u, 1u: { return 0.8; }
+        case 2u, 3u: { return 0.9; }
+        case 4u: { return 1.0; }
+        default: { return 0.6; }
+    }
+}
+
+@vertex
+fn vs_main(
+    model: VertexInput,
+    instance: InstanceInput,
+) -> VertexOutput {
+    let model_matrix = mat4x4<f32>(
+        instance.model_matrix_0,
+        instance.model_matrix_1,
+        instance.model_matrix_2,
+        instance.model_matrix_3,
+    );
+
+    let corner = vec3<f32>(
+        f32(model.data & 63u),
+        f32((model.data >> 6u) & 63u),
+        f32((model.data >> 12u) & 63u),
+    );
+
+    let face = (model.data >> 18u) & 7u;
+    let uv = vec2<f32>(f32((model.data >> 21u) & 1u), f32((model.data >> 22u) & 1u));
+    let ao = f32((model.data >> 23u) & 3u);
+
+    // Blocks are two units wide and centered in their coordinate.
+    let position = corner * 2.0 - 1.0;
+
+    var out: VertexOutput;
+    // Every face shows the whole texture, the layer in the bits 25-31 is left for a future atlas.
+    out.tex_coords = uv;
+    // Places without light are never completely black.
+    let light = max(model.tint.a, 0.08);
+    out.shade = face_shade(face) * (0.4 + 0.2 * ao) * light;
+    // Tints are given in sRGB and the lighting is done in linear space.
+    out.tint = pow(model.tint.rgb, vec3<f32>(2.2));
+    out.clip_position = camera.view_proj * model_matrix * vec4<f32>(position, 1.0);
+    return out;
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    let color = textureSample(t_diffuse, s_diffuse, in.tex_coords);
+    return vec4<f32>(color.rgb * in.tint * in.shade, color.a);
+}
+
+@fragment
+fn fs_cutout(in: VertexOutput) -> @location(0) vec4<f32> {
+    let color = textureSample(t_diffuse, s_diffuse, in.tex_coords);
+    if color.a < 0.5 {
+        discard;
+    }
+    return vec4<f32>(color.rgb * in.tint * in.shade, 1.0);
+}
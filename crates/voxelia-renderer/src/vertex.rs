@@ -1,4 +1,19 @@
-//! This module defines the [ModelVertex] structure that describes a vertex inside shaders.
+//! This module defines the vertex structures that describe a vertex inside shaders: the generic
+//! [ModelVertex] and the packed [VoxelVertex] used for voxel terrain.
+
+/// Kinds of vertices that the passes know how to draw. Each one of them needs its own pipeline.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum VertexKind {
+    Model,
+    Voxel,
+}
+
+/// Something that can be stored inside of a vertex buffer.
+pub trait Vertex: bytemuck::Pod {
+    const KIND: VertexKind;
+
+    fn desc<'a>() -> wgpu::VertexBufferLayout<'a>;
+}
 
 /// A primitive vertex that contains position, a normal vector, and a texture coordinate. Usually
 /// used for 3D objects.
@@ -9,7 +24,33 @@ pub struct ModelVertex {
     pub tex_coords: [f32; 2],
 }
 
-pub type ModelIndex = u16;
+/// Index type used while building meshes. It gets narrowed down to 16 bits when uploading to the
+/// GPU if the mesh is small enough, see [index_format].
+pub type ModelIndex = u32;
+
+/// Chooses the smallest [wgpu::IndexFormat] that is able to address `vertex_count` vertices.
+pub fn index_format(vertex_count: usize) -> wgpu::IndexFormat {
+    if vertex_count <= u16::MAX as usize {
+        wgpu::IndexFormat::Uint16
+    } else {
+        wgpu::IndexFormat::Uint32
+    }
+}
+
+/// Converts indices into the raw bytes of the buffer for a given [wgpu::IndexFormat]. Panics if an
+/// index does not fit in the format.
+pub fn index_bytes(indices: &[ModelIndex], format: wgpu::IndexFormat) -> Vec<u8> {
+    match format {
+        wgpu::IndexFormat::Uint16 => {
+            let narrow = indices
+                .iter()
+                .map(|x| u16::try_from(*x).expect("the index does not fit in 16 bits"))
+                .collect::<Vec<_>>();
+            bytemuck::cast_slice(&narrow).to_vec()
+        }
+        wgpu::IndexFormat::Uint32 => bytemuck::cast_slice(indices).to_vec(),
+    }
+}
 
 impl ModelVertex {
     pub fn add(&self, position: [f32; 3]) -> ModelVertex {
@@ -29,12 +70,162 @@ impl ModelVertex {
         0 => Float32x3,
         1 => Float32x2
     ];
+}
+
+impl Vertex for ModelVertex {
+    const KIND: VertexKind = VertexKind::Model;
 
-    pub fn desc<'a>() -> wgpu::VertexBufferLayout<'a> {
+    fn desc<'a>() -> wgpu::VertexBufferLayout<'a> {
         wgpu::VertexBufferLayout {
             array_stride: std::mem::size_of::<ModelVertex>() as wgpu::BufferAddress,
             step_mode: wgpu::VertexStepMode::Vertex,
             attributes: &Self::DESC,
         }
     }
-}
\ No newline at end of file
+}
+
+/// A vertex of a voxel face packed inside of a [u32] and unpacked by `shaders/voxel.wgsl`, followed
+/// by the color that tints it. The bits of `data` are laid out like this, from the least
+/// significant one:
+///
+/// | bits  | content                                        |
+/// |-------|------------------------------------------------|
+/// | 0-5   | x of the corner inside of the chunk            |
+/// | 6-11  | y of the corner inside of the chunk            |
+/// | 12-17 | z of the corner inside of the chunk            |
+/// | 18-20 | direction of the face                          |
+/// | 21-22 | texture coordinate of the corner (u and v)     |
+/// | 23-24 | ambient occlusion, 0 is fully occluded         |
+/// | 25-31 | texture layer, unused until there is an atlas  |
+#[repr(C)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct VoxelVertex {
+    pub data: u32,
+    /// Color multiplied with the texture in RGB, white leaves the texture untouched. The alpha is
+    /// the light that reaches the vertex.
+    pub tint: [u8; 4],
+}
+
+impl VoxelVertex {
+    pub const MAX_COORD: u32 = 0x3F;
+    pub const MAX_LAYER: u32 = 0x7F;
+
+    pub const WHITE: [u8; 4] = [255; 4];
+
+    pub const DESC: [wgpu::VertexAttribute; 2] = wgpu::vertex_attr_array![
+        0 => Uint32,
+        1 => Unorm8x4
+    ];
+
+    /// Packs a vertex. The `corner` is the position of the vertex in blocks relative to the origin
+    /// of the chunk and `tex_coords` are either 0 or 1. Panics if a value does not fit in its bits.
+    pub fn new(corner: [u32; 3], face: u32, tex_coords: [u32; 2], ao: u32, layer: u32) -> Self {
+        assert!(corner.iter().all(|x| *x <= Self::MAX_COORD), "the corner {corner:?} is outside of the chunk");
+        assert!(
+            face < 6 && tex_coords.iter().all(|x| *x <= 1) && ao < 4,
+            "the face, texture coordinates or ambient occlusion are out of range"
+        );
+        assert!(layer <= Self::MAX_LAYER, "the texture layer {layer} is over {}", Self::MAX_LAYER);
+
+        VoxelVertex {
+            data: corner[0]
+                | corner[1] << 6
+                | corner[2] << 12
+                | face << 18
+                | tex_coords[0] << 21
+                | tex_coords[1] << 22
+                | ao << 23
+                | layer << 25,
+            tint: Self::WHITE,
+        }
+    }
+
+    pub fn with_tint(mut self, tint: [u8; 4]) -> Self {
+        self.tint = tint;
+        self
+    }
+
+    pub fn corner(&self) -> [u32; 3] {
+        [
+            self.data & Self::MAX_COORD,
+            (self.data >> 6) & Self::MAX_COORD,
+            (self.data >> 12) & Self::MAX_COORD,
+        ]
+    }
+
+    pub fn face(&self) -> u32 {
+        (self.data >> 18) & 0x7
+    }
+
+    pub fn tex_coords(&self) -> [u32; 2] {
+        [(self.data >> 21) & 1, (self.data >> 22) & 1]
+    }
+
+    pub fn ao(&self) -> u32 {
+        (self.data >> 23) & 0x3
+    }
+
+    pub fn layer(&self) -> u32 {
+        self.data >> 25
+    }
+}
+
+impl Vertex for VoxelVertex {
+    const KIND: VertexKind = VertexKind::Voxel;
+
+    fn desc<'a>() -> wgpu::VertexBufferLayout<'a> {
+        wgpu::VertexBufferLayout {
+            array_stride: std::mem::size_of::<VoxelVertex>() as wgpu::BufferAddress,
+            step_mode: wgpu::VertexStepMode::Vertex,
+            attributes: &Self::DESC,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn indices_use_the_smallest_format() {
+        assert_eq!(index_format(u16::MAX as usize), wgpu::IndexFormat::Uint16);
+        assert_eq!(index_format(u16::MAX as usize + 1), wgpu::IndexFormat::Uint32);
+
+        assert_eq!(index_bytes(&[1, 0xfffe], wgpu::IndexFormat::Uint16), [1, 0, 0xfe, 0xff]);
+        assert_eq!(index_bytes(&[0x10000], wgpu::IndexFormat::Uint32), [0, 0, 1, 0]);
+    }
+
+    #[test]
+    fn voxel_vertices_come_back_unpacked() {
+        for (corner, face, tex_coords, ao, layer) in [
+            ([0, 0, 0], 0, [0, 0], 0, 0),
+            ([16, 32, 16], 5, [1, 1], 3, VoxelVertex::MAX_LAYER),
+            ([VoxelVertex::MAX_COORD, 7, 1], 3, [1, 0], 2, 42),
+            ([5, VoxelVertex::MAX_COORD, 9], 4, [0, 1], 1, 1),
+        ] {
+            let vertex = VoxelVertex::new(corner, face, tex_coords, ao, layer);
+
+            assert_eq!(vertex.corner(), corner);
+            assert_eq!(vertex.face(), face);
+            assert_eq!(vertex.tex_coords(), tex_coords);
+            assert_eq!(vertex.ao(), ao);
+            assert_eq!(vertex.layer(), layer);
+        }
+
+        // The layout that `shaders/voxel.wgsl` unpacks.
+        let vertex = VoxelVertex::new([1, 2, 3], 4, [1, 0], 2, 5);
+        assert_eq!(vertex.data, 1 | 2 << 6 | 3 << 12 | 4 << 18 | 1 << 21 | 2 << 23 | 5 << 25);
+    }
+
+    #[test]
+    #[should_panic]
+    fn layers_that_do_not_fit_are_rejected() {
+        VoxelVertex::new([0, 0, 0], 0, [0, 0], 0, VoxelVertex::MAX_LAYER + 1);
+    }
+
+    #[test]
+    #[should_panic]
+    fn indices_that_do_not_fit_are_rejected() {
+        index_bytes(&[0x10000], wgpu::IndexFormat::Uint16);
+    }
+}
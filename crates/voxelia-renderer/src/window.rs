@@ -36,6 +36,8 @@ pub enum WindowEvents {
         delta: (f64, f64),
     },
     Draw,
+    /// The window was closed and the event loop is about to stop.
+    Closed,
 }
 
 impl Window {
@@ -139,6 +141,7 @@ impl Window {
                     func(&self.window, WindowEvents::Draw)
                 }
                 Event::MainEventsCleared => func(&self.window, WindowEvents::Draw),
+                Event::LoopDestroyed => func(&self.window, WindowEvents::Closed),
                 _ => {}
             })
     }